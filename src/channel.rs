@@ -1,3 +1,5 @@
+use crate::PackError;
+
 #[derive(Copy, Clone, PartialOrd, PartialEq, Ord, Eq, Hash, Debug)]
 pub struct Index(u8);
 impl Index {
@@ -51,3 +53,124 @@ impl From<Index> for u8 {
         i.0
     }
 }
+/// Bit field of the 37 LE data channels (`Index` 0 to 36). Bit `n` is set if data channel `n`
+/// is used.
+#[derive(Copy, Clone, PartialOrd, PartialEq, Ord, Eq, Hash, Debug)]
+pub struct ChannelMap(u64);
+impl ChannelMap {
+    pub const BYTE_LEN: usize = 5;
+    pub const DATA_CHANNEL_COUNT: u8 = 37;
+    const ALL_BITS: u64 = (1 << Self::DATA_CHANNEL_COUNT) - 1;
+    /// Every data channel used.
+    pub const ALL: ChannelMap = ChannelMap(Self::ALL_BITS);
+    pub const fn zeroed() -> ChannelMap {
+        ChannelMap(0)
+    }
+    /// Returns `None` if any bit above the 37 data channels is set.
+    pub fn new_checked(bits: u64) -> Option<ChannelMap> {
+        if bits & !Self::ALL_BITS == 0 {
+            Some(ChannelMap(bits))
+        } else {
+            None
+        }
+    }
+    pub const fn bits(self) -> u64 {
+        self.0
+    }
+    /// Marks the data channel as used. Advertising channels are ignored.
+    pub fn enable(&mut self, index: Index) {
+        if !index.is_advertising() {
+            self.0 |= 1 << index.0;
+        }
+    }
+    pub fn disable(&mut self, index: Index) {
+        if !index.is_advertising() {
+            self.0 &= !(1 << index.0);
+        }
+    }
+    /// If the data channel is used. Always `false` for advertising channels.
+    pub fn get(self, index: Index) -> bool {
+        !index.is_advertising() && self.0 & (1 << index.0) != 0
+    }
+    /// Number of used data channels.
+    pub fn used_count(self) -> u8 {
+        self.0.count_ones() as u8
+    }
+    /// Iterates over every used data channel in ascending order.
+    pub fn used_channels(self) -> impl Iterator<Item = Index> {
+        (0..Self::DATA_CHANNEL_COUNT)
+            .filter(move |i| self.0 & (1 << i) != 0)
+            .map(Index)
+    }
+    pub fn pack_into(self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        buf.copy_from_slice(&self.0.to_le_bytes()[..Self::BYTE_LEN]);
+        Ok(())
+    }
+    /// Bits 37 to 39 are reserved for future use and ignored.
+    pub fn unpack_from(buf: &[u8]) -> Result<Self, PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        let mut bytes = [0_u8; 8];
+        bytes[..Self::BYTE_LEN].copy_from_slice(buf);
+        Ok(ChannelMap(u64::from_le_bytes(bytes) & Self::ALL_BITS))
+    }
+}
+impl Default for ChannelMap {
+    fn default() -> Self {
+        Self::ALL
+    }
+}
+impl From<ChannelMap> for u64 {
+    fn from(m: ChannelMap) -> Self {
+        m.0
+    }
+}
+/// RF channel (0 to 39) numbered by frequency (`2402 MHz + 2 MHz * channel`). Used by Direct
+/// Test Mode instead of the Link Layer channel `Index`.
+#[derive(Copy, Clone, PartialOrd, PartialEq, Ord, Eq, Hash, Debug, Default)]
+pub struct RFChannel(u8);
+impl RFChannel {
+    pub const MAX_U8: u8 = 39;
+    pub const MAX: RFChannel = RFChannel(Self::MAX_U8);
+    pub fn new_checked(channel: u8) -> Option<RFChannel> {
+        if channel > Self::MAX_U8 {
+            None
+        } else {
+            Some(RFChannel(channel))
+        }
+    }
+    /// Every RF channel from the lowest to the highest frequency.
+    pub fn all() -> impl Iterator<Item = RFChannel> {
+        (0..=Self::MAX_U8).map(RFChannel)
+    }
+    /// Returns channel frequency in MHz.
+    pub fn frequency(self) -> usize {
+        2402 + 2 * usize::from(self.0)
+    }
+    /// Link Layer channel `Index` on the same frequency.
+    pub fn index(self) -> Index {
+        Index(match self.0 {
+            0 => 37,
+            12 => 38,
+            39 => 39,
+            c if c < 12 => c - 1,
+            c => c - 2,
+        })
+    }
+}
+impl From<Index> for RFChannel {
+    fn from(index: Index) -> Self {
+        RFChannel(match index.0 {
+            37 => 0,
+            38 => 12,
+            39 => 39,
+            i if i < 11 => i + 1,
+            i => i + 2,
+        })
+    }
+}
+impl From<RFChannel> for u8 {
+    fn from(c: RFChannel) -> Self {
+        c.0
+    }
+}
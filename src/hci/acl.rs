@@ -0,0 +1,614 @@
+//! HCI ACL Data packets and ACL buffer accounting (flow control) for both directions.
+use crate::bytes::Storage;
+use crate::hci::baseband::{HostBufferSize, HostNumberOfCompletedPackets};
+use crate::hci::event::{Event, EventCode, EventPacket};
+use crate::hci::le::connection::{BufferSizeV1, BufferSizeV2};
+use crate::hci::link_control::DisconnectionComplete;
+use crate::hci::packet::{PacketType, RawPacket};
+use crate::hci::{ErrorCode, MAX_ACL_SIZE};
+use crate::le::connection::ConnectionHandle;
+use crate::{ConversionError, PackError};
+use alloc::collections::BTreeMap;
+use core::convert::{TryFrom, TryInto};
+
+/// ACL Packet Boundary Flag. Tells if the ACL packet is the start of a L2CAP PDU or a
+/// continuing fragment.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+#[repr(u8)]
+pub enum PacketBoundary {
+    FirstNonFlushable = 0b00,
+    Continuing = 0b01,
+    FirstFlushable = 0b10,
+    /// Complete L2CAP PDU. Only used for BR/EDR loopback.
+    Complete = 0b11,
+}
+impl From<PacketBoundary> for u8 {
+    fn from(b: PacketBoundary) -> Self {
+        b as u8
+    }
+}
+impl TryFrom<u8> for PacketBoundary {
+    type Error = ConversionError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0b00 => Ok(PacketBoundary::FirstNonFlushable),
+            0b01 => Ok(PacketBoundary::Continuing),
+            0b10 => Ok(PacketBoundary::FirstFlushable),
+            0b11 => Ok(PacketBoundary::Complete),
+            _ => Err(ConversionError(())),
+        }
+    }
+}
+/// ACL Broadcast Flag. LE only uses `PointToPoint`.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+#[repr(u8)]
+pub enum BroadcastFlag {
+    PointToPoint = 0b00,
+    BREDRBroadcast = 0b01,
+}
+impl From<BroadcastFlag> for u8 {
+    fn from(b: BroadcastFlag) -> Self {
+        b as u8
+    }
+}
+impl TryFrom<u8> for BroadcastFlag {
+    type Error = ConversionError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0b00 => Ok(BroadcastFlag::PointToPoint),
+            0b01 => Ok(BroadcastFlag::BREDRBroadcast),
+            _ => Err(ConversionError(())),
+        }
+    }
+}
+/// HCI ACL Data packet header. 12-bit Connection Handle, 2-bit `PacketBoundary`, 2-bit
+/// `BroadcastFlag` and a 16-bit data length.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct ACLHeader {
+    pub handle: ConnectionHandle,
+    pub boundary: PacketBoundary,
+    pub broadcast: BroadcastFlag,
+    pub data_len: u16,
+}
+impl ACLHeader {
+    pub const BYTE_LEN: usize = 4;
+    pub fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        let handle_flags = u16::from(self.handle)
+            | (u16::from(u8::from(self.boundary)) << 12)
+            | (u16::from(u8::from(self.broadcast)) << 14);
+        buf[..2].copy_from_slice(&handle_flags.to_le_bytes());
+        buf[2..4].copy_from_slice(&self.data_len.to_le_bytes());
+        Ok(())
+    }
+    pub fn unpack_from(buf: &[u8]) -> Result<Self, PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        let handle_flags = u16::from_le_bytes([buf[0], buf[1]]);
+        Ok(ACLHeader {
+            handle: ConnectionHandle::new_checked(handle_flags & 0x0FFF)
+                .ok_or(PackError::bad_index(0))?,
+            boundary: PacketBoundary::try_from(((handle_flags >> 12) & 0b11) as u8)
+                .map_err(|_| PackError::bad_index(1))?,
+            broadcast: BroadcastFlag::try_from(((handle_flags >> 14) & 0b11) as u8)
+                .map_err(|_| PackError::bad_index(1))?,
+            data_len: u16::from_le_bytes([buf[2], buf[3]]),
+        })
+    }
+}
+/// HCI ACL Data packet. Usually carries a (fragment of a) L2CAP PDU.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct ACLPacket<Buf> {
+    pub handle: ConnectionHandle,
+    pub boundary: PacketBoundary,
+    pub broadcast: BroadcastFlag,
+    pub data: Buf,
+}
+impl<Buf: AsRef<[u8]>> ACLPacket<Buf> {
+    pub fn new(handle: ConnectionHandle, boundary: PacketBoundary, data: Buf) -> Self {
+        Self {
+            handle,
+            boundary,
+            broadcast: BroadcastFlag::PointToPoint,
+            data,
+        }
+    }
+    pub fn as_ref(&self) -> ACLPacket<&[u8]> {
+        ACLPacket {
+            handle: self.handle,
+            boundary: self.boundary,
+            broadcast: self.broadcast,
+            data: self.data.as_ref(),
+        }
+    }
+    /// # Errors
+    /// Returns `PackError::InvalidFields` if the data is longer than an `u16`.
+    pub fn header(&self) -> Result<ACLHeader, PackError> {
+        Ok(ACLHeader {
+            handle: self.handle,
+            boundary: self.boundary,
+            broadcast: self.broadcast,
+            data_len: self
+                .data
+                .as_ref()
+                .len()
+                .try_into()
+                .map_err(|_| PackError::InvalidFields)?,
+        })
+    }
+    pub fn byte_len(&self) -> usize {
+        ACLHeader::BYTE_LEN + self.data.as_ref().len()
+    }
+    pub fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(self.byte_len(), buf)?;
+        if self.data.as_ref().len() > MAX_ACL_SIZE {
+            return Err(PackError::InvalidFields);
+        }
+        self.header()?.pack_into(&mut buf[..ACLHeader::BYTE_LEN])?;
+        buf[ACLHeader::BYTE_LEN..].copy_from_slice(self.data.as_ref());
+        Ok(())
+    }
+    pub fn to_raw_packet<NewStorage: Storage<u8>>(
+        &self,
+    ) -> Result<RawPacket<NewStorage>, PackError> {
+        let mut buf = NewStorage::with_size(self.byte_len());
+        self.pack_into(buf.as_mut())?;
+        Ok(RawPacket {
+            packet_type: PacketType::ACLData,
+            buf,
+        })
+    }
+    pub fn to_new_storage<NewStorage: Storage<u8>>(&self) -> ACLPacket<NewStorage> {
+        ACLPacket {
+            handle: self.handle,
+            boundary: self.boundary,
+            broadcast: self.broadcast,
+            data: NewStorage::from_slice(self.data.as_ref()),
+        }
+    }
+}
+impl<'a> ACLPacket<&'a [u8]> {
+    /// Splits a L2CAP PDU into ACL Data packets with at most `max_fragment_len` bytes of data
+    /// each. The first packet is `PacketBoundary::FirstNonFlushable` and the rest are
+    /// `PacketBoundary::Continuing`.
+    /// # Panics
+    /// Panics if `max_fragment_len` is `0`.
+    pub fn fragments(
+        handle: ConnectionHandle,
+        pdu: &'a [u8],
+        max_fragment_len: usize,
+    ) -> impl Iterator<Item = ACLPacket<&'a [u8]>> + 'a {
+        pdu.chunks(max_fragment_len)
+            .enumerate()
+            .map(move |(i, fragment)| {
+                let boundary = if i == 0 {
+                    PacketBoundary::FirstNonFlushable
+                } else {
+                    PacketBoundary::Continuing
+                };
+                ACLPacket::new(handle, boundary, fragment)
+            })
+    }
+    pub fn unpack_from(buf: &'a [u8]) -> Result<Self, PackError> {
+        if buf.len() < ACLHeader::BYTE_LEN {
+            return Err(PackError::BadLength {
+                expected: ACLHeader::BYTE_LEN,
+                got: buf.len(),
+            });
+        }
+        let header = ACLHeader::unpack_from(&buf[..ACLHeader::BYTE_LEN])?;
+        let data = &buf[ACLHeader::BYTE_LEN..];
+        PackError::expect_length(usize::from(header.data_len), data)?;
+        Ok(ACLPacket {
+            handle: header.handle,
+            boundary: header.boundary,
+            broadcast: header.broadcast,
+            data,
+        })
+    }
+}
+impl<'a> TryFrom<RawPacket<&'a [u8]>> for ACLPacket<&'a [u8]> {
+    type Error = PackError;
+
+    fn try_from(packet: RawPacket<&'a [u8]>) -> Result<Self, Self::Error> {
+        if packet.packet_type != PacketType::ACLData {
+            Err(PackError::BadOpcode)
+        } else {
+            ACLPacket::unpack_from(packet.buf)
+        }
+    }
+}
+/// Number of completed packets for a single Connection Handle.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash, Default)]
+pub struct CompletedPackets {
+    pub handle: ConnectionHandle,
+    pub num_completed_packets: u16,
+}
+impl CompletedPackets {
+    pub const BYTE_LEN: usize = 4;
+    pub fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        buf[..2].copy_from_slice(&u16::from(self.handle).to_le_bytes());
+        buf[2..].copy_from_slice(&self.num_completed_packets.to_le_bytes());
+        Ok(())
+    }
+    pub fn unpack_from(buf: &[u8]) -> Result<Self, PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        Ok(CompletedPackets {
+            handle: ConnectionHandle::new_checked(u16::from_le_bytes([buf[0], buf[1]]))
+                .ok_or(PackError::bad_index(0))?,
+            num_completed_packets: u16::from_le_bytes([buf[2], buf[3]]),
+        })
+    }
+}
+/// Packs a `Num_Handles` byte followed by each `CompletedPackets`. Shared by the
+/// `NumberOfCompletedPackets` event and the `HostNumberOfCompletedPackets` command.
+pub(crate) fn pack_completed_packets(
+    completed: &[CompletedPackets],
+    buf: &mut [u8],
+) -> Result<(), PackError> {
+    PackError::expect_length(1 + completed.len() * CompletedPackets::BYTE_LEN, buf)?;
+    buf[0] = completed
+        .len()
+        .try_into()
+        .map_err(|_| PackError::InvalidFields)?;
+    for (c, chunk) in completed
+        .iter()
+        .zip(buf[1..].chunks_exact_mut(CompletedPackets::BYTE_LEN))
+    {
+        c.pack_into(chunk)?;
+    }
+    Ok(())
+}
+pub(crate) fn unpack_completed_packets<Buf: Storage<CompletedPackets>>(
+    buf: &[u8],
+) -> Result<Buf, PackError> {
+    let num_handles = usize::from(*buf.get(0).ok_or(PackError::BadLength {
+        expected: 1,
+        got: 0,
+    })?);
+    PackError::expect_length(1 + num_handles * CompletedPackets::BYTE_LEN, buf)?;
+    let mut out = Buf::with_size(num_handles);
+    for (c, chunk) in out
+        .as_mut()
+        .iter_mut()
+        .zip(buf[1..].chunks_exact(CompletedPackets::BYTE_LEN))
+    {
+        *c = CompletedPackets::unpack_from(chunk)?;
+    }
+    Ok(out)
+}
+/// Number Of Completed Packets Event. Sent by the controller when ACL packets have been
+/// transmitted (or flushed) and the buffers are free for the host to use again.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct NumberOfCompletedPackets<Buf = Box<[CompletedPackets]>> {
+    pub completed: Buf,
+}
+impl<Buf: Storage<CompletedPackets>> Event for NumberOfCompletedPackets<Buf> {
+    const EVENT_CODE: EventCode = EventCode::NumberOfCompletedPackets;
+
+    fn event_byte_len(&self) -> usize {
+        1 + self.completed.as_ref().len() * CompletedPackets::BYTE_LEN
+    }
+
+    fn event_unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        Ok(NumberOfCompletedPackets {
+            completed: unpack_completed_packets(buf)?,
+        })
+    }
+
+    fn event_pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        pack_completed_packets(self.completed.as_ref(), buf)
+    }
+}
+/// Tracks the controller's free ACL data buffers (Host to Controller flow control). The host may
+/// only send an ACL packet if the controller has a free buffer. Buffers are freed by
+/// `NumberOfCompletedPackets` events.
+#[derive(Clone, Debug, Default)]
+pub struct ControllerBuffers {
+    max_data_len: u16,
+    total: u16,
+    in_flight: BTreeMap<ConnectionHandle, u16>,
+}
+impl ControllerBuffers {
+    pub fn new(max_data_len: u16, total: u16) -> Self {
+        Self {
+            max_data_len,
+            total,
+            in_flight: BTreeMap::new(),
+        }
+    }
+    /// Creates the buffer accounting from the LE Read Buffer Size return. Returns `None` if the
+    /// controller shares its buffers with BR/EDR (`le_acl_data_packet_len == 0`).
+    pub fn from_buffer_size_v1(size: &BufferSizeV1) -> Option<Self> {
+        if size.le_acl_data_packet_len == 0 || size.total_num_le_acl_data_packets == 0 {
+            None
+        } else {
+            Some(Self::new(
+                size.le_acl_data_packet_len,
+                size.total_num_le_acl_data_packets.into(),
+            ))
+        }
+    }
+    pub fn from_buffer_size_v2(size: &BufferSizeV2) -> Option<Self> {
+        if size.le_acl_data_packet_len == 0 || size.total_num_le_acl_data_packets == 0 {
+            None
+        } else {
+            Some(Self::new(
+                size.le_acl_data_packet_len,
+                size.total_num_le_acl_data_packets.into(),
+            ))
+        }
+    }
+    /// Creates the ISO buffer accounting from the LE Read Buffer Size V2 return. ISO buffers are
+    /// separate from the ACL buffers but are freed by the same `NumberOfCompletedPackets` events.
+    /// Returns `None` if the controller has no ISO buffers.
+    pub fn iso_from_buffer_size_v2(size: &BufferSizeV2) -> Option<Self> {
+        if size.iso_data_packet_len == 0 || size.total_num_iso_data_packets == 0 {
+            None
+        } else {
+            Some(Self::new(
+                size.iso_data_packet_len,
+                size.total_num_iso_data_packets.into(),
+            ))
+        }
+    }
+    /// Max data length of a single ACL packet the controller can accept.
+    pub fn max_data_len(&self) -> u16 {
+        self.max_data_len
+    }
+    pub fn total(&self) -> u16 {
+        self.total
+    }
+    /// Number of packets sent but not completed yet.
+    pub fn in_flight(&self) -> u16 {
+        self.in_flight.values().sum()
+    }
+    /// Number of packets sent on `handle` but not completed yet.
+    pub fn in_flight_for(&self, handle: ConnectionHandle) -> u16 {
+        self.in_flight.get(&handle).copied().unwrap_or(0)
+    }
+    /// Number of free controller buffers.
+    pub fn available(&self) -> u16 {
+        self.total.saturating_sub(self.in_flight())
+    }
+    /// Reserves a buffer for a packet on `handle`. Returns `false` if no buffers are free.
+    pub fn try_acquire(&mut self, handle: ConnectionHandle) -> bool {
+        if self.available() == 0 {
+            false
+        } else {
+            *self.in_flight.entry(handle).or_insert(0) += 1;
+            true
+        }
+    }
+    /// Frees the buffers reported by a `NumberOfCompletedPackets` event.
+    pub fn complete(&mut self, completed: &[CompletedPackets]) {
+        for c in completed {
+            if let Some(count) = self.in_flight.get_mut(&c.handle) {
+                *count = count.saturating_sub(c.num_completed_packets);
+                if *count == 0 {
+                    self.in_flight.remove(&c.handle);
+                }
+            }
+        }
+    }
+    /// Frees all buffers used by `handle`. The controller discards any packets left for a
+    /// connection once it disconnects without sending `NumberOfCompletedPackets` for them.
+    pub fn disconnected(&mut self, handle: ConnectionHandle) {
+        self.in_flight.remove(&handle);
+    }
+}
+/// The controller's ACL and ISO data buffers. They are separate but freed by the same
+/// `NumberOfCompletedPackets` events (a handle is either an ACL connection or a CIS/BIS) so every
+/// event should go through [`DataBuffers::process_event`] once.
+#[derive(Clone, Debug, Default)]
+pub struct DataBuffers {
+    /// `None` if ACL Data packets aren't flow controlled.
+    pub acl: Option<ControllerBuffers>,
+    /// `None` if ISO Data packets aren't flow controlled.
+    pub iso: Option<ControllerBuffers>,
+}
+impl DataBuffers {
+    pub fn new(acl: Option<ControllerBuffers>, iso: Option<ControllerBuffers>) -> Self {
+        Self { acl, iso }
+    }
+    fn each(&mut self) -> impl Iterator<Item = &mut ControllerBuffers> {
+        self.acl.iter_mut().chain(self.iso.iter_mut())
+    }
+    /// Frees buffers if `event` is a `NumberOfCompletedPackets` or a successful
+    /// `DisconnectionComplete` event. Other events are ignored.
+    pub fn process_event<S: AsRef<[u8]>>(
+        &mut self,
+        event: &EventPacket<S>,
+    ) -> Result<(), PackError> {
+        if event.event_code == NumberOfCompletedPackets::<Box<[_]>>::EVENT_CODE {
+            let completed = NumberOfCompletedPackets::<Box<[_]>>::unpack_event_packet(event)?;
+            for buffers in self.each() {
+                buffers.complete(completed.completed.as_ref());
+            }
+        } else if event.event_code == DisconnectionComplete::EVENT_CODE {
+            let disconnection = DisconnectionComplete::unpack_event_packet(event)?;
+            if disconnection.status == ErrorCode::Ok {
+                for buffers in self.each() {
+                    buffers.disconnected(disconnection.handle);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+/// Tracks the host's ACL data buffers (Controller to Host flow control). Once enabled with
+/// `SetControllerToHostFlowControl` and `HostBufferSize`, the host reports each ACL packet it is
+/// done with by sending `HostNumberOfCompletedPackets`.
+#[derive(Clone, Debug, Default)]
+pub struct HostBuffers {
+    max_data_len: u16,
+    total: u16,
+    completed: BTreeMap<ConnectionHandle, u16>,
+}
+impl HostBuffers {
+    pub fn new(max_data_len: u16, total: u16) -> Self {
+        Self {
+            max_data_len,
+            total,
+            completed: BTreeMap::new(),
+        }
+    }
+    pub fn max_data_len(&self) -> u16 {
+        self.max_data_len
+    }
+    pub fn total(&self) -> u16 {
+        self.total
+    }
+    /// `HostBufferSize` command describing these buffers. No synchronous buffers are reported.
+    pub fn host_buffer_size(&self) -> HostBufferSize {
+        HostBufferSize {
+            acl_data_packet_len: self.max_data_len,
+            synchronous_data_packet_len: 0,
+            total_num_acl_data_packets: self.total,
+            total_num_synchronous_data_packets: 0,
+        }
+    }
+    /// Marks a packet received on `handle` as processed by the host.
+    pub fn processed(&mut self, handle: ConnectionHandle) {
+        *self.completed.entry(handle).or_insert(0) += 1;
+    }
+    /// Number of processed packets not reported to the controller yet.
+    pub fn pending(&self) -> u16 {
+        self.completed.values().sum()
+    }
+    /// Takes the processed packet counts as a `HostNumberOfCompletedPackets` command or `None`
+    /// if no packets were processed since the last call.
+    pub fn take_completed(&mut self) -> Option<HostNumberOfCompletedPackets> {
+        if self.completed.is_empty() {
+            return None;
+        }
+        let completed = core::mem::take(&mut self.completed)
+            .into_iter()
+            .map(|(handle, num_completed_packets)| CompletedPackets {
+                handle,
+                num_completed_packets,
+            })
+            .collect();
+        Some(HostNumberOfCompletedPackets { completed })
+    }
+    pub fn disconnected(&mut self, handle: ConnectionHandle) {
+        self.completed.remove(&handle);
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hci::test_packets::assert_event;
+
+    #[test]
+    fn header_flags() {
+        let data = [0xAA, 0xBB];
+        // Handle 0x0ABC, PB = 0b10 (bits 12-13), BC = 0b01 (bits 14-15).
+        let packet = ACLPacket {
+            handle: ConnectionHandle::new(0x0ABC),
+            boundary: PacketBoundary::FirstFlushable,
+            broadcast: BroadcastFlag::BREDRBroadcast,
+            data: &data[..],
+        };
+        let bytes = [0xBC, 0x6A, 0x02, 0x00, 0xAA, 0xBB];
+        let mut buf = [0_u8; 6];
+        packet.pack_into(&mut buf).unwrap();
+        assert_eq!(buf, bytes);
+        assert_eq!(ACLPacket::unpack_from(&bytes[..]), Ok(packet));
+        // Continuing fragment, point to point.
+        let header = ACLHeader::unpack_from(&[0x40, 0x10, 0x1B, 0x00]).unwrap();
+        assert_eq!(header.handle, ConnectionHandle::new(0x0040));
+        assert_eq!(header.boundary, PacketBoundary::Continuing);
+        assert_eq!(header.broadcast, BroadcastFlag::PointToPoint);
+        assert_eq!(header.data_len, 0x1B);
+        // BC = 0b10 is reserved.
+        assert!(ACLHeader::unpack_from(&[0x40, 0x80, 0x00, 0x00]).is_err());
+    }
+    #[test]
+    fn header_rejects_oversized_data() {
+        let data = vec![0_u8; usize::from(u16::MAX) + 1];
+        let packet = ACLPacket::new(
+            ConnectionHandle::new(0x0040),
+            PacketBoundary::FirstNonFlushable,
+            &data[..],
+        );
+        assert_eq!(packet.header(), Err(PackError::InvalidFields));
+    }
+    #[test]
+    fn number_of_completed_packets() {
+        let event = NumberOfCompletedPackets {
+            completed: vec![
+                CompletedPackets {
+                    handle: ConnectionHandle::new(0x0040),
+                    num_completed_packets: 3,
+                },
+                CompletedPackets {
+                    handle: ConnectionHandle::new(0x0041),
+                    num_completed_packets: 0x0102,
+                },
+            ]
+            .into_boxed_slice(),
+        };
+        assert_event(
+            &event,
+            &[0x02, 0x40, 0x00, 0x03, 0x00, 0x41, 0x00, 0x02, 0x01],
+        );
+        assert_eq!(
+            u8::from(NumberOfCompletedPackets::<Box<[_]>>::EVENT_CODE),
+            0x13
+        );
+        // Num_Handles doesn't match the length.
+        assert!(NumberOfCompletedPackets::<Box<[_]>>::event_unpack_from(&[
+            0x02, 0x40, 0x00, 0x03, 0x00
+        ])
+        .is_err());
+    }
+    #[test]
+    fn completions_free_acl_and_iso_buffers() {
+        let (acl, cis) = (ConnectionHandle::new(0x0040), ConnectionHandle::new(0x0060));
+        let mut buffers = DataBuffers::new(
+            Some(ControllerBuffers::new(27, 1)),
+            Some(ControllerBuffers::new(251, 1)),
+        );
+        assert!(buffers.acl.as_mut().unwrap().try_acquire(acl));
+        assert!(buffers.iso.as_mut().unwrap().try_acquire(cis));
+        // One event completing a packet of each kind.
+        let event = EventPacket::new(
+            EventCode::NumberOfCompletedPackets,
+            &[0x02, 0x40, 0x00, 0x01, 0x00, 0x60, 0x00, 0x01, 0x00][..],
+        );
+        buffers.process_event(&event).unwrap();
+        assert_eq!(buffers.acl.as_ref().unwrap().available(), 1);
+        assert_eq!(buffers.iso.as_ref().unwrap().available(), 1);
+
+        assert!(buffers.iso.as_mut().unwrap().try_acquire(cis));
+        let event = EventPacket::new(
+            EventCode::DisconnectionComplete,
+            &[0x00, 0x60, 0x00, 0x13][..],
+        );
+        buffers.process_event(&event).unwrap();
+        assert_eq!(buffers.iso.as_ref().unwrap().available(), 1);
+    }
+    #[test]
+    fn disconnection_frees_buffers() {
+        let (a, b) = (ConnectionHandle::new(0x0040), ConnectionHandle::new(0x0041));
+        let mut buffers = ControllerBuffers::new(27, 3);
+        assert!(buffers.try_acquire(a));
+        assert!(buffers.try_acquire(a));
+        assert!(buffers.try_acquire(b));
+        assert!(!buffers.try_acquire(b));
+        buffers.complete(&[CompletedPackets {
+            handle: a,
+            num_completed_packets: 1,
+        }]);
+        assert_eq!(buffers.in_flight_for(a), 1);
+        buffers.disconnected(a);
+        assert_eq!(buffers.available(), 2);
+        assert_eq!(buffers.in_flight_for(b), 1);
+    }
+}
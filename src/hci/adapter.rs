@@ -1,9 +1,12 @@
 use crate::bytes::Storage;
 use crate::error::IOError;
+use crate::hci::acl::ACLPacket;
 use crate::hci::command::{Command, CommandPacket};
 use crate::hci::event::EventPacket;
+use crate::hci::iso::ISOPacket;
 use crate::hci::stream::HCI_EVENT_READ_TRIES;
 use crate::hci::StreamError;
+use crate::le::features::LEFeature;
 use crate::{hci, LocalBoxFuture};
 
 #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
@@ -13,6 +16,13 @@ pub enum Error {
     IOError(IOError),
     StreamError(hci::StreamError),
     ErrorCode(hci::ErrorCode),
+    /// The controller didn't return a `CommandComplete` or `CommandStatus` for the command with
+    /// this `Opcode` in time.
+    CommandTimeout(hci::Opcode),
+    /// The controller doesn't support the command with this `Opcode`.
+    UnsupportedCommand(hci::Opcode),
+    /// The controller doesn't support this LE feature.
+    UnsupportedFeature(LEFeature),
 }
 impl core::fmt::Display for Error {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
@@ -54,6 +64,42 @@ pub trait Adapter {
     fn read_event<'s, 'p: 's, S: Storage<u8> + 'p>(
         &'s mut self,
     ) -> LocalBoxFuture<'s, Result<EventPacket<S>, Error>>;
+
+    /// Write an ACL Data packet. Returns `IOError::NotImplemented` by default for adapters
+    /// without an ACL data path.
+    fn write_acl<'s, 'p: 's>(
+        &'s mut self,
+        packet: ACLPacket<&'p [u8]>,
+    ) -> LocalBoxFuture<'s, Result<(), Error>> {
+        let _ = packet;
+        Box::pin(async { Err(Error::IOError(IOError::NotImplemented)) })
+    }
+
+    /// Read an ACL Data packet. Returns `IOError::NotImplemented` by default for adapters
+    /// without an ACL data path.
+    fn read_acl<'s, 'p: 's, S: Storage<u8> + 'p>(
+        &'s mut self,
+    ) -> LocalBoxFuture<'s, Result<ACLPacket<S>, Error>> {
+        Box::pin(async { Err(Error::IOError(IOError::NotImplemented)) })
+    }
+
+    /// Write an ISO Data packet. Returns `IOError::NotImplemented` by default for adapters
+    /// without an ISO data path.
+    fn write_iso<'s, 'p: 's>(
+        &'s mut self,
+        packet: ISOPacket<&'p [u8]>,
+    ) -> LocalBoxFuture<'s, Result<(), Error>> {
+        let _ = packet;
+        Box::pin(async { Err(Error::IOError(IOError::NotImplemented)) })
+    }
+
+    /// Read an ISO Data packet. Returns `IOError::NotImplemented` by default for adapters
+    /// without an ISO data path.
+    fn read_iso<'s, 'p: 's, S: Storage<u8> + 'p>(
+        &'s mut self,
+    ) -> LocalBoxFuture<'s, Result<ISOPacket<S>, Error>> {
+        Box::pin(async { Err(Error::IOError(IOError::NotImplemented)) })
+    }
 }
 
 /// Dummy HCI Adapter that panics with `unimplemented!` on any function call.
@@ -105,5 +151,5 @@ pub async fn send_command<
             }
         }
     }
-    Err(hci::adapter::Error::StreamError(StreamError::StreamFailed))
+    Err(hci::adapter::Error::CommandTimeout(Cmd::opcode()))
 }
@@ -0,0 +1,255 @@
+//! LE connection functions for [`LEAdapter`]. Keep a [`Connection`] up to date with the
+//! controller.
+use crate::channel::ChannelMap;
+use crate::hci::acl::ACLPacket;
+use crate::hci::adapter;
+use crate::hci::adapters::le::LEAdapter;
+use crate::hci::adapters::UnrecognizedEventHandler;
+use crate::hci::le::channel::{ReadChannelMap, SetHostChannelClassification};
+use crate::hci::le::connection::{
+    ConnectionCompleteEvent, ReadRemoteFeatures, ReadRemoteFeaturesComplete, RequestPeerSCA,
+    RequestPeerSCAComplete,
+};
+use crate::hci::le::data_length::{
+    ReadMaximumDataLength, ReadSuggestedDefaultDataLength, SetDataLength,
+    WriteSuggestedDefaultDataLength,
+};
+use crate::hci::le::phy::{CodedPHYOptions, PHYUpdateComplete, ReadPHY, SetDefaultPHY, SetPHY};
+use crate::hci::link_control::{
+    ReadRemoteVersionInformation, ReadRemoteVersionInformationComplete,
+};
+use crate::le::connection::{Connection, DataLength, MasterClockAccuracy, RemoteVersion};
+use crate::le::features::LEFeatures;
+use crate::le::phy::PHYMask;
+use core::cmp::min;
+
+impl<A: adapter::Adapter, H: UnrecognizedEventHandler> LEAdapter<A, H> {
+    /// Waits for a connection to be established (as the master or the slave).
+    pub async fn wait_for_connection(&mut self) -> Result<Connection, adapter::Error> {
+        let complete = self
+            .wait_for_meta_event(|_: &ConnectionCompleteEvent| true)
+            .await?;
+        complete.status.error()?;
+        Ok(complete.connection())
+    }
+    /// Reads the current TX and RX `PHY` of `connection` and updates it.
+    pub async fn read_phy(&mut self, connection: &mut Connection) -> Result<(), adapter::Error> {
+        let r = self
+            .adapter
+            .hci_send_command(ReadPHY {
+                handle: connection.handle,
+            })
+            .await?;
+        r.params.status.error()?;
+        connection.tx_phy = r.params.tx_phy;
+        connection.rx_phy = r.params.rx_phy;
+        Ok(())
+    }
+    /// Sets the PHYs preferred for all future connections. `None` means no preference.
+    pub async fn set_default_phy(
+        &mut self,
+        tx_phys: Option<PHYMask>,
+        rx_phys: Option<PHYMask>,
+    ) -> Result<(), adapter::Error> {
+        self.adapter
+            .hci_send_command(SetDefaultPHY { tx_phys, rx_phys })
+            .await?
+            .params
+            .status
+            .error()?;
+        Ok(())
+    }
+    /// Requests a PHY change on `connection` and waits for the [`PHYUpdateComplete`]. The PHYs
+    /// picked by the controller (and peer) may not be the preferred ones so check
+    /// `connection.tx_phy` and `connection.rx_phy` after. `phy_options` only matter if
+    /// `PHY::LECoded` is allowed.
+    pub async fn set_phy(
+        &mut self,
+        connection: &mut Connection,
+        tx_phys: Option<PHYMask>,
+        rx_phys: Option<PHYMask>,
+        phy_options: CodedPHYOptions,
+    ) -> Result<(), adapter::Error> {
+        let handle = connection.handle;
+        self.adapter
+            .hci_send_command(SetPHY {
+                handle,
+                tx_phys,
+                rx_phys,
+                phy_options,
+            })
+            .await?
+            .status
+            .error()?;
+        let update = self
+            .wait_for_meta_event(|e: &PHYUpdateComplete| e.handle == handle)
+            .await?;
+        update.status.error()?;
+        update.update_connection(connection);
+        Ok(())
+    }
+    /// Suggests the max TX payload `tx_octets` and `tx_time` (in microseconds) for `connection`.
+    /// The controller sends a
+    /// [`DataLengthChange`](crate::hci::le::data_length::DataLengthChange) event (to update
+    /// `connection` with) only if the data length actually changes.
+    pub async fn set_data_length(
+        &mut self,
+        connection: &Connection,
+        tx_octets: u16,
+        tx_time: u16,
+    ) -> Result<(), adapter::Error> {
+        self.adapter
+            .hci_send_command(SetDataLength {
+                handle: connection.handle,
+                tx_octets,
+                tx_time,
+            })
+            .await?
+            .params
+            .status
+            .error()?;
+        Ok(())
+    }
+    /// Returns the suggested max TX payload octets and time (in microseconds) for new
+    /// connections.
+    pub async fn read_suggested_default_data_length(
+        &mut self,
+    ) -> Result<(u16, u16), adapter::Error> {
+        let r = self
+            .adapter
+            .hci_send_command(ReadSuggestedDefaultDataLength())
+            .await?;
+        r.params.status.error()?;
+        Ok((r.params.max_tx_octets, r.params.max_tx_time))
+    }
+    pub async fn write_suggested_default_data_length(
+        &mut self,
+        max_tx_octets: u16,
+        max_tx_time: u16,
+    ) -> Result<(), adapter::Error> {
+        self.adapter
+            .hci_send_command(WriteSuggestedDefaultDataLength {
+                max_tx_octets,
+                max_tx_time,
+            })
+            .await?
+            .params
+            .status
+            .error()?;
+        Ok(())
+    }
+    /// Returns the max payload octets and time (in microseconds) the controller supports.
+    pub async fn read_maximum_data_length(&mut self) -> Result<DataLength, adapter::Error> {
+        let r = self
+            .adapter
+            .hci_send_command(ReadMaximumDataLength())
+            .await?;
+        r.params.status.error()?;
+        Ok(r.params.supported)
+    }
+    /// Sends a L2CAP PDU on `connection`. The PDU is fragmented into ACL Data packets as big as
+    /// both the controller's ACL buffers and `connection.data_length.max_tx_octets` allow.
+    pub async fn write_l2cap_pdu(
+        &mut self,
+        connection: &Connection,
+        pdu: &[u8],
+    ) -> Result<(), adapter::Error> {
+        let max_tx_octets = connection.data_length.max_tx_octets;
+        let fragment_len = usize::from(match &self.adapter.buffers.acl {
+            Some(acl) => min(acl.max_data_len(), max_tx_octets),
+            None => max_tx_octets,
+        });
+        if fragment_len == 0 {
+            return Err(adapter::Error::BadParameter);
+        }
+        for packet in ACLPacket::fragments(connection.handle, pdu, fragment_len) {
+            self.adapter.hci_write_acl(packet).await?;
+        }
+        Ok(())
+    }
+    /// Marks the data channels cleared in `map` as bad so the controller avoids them (on every
+    /// connection).
+    pub async fn set_host_channel_classification(
+        &mut self,
+        map: ChannelMap,
+    ) -> Result<(), adapter::Error> {
+        self.adapter
+            .hci_send_command(SetHostChannelClassification(map))
+            .await?
+            .params
+            .status
+            .error()?;
+        Ok(())
+    }
+    /// Reads the current data `ChannelMap` of `connection`.
+    pub async fn read_channel_map(
+        &mut self,
+        connection: &Connection,
+    ) -> Result<ChannelMap, adapter::Error> {
+        let r = self
+            .adapter
+            .hci_send_command(ReadChannelMap {
+                handle: connection.handle,
+            })
+            .await?;
+        r.params.status.error()?;
+        Ok(r.params.channel_map)
+    }
+    /// Reads the LE features of the peer and stores them in `connection.remote_features`.
+    pub async fn read_remote_features(
+        &mut self,
+        connection: &mut Connection,
+    ) -> Result<LEFeatures, adapter::Error> {
+        let handle = connection.handle;
+        self.adapter
+            .hci_send_command(ReadRemoteFeatures { handle })
+            .await?
+            .status
+            .error()?;
+        let complete = self
+            .wait_for_meta_event(|e: &ReadRemoteFeaturesComplete| e.handle == handle)
+            .await?;
+        complete.status.error()?;
+        connection.remote_features = Some(complete.features);
+        Ok(complete.features)
+    }
+    /// Reads the version of the peer and stores it in `connection.remote_version`. The
+    /// controller may answer from its cache without asking the peer again.
+    pub async fn read_remote_version(
+        &mut self,
+        connection: &mut Connection,
+    ) -> Result<RemoteVersion, adapter::Error> {
+        let handle = connection.handle;
+        self.adapter
+            .hci_send_command(ReadRemoteVersionInformation { handle })
+            .await?
+            .status
+            .error()?;
+        let complete = self
+            .adapter
+            .wait_for_event(|e: &ReadRemoteVersionInformationComplete| e.handle == handle)
+            .await?;
+        complete.status.error()?;
+        connection.remote_version = Some(complete.remote_version);
+        Ok(complete.remote_version)
+    }
+    /// Requests the sleep clock accuracy of the peer and stores it in `connection.peer_sca`.
+    /// Combine with [`Connection::window_widening`] for the connection timing.
+    pub async fn request_peer_sca(
+        &mut self,
+        connection: &mut Connection,
+    ) -> Result<MasterClockAccuracy, adapter::Error> {
+        let handle = connection.handle;
+        self.adapter
+            .hci_send_command(RequestPeerSCA { handle })
+            .await?
+            .status
+            .error()?;
+        let complete = self
+            .wait_for_meta_event(|e: &RequestPeerSCAComplete| e.handle == handle)
+            .await?;
+        complete.status.error()?;
+        connection.peer_sca = Some(complete.peer_clock_accuracy);
+        Ok(complete.peer_clock_accuracy)
+    }
+}
@@ -0,0 +1,66 @@
+//! Controller information functions for [`Adapter`] and [`LEAdapter`].
+use crate::hci::adapter;
+use crate::hci::adapters::le::LEAdapter;
+use crate::hci::adapters::{Adapter, UnrecognizedEventHandler};
+use crate::hci::info::{
+    ControllerInfo, LMPFeatures, LocalVersion, ReadBDADDR, ReadLocalSupportedCommands,
+    ReadLocalSupportedFeatures, ReadLocalVersionInformation, SupportedCommands,
+};
+use crate::hci::le::info::{self as le_info, LESupportedStates};
+use crate::le::features::LEFeatures;
+use crate::BTAddress;
+
+impl<A: adapter::Adapter, H: UnrecognizedEventHandler> Adapter<A, H> {
+    pub async fn read_local_version(&mut self) -> Result<LocalVersion, adapter::Error> {
+        let r = self.hci_send_command(ReadLocalVersionInformation()).await?;
+        r.params.status.error()?;
+        Ok(r.params.version)
+    }
+    pub async fn read_local_supported_commands(
+        &mut self,
+    ) -> Result<SupportedCommands, adapter::Error> {
+        let r = self.hci_send_command(ReadLocalSupportedCommands()).await?;
+        r.params.status.error()?;
+        Ok(r.params.commands)
+    }
+    pub async fn read_local_supported_features(&mut self) -> Result<LMPFeatures, adapter::Error> {
+        let r = self.hci_send_command(ReadLocalSupportedFeatures()).await?;
+        r.params.status.error()?;
+        Ok(r.params.features)
+    }
+    /// Reads the public device address (BD_ADDR) of the controller.
+    pub async fn read_bd_addr(&mut self) -> Result<BTAddress, adapter::Error> {
+        let r = self.hci_send_command(ReadBDADDR()).await?;
+        r.params.status.error()?;
+        Ok(r.params.address)
+    }
+}
+impl<A: adapter::Adapter, H: UnrecognizedEventHandler> LEAdapter<A, H> {
+    pub async fn read_le_local_supported_features(&mut self) -> Result<LEFeatures, adapter::Error> {
+        let r = self
+            .adapter
+            .hci_send_command(le_info::ReadLocalSupportedFeatures())
+            .await?;
+        r.params.status.error()?;
+        Ok(r.params.features)
+    }
+    pub async fn read_le_supported_states(&mut self) -> Result<LESupportedStates, adapter::Error> {
+        let r = self
+            .adapter
+            .hci_send_command(le_info::ReadSupportedStates())
+            .await?;
+        r.params.status.error()?;
+        Ok(r.params.states)
+    }
+    /// Reads everything in a [`ControllerInfo`]. Best done once after a reset.
+    pub async fn read_controller_info(&mut self) -> Result<ControllerInfo, adapter::Error> {
+        Ok(ControllerInfo {
+            version: self.adapter.read_local_version().await?,
+            commands: self.adapter.read_local_supported_commands().await?,
+            lmp_features: self.adapter.read_local_supported_features().await?,
+            address: self.adapter.read_bd_addr().await?,
+            le_features: self.read_le_local_supported_features().await?,
+            le_states: self.read_le_supported_states().await?,
+        })
+    }
+}
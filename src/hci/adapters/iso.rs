@@ -0,0 +1,221 @@
+//! LE Isochronous Channels functions for [`LEAdapter`].
+use crate::hci::adapter;
+use crate::hci::adapters::le::LEAdapter;
+use crate::hci::adapters::UnrecognizedEventHandler;
+use crate::hci::iso::{ISOPacket, ISOSDUReassembler, ISOSDU};
+use crate::hci::le::iso::{
+    AcceptCISRequest, BIGCreateSync, BIGInfoAdvertisingReport, BIGSyncEstablished,
+    BIGTerminateSync, CISConnection, CISEstablished, CISRequest, CreateBIG, CreateBIGComplete,
+    CreateCIS, RejectCISRequest, RemoveCIG, RemoveISODataPath, SetCIGParameters, SetupISODataPath,
+    TerminateBIG, TerminateBIGComplete,
+};
+use crate::hci::{ErrorCode, StreamError};
+use crate::le::connection::ConnectionHandle;
+use crate::Stream;
+
+impl<A: adapter::Adapter, H: UnrecognizedEventHandler> LEAdapter<A, H> {
+    /// Creates (or modifies) a CIG. Returns the `ConnectionHandle` of each CIS in the same order
+    /// as `parameters.cis`.
+    pub async fn set_cig_parameters(
+        &mut self,
+        parameters: SetCIGParameters,
+    ) -> Result<Box<[ConnectionHandle]>, adapter::Error> {
+        let r = self.adapter.hci_send_command(parameters).await?;
+        r.params.status.error()?;
+        Ok(r.params.handles)
+    }
+    /// Removes a CIG. All of its CISes must be disconnected first.
+    pub async fn remove_cig(&mut self, cig_id: u8) -> Result<(), adapter::Error> {
+        self.adapter
+            .hci_send_command(RemoveCIG { cig_id })
+            .await?
+            .params
+            .status
+            .error()?;
+        Ok(())
+    }
+    /// Creates the CISes (as the master) and waits for a [`CISEstablished`] event for each one.
+    /// The `status` of each event should be checked because CISes may fail individually.
+    pub async fn create_cis(
+        &mut self,
+        cis: &[CISConnection],
+    ) -> Result<Box<[CISEstablished]>, adapter::Error> {
+        self.adapter
+            .hci_send_command(CreateCIS { cis: cis.into() })
+            .await?
+            .status
+            .error()?;
+        let mut pending: Vec<ConnectionHandle> = cis.iter().map(|c| c.cis_handle).collect();
+        let mut out = Vec::with_capacity(pending.len());
+        while !pending.is_empty() {
+            let established = self
+                .wait_for_meta_event(|e: &CISEstablished| pending.contains(&e.handle))
+                .await?;
+            pending.retain(|h| *h != established.handle);
+            out.push(established);
+        }
+        Ok(out.into_boxed_slice())
+    }
+    /// Waits for the master to request a CIS. Answer with [`LEAdapter::accept_cis_request`] or
+    /// [`LEAdapter::reject_cis_request`].
+    pub async fn wait_for_cis_request(&mut self) -> Result<CISRequest, adapter::Error> {
+        self.wait_for_meta_event(|_: &CISRequest| true).await
+    }
+    /// Accepts a [`CISRequest`] (as the slave) and waits for the CIS to be established.
+    pub async fn accept_cis_request(
+        &mut self,
+        handle: ConnectionHandle,
+    ) -> Result<CISEstablished, adapter::Error> {
+        self.adapter
+            .hci_send_command(AcceptCISRequest { handle })
+            .await?
+            .status
+            .error()?;
+        self.wait_for_meta_event(|e: &CISEstablished| e.handle == handle)
+            .await
+    }
+    pub async fn reject_cis_request(
+        &mut self,
+        handle: ConnectionHandle,
+        reason: ErrorCode,
+    ) -> Result<(), adapter::Error> {
+        self.adapter
+            .hci_send_command(RejectCISRequest { handle, reason })
+            .await?
+            .params
+            .status
+            .error()?;
+        Ok(())
+    }
+    /// Sets up an ISO Data Path. See [`SetupISODataPath::hci`] for sending and receiving the
+    /// SDUs over HCI.
+    pub async fn setup_iso_data_path(
+        &mut self,
+        data_path: SetupISODataPath,
+    ) -> Result<(), adapter::Error> {
+        self.adapter
+            .hci_send_command(data_path)
+            .await?
+            .params
+            .status
+            .error()?;
+        Ok(())
+    }
+    pub async fn remove_iso_data_path(
+        &mut self,
+        handle: ConnectionHandle,
+        input: bool,
+        output: bool,
+    ) -> Result<(), adapter::Error> {
+        self.adapter
+            .hci_send_command(RemoveISODataPath {
+                handle,
+                input,
+                output,
+            })
+            .await?
+            .params
+            .status
+            .error()?;
+        Ok(())
+    }
+    /// Creates a BIG (as a broadcaster) on the periodic advertising set `advertising_handle` and
+    /// waits for it to be created. Returns the [`CreateBIGComplete`] holding the BIS handles.
+    pub async fn create_big(
+        &mut self,
+        create_big: CreateBIG,
+    ) -> Result<CreateBIGComplete, adapter::Error> {
+        let big_handle = create_big.big_handle;
+        self.adapter
+            .hci_send_command(create_big)
+            .await?
+            .status
+            .error()?;
+        let complete = self
+            .wait_for_meta_event(|e: &CreateBIGComplete| e.big_handle == big_handle)
+            .await?;
+        complete.status.error()?;
+        Ok(complete)
+    }
+    /// Terminates a BIG (as a broadcaster) and waits for it to stop.
+    pub async fn terminate_big(
+        &mut self,
+        big_handle: u8,
+        reason: ErrorCode,
+    ) -> Result<(), adapter::Error> {
+        self.adapter
+            .hci_send_command(TerminateBIG { big_handle, reason })
+            .await?
+            .status
+            .error()?;
+        self.wait_for_meta_event(|e: &TerminateBIGComplete| e.big_handle == big_handle)
+            .await?;
+        Ok(())
+    }
+    /// Waits for a [`BIGInfoAdvertisingReport`] on the periodic advertising train `sync_handle`.
+    pub async fn wait_for_biginfo_report(
+        &mut self,
+        sync_handle: u16,
+    ) -> Result<BIGInfoAdvertisingReport, adapter::Error> {
+        self.wait_for_meta_event(|e: &BIGInfoAdvertisingReport| e.sync_handle == sync_handle)
+            .await
+    }
+    /// Synchronizes to a BIG (as a receiver) and waits for the sync to be established. Returns
+    /// the [`BIGSyncEstablished`] holding the BIS handles.
+    pub async fn big_create_sync(
+        &mut self,
+        create_sync: BIGCreateSync,
+    ) -> Result<BIGSyncEstablished, adapter::Error> {
+        let big_handle = create_sync.big_handle;
+        self.adapter
+            .hci_send_command(create_sync)
+            .await?
+            .status
+            .error()?;
+        let established = self
+            .wait_for_meta_event(|e: &BIGSyncEstablished| e.big_handle == big_handle)
+            .await?;
+        established.status.error()?;
+        Ok(established)
+    }
+    pub async fn big_terminate_sync(&mut self, big_handle: u8) -> Result<(), adapter::Error> {
+        self.adapter
+            .hci_send_command(BIGTerminateSync { big_handle })
+            .await?
+            .params
+            .status
+            .error()?;
+        Ok(())
+    }
+    /// Returns a Stream of the SDUs received on `handles` (CIS or BIS handles with an HCI ISO Data
+    /// Path set up). ISO Data packets for other handles are dropped.
+    pub fn iso_sdu_stream<'a>(
+        &'a mut self,
+        handles: &'a [ConnectionHandle],
+    ) -> impl Stream<Item = Result<ISOSDU, adapter::Error>> + 'a {
+        futures_util::stream::unfold(
+            (self, ISOSDUReassembler::new()),
+            move |(s, mut reassembler)| async move {
+                loop {
+                    let packet: ISOPacket<Box<[u8]>> = match s.adapter.hci_read_iso().await {
+                        Ok(packet) => packet,
+                        Err(e) => return Some((Err(e), (s, reassembler))),
+                    };
+                    if !handles.contains(&packet.handle) {
+                        continue;
+                    }
+                    match reassembler.push(&packet) {
+                        Ok(Some(sdu)) => return Some((Ok(sdu), (s, reassembler))),
+                        Ok(None) => (),
+                        Err(e) => {
+                            return Some((
+                                Err(adapter::Error::StreamError(StreamError::DataError(e))),
+                                (s, reassembler),
+                            ))
+                        }
+                    }
+                }
+            },
+        )
+    }
+}
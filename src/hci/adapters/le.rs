@@ -1,3 +1,4 @@
+use crate::hci::acl::ControllerBuffers;
 use crate::hci::adapters::{Adapter, UnrecognizedEventHandler};
 use crate::hci::baseband::{EventMask, EventMaskFlags};
 use crate::hci::le::mask::{MetaEventMask, SetMetaEventMask};
@@ -128,6 +129,16 @@ impl<A: adapter::Adapter, H: UnrecognizedEventHandler> LEAdapter<A, H> {
         r.params.status.error()?;
         Ok(r.params)
     }
+    /// Reads the LE ACL buffer size into [`ControllerBuffers`] for host to controller flow
+    /// control and sets them as the adapter's ACL buffers. Returns `None` if the controller has
+    /// no dedicated LE buffers (and shares the BR/EDR buffers instead).
+    pub async fn read_controller_buffers(
+        &mut self,
+    ) -> Result<Option<ControllerBuffers>, adapter::Error> {
+        let buffers = ControllerBuffers::from_buffer_size_v1(&self.read_buffer_size_v1().await?);
+        self.adapter.buffers.acl.clone_from(&buffers);
+        Ok(buffers)
+    }
     pub async fn set_scan_response_data(&mut self, data: &[u8]) -> Result<(), adapter::Error> {
         let rsp =
             le::commands::SetScanResponseData::try_from(data).map_err(StreamError::CommandError)?;
@@ -164,6 +175,27 @@ impl<A: adapter::Adapter, H: UnrecognizedEventHandler> LEAdapter<A, H> {
             .error()?;
         Ok(())
     }
+    /// Reads events until an `M` LE Meta Event that `filter` accepts. Any other event is passed to
+    /// the `event_handler`. Doesn't set the HCI `EventMask` or `MetaEventMask`.
+    pub async fn wait_for_meta_event<M: MetaEvent>(
+        &mut self,
+        mut filter: impl FnMut(&M) -> bool,
+    ) -> Result<M, adapter::Error> {
+        loop {
+            let event = self.adapter.hci_read_event::<H::Buf>().await?;
+            if event.event_code == EventCode::LEMeta {
+                let meta =
+                    RawMetaEvent::try_from(event.as_ref()).map_err(StreamError::EventError)?;
+                if meta.code == M::META_CODE {
+                    let m = M::meta_unpack_packet(meta).map_err(StreamError::EventError)?;
+                    if filter(&m) {
+                        return Ok(m);
+                    }
+                }
+            }
+            self.adapter.event_handler.handle(event)?;
+        }
+    }
     /// Returns a Stream of `RawMetaEvent`s without setting the HCI `EventMask`.
     pub fn meta_event_stream_without_mask<'a, 'b: 'a, Buf: Storage<u8> + 'b>(
         &'a mut self,
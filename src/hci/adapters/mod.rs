@@ -1,13 +1,24 @@
 //! Contains logic for HCI Adapters (usually byte streams).
 pub mod buffer;
+pub mod connection;
+pub mod info;
+pub mod iso;
 pub mod le;
+pub mod power;
+pub mod queue;
+pub mod test;
 
 use crate::bytes::Storage;
+use crate::hci::acl::{ACLPacket, DataBuffers, HostBuffers};
 use crate::hci::adapter;
 use crate::hci::adapters::le::LEAdapter;
-use crate::hci::baseband::{EventMask, Reset, SetEventMask};
+use crate::hci::baseband::{
+    EventMask, FlowControlEnable, Reset, SetControllerToHostFlowControl, SetEventMask,
+};
 use crate::hci::command::Command;
-use crate::hci::event::EventPacket;
+use crate::hci::event::{Event, EventPacket};
+use crate::hci::iso::{ISOHeader, ISOPacket};
+use crate::hci::StreamError;
 use crate::Stream;
 
 // TODO: Make this more generic
@@ -32,9 +43,12 @@ impl<Buf: Storage<u8>> UnrecognizedEventHandler for DummyUnrecognizedEventHandle
         Ok(())
     }
 }
+/// Wraps an [`adapter::Adapter`]. Every event read through it updates the controller's data
+/// `buffers` (host to controller flow control) before being returned or handled.
 pub struct Adapter<A: adapter::Adapter, H: UnrecognizedEventHandler> {
     pub adapter: A,
     pub event_handler: H,
+    pub buffers: DataBuffers,
 }
 impl<A: adapter::Adapter> Adapter<A, DummyUnrecognizedEventHandler<Box<[u8]>>> {
     pub fn new(adapter: A) -> Self {
@@ -49,6 +63,7 @@ impl<A: adapter::Adapter, H: UnrecognizedEventHandler> Adapter<A, H> {
         Self {
             adapter,
             event_handler,
+            buffers: DataBuffers::default(),
         }
     }
     pub fn le(self) -> le::LEAdapter<A, H> {
@@ -59,24 +74,50 @@ impl<A: adapter::Adapter, H: UnrecognizedEventHandler> Adapter<A, H> {
         cmd: Cmd,
     ) -> Result<Cmd::Return, adapter::Error> {
         let event_handler = &mut self.event_handler;
+        let buffers = &mut self.buffers;
         adapter::send_command::<_, _, H::Buf, _>(
             &mut self.adapter,
             cmd,
-            Some(|e| event_handler.handle(e)),
+            Some(|e: EventPacket<H::Buf>| {
+                buffers.process_event(&e).map_err(StreamError::EventError)?;
+                event_handler.handle(e)
+            }),
         )
         .await
     }
     pub async fn hci_read_event<Buf: Storage<u8>>(
         &mut self,
     ) -> Result<EventPacket<Buf>, adapter::Error> {
-        self.adapter.read_event().await
+        let event = self.adapter.read_event::<Buf>().await?;
+        self.buffers
+            .process_event(&event)
+            .map_err(StreamError::EventError)?;
+        Ok(event)
+    }
+    /// Reads events until an `E` Event that `filter` accepts. Any other event is passed to the
+    /// `event_handler`. Doesn't set the HCI `EventMask`.
+    pub async fn wait_for_event<E: Event>(
+        &mut self,
+        mut filter: impl FnMut(&E) -> bool,
+    ) -> Result<E, adapter::Error> {
+        loop {
+            let event = self.hci_read_event::<H::Buf>().await?;
+            if event.event_code == E::EVENT_CODE {
+                let e = E::unpack_event_packet(&event).map_err(StreamError::EventError)?;
+                if filter(&e) {
+                    return Ok(e);
+                }
+            }
+            self.event_handler.handle(event)?;
+        }
     }
     pub fn hci_event_stream<'a, 'b: 'a, Buf: Storage<u8> + 'b>(
         &'a mut self,
     ) -> impl Stream<Item = Result<EventPacket<Buf>, adapter::Error>> + 'a {
-        futures_util::stream::unfold(self, move |s| async move {
-            Some((s.adapter.read_event().await, s))
-        })
+        futures_util::stream::unfold(
+            self,
+            move |s| async move { Some((s.hci_read_event().await, s)) },
+        )
     }
     pub async fn set_event_mask(&mut self, mask: EventMask) -> Result<(), adapter::Error> {
         self.hci_send_command(SetEventMask(mask))
@@ -90,8 +131,183 @@ impl<A: adapter::Adapter, H: UnrecognizedEventHandler> Adapter<A, H> {
         self.hci_send_command(Reset).await?.params.status.error()?;
         Ok(())
     }
+    /// Writes an ACL Data packet once the controller has a free ACL buffer (if
+    /// `buffers.acl` is set, right away otherwise). Events read while waiting are passed to the
+    /// `event_handler`.
+    pub async fn hci_write_acl(&mut self, packet: ACLPacket<&[u8]>) -> Result<(), adapter::Error> {
+        if let Some(acl) = &self.buffers.acl {
+            if packet.data.len() > usize::from(acl.max_data_len()) {
+                return Err(adapter::Error::BadParameter);
+            }
+        }
+        while let Some(acl) = &mut self.buffers.acl {
+            if acl.try_acquire(packet.handle) {
+                break;
+            }
+            let event = self.hci_read_event::<H::Buf>().await?;
+            self.event_handler.handle(event)?;
+        }
+        self.adapter.write_acl(packet).await
+    }
+    /// Reads an ACL Data packet. If controller to host flow control is enabled, call
+    /// [`HostBuffers::processed`] once the packet is processed and report it with
+    /// [`Adapter::report_completed_packets`].
+    pub async fn hci_read_acl<Buf: Storage<u8>>(
+        &mut self,
+    ) -> Result<ACLPacket<Buf>, adapter::Error> {
+        self.adapter.read_acl().await
+    }
+    /// Writes an ISO Data packet once the controller has a free ISO buffer (if
+    /// `buffers.iso` is set, right away otherwise). Events read while waiting are passed to the
+    /// `event_handler`.
+    pub async fn hci_write_iso(&mut self, packet: ISOPacket<&[u8]>) -> Result<(), adapter::Error> {
+        if let Some(iso) = &self.buffers.iso {
+            if packet.byte_len() - ISOHeader::BYTE_LEN > usize::from(iso.max_data_len()) {
+                return Err(adapter::Error::BadParameter);
+            }
+        }
+        while let Some(iso) = &mut self.buffers.iso {
+            if iso.try_acquire(packet.handle) {
+                break;
+            }
+            let event = self.hci_read_event::<H::Buf>().await?;
+            self.event_handler.handle(event)?;
+        }
+        self.adapter.write_iso(packet).await
+    }
+    pub async fn hci_read_iso<Buf: Storage<u8>>(
+        &mut self,
+    ) -> Result<ISOPacket<Buf>, adapter::Error> {
+        self.adapter.read_iso().await
+    }
+    /// Enables controller to host ACL flow control with the size of the host's `buffers`.
+    pub async fn enable_host_flow_control(
+        &mut self,
+        buffers: &HostBuffers,
+    ) -> Result<(), adapter::Error> {
+        self.hci_send_command(buffers.host_buffer_size())
+            .await?
+            .params
+            .status
+            .error()?;
+        self.hci_send_command(SetControllerToHostFlowControl(FlowControlEnable::ACLOnly))
+            .await?
+            .params
+            .status
+            .error()?;
+        Ok(())
+    }
+    /// Sends the `HostNumberOfCompletedPackets` command for the packets processed since the last
+    /// report (if any). The controller doesn't return an event for this command so it doesn't wait.
+    pub async fn report_completed_packets(
+        &mut self,
+        buffers: &mut HostBuffers,
+    ) -> Result<(), adapter::Error> {
+        match buffers.take_completed() {
+            Some(command) => {
+                let packet = command
+                    .pack_command_packet::<H::Buf>()
+                    .map_err(StreamError::CommandError)?;
+                self.adapter.write_command(packet.as_ref()).await
+            }
+            None => Ok(()),
+        }
+    }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hci::acl::{ControllerBuffers, PacketBoundary};
+    use crate::hci::command::CommandPacket;
+    use crate::hci::event::EventCode;
+    use crate::le::connection::ConnectionHandle;
+    use crate::LocalBoxFuture;
+    use alloc::collections::VecDeque;
+    use alloc::vec::Vec;
+    use futures_util::FutureExt;
+
+    /// Controller sending `events` (code and parameters) then nothing.
+    #[derive(Default)]
+    struct Controller {
+        events: VecDeque<(EventCode, Vec<u8>)>,
+        acl: usize,
+        iso: usize,
+    }
+    impl adapter::Adapter for Controller {
+        fn write_command<'s, 'p: 's>(
+            &'s mut self,
+            _packet: CommandPacket<&'p [u8]>,
+        ) -> LocalBoxFuture<'s, Result<(), adapter::Error>> {
+            Box::pin(async { Ok(()) })
+        }
+        fn read_event<'s, 'p: 's, S: Storage<u8> + 'p>(
+            &'s mut self,
+        ) -> LocalBoxFuture<'s, Result<EventPacket<S>, adapter::Error>> {
+            let event = self.events.pop_front();
+            Box::pin(async move {
+                match event {
+                    Some((code, parameters)) => {
+                        Ok(EventPacket::new(code, S::from_slice(&parameters)))
+                    }
+                    None => futures_util::future::pending().await,
+                }
+            })
+        }
+        fn write_acl<'s, 'p: 's>(
+            &'s mut self,
+            _packet: ACLPacket<&'p [u8]>,
+        ) -> LocalBoxFuture<'s, Result<(), adapter::Error>> {
+            self.acl += 1;
+            Box::pin(async { Ok(()) })
+        }
+        fn write_iso<'s, 'p: 's>(
+            &'s mut self,
+            _packet: ISOPacket<&'p [u8]>,
+        ) -> LocalBoxFuture<'s, Result<(), adapter::Error>> {
+            self.iso += 1;
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    #[test]
+    fn any_event_read_frees_acl_and_iso_buffers() {
+        let (acl_handle, cis_handle) =
+            (ConnectionHandle::new(0x0040), ConnectionHandle::new(0x0060));
+        let mut controller = Controller::default();
+        // Completes one packet on each handle while waiting for the Reset return.
+        controller.events.push_back((
+            EventCode::NumberOfCompletedPackets,
+            vec![0x02, 0x40, 0x00, 0x01, 0x00, 0x60, 0x00, 0x01, 0x00],
+        ));
+        controller
+            .events
+            .push_back((EventCode::CommandComplete, vec![0x01, 0x03, 0x0C, 0x00]));
+        let mut adapter = Adapter::new(controller);
+        adapter.buffers = DataBuffers::new(
+            Some(ControllerBuffers::new(27, 1)),
+            Some(ControllerBuffers::new(251, 1)),
+        );
+        let acl = ACLPacket::new(acl_handle, PacketBoundary::FirstNonFlushable, &[0x01][..]);
+        let iso = ISOPacket::new_complete(cis_handle, 0, None, &[0x02][..]);
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            adapter.hci_write_acl(acl).await.unwrap();
+            adapter.hci_write_iso(iso).await.unwrap();
+            assert_eq!(adapter.buffers.acl.as_ref().unwrap().available(), 0);
+            assert_eq!(adapter.buffers.iso.as_ref().unwrap().available(), 0);
+            adapter.reset().await.unwrap();
+            assert_eq!(adapter.buffers.acl.as_ref().unwrap().available(), 1);
+            assert_eq!(adapter.buffers.iso.as_ref().unwrap().available(), 1);
+            // Doesn't wait (or read events).
+            adapter.hci_write_acl(acl).now_or_never().unwrap().unwrap();
+            adapter.hci_write_iso(iso).now_or_never().unwrap().unwrap();
+        });
+        assert_eq!((adapter.adapter.acl, adapter.adapter.iso), (2, 2));
+    }
+}
 
 /*
 use crate::hci::{
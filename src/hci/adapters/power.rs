@@ -0,0 +1,243 @@
+//! LE Power Control functions for [`LEAdapter`]. Path loss and transmit power changes of a
+//! connection are exposed as a Stream of [`PowerControlEvent`]s.
+use crate::hci::adapter;
+use crate::hci::adapters::le::LEAdapter;
+use crate::hci::adapters::UnrecognizedEventHandler;
+use crate::hci::baseband::{EventMask, EventMaskFlags};
+use crate::hci::le::mask::MetaEventMask;
+use crate::hci::le::power::{
+    EnhancedReadTransmitPowerLevel, PathLossThreshold, PowerControlEvent,
+    ReadRemoteTransmitPowerLevel, SetPathLossReportingEnable, SetPathLossReportingParameters,
+    SetTransmitPowerReportingEnable, TransmitPowerReporting, TransmitPowerReportingReason,
+};
+use crate::hci::le::{MetaEvent, MetaEventCode, RawMetaEvent};
+use crate::hci::StreamError;
+use crate::le::connection::ConnectionHandle;
+use crate::le::phy::TxPHY;
+use crate::Stream;
+use futures_util::StreamExt;
+
+impl<A: adapter::Adapter, H: UnrecognizedEventHandler> LEAdapter<A, H> {
+    /// Reads the current and max transmit power level (in dBm) of a connection on `phy`.
+    pub async fn read_transmit_power_level(
+        &mut self,
+        handle: ConnectionHandle,
+        phy: TxPHY,
+    ) -> Result<(i8, i8), adapter::Error> {
+        let r = self
+            .adapter
+            .hci_send_command(EnhancedReadTransmitPowerLevel { handle, phy })
+            .await?;
+        r.params.status.error()?;
+        Ok((r.params.current_tx_power_level, r.params.max_tx_power_level))
+    }
+    /// Reads the transmit power level the peer uses on `phy`.
+    pub async fn read_remote_transmit_power_level(
+        &mut self,
+        handle: ConnectionHandle,
+        phy: TxPHY,
+    ) -> Result<TransmitPowerReporting, adapter::Error> {
+        self.adapter
+            .hci_send_command(ReadRemoteTransmitPowerLevel { handle, phy })
+            .await?
+            .status
+            .error()?;
+        let report = self
+            .wait_for_meta_event(|e: &TransmitPowerReporting| {
+                e.handle == handle && e.reason == TransmitPowerReportingReason::ReadRemoteComplete
+            })
+            .await?;
+        report.status.error()?;
+        Ok(report)
+    }
+    /// Sets the path loss zones of a connection and enables path loss reporting.
+    pub async fn enable_path_loss_reporting(
+        &mut self,
+        parameters: SetPathLossReportingParameters,
+    ) -> Result<(), adapter::Error> {
+        let handle = parameters.handle;
+        self.adapter
+            .hci_send_command(parameters)
+            .await?
+            .params
+            .status
+            .error()?;
+        self.set_path_loss_reporting_enable(handle, true).await
+    }
+    pub async fn set_path_loss_reporting_enable(
+        &mut self,
+        handle: ConnectionHandle,
+        is_enabled: bool,
+    ) -> Result<(), adapter::Error> {
+        self.adapter
+            .hci_send_command(SetPathLossReportingEnable { handle, is_enabled })
+            .await?
+            .params
+            .status
+            .error()?;
+        Ok(())
+    }
+    /// Enables reporting local and/or remote transmit power changes of a connection.
+    pub async fn set_transmit_power_reporting_enable(
+        &mut self,
+        handle: ConnectionHandle,
+        local_enabled: bool,
+        remote_enabled: bool,
+    ) -> Result<(), adapter::Error> {
+        self.adapter
+            .hci_send_command(SetTransmitPowerReportingEnable {
+                handle,
+                local_enabled,
+                remote_enabled,
+            })
+            .await?
+            .params
+            .status
+            .error()?;
+        Ok(())
+    }
+    /// Enables the power control meta events (on top of the default LE meta events) and returns
+    /// a Stream of the power control events of `handle`. Reporting still has to be enabled with
+    /// [`LEAdapter::enable_path_loss_reporting`] and/or
+    /// [`LEAdapter::set_transmit_power_reporting_enable`].
+    pub async fn power_control_stream<'a>(
+        &'a mut self,
+        handle: ConnectionHandle,
+    ) -> Result<impl Stream<Item = Result<PowerControlEvent, adapter::Error>> + 'a, adapter::Error>
+    {
+        let mut meta_mask = MetaEventMask::default();
+        meta_mask.enable_event(MetaEventCode::PathLossThreshold);
+        meta_mask.enable_event(MetaEventCode::TransmitPowerReporting);
+        let mut event_mask = EventMask::zeroed();
+        event_mask.enable_event(EventMaskFlags::LEMetaEvent);
+        self.adapter.set_event_mask(event_mask).await?;
+        self.set_meta_event_mask(meta_mask).await?;
+        Ok(self.power_control_stream_without_mask(handle))
+    }
+    /// Returns a Stream of the power control events of `handle` without setting any event masks.
+    /// Other LE Meta events are ignored.
+    pub fn power_control_stream_without_mask<'a>(
+        &'a mut self,
+        handle: ConnectionHandle,
+    ) -> impl Stream<Item = Result<PowerControlEvent, adapter::Error>> + 'a {
+        self.meta_event_stream_without_mask().filter_map(
+            move |meta_event: Result<RawMetaEvent<Box<[u8]>>, adapter::Error>| async move {
+                let meta_event = match meta_event {
+                    Ok(meta_event) => meta_event,
+                    Err(e) => return Some(Err(e)),
+                };
+                let event = match meta_event.code {
+                    MetaEventCode::PathLossThreshold => {
+                        PathLossThreshold::meta_unpack_packet(meta_event.as_ref())
+                            .map(PowerControlEvent::PathLoss)
+                    }
+                    MetaEventCode::TransmitPowerReporting => {
+                        TransmitPowerReporting::meta_unpack_packet(meta_event.as_ref())
+                            .map(PowerControlEvent::TransmitPower)
+                    }
+                    _ => return None,
+                };
+                match event {
+                    Ok(event) if event.handle() == handle => Some(Ok(event)),
+                    Ok(_) => None,
+                    Err(e) => Some(Err(adapter::Error::StreamError(StreamError::EventError(e)))),
+                }
+            },
+        )
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytes::Storage;
+    use crate::hci::adapters::Adapter;
+    use crate::hci::command::CommandPacket;
+    use crate::hci::event::{EventCode, EventPacket};
+    use crate::hci::le::power::PathLossZone;
+    use crate::hci::ErrorCode;
+    use crate::LocalBoxFuture;
+    use alloc::collections::VecDeque;
+    use alloc::vec::Vec;
+
+    /// Controller sending `events` (code and parameters) then nothing.
+    struct Events(VecDeque<(EventCode, Vec<u8>)>);
+    impl adapter::Adapter for Events {
+        fn write_command<'s, 'p: 's>(
+            &'s mut self,
+            _packet: CommandPacket<&'p [u8]>,
+        ) -> LocalBoxFuture<'s, Result<(), adapter::Error>> {
+            Box::pin(async { Ok(()) })
+        }
+        fn read_event<'s, 'p: 's, S: Storage<u8> + 'p>(
+            &'s mut self,
+        ) -> LocalBoxFuture<'s, Result<EventPacket<S>, adapter::Error>> {
+            let event = self.0.pop_front();
+            Box::pin(async move {
+                match event {
+                    Some((code, parameters)) => {
+                        Ok(EventPacket::new(code, S::from_slice(&parameters)))
+                    }
+                    None => futures_util::future::pending().await,
+                }
+            })
+        }
+    }
+    fn path_loss(handle: u16) -> PathLossThreshold {
+        PathLossThreshold {
+            handle: ConnectionHandle::new(handle),
+            current_path_loss: Some(50),
+            zone_entered: PathLossZone::Middle,
+        }
+    }
+    fn meta<E: MetaEvent>(event: &E) -> (EventCode, Vec<u8>) {
+        let mut parameters = vec![0_u8; 1 + event.meta_byte_len()];
+        parameters[0] = E::META_CODE.into();
+        event.meta_pack_into(&mut parameters[1..]).unwrap();
+        (EventCode::LEMeta, parameters)
+    }
+
+    #[test]
+    fn power_control_stream_filters_handle() {
+        let transmit_power = TransmitPowerReporting {
+            status: ErrorCode::Ok,
+            handle: ConnectionHandle::new(0x0040),
+            reason: TransmitPowerReportingReason::LocalChange,
+            phy: Some(TxPHY::LE1M),
+            tx_power_level: 0,
+            at_min_level: false,
+            at_max_level: false,
+            delta: 0,
+        };
+        let events = Events(
+            vec![
+                meta(&path_loss(0x0041)),
+                (EventCode::InquiryComplete, vec![0x00]),
+                meta(&transmit_power),
+                meta(&TransmitPowerReporting {
+                    handle: ConnectionHandle::new(0x0041),
+                    ..transmit_power
+                }),
+                meta(&path_loss(0x0040)),
+            ]
+            .into_iter()
+            .collect(),
+        );
+        let mut adapter = Adapter::new(events).le();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let received = runtime.block_on(
+            adapter
+                .power_control_stream_without_mask(ConnectionHandle::new(0x0040))
+                .take(2)
+                .collect::<Vec<_>>(),
+        );
+        assert_eq!(
+            received,
+            vec![
+                Ok(PowerControlEvent::TransmitPower(transmit_power)),
+                Ok(PowerControlEvent::PathLoss(path_loss(0x0040))),
+            ]
+        );
+    }
+}
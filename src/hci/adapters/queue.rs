@@ -0,0 +1,401 @@
+//! HCI Command queue with command flow control. The controller tells the host how many commands
+//! it can accept (`Num_HCI_Command_Packets`) in every `CommandComplete` and `CommandStatus` event.
+//! [`CommandQueue`] tracks that credit so multiple commands can be in flight at once and matches
+//! the return events back to the commands by `Opcode`.
+use crate::hci::acl::DataBuffers;
+use crate::hci::adapter;
+use crate::hci::adapters::{Adapter, UnrecognizedEventHandler};
+use crate::hci::command::Command;
+use crate::hci::event::{CommandStatus, Event, EventCode, EventPacket};
+use crate::hci::stream::HCI_EVENT_READ_TRIES;
+use crate::hci::{Opcode, StreamError};
+use crate::LocalBoxFuture;
+use alloc::collections::VecDeque;
+use core::time::Duration;
+use futures_util::future::{select, Either};
+
+/// Returns a future that completes after the given `Duration`. Used to time out commands without
+/// tying the queue to an executor (ex: `|d| Box::pin(tokio::time::sleep(d))`).
+pub type SleepFn = Box<dyn FnMut(Duration) -> LocalBoxFuture<'static, ()>>;
+
+/// HCI Command Queue. Wraps an [`adapter::Adapter`] and only writes commands when the controller
+/// has command credits left. Commands can be pipelined with [`CommandQueue::submit`] and their
+/// returns collected later with [`CommandQueue::wait_return`]. Events that aren't returns for an
+/// in-flight command are passed to the `event_handler`. Like [`Adapter`], every event read
+/// updates the data `buffers`.
+pub struct CommandQueue<A: adapter::Adapter, H: UnrecognizedEventHandler> {
+    pub adapter: A,
+    pub event_handler: H,
+    pub buffers: DataBuffers,
+    credits: u8,
+    in_flight: VecDeque<Opcode>,
+    returns: VecDeque<EventPacket<H::Buf>>,
+    sleep: Option<SleepFn>,
+    timeout: Duration,
+}
+impl<A: adapter::Adapter, H: UnrecognizedEventHandler> CommandQueue<A, H> {
+    /// Default per-command timeout (1 second). Only used if a [`SleepFn`] is set.
+    pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(1);
+    /// Controllers allow one command before reporting their real command credit.
+    pub const INITIAL_CREDITS: u8 = 1;
+    pub fn new(adapter: A, event_handler: H) -> Self {
+        Self {
+            adapter,
+            event_handler,
+            buffers: DataBuffers::default(),
+            credits: Self::INITIAL_CREDITS,
+            in_flight: VecDeque::new(),
+            returns: VecDeque::new(),
+            sleep: None,
+            timeout: Self::DEFAULT_TIMEOUT,
+        }
+    }
+    /// Sets the sleep function used to time out commands. Without a sleep function, a command
+    /// times out after `HCI_EVENT_READ_TRIES` events without its return.
+    pub fn set_sleep(&mut self, sleep: SleepFn) {
+        self.sleep = Some(sleep);
+    }
+    /// Sets the timeout for each command.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+    /// Number of commands the controller can currently accept.
+    pub fn credits(&self) -> u8 {
+        self.credits
+    }
+    /// Opcodes of commands that have been sent but haven't been returned yet (oldest first).
+    pub fn in_flight(&self) -> impl Iterator<Item = Opcode> + '_ {
+        self.in_flight.iter().copied()
+    }
+    pub fn into_inner(self) -> Adapter<A, H> {
+        Adapter {
+            adapter: self.adapter,
+            event_handler: self.event_handler,
+            buffers: self.buffers,
+        }
+    }
+    async fn read_tracked_event(&mut self) -> Result<EventPacket<H::Buf>, adapter::Error> {
+        let event = self.adapter.read_event::<H::Buf>().await?;
+        self.buffers
+            .process_event(&event)
+            .map_err(StreamError::EventError)?;
+        Ok(event)
+    }
+    /// Updates the credit from `event` and buffers it if it's a return for an in-flight command.
+    /// Returns the event back if it isn't a return.
+    fn route_event(&mut self, event: EventPacket<H::Buf>) -> Option<EventPacket<H::Buf>> {
+        let (credits, opcode) = match event.command_return_info() {
+            Some(info) => info,
+            None => return Some(event),
+        };
+        self.credits = credits;
+        // `Opcode::nop()` returns only update the credit.
+        if opcode.is_nop() {
+            return None;
+        }
+        match self.in_flight.iter().position(|o| *o == opcode) {
+            Some(index) => {
+                self.in_flight.remove(index);
+                self.returns.push_back(event);
+                None
+            }
+            None => Some(event),
+        }
+    }
+    async fn read_and_process(&mut self) -> Result<(), adapter::Error> {
+        let event = self.read_tracked_event().await?;
+        match self.route_event(event) {
+            Some(event) => self.event_handler.handle(event),
+            None => Ok(()),
+        }
+    }
+    /// Reads events until the controller has at least one command credit.
+    pub async fn wait_for_credit(&mut self) -> Result<(), adapter::Error> {
+        while self.credits == 0 {
+            self.read_and_process().await?;
+        }
+        Ok(())
+    }
+    /// Writes `command` once there is a command credit without waiting for its return. Collect
+    /// the return with [`CommandQueue::wait_return`].
+    pub async fn submit<Cmd: Command>(&mut self, command: &Cmd) -> Result<(), adapter::Error> {
+        let packet = command
+            .pack_command_packet::<H::Buf>()
+            .map_err(StreamError::CommandError)?;
+        self.wait_for_credit().await?;
+        self.adapter.write_command(packet.as_ref()).await?;
+        self.credits -= 1;
+        self.in_flight.push_back(Cmd::opcode());
+        Ok(())
+    }
+    fn take_return<Cmd: Command>(&mut self) -> Result<Option<Cmd::Return>, adapter::Error> {
+        let mut index = 0;
+        while index < self.returns.len() {
+            let event = self.returns[index].as_ref();
+            if let Some(ret) = Cmd::unpack_return(event).map_err(StreamError::EventError)? {
+                self.returns.remove(index);
+                return Ok(Some(ret));
+            }
+            // Commands returning `CommandComplete` get a `CommandStatus` if the controller
+            // rejects them (ex: `ErrorCode::UnknownHCICommand`).
+            if event.event_code == EventCode::CommandStatus
+                && event.command_return_info().map(|(_, opcode)| opcode) == Some(Cmd::opcode())
+            {
+                let status = CommandStatus::event_unpack_from(event.parameters)
+                    .map_err(StreamError::EventError)?;
+                self.returns.remove(index);
+                status.status.error()?;
+            } else {
+                index += 1;
+            }
+        }
+        Ok(None)
+    }
+    async fn read_return<Cmd: Command>(&mut self) -> Result<Cmd::Return, adapter::Error> {
+        let mut tries = 0;
+        loop {
+            if let Some(ret) = self.take_return::<Cmd>()? {
+                return Ok(ret);
+            }
+            if self.sleep.is_none() {
+                if tries == HCI_EVENT_READ_TRIES {
+                    return Err(adapter::Error::CommandTimeout(Cmd::opcode()));
+                }
+                tries += 1;
+            }
+            self.read_and_process().await?;
+        }
+    }
+    /// Waits for the return (`CommandComplete` or `CommandStatus`) of a command previously sent
+    /// with [`CommandQueue::submit`].
+    ///
+    /// When the timeout expires, the `read_event` in progress is dropped. Events are only lost
+    /// if the adapter's `read_event` isn't cancel safe (drops an event it has partly read).
+    /// # Errors
+    /// Returns `adapter::Error::CommandTimeout` if the return doesn't arrive within the timeout
+    /// and `adapter::Error::ErrorCode` if a command expecting a `CommandComplete` gets a failed
+    /// `CommandStatus` instead.
+    pub async fn wait_return<Cmd: Command>(&mut self) -> Result<Cmd::Return, adapter::Error> {
+        let timeout = self.timeout;
+        let result = match self.sleep.as_mut().map(|sleep| sleep(timeout)) {
+            None => self.read_return::<Cmd>().await,
+            Some(sleep) => match select(Box::pin(self.read_return::<Cmd>()), sleep).await {
+                Either::Left((result, _)) => result,
+                Either::Right(((), _)) => Err(adapter::Error::CommandTimeout(Cmd::opcode())),
+            },
+        };
+        if let Err(adapter::Error::CommandTimeout(opcode)) = result {
+            // The return will likely never come so stop tracking the command and give back its
+            // credit (the return would have).
+            if let Some(index) = self.in_flight.iter().position(|o| *o == opcode) {
+                self.in_flight.remove(index);
+                self.credits = self.credits.saturating_add(1);
+            }
+        }
+        result
+    }
+    /// Sends `command` and waits for its return.
+    pub async fn send<Cmd: Command>(
+        &mut self,
+        command: Cmd,
+    ) -> Result<Cmd::Return, adapter::Error> {
+        self.submit(&command).await?;
+        self.wait_return::<Cmd>().await
+    }
+    /// Reads the next event that isn't a return for an in-flight command. Command credits are
+    /// still tracked.
+    pub async fn read_event(&mut self) -> Result<EventPacket<H::Buf>, adapter::Error> {
+        loop {
+            let event = self.read_tracked_event().await?;
+            if let Some(event) = self.route_event(event) {
+                return Ok(event);
+            }
+        }
+    }
+}
+impl<A: adapter::Adapter, H: UnrecognizedEventHandler> From<Adapter<A, H>> for CommandQueue<A, H> {
+    fn from(adapter: Adapter<A, H>) -> Self {
+        let mut queue = CommandQueue::new(adapter.adapter, adapter.event_handler);
+        queue.buffers = adapter.buffers;
+        queue
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytes::Storage;
+    use crate::hci::adapters::DummyUnrecognizedEventHandler;
+    use crate::hci::baseband::Reset;
+    use crate::hci::command::CommandPacket;
+    use crate::hci::info::ReadBDADDR;
+    use crate::hci::le::phy::{CodedPHYOptions, SetPHY};
+    use crate::hci::ErrorCode;
+    use crate::le::connection::ConnectionHandle;
+    use alloc::vec::Vec;
+
+    /// Controller sending `events` (code and parameters) then nothing.
+    #[derive(Default)]
+    struct Controller {
+        commands: Vec<Opcode>,
+        events: VecDeque<(EventCode, Vec<u8>)>,
+    }
+    impl Controller {
+        fn new(events: &[(EventCode, &[u8])]) -> Controller {
+            Controller {
+                commands: Vec::new(),
+                events: events
+                    .iter()
+                    .map(|(code, parameters)| (*code, parameters.to_vec()))
+                    .collect(),
+            }
+        }
+    }
+    impl adapter::Adapter for Controller {
+        fn write_command<'s, 'p: 's>(
+            &'s mut self,
+            packet: CommandPacket<&'p [u8]>,
+        ) -> LocalBoxFuture<'s, Result<(), adapter::Error>> {
+            self.commands.push(packet.opcode);
+            Box::pin(async { Ok(()) })
+        }
+        fn read_event<'s, 'p: 's, S: Storage<u8> + 'p>(
+            &'s mut self,
+        ) -> LocalBoxFuture<'s, Result<EventPacket<S>, adapter::Error>> {
+            let event = self.events.pop_front();
+            Box::pin(async move {
+                match event {
+                    Some((code, parameters)) => {
+                        Ok(EventPacket::new(code, S::from_slice(&parameters)))
+                    }
+                    None => futures_util::future::pending().await,
+                }
+            })
+        }
+    }
+    fn queue(
+        events: &[(EventCode, &[u8])],
+    ) -> CommandQueue<Controller, DummyUnrecognizedEventHandler<Box<[u8]>>> {
+        CommandQueue::new(
+            Controller::new(events),
+            DummyUnrecognizedEventHandler::new(),
+        )
+    }
+    fn block_on<F: core::future::Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+    fn set_phy() -> SetPHY {
+        SetPHY {
+            handle: ConnectionHandle::new(0x0040),
+            tx_phys: None,
+            rx_phys: None,
+            phy_options: CodedPHYOptions::default(),
+        }
+    }
+
+    #[test]
+    fn matches_returns_by_opcode() {
+        let mut queue = queue(&[
+            // `Opcode::nop()` return raising the credit to 2.
+            (EventCode::CommandComplete, &[0x02, 0x00, 0x00]),
+            // Returns in the opposite order of the commands.
+            (
+                EventCode::CommandComplete,
+                &[0x01, 0x09, 0x10, 0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06],
+            ),
+            (EventCode::CommandComplete, &[0x01, 0x03, 0x0C, 0x00]),
+        ]);
+        block_on(async {
+            queue.submit(&Reset).await.unwrap();
+            assert_eq!(queue.credits(), 0);
+            queue.submit(&ReadBDADDR()).await.unwrap();
+            assert_eq!(queue.credits(), 1);
+            assert_eq!(
+                queue.in_flight().collect::<Vec<_>>(),
+                vec![Reset::opcode(), ReadBDADDR::opcode()]
+            );
+            let reset = queue.wait_return::<Reset>().await.unwrap();
+            assert_eq!(reset.params.status, ErrorCode::Ok);
+            // Already read while waiting for the Reset return.
+            assert!(queue.adapter.events.is_empty());
+            let address = queue.wait_return::<ReadBDADDR>().await.unwrap();
+            assert_eq!(address.opcode, ReadBDADDR::opcode());
+            assert_eq!(address.params.status, ErrorCode::Ok);
+            assert_eq!(queue.in_flight().count(), 0);
+            assert_eq!(queue.credits(), 1);
+        });
+    }
+    #[test]
+    fn command_status_and_command_complete_returns() {
+        let mut queue = queue(&[
+            (EventCode::CommandComplete, &[0x02, 0x00, 0x00]),
+            // SetPHY returns a CommandStatus.
+            (EventCode::CommandStatus, &[0x00, 0x02, 0x32, 0x20]),
+            // Reset rejected with a CommandStatus instead of its CommandComplete.
+            (EventCode::CommandStatus, &[0x01, 0x02, 0x03, 0x0C]),
+        ]);
+        block_on(async {
+            queue.submit(&set_phy()).await.unwrap();
+            queue.submit(&Reset).await.unwrap();
+            let status = queue.wait_return::<SetPHY>().await.unwrap();
+            assert_eq!(status.status, ErrorCode::Ok);
+            assert_eq!(status.opcode, SetPHY::opcode());
+            assert_eq!(
+                queue.wait_return::<Reset>().await.err(),
+                Some(adapter::Error::ErrorCode(ErrorCode::UnknownHCICommand))
+            );
+            assert_eq!(queue.in_flight().count(), 0);
+            assert_eq!(queue.credits(), 2);
+        });
+    }
+    #[test]
+    fn no_credit_until_controller_gives_one() {
+        let mut queue = queue(&[
+            // Num_HCI_Command_Packets = 0: no command can be sent for now.
+            (EventCode::CommandComplete, &[0x00, 0x03, 0x0C, 0x00]),
+            (EventCode::CommandComplete, &[0x01, 0x00, 0x00]),
+        ]);
+        block_on(async {
+            queue.send(Reset).await.unwrap();
+            assert_eq!(queue.credits(), 0);
+            assert_eq!(queue.adapter.events.len(), 1);
+            queue.submit(&Reset).await.unwrap();
+            // Only written after the `Opcode::nop()` return gave a credit back.
+            assert!(queue.adapter.events.is_empty());
+            assert_eq!(queue.credits(), 0);
+        });
+        assert_eq!(
+            queue.adapter.commands,
+            vec![Reset::opcode(), Reset::opcode()]
+        );
+    }
+
+    #[test]
+    fn timeout_gives_back_credit() {
+        // Controller that never answers.
+        let mut queue = queue(&[]);
+        queue.set_sleep(Box::new(|_| Box::pin(async {})));
+        block_on(async {
+            queue.submit(&Reset).await.unwrap();
+            assert_eq!(queue.credits(), 0);
+            let opcode = Reset::opcode();
+            assert_eq!(
+                queue.wait_return::<Reset>().await.err(),
+                Some(adapter::Error::CommandTimeout(opcode))
+            );
+            assert_eq!(queue.credits(), 1);
+            assert_eq!(queue.in_flight().count(), 0);
+            // Would wait forever for a credit without the timed out command's credit.
+            queue.submit(&Reset).await.unwrap();
+        });
+        assert_eq!(
+            queue.adapter.commands,
+            vec![Reset::opcode(), Reset::opcode()]
+        );
+    }
+}
@@ -0,0 +1,300 @@
+//! LE Direct Test Mode (DTM) functions for [`LEAdapter`]. [`DTMSweep`] runs a test on a list of
+//! RF channels one after another for production testing.
+use crate::channel::RFChannel;
+use crate::hci::adapter;
+use crate::hci::adapters::le::LEAdapter;
+use crate::hci::adapters::queue::SleepFn;
+use crate::hci::adapters::UnrecognizedEventHandler;
+use crate::hci::command::Command;
+use crate::hci::event::{CommandComplete, StatusReturn};
+use crate::hci::le::test::{
+    ModulationIndex, PacketPayload, ReceiverTestV1, ReceiverTestV2, TestEnd, TransmitterTestV1,
+    TransmitterTestV2,
+};
+use crate::le::phy::TxPHY;
+use alloc::vec::Vec;
+use core::time::Duration;
+
+/// Direction of a DTM test.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub enum DTMMode {
+    Receiver {
+        modulation_index: ModulationIndex,
+    },
+    Transmitter {
+        test_data_len: u8,
+        payload: PacketPayload,
+    },
+}
+/// Runs a DTM test on each channel in `channels` for `dwell` each. The v1 test commands are used
+/// on the LE 1M PHY with the standard modulation index so older controllers work too.
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct DTMSweep {
+    pub channels: Vec<RFChannel>,
+    pub dwell: Duration,
+    pub phy: TxPHY,
+    pub mode: DTMMode,
+}
+impl DTMSweep {
+    /// Receiver sweep across every RF channel on the LE 1M PHY.
+    pub fn receiver(dwell: Duration) -> DTMSweep {
+        DTMSweep {
+            channels: RFChannel::all().collect(),
+            dwell,
+            phy: TxPHY::LE1M,
+            mode: DTMMode::Receiver {
+                modulation_index: ModulationIndex::Standard,
+            },
+        }
+    }
+    /// Transmitter sweep across every RF channel on the LE 1M PHY.
+    pub fn transmitter(dwell: Duration, test_data_len: u8, payload: PacketPayload) -> DTMSweep {
+        DTMSweep {
+            channels: RFChannel::all().collect(),
+            dwell,
+            phy: TxPHY::LE1M,
+            mode: DTMMode::Transmitter {
+                test_data_len,
+                payload,
+            },
+        }
+    }
+}
+/// Result of one channel in a [`DTMSweep`]. `num_packets` is always 0 for a transmitter test.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct DTMChannelResult {
+    pub channel: RFChannel,
+    pub num_packets: u16,
+}
+impl<A: adapter::Adapter, H: UnrecognizedEventHandler> LEAdapter<A, H> {
+    /// Starts a DTM test (any of the receiver or transmitter test commands). The test runs
+    /// until [`LEAdapter::test_end`].
+    pub async fn start_test<Cmd: Command<Return = CommandComplete<StatusReturn>>>(
+        &mut self,
+        test: Cmd,
+    ) -> Result<(), adapter::Error> {
+        self.adapter
+            .hci_send_command(test)
+            .await?
+            .params
+            .status
+            .error()?;
+        Ok(())
+    }
+    /// Ends the running DTM test. Returns the number of packets received (0 if transmitting).
+    pub async fn test_end(&mut self) -> Result<u16, adapter::Error> {
+        let r = self.adapter.hci_send_command(TestEnd()).await?;
+        r.params.status.error()?;
+        Ok(r.params.num_packets)
+    }
+    async fn start_sweep_channel(
+        &mut self,
+        sweep: &DTMSweep,
+        channel: RFChannel,
+    ) -> Result<(), adapter::Error> {
+        match (sweep.mode, sweep.phy) {
+            (
+                DTMMode::Receiver {
+                    modulation_index: ModulationIndex::Standard,
+                },
+                TxPHY::LE1M,
+            ) => {
+                self.start_test(ReceiverTestV1 {
+                    rx_channel: channel,
+                })
+                .await
+            }
+            (DTMMode::Receiver { modulation_index }, phy) => {
+                self.start_test(ReceiverTestV2 {
+                    rx_channel: channel,
+                    phy: phy.phy(),
+                    modulation_index,
+                })
+                .await
+            }
+            (
+                DTMMode::Transmitter {
+                    test_data_len,
+                    payload,
+                },
+                TxPHY::LE1M,
+            ) => {
+                self.start_test(TransmitterTestV1 {
+                    tx_channel: channel,
+                    test_data_len,
+                    payload,
+                })
+                .await
+            }
+            (
+                DTMMode::Transmitter {
+                    test_data_len,
+                    payload,
+                },
+                phy,
+            ) => {
+                self.start_test(TransmitterTestV2 {
+                    tx_channel: channel,
+                    test_data_len,
+                    payload,
+                    phy,
+                })
+                .await
+            }
+        }
+    }
+    /// Runs `sweep`, waiting `sweep.dwell` (using `sleep`) on each channel before ending the
+    /// test. Stops at the first error.
+    pub async fn run_dtm_sweep(
+        &mut self,
+        sweep: &DTMSweep,
+        sleep: &mut SleepFn,
+    ) -> Result<Vec<DTMChannelResult>, adapter::Error> {
+        let mut results = Vec::with_capacity(sweep.channels.len());
+        for &channel in &sweep.channels {
+            self.start_sweep_channel(sweep, channel).await?;
+            sleep(sweep.dwell).await;
+            results.push(DTMChannelResult {
+                channel,
+                num_packets: self.test_end().await?,
+            });
+        }
+        Ok(results)
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytes::Storage;
+    use crate::hci::adapters::Adapter;
+    use crate::hci::command::CommandPacket;
+    use crate::hci::event::{EventCode, EventPacket};
+    use crate::hci::{ErrorCode, Opcode};
+    use crate::LocalBoxFuture;
+    use alloc::collections::VecDeque;
+
+    /// Controller sending `events` (code and parameters) then nothing.
+    #[derive(Default)]
+    struct Controller {
+        commands: Vec<(Opcode, Vec<u8>)>,
+        events: VecDeque<(EventCode, Vec<u8>)>,
+    }
+    impl adapter::Adapter for Controller {
+        fn write_command<'s, 'p: 's>(
+            &'s mut self,
+            packet: CommandPacket<&'p [u8]>,
+        ) -> LocalBoxFuture<'s, Result<(), adapter::Error>> {
+            self.commands
+                .push((packet.opcode, packet.parameters.to_vec()));
+            Box::pin(async { Ok(()) })
+        }
+        fn read_event<'s, 'p: 's, S: Storage<u8> + 'p>(
+            &'s mut self,
+        ) -> LocalBoxFuture<'s, Result<EventPacket<S>, adapter::Error>> {
+            let event = self.events.pop_front();
+            Box::pin(async move {
+                match event {
+                    Some((code, parameters)) => {
+                        Ok(EventPacket::new(code, S::from_slice(&parameters)))
+                    }
+                    None => futures_util::future::pending().await,
+                }
+            })
+        }
+    }
+    fn command_complete(parameters: &[u8]) -> (EventCode, Vec<u8>) {
+        (EventCode::CommandComplete, parameters.to_vec())
+    }
+    fn channel(channel: u8) -> RFChannel {
+        RFChannel::new_checked(channel).unwrap()
+    }
+    fn run(
+        controller: Controller,
+        sweep: &DTMSweep,
+    ) -> (
+        Result<Vec<DTMChannelResult>, adapter::Error>,
+        Vec<(Opcode, Vec<u8>)>,
+    ) {
+        let mut adapter = Adapter::new(controller).le();
+        let mut no_sleep: SleepFn = Box::new(|_| Box::pin(async {}));
+        let results = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(adapter.run_dtm_sweep(sweep, &mut no_sleep));
+        (results, adapter.adapter.adapter.commands)
+    }
+
+    #[test]
+    fn receiver_sweep() {
+        let controller = Controller {
+            commands: Vec::new(),
+            events: vec![
+                command_complete(&[0x01, 0x33, 0x20, 0x00]),
+                command_complete(&[0x01, 0x1F, 0x20, 0x00, 0x10, 0x00]),
+                command_complete(&[0x01, 0x33, 0x20, 0x00]),
+                command_complete(&[0x01, 0x1F, 0x20, 0x00, 0x20, 0x00]),
+            ]
+            .into_iter()
+            .collect(),
+        };
+        let sweep = DTMSweep {
+            channels: vec![channel(0), channel(39)],
+            phy: TxPHY::LE2M,
+            ..DTMSweep::receiver(Duration::from_millis(10))
+        };
+        let (results, commands) = run(controller, &sweep);
+        assert_eq!(
+            results,
+            Ok(vec![
+                DTMChannelResult {
+                    channel: channel(0),
+                    num_packets: 16,
+                },
+                DTMChannelResult {
+                    channel: channel(39),
+                    num_packets: 32,
+                },
+            ])
+        );
+        // The LE 2M PHY needs the Enhanced Receiver Test.
+        assert_eq!(
+            commands,
+            vec![
+                (ReceiverTestV2::opcode(), vec![0x00, 0x02, 0x00]),
+                (TestEnd::opcode(), vec![]),
+                (ReceiverTestV2::opcode(), vec![0x27, 0x02, 0x00]),
+                (TestEnd::opcode(), vec![]),
+            ]
+        );
+    }
+    #[test]
+    fn transmitter_sweep_stops_at_first_error() {
+        let controller = Controller {
+            commands: Vec::new(),
+            events: vec![
+                command_complete(&[0x01, 0x1E, 0x20, 0x00]),
+                command_complete(&[0x01, 0x1F, 0x20, 0x00, 0x00, 0x00]),
+                command_complete(&[0x01, 0x1E, 0x20, 0x0C]),
+            ]
+            .into_iter()
+            .collect(),
+        };
+        let sweep = DTMSweep {
+            channels: vec![channel(0), channel(1), channel(2)],
+            ..DTMSweep::transmitter(Duration::from_millis(10), 37, PacketPayload::PRBS9)
+        };
+        let (results, commands) = run(controller, &sweep);
+        assert_eq!(
+            results,
+            Err(adapter::Error::ErrorCode(ErrorCode::CommandDisallowed))
+        );
+        assert_eq!(
+            commands,
+            vec![
+                (TransmitterTestV1::opcode(), vec![0x00, 0x25, 0x00]),
+                (TestEnd::opcode(), vec![]),
+                (TransmitterTestV1::opcode(), vec![0x01, 0x25, 0x00]),
+            ]
+        );
+    }
+}
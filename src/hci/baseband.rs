@@ -1,8 +1,9 @@
+use crate::hci::acl::{pack_completed_packets, unpack_completed_packets, CompletedPackets};
 use crate::hci::command::Command;
 use crate::hci::event::{CommandComplete, StatusReturn};
 use crate::hci::{Opcode, OCF, OGF};
-use crate::PackError;
-use core::convert::TryInto;
+use crate::{ConversionError, PackError};
+use core::convert::{TryFrom, TryInto};
 
 pub enum ControllerBasebandOpcode {
     SetEventMask = 0x0001,
@@ -12,6 +13,9 @@ pub enum ControllerBasebandOpcode {
     ReadPIN = 0x0009,
     WritePIN = 0x000A,
     ReadStoredLinkKey = 0x000D,
+    SetControllerToHostFlowControl = 0x0031,
+    HostBufferSize = 0x0033,
+    HostNumberOfCompletedPackets = 0x0035,
 }
 impl From<ControllerBasebandOpcode> for u16 {
     fn from(opcode: ControllerBasebandOpcode) -> Self {
@@ -180,3 +184,145 @@ impl Command for SetEventMask {
         ))))
     }
 }
+/// Controller to Host flow control setting. See [`SetControllerToHostFlowControl`].
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+#[repr(u8)]
+pub enum FlowControlEnable {
+    Off = 0x00,
+    ACLOnly = 0x01,
+    SynchronousOnly = 0x02,
+    ACLAndSynchronous = 0x03,
+}
+impl From<FlowControlEnable> for u8 {
+    fn from(f: FlowControlEnable) -> Self {
+        f as u8
+    }
+}
+impl TryFrom<u8> for FlowControlEnable {
+    type Error = ConversionError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x00 => Ok(FlowControlEnable::Off),
+            0x01 => Ok(FlowControlEnable::ACLOnly),
+            0x02 => Ok(FlowControlEnable::SynchronousOnly),
+            0x03 => Ok(FlowControlEnable::ACLAndSynchronous),
+            _ => Err(ConversionError(())),
+        }
+    }
+}
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct SetControllerToHostFlowControl(pub FlowControlEnable);
+impl SetControllerToHostFlowControl {
+    pub const BYTE_LEN: usize = 1;
+    pub const OPCODE: ControllerBasebandOpcode =
+        ControllerBasebandOpcode::SetControllerToHostFlowControl;
+}
+impl Command for SetControllerToHostFlowControl {
+    type Return = CommandComplete<StatusReturn>;
+
+    fn opcode() -> Opcode {
+        Self::OPCODE.into()
+    }
+
+    fn byte_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        buf[0] = self.0.into();
+        Ok(())
+    }
+
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        Ok(SetControllerToHostFlowControl(
+            FlowControlEnable::try_from(buf[0]).map_err(|_| PackError::bad_index(0))?,
+        ))
+    }
+}
+/// Tells the controller the size of the host's data buffers. Used for Controller to Host flow
+/// control.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash, Default)]
+pub struct HostBufferSize {
+    pub acl_data_packet_len: u16,
+    pub synchronous_data_packet_len: u8,
+    pub total_num_acl_data_packets: u16,
+    pub total_num_synchronous_data_packets: u16,
+}
+impl HostBufferSize {
+    pub const BYTE_LEN: usize = 2 + 1 + 2 + 2;
+    pub const OPCODE: ControllerBasebandOpcode = ControllerBasebandOpcode::HostBufferSize;
+}
+impl Command for HostBufferSize {
+    type Return = CommandComplete<StatusReturn>;
+
+    fn opcode() -> Opcode {
+        Self::OPCODE.into()
+    }
+
+    fn byte_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        buf[0..2].copy_from_slice(&self.acl_data_packet_len.to_le_bytes());
+        buf[2] = self.synchronous_data_packet_len;
+        buf[3..5].copy_from_slice(&self.total_num_acl_data_packets.to_le_bytes());
+        buf[5..7].copy_from_slice(&self.total_num_synchronous_data_packets.to_le_bytes());
+        Ok(())
+    }
+
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        Ok(HostBufferSize {
+            acl_data_packet_len: u16::from_le_bytes([buf[0], buf[1]]),
+            synchronous_data_packet_len: buf[2],
+            total_num_acl_data_packets: u16::from_le_bytes([buf[3], buf[4]]),
+            total_num_synchronous_data_packets: u16::from_le_bytes([buf[5], buf[6]]),
+        })
+    }
+}
+/// Reports ACL packets the host is done processing. Only used when Controller to Host flow
+/// control is enabled. The controller doesn't return anything for this command unless there is
+/// an error, so it should be written without waiting for a return.
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash, Default)]
+pub struct HostNumberOfCompletedPackets {
+    pub completed: Box<[CompletedPackets]>,
+}
+impl HostNumberOfCompletedPackets {
+    pub const OPCODE: ControllerBasebandOpcode =
+        ControllerBasebandOpcode::HostNumberOfCompletedPackets;
+}
+impl Command for HostNumberOfCompletedPackets {
+    type Return = CommandComplete<StatusReturn>;
+
+    fn opcode() -> Opcode {
+        Self::OPCODE.into()
+    }
+
+    fn byte_len(&self) -> usize {
+        1 + self.completed.len() * CompletedPackets::BYTE_LEN
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        pack_completed_packets(self.completed.as_ref(), buf)
+    }
+
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        Ok(HostNumberOfCompletedPackets {
+            completed: unpack_completed_packets(buf)?,
+        })
+    }
+}
@@ -0,0 +1,326 @@
+//! Async mgmt client over the BlueZ control channel.
+use crate::error::IOError;
+use crate::hci::bluez_mgmt::commands::{
+    AddAdvertising, AdvertisingSetting, ControllerInformation, IOCapability, IdentityResolvingKey,
+    LoadIdentityResolvingKeys, LoadLongTermKeys, LongTermKey, MgmtAddress, MgmtAddressTypes,
+    PairDevice, ReadControllerIndexList, ReadControllerInformation, RemoveAdvertising,
+    SetAdvertising, SetConnectable, SetLE, SetPowered, Settings, StartDiscovery, StopDiscovery,
+};
+use crate::hci::bluez_mgmt::events::{MgmtEvent, MgmtEventPacket};
+use crate::hci::bluez_mgmt::{
+    ControllerIndex, MgmtCommand, MgmtError, MgmtReturn, MgmtStatus, HEADER_LEN, MAX_PARAMETERS_LEN,
+};
+use crate::hci::bluez_socket::{AdapterID, HCIChannel, HCISocket};
+use crate::Stream;
+use alloc::collections::VecDeque;
+
+/// Client for the BlueZ Management API. Events that arrive while waiting for a command to
+/// complete are queued and returned by [`MgmtClient::read_event`] later.
+#[derive(Debug)]
+pub struct MgmtClient {
+    socket: tokio::net::UnixStream,
+    buf: Box<[u8]>,
+    queued_events: VecDeque<MgmtEventPacket>,
+}
+impl MgmtClient {
+    /// Opens the control channel. Must be called inside a tokio runtime.
+    pub fn open() -> Result<MgmtClient, IOError> {
+        let socket = HCISocket::bind_channel(AdapterID::NONE, HCIChannel::Control)?;
+        socket.set_nonblocking(true)?;
+        Ok(Self::from_stream(tokio::net::UnixStream::from_std(socket)?))
+    }
+    /// Wraps an already bound control channel socket (or anything speaking the mgmt protocol).
+    pub fn from_stream(socket: tokio::net::UnixStream) -> MgmtClient {
+        MgmtClient {
+            socket,
+            buf: vec![0_u8; HEADER_LEN + MAX_PARAMETERS_LEN].into_boxed_slice(),
+            queued_events: VecDeque::new(),
+        }
+    }
+    async fn write_packet(&mut self, packet: &[u8]) -> Result<(), MgmtError> {
+        loop {
+            self.socket.writable().await.map_err(IOError::from)?;
+            match self.socket.try_write(packet) {
+                Ok(len) if len == packet.len() => return Ok(()),
+                Ok(_) => return Err(MgmtError::IOError(IOError::Other)),
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {}
+                Err(e) => return Err(MgmtError::IOError(e.into())),
+            }
+        }
+    }
+    /// Reads the next event from the socket (ignoring the queued events).
+    async fn read_packet(&mut self) -> Result<MgmtEventPacket, MgmtError> {
+        loop {
+            self.socket.readable().await.map_err(IOError::from)?;
+            match self.socket.try_read(&mut self.buf) {
+                Ok(0) => return Err(MgmtError::IOError(IOError::Closed)),
+                Ok(len) => return Ok(MgmtEventPacket::unpack_from(&self.buf[..len])?),
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {}
+                Err(e) => return Err(MgmtError::IOError(e.into())),
+            }
+        }
+    }
+    /// Returns the next mgmt event.
+    pub async fn read_event(&mut self) -> Result<MgmtEventPacket, MgmtError> {
+        match self.queued_events.pop_front() {
+            Some(event) => Ok(event),
+            None => self.read_packet().await,
+        }
+    }
+    /// Returns a Stream of every mgmt event (from every controller).
+    pub fn event_stream(&mut self) -> impl Stream<Item = Result<MgmtEventPacket, MgmtError>> + '_ {
+        futures_util::stream::unfold(self, |client| async move {
+            let event = client.read_event().await;
+            Some((event, client))
+        })
+    }
+    /// Sends `command` to controller `index` and waits for it to complete.
+    pub async fn send_command<Cmd: MgmtCommand>(
+        &mut self,
+        index: ControllerIndex,
+        command: Cmd,
+    ) -> Result<Cmd::Return, MgmtError> {
+        let opcode = u16::from(Cmd::OPCODE);
+        self.write_packet(&command.pack_packet(index)?).await?;
+        loop {
+            let packet = self.read_packet().await?;
+            if packet.index == index {
+                match packet.event {
+                    MgmtEvent::CommandComplete {
+                        opcode: event_opcode,
+                        status,
+                        parameters,
+                    } if event_opcode == opcode => {
+                        status.error()?;
+                        return Ok(Cmd::Return::unpack_from(&parameters)?);
+                    }
+                    MgmtEvent::CommandStatus {
+                        opcode: event_opcode,
+                        status,
+                    } if event_opcode == opcode && status != MgmtStatus::Success => {
+                        return Err(MgmtError::Status(status));
+                    }
+                    event => self
+                        .queued_events
+                        .push_back(MgmtEventPacket { index, event }),
+                }
+            } else {
+                self.queued_events.push_back(packet);
+            }
+        }
+    }
+    /// Returns the index of every controller.
+    pub async fn read_controller_index_list(&mut self) -> Result<Vec<ControllerIndex>, MgmtError> {
+        Ok(self
+            .send_command(ControllerIndex::NONE, ReadControllerIndexList)
+            .await?
+            .0
+            .into_iter()
+            .map(ControllerIndex)
+            .collect())
+    }
+    pub async fn read_controller_information(
+        &mut self,
+        index: ControllerIndex,
+    ) -> Result<ControllerInformation, MgmtError> {
+        self.send_command(index, ReadControllerInformation).await
+    }
+    /// Powers the controller on or off. Returns the new current settings.
+    pub async fn set_powered(
+        &mut self,
+        index: ControllerIndex,
+        is_enabled: bool,
+    ) -> Result<Settings, MgmtError> {
+        self.send_command(index, SetPowered { is_enabled }).await
+    }
+    pub async fn set_le(
+        &mut self,
+        index: ControllerIndex,
+        is_enabled: bool,
+    ) -> Result<Settings, MgmtError> {
+        self.send_command(index, SetLE { is_enabled }).await
+    }
+    pub async fn set_connectable(
+        &mut self,
+        index: ControllerIndex,
+        is_enabled: bool,
+    ) -> Result<Settings, MgmtError> {
+        self.send_command(index, SetConnectable { is_enabled })
+            .await
+    }
+    pub async fn set_advertising(
+        &mut self,
+        index: ControllerIndex,
+        setting: AdvertisingSetting,
+    ) -> Result<Settings, MgmtError> {
+        self.send_command(index, SetAdvertising { setting }).await
+    }
+    /// Adds an advertising instance. Returns the instance number.
+    pub async fn add_advertising(
+        &mut self,
+        index: ControllerIndex,
+        advertising: AddAdvertising,
+    ) -> Result<u8, MgmtError> {
+        Ok(self.send_command(index, advertising).await?.0)
+    }
+    /// Removes advertising instance `instance` (0 for every instance).
+    pub async fn remove_advertising(
+        &mut self,
+        index: ControllerIndex,
+        instance: u8,
+    ) -> Result<(), MgmtError> {
+        self.send_command(index, RemoveAdvertising { instance })
+            .await?;
+        Ok(())
+    }
+    /// Starts discovery. Found devices arrive as `MgmtEvent::DeviceFound` events.
+    pub async fn start_discovery(
+        &mut self,
+        index: ControllerIndex,
+        address_types: MgmtAddressTypes,
+    ) -> Result<(), MgmtError> {
+        self.send_command(index, StartDiscovery { address_types })
+            .await?;
+        Ok(())
+    }
+    pub async fn stop_discovery(
+        &mut self,
+        index: ControllerIndex,
+        address_types: MgmtAddressTypes,
+    ) -> Result<(), MgmtError> {
+        self.send_command(index, StopDiscovery { address_types })
+            .await?;
+        Ok(())
+    }
+    /// Pairs with `address`. Only returns once pairing finished. New keys arrive as
+    /// `MgmtEvent::NewLongTermKey` and `MgmtEvent::NewIdentityResolvingKey` events.
+    pub async fn pair_device(
+        &mut self,
+        index: ControllerIndex,
+        address: MgmtAddress,
+        io_capability: IOCapability,
+    ) -> Result<MgmtAddress, MgmtError> {
+        self.send_command(
+            index,
+            PairDevice {
+                address,
+                io_capability,
+            },
+        )
+        .await
+    }
+    /// Replaces the Long Term Keys of the controller with `keys`.
+    pub async fn load_long_term_keys(
+        &mut self,
+        index: ControllerIndex,
+        keys: Vec<LongTermKey>,
+    ) -> Result<(), MgmtError> {
+        self.send_command(index, LoadLongTermKeys { keys }).await
+    }
+    /// Replaces the Identity Resolving Keys of the controller with `keys`.
+    pub async fn load_identity_resolving_keys(
+        &mut self,
+        index: ControllerIndex,
+        keys: Vec<IdentityResolvingKey>,
+    ) -> Result<(), MgmtError> {
+        self.send_command(index, LoadIdentityResolvingKeys { keys })
+            .await
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hci::bluez_mgmt::commands::Setting;
+    use std::os::unix::io::{AsRawFd, FromRawFd};
+
+    /// `SOCK_SEQPACKET` pair so packet boundaries are kept like on the control channel.
+    fn seqpacket_pair() -> (
+        std::os::unix::net::UnixStream,
+        std::os::unix::net::UnixStream,
+    ) {
+        let mut fds = [0; 2];
+        assert_eq!(
+            unsafe {
+                libc::socketpair(
+                    libc::AF_UNIX,
+                    libc::SOCK_SEQPACKET | libc::SOCK_CLOEXEC,
+                    0,
+                    fds.as_mut_ptr(),
+                )
+            },
+            0
+        );
+        unsafe {
+            (
+                std::os::unix::net::UnixStream::from_raw_fd(fds[0]),
+                std::os::unix::net::UnixStream::from_raw_fd(fds[1]),
+            )
+        }
+    }
+    fn send(socket: &std::os::unix::net::UnixStream, packet: &[u8]) {
+        assert_eq!(
+            unsafe {
+                libc::send(
+                    socket.as_raw_fd(),
+                    packet.as_ptr() as *const libc::c_void,
+                    packet.len(),
+                    0,
+                )
+            },
+            packet.len() as isize
+        );
+    }
+    #[test]
+    fn command_response() {
+        let (kernel, client) = seqpacket_pair();
+        client.set_nonblocking(true).unwrap();
+        // New Settings of controller 1 arrives before the reply and is queued.
+        send(
+            &kernel,
+            &[0x06, 0x00, 0x01, 0x00, 0x04, 0x00, 0x00, 0x02, 0x00, 0x00],
+        );
+        // Command Complete for Set Powered on controller 0, Success, Powered | LE.
+        send(
+            &kernel,
+            &[
+                0x01, 0x00, 0x00, 0x00, 0x07, 0x00, 0x05, 0x00, 0x00, 0x01, 0x02, 0x00, 0x00,
+            ],
+        );
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_io()
+            .build()
+            .unwrap();
+        runtime.block_on(async move {
+            let mut mgmt =
+                MgmtClient::from_stream(tokio::net::UnixStream::from_std(client).unwrap());
+            let settings = mgmt.set_powered(ControllerIndex(0), true).await.unwrap();
+            assert!(settings.get(Setting::Powered));
+            assert!(settings.get(Setting::LE));
+            let queued = mgmt.read_event().await.unwrap();
+            assert_eq!(queued.index, ControllerIndex(1));
+            assert_eq!(queued.event, MgmtEvent::NewSettings(Settings(1 << 9)));
+            // Command Status for Set LE, Not Supported.
+            send(
+                &kernel,
+                &[0x02, 0x00, 0x00, 0x00, 0x03, 0x00, 0x0D, 0x00, 0x0C],
+            );
+            assert_eq!(
+                mgmt.set_le(ControllerIndex(0), true).await,
+                Err(MgmtError::Status(MgmtStatus::NotSupported))
+            );
+            let mut buf = [0_u8; 16];
+            let len = unsafe {
+                libc::recv(
+                    kernel.as_raw_fd(),
+                    buf.as_mut_ptr() as *mut libc::c_void,
+                    buf.len(),
+                    0,
+                )
+            };
+            assert_eq!(
+                &buf[..len as usize],
+                &[0x05, 0x00, 0x00, 0x00, 0x01, 0x00, 0x01]
+            );
+        });
+    }
+}
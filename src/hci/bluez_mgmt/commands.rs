@@ -0,0 +1,553 @@
+//! Mgmt commands and their return parameters.
+use crate::hci::bluez_mgmt::{MgmtCommand, MgmtOpcode, MgmtReturn};
+use crate::{BTAddress, ConversionError, PackError};
+use core::convert::{TryFrom, TryInto};
+
+/// Address type used by mgmt (not the same values as the HCI address types).
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+#[repr(u8)]
+pub enum MgmtAddressType {
+    BREDR = 0x00,
+    LEPublic = 0x01,
+    LERandom = 0x02,
+}
+impl MgmtAddressType {
+    pub const BYTE_LEN: usize = 1;
+}
+impl From<MgmtAddressType> for u8 {
+    fn from(t: MgmtAddressType) -> Self {
+        t as u8
+    }
+}
+impl TryFrom<u8> for MgmtAddressType {
+    type Error = ConversionError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x00 => Ok(MgmtAddressType::BREDR),
+            0x01 => Ok(MgmtAddressType::LEPublic),
+            0x02 => Ok(MgmtAddressType::LERandom),
+            _ => Err(ConversionError(())),
+        }
+    }
+}
+/// Address and address type of a remote device.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct MgmtAddress {
+    pub address: BTAddress,
+    pub address_type: MgmtAddressType,
+}
+impl MgmtAddress {
+    pub const BYTE_LEN: usize = BTAddress::LEN + MgmtAddressType::BYTE_LEN;
+    pub fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        self.address.pack_into(&mut buf[..BTAddress::LEN])?;
+        buf[BTAddress::LEN] = self.address_type.into();
+        Ok(())
+    }
+    pub fn unpack_from(buf: &[u8]) -> Result<Self, PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        Ok(MgmtAddress {
+            address: BTAddress::unpack_from(&buf[..BTAddress::LEN])?,
+            address_type: MgmtAddressType::try_from(buf[BTAddress::LEN])
+                .map_err(|_| PackError::bad_index(BTAddress::LEN))?,
+        })
+    }
+}
+/// Bitmask of address types. Used to select what to discover.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash, Default)]
+pub struct MgmtAddressTypes(pub u8);
+impl MgmtAddressTypes {
+    pub const BREDR: MgmtAddressTypes = MgmtAddressTypes(1 << 0);
+    pub const LE: MgmtAddressTypes = MgmtAddressTypes((1 << 1) | (1 << 2));
+    pub const ALL: MgmtAddressTypes = MgmtAddressTypes(0b111);
+    pub fn enable(&mut self, address_type: MgmtAddressType) {
+        self.0 |= 1 << u8::from(address_type);
+    }
+    pub fn get(self, address_type: MgmtAddressType) -> bool {
+        self.0 & (1 << u8::from(address_type)) != 0
+    }
+}
+/// Controller settings bits (supported and current settings).
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+#[repr(u8)]
+pub enum Setting {
+    Powered = 0,
+    Connectable = 1,
+    FastConnectable = 2,
+    Discoverable = 3,
+    Bondable = 4,
+    LinkLevelSecurity = 5,
+    SecureSimplePairing = 6,
+    BREDR = 7,
+    HighSpeed = 8,
+    LE = 9,
+    Advertising = 10,
+    SecureConnections = 11,
+    DebugKeys = 12,
+    Privacy = 13,
+    ControllerConfiguration = 14,
+    StaticAddress = 15,
+}
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash, Default)]
+pub struct Settings(pub u32);
+impl Settings {
+    pub const BYTE_LEN: usize = 4;
+    pub fn get(self, setting: Setting) -> bool {
+        self.0 & (1_u32 << (setting as u8)) != 0
+    }
+    pub fn enable(&mut self, setting: Setting) {
+        self.0 |= 1_u32 << (setting as u8);
+    }
+    pub fn disable(&mut self, setting: Setting) {
+        self.0 &= !(1_u32 << (setting as u8));
+    }
+    pub fn unpack_from(buf: &[u8]) -> Result<Self, PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        Ok(Settings(u32::from_le_bytes(
+            buf.try_into().expect("length checked above"),
+        )))
+    }
+}
+impl MgmtReturn for Settings {
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError> {
+        Settings::unpack_from(buf)
+    }
+}
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash, Default)]
+pub struct ReadControllerIndexList;
+impl MgmtCommand for ReadControllerIndexList {
+    type Return = ControllerIndexList;
+    const OPCODE: MgmtOpcode = MgmtOpcode::ReadControllerIndexList;
+
+    fn byte_len(&self) -> usize {
+        0
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(0, buf)
+    }
+}
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash, Default)]
+pub struct ControllerIndexList(pub Vec<u16>);
+impl MgmtReturn for ControllerIndexList {
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError> {
+        PackError::atleast_length(2, buf)?;
+        let count = usize::from(u16::from_le_bytes([buf[0], buf[1]]));
+        PackError::expect_length(2 + count * 2, buf)?;
+        Ok(ControllerIndexList(
+            buf[2..]
+                .chunks_exact(2)
+                .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                .collect(),
+        ))
+    }
+}
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash, Default)]
+pub struct ReadControllerInformation;
+impl MgmtCommand for ReadControllerInformation {
+    type Return = ControllerInformation;
+    const OPCODE: MgmtOpcode = MgmtOpcode::ReadControllerInformation;
+
+    fn byte_len(&self) -> usize {
+        0
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(0, buf)
+    }
+}
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct ControllerInformation {
+    pub address: BTAddress,
+    pub bluetooth_version: u8,
+    pub manufacturer: u16,
+    pub supported_settings: Settings,
+    pub current_settings: Settings,
+    pub class_of_device: [u8; 3],
+    pub name: String,
+    pub short_name: String,
+}
+impl ControllerInformation {
+    pub const NAME_LEN: usize = 249;
+    pub const SHORT_NAME_LEN: usize = 11;
+    pub const BYTE_LEN: usize = 6 + 1 + 2 + 4 + 4 + 3 + Self::NAME_LEN + Self::SHORT_NAME_LEN;
+}
+/// Names are NUL terminated UTF-8.
+fn unpack_name(buf: &[u8]) -> String {
+    let end = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    String::from_utf8_lossy(&buf[..end]).into_owned()
+}
+impl MgmtReturn for ControllerInformation {
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        let name_start = 20;
+        let short_name_start = name_start + Self::NAME_LEN;
+        Ok(ControllerInformation {
+            address: BTAddress::unpack_from(&buf[..6])?,
+            bluetooth_version: buf[6],
+            manufacturer: u16::from_le_bytes([buf[7], buf[8]]),
+            supported_settings: Settings::unpack_from(&buf[9..13])?,
+            current_settings: Settings::unpack_from(&buf[13..17])?,
+            class_of_device: [buf[17], buf[18], buf[19]],
+            name: unpack_name(&buf[name_start..short_name_start]),
+            short_name: unpack_name(&buf[short_name_start..]),
+        })
+    }
+}
+macro_rules! bool_setting_command {
+    ($name:ident, $opcode:ident) => {
+        #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash, Default)]
+        pub struct $name {
+            pub is_enabled: bool,
+        }
+        impl MgmtCommand for $name {
+            type Return = Settings;
+            const OPCODE: MgmtOpcode = MgmtOpcode::$opcode;
+
+            fn byte_len(&self) -> usize {
+                1
+            }
+
+            fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+                PackError::expect_length(1, buf)?;
+                buf[0] = self.is_enabled.into();
+                Ok(())
+            }
+        }
+    };
+}
+bool_setting_command!(SetPowered, SetPowered);
+bool_setting_command!(SetConnectable, SetConnectable);
+bool_setting_command!(SetBondable, SetBondable);
+bool_setting_command!(SetLE, SetLE);
+
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+#[repr(u8)]
+pub enum AdvertisingSetting {
+    Disabled = 0x00,
+    Enabled = 0x01,
+    /// Advertise as connectable even if the `Connectable` setting is off.
+    Connectable = 0x02,
+}
+impl From<AdvertisingSetting> for u8 {
+    fn from(s: AdvertisingSetting) -> Self {
+        s as u8
+    }
+}
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct SetAdvertising {
+    pub setting: AdvertisingSetting,
+}
+impl MgmtCommand for SetAdvertising {
+    type Return = Settings;
+    const OPCODE: MgmtOpcode = MgmtOpcode::SetAdvertising;
+
+    fn byte_len(&self) -> usize {
+        1
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(1, buf)?;
+        buf[0] = self.setting.into();
+        Ok(())
+    }
+}
+/// `AddAdvertising` flags.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash, Default)]
+pub struct AdvertisingFlags(pub u32);
+impl AdvertisingFlags {
+    pub const CONNECTABLE: AdvertisingFlags = AdvertisingFlags(1 << 0);
+    pub const DISCOVERABLE: AdvertisingFlags = AdvertisingFlags(1 << 1);
+    pub const LIMITED_DISCOVERABLE: AdvertisingFlags = AdvertisingFlags(1 << 2);
+    pub const MANAGED_FLAGS: AdvertisingFlags = AdvertisingFlags(1 << 3);
+    pub const TX_POWER: AdvertisingFlags = AdvertisingFlags(1 << 4);
+    pub const APPEARANCE: AdvertisingFlags = AdvertisingFlags(1 << 5);
+    pub const LOCAL_NAME: AdvertisingFlags = AdvertisingFlags(1 << 6);
+}
+impl core::ops::BitOr for AdvertisingFlags {
+    type Output = AdvertisingFlags;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        AdvertisingFlags(self.0 | rhs.0)
+    }
+}
+/// Adds (or replaces) an advertising instance. `instance` starts at 1. `duration` and `timeout`
+/// are in seconds (0 for the defaults).
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash, Default)]
+pub struct AddAdvertising {
+    pub instance: u8,
+    pub flags: AdvertisingFlags,
+    pub duration: u16,
+    pub timeout: u16,
+    pub advertising_data: Vec<u8>,
+    pub scan_response_data: Vec<u8>,
+}
+impl AddAdvertising {
+    pub const HEADER_LEN: usize = 11;
+}
+impl MgmtCommand for AddAdvertising {
+    type Return = AdvertisingInstance;
+    const OPCODE: MgmtOpcode = MgmtOpcode::AddAdvertising;
+
+    fn byte_len(&self) -> usize {
+        Self::HEADER_LEN + self.advertising_data.len() + self.scan_response_data.len()
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(self.byte_len(), buf)?;
+        let adv_len =
+            u8::try_from(self.advertising_data.len()).map_err(|_| PackError::InvalidFields)?;
+        let scan_rsp_len =
+            u8::try_from(self.scan_response_data.len()).map_err(|_| PackError::InvalidFields)?;
+        buf[0] = self.instance;
+        buf[1..5].copy_from_slice(&self.flags.0.to_le_bytes());
+        buf[5..7].copy_from_slice(&self.duration.to_le_bytes());
+        buf[7..9].copy_from_slice(&self.timeout.to_le_bytes());
+        buf[9] = adv_len;
+        buf[10] = scan_rsp_len;
+        let adv_end = Self::HEADER_LEN + self.advertising_data.len();
+        buf[Self::HEADER_LEN..adv_end].copy_from_slice(&self.advertising_data);
+        buf[adv_end..].copy_from_slice(&self.scan_response_data);
+        Ok(())
+    }
+}
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash, Default)]
+pub struct AdvertisingInstance(pub u8);
+impl MgmtReturn for AdvertisingInstance {
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError> {
+        PackError::expect_length(1, buf)?;
+        Ok(AdvertisingInstance(buf[0]))
+    }
+}
+/// Removes advertising instance `instance` or every instance if `instance == 0`.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash, Default)]
+pub struct RemoveAdvertising {
+    pub instance: u8,
+}
+impl MgmtCommand for RemoveAdvertising {
+    type Return = AdvertisingInstance;
+    const OPCODE: MgmtOpcode = MgmtOpcode::RemoveAdvertising;
+
+    fn byte_len(&self) -> usize {
+        1
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(1, buf)?;
+        buf[0] = self.instance;
+        Ok(())
+    }
+}
+macro_rules! discovery_command {
+    ($name:ident, $opcode:ident) => {
+        #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash, Default)]
+        pub struct $name {
+            pub address_types: MgmtAddressTypes,
+        }
+        impl MgmtCommand for $name {
+            type Return = MgmtAddressTypes;
+            const OPCODE: MgmtOpcode = MgmtOpcode::$opcode;
+
+            fn byte_len(&self) -> usize {
+                1
+            }
+
+            fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+                PackError::expect_length(1, buf)?;
+                buf[0] = self.address_types.0;
+                Ok(())
+            }
+        }
+    };
+}
+discovery_command!(StartDiscovery, StartDiscovery);
+discovery_command!(StopDiscovery, StopDiscovery);
+impl MgmtReturn for MgmtAddressTypes {
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError> {
+        PackError::expect_length(1, buf)?;
+        Ok(MgmtAddressTypes(buf[0]))
+    }
+}
+impl MgmtReturn for MgmtAddress {
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError> {
+        MgmtAddress::unpack_from(buf)
+    }
+}
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+#[repr(u8)]
+pub enum IOCapability {
+    DisplayOnly = 0x00,
+    DisplayYesNo = 0x01,
+    KeyboardOnly = 0x02,
+    NoInputNoOutput = 0x03,
+    KeyboardDisplay = 0x04,
+}
+impl From<IOCapability> for u8 {
+    fn from(c: IOCapability) -> Self {
+        c as u8
+    }
+}
+/// Pairs with a remote device. The `CommandComplete` only arrives once pairing is done (or
+/// failed).
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct PairDevice {
+    pub address: MgmtAddress,
+    pub io_capability: IOCapability,
+}
+impl MgmtCommand for PairDevice {
+    type Return = MgmtAddress;
+    const OPCODE: MgmtOpcode = MgmtOpcode::PairDevice;
+
+    fn byte_len(&self) -> usize {
+        MgmtAddress::BYTE_LEN + 1
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(self.byte_len(), buf)?;
+        self.address.pack_into(&mut buf[..MgmtAddress::BYTE_LEN])?;
+        buf[MgmtAddress::BYTE_LEN] = self.io_capability.into();
+        Ok(())
+    }
+}
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+#[repr(u8)]
+pub enum LongTermKeyType {
+    Unauthenticated = 0x00,
+    Authenticated = 0x01,
+    UnauthenticatedP256 = 0x02,
+    AuthenticatedP256 = 0x03,
+    DebugP256 = 0x04,
+}
+impl From<LongTermKeyType> for u8 {
+    fn from(t: LongTermKeyType) -> Self {
+        t as u8
+    }
+}
+impl TryFrom<u8> for LongTermKeyType {
+    type Error = ConversionError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x00 => Ok(LongTermKeyType::Unauthenticated),
+            0x01 => Ok(LongTermKeyType::Authenticated),
+            0x02 => Ok(LongTermKeyType::UnauthenticatedP256),
+            0x03 => Ok(LongTermKeyType::AuthenticatedP256),
+            0x04 => Ok(LongTermKeyType::DebugP256),
+            _ => Err(ConversionError(())),
+        }
+    }
+}
+/// LE Long Term Key. `is_master` is `true` if the key is used when connecting as master.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct LongTermKey {
+    pub address: MgmtAddress,
+    pub key_type: LongTermKeyType,
+    pub is_master: bool,
+    pub encryption_size: u8,
+    pub ediv: u16,
+    pub rand: [u8; 8],
+    pub value: [u8; 16],
+}
+impl LongTermKey {
+    pub const BYTE_LEN: usize = MgmtAddress::BYTE_LEN + 1 + 1 + 1 + 2 + 8 + 16;
+    pub fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        self.address.pack_into(&mut buf[..7])?;
+        buf[7] = self.key_type.into();
+        buf[8] = self.is_master.into();
+        buf[9] = self.encryption_size;
+        buf[10..12].copy_from_slice(&self.ediv.to_le_bytes());
+        buf[12..20].copy_from_slice(&self.rand);
+        buf[20..36].copy_from_slice(&self.value);
+        Ok(())
+    }
+    pub fn unpack_from(buf: &[u8]) -> Result<Self, PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        Ok(LongTermKey {
+            address: MgmtAddress::unpack_from(&buf[..7])?,
+            key_type: LongTermKeyType::try_from(buf[7]).map_err(|_| PackError::bad_index(7))?,
+            is_master: buf[8] != 0,
+            encryption_size: buf[9],
+            ediv: u16::from_le_bytes([buf[10], buf[11]]),
+            rand: buf[12..20].try_into().expect("length checked above"),
+            value: buf[20..36].try_into().expect("length checked above"),
+        })
+    }
+}
+/// Replaces every Long Term Key the kernel knows with `keys`.
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash, Default)]
+pub struct LoadLongTermKeys {
+    pub keys: Vec<LongTermKey>,
+}
+impl MgmtCommand for LoadLongTermKeys {
+    type Return = ();
+    const OPCODE: MgmtOpcode = MgmtOpcode::LoadLongTermKeys;
+
+    fn byte_len(&self) -> usize {
+        2 + self.keys.len() * LongTermKey::BYTE_LEN
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(self.byte_len(), buf)?;
+        let count = u16::try_from(self.keys.len()).map_err(|_| PackError::InvalidFields)?;
+        buf[..2].copy_from_slice(&count.to_le_bytes());
+        for (key, key_buf) in self
+            .keys
+            .iter()
+            .zip(buf[2..].chunks_exact_mut(LongTermKey::BYTE_LEN))
+        {
+            key.pack_into(key_buf)?;
+        }
+        Ok(())
+    }
+}
+/// LE Identity Resolving Key of a peer.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct IdentityResolvingKey {
+    pub address: MgmtAddress,
+    pub value: [u8; 16],
+}
+impl IdentityResolvingKey {
+    pub const BYTE_LEN: usize = MgmtAddress::BYTE_LEN + 16;
+    pub fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        self.address.pack_into(&mut buf[..7])?;
+        buf[7..].copy_from_slice(&self.value);
+        Ok(())
+    }
+    pub fn unpack_from(buf: &[u8]) -> Result<Self, PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        Ok(IdentityResolvingKey {
+            address: MgmtAddress::unpack_from(&buf[..7])?,
+            value: buf[7..].try_into().expect("length checked above"),
+        })
+    }
+}
+/// Replaces every Identity Resolving Key the kernel knows with `keys`.
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash, Default)]
+pub struct LoadIdentityResolvingKeys {
+    pub keys: Vec<IdentityResolvingKey>,
+}
+impl MgmtCommand for LoadIdentityResolvingKeys {
+    type Return = ();
+    const OPCODE: MgmtOpcode = MgmtOpcode::LoadIdentityResolvingKeys;
+
+    fn byte_len(&self) -> usize {
+        2 + self.keys.len() * IdentityResolvingKey::BYTE_LEN
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(self.byte_len(), buf)?;
+        let count = u16::try_from(self.keys.len()).map_err(|_| PackError::InvalidFields)?;
+        buf[..2].copy_from_slice(&count.to_le_bytes());
+        for (key, key_buf) in self
+            .keys
+            .iter()
+            .zip(buf[2..].chunks_exact_mut(IdentityResolvingKey::BYTE_LEN))
+        {
+            key.pack_into(key_buf)?;
+        }
+        Ok(())
+    }
+}
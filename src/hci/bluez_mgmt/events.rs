@@ -0,0 +1,337 @@
+//! Mgmt events. Sent by the kernel on the control channel as replies to commands or whenever a
+//! controller changes.
+use crate::hci::bluez_mgmt::commands::{
+    IdentityResolvingKey, LongTermKey, MgmtAddress, MgmtAddressTypes, Settings,
+};
+use crate::hci::bluez_mgmt::{ControllerIndex, MgmtHeader, MgmtStatus, HEADER_LEN};
+use crate::{ConversionError, PackError, RSSI};
+use core::convert::TryFrom;
+
+/// Mgmt event codes.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+#[repr(u16)]
+pub enum MgmtEventCode {
+    CommandComplete = 0x0001,
+    CommandStatus = 0x0002,
+    ControllerError = 0x0003,
+    IndexAdded = 0x0004,
+    IndexRemoved = 0x0005,
+    NewSettings = 0x0006,
+    NewLongTermKey = 0x000A,
+    DeviceConnected = 0x000B,
+    DeviceDisconnected = 0x000C,
+    ConnectFailed = 0x000D,
+    UserConfirmationRequest = 0x000F,
+    AuthenticationFailed = 0x0011,
+    DeviceFound = 0x0012,
+    Discovering = 0x0013,
+    DeviceUnpaired = 0x0016,
+    PasskeyNotify = 0x0017,
+    NewIdentityResolvingKey = 0x0018,
+    AdvertisingAdded = 0x0023,
+    AdvertisingRemoved = 0x0024,
+}
+impl From<MgmtEventCode> for u16 {
+    fn from(c: MgmtEventCode) -> Self {
+        c as u16
+    }
+}
+impl TryFrom<u16> for MgmtEventCode {
+    type Error = ConversionError;
+
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
+        match value {
+            0x0001 => Ok(MgmtEventCode::CommandComplete),
+            0x0002 => Ok(MgmtEventCode::CommandStatus),
+            0x0003 => Ok(MgmtEventCode::ControllerError),
+            0x0004 => Ok(MgmtEventCode::IndexAdded),
+            0x0005 => Ok(MgmtEventCode::IndexRemoved),
+            0x0006 => Ok(MgmtEventCode::NewSettings),
+            0x000A => Ok(MgmtEventCode::NewLongTermKey),
+            0x000B => Ok(MgmtEventCode::DeviceConnected),
+            0x000C => Ok(MgmtEventCode::DeviceDisconnected),
+            0x000D => Ok(MgmtEventCode::ConnectFailed),
+            0x000F => Ok(MgmtEventCode::UserConfirmationRequest),
+            0x0011 => Ok(MgmtEventCode::AuthenticationFailed),
+            0x0012 => Ok(MgmtEventCode::DeviceFound),
+            0x0013 => Ok(MgmtEventCode::Discovering),
+            0x0016 => Ok(MgmtEventCode::DeviceUnpaired),
+            0x0017 => Ok(MgmtEventCode::PasskeyNotify),
+            0x0018 => Ok(MgmtEventCode::NewIdentityResolvingKey),
+            0x0023 => Ok(MgmtEventCode::AdvertisingAdded),
+            0x0024 => Ok(MgmtEventCode::AdvertisingRemoved),
+            _ => Err(ConversionError(())),
+        }
+    }
+}
+/// Parsed mgmt event. Events this module doesn't parse are returned as `Other` with the raw
+/// parameters.
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub enum MgmtEvent {
+    CommandComplete {
+        opcode: u16,
+        status: MgmtStatus,
+        parameters: Box<[u8]>,
+    },
+    CommandStatus {
+        opcode: u16,
+        status: MgmtStatus,
+    },
+    /// Error code from the controller (HCI error code).
+    ControllerError(u8),
+    IndexAdded,
+    IndexRemoved,
+    NewSettings(Settings),
+    NewLongTermKey {
+        store_hint: bool,
+        key: LongTermKey,
+    },
+    DeviceConnected {
+        address: MgmtAddress,
+        flags: u32,
+        eir_data: Box<[u8]>,
+    },
+    DeviceDisconnected {
+        address: MgmtAddress,
+        reason: u8,
+    },
+    ConnectFailed {
+        address: MgmtAddress,
+        status: MgmtStatus,
+    },
+    UserConfirmationRequest {
+        address: MgmtAddress,
+        confirm_hint: bool,
+        value: u32,
+    },
+    AuthenticationFailed {
+        address: MgmtAddress,
+        status: MgmtStatus,
+    },
+    DeviceFound {
+        address: MgmtAddress,
+        /// `None` if the RSSI is unknown.
+        rssi: Option<RSSI>,
+        flags: u32,
+        eir_data: Box<[u8]>,
+    },
+    Discovering {
+        address_types: MgmtAddressTypes,
+        is_discovering: bool,
+    },
+    DeviceUnpaired(MgmtAddress),
+    PasskeyNotify {
+        address: MgmtAddress,
+        passkey: u32,
+        entered: u8,
+    },
+    NewIdentityResolvingKey {
+        store_hint: bool,
+        random_address: crate::BTAddress,
+        key: IdentityResolvingKey,
+    },
+    AdvertisingAdded(u8),
+    AdvertisingRemoved(u8),
+    Other {
+        code: u16,
+        parameters: Box<[u8]>,
+    },
+}
+fn status_at(buf: &[u8], index: usize) -> Result<MgmtStatus, PackError> {
+    MgmtStatus::try_from(buf[index]).map_err(|_| PackError::bad_index(index))
+}
+fn u16_at(buf: &[u8], index: usize) -> u16 {
+    u16::from_le_bytes([buf[index], buf[index + 1]])
+}
+fn u32_at(buf: &[u8], index: usize) -> u32 {
+    u32::from_le_bytes([buf[index], buf[index + 1], buf[index + 2], buf[index + 3]])
+}
+/// Unpacks `[flags(4), eir_len(2), eir]` shared by `DeviceConnected` (after the address) and
+/// `DeviceFound` (after the address and RSSI).
+fn unpack_eir(buf: &[u8]) -> Result<(u32, Box<[u8]>), PackError> {
+    PackError::atleast_length(6, buf)?;
+    let eir_len = usize::from(u16_at(buf, 4));
+    PackError::expect_length(6 + eir_len, buf)?;
+    Ok((u32_at(buf, 0), buf[6..].into()))
+}
+impl MgmtEvent {
+    pub fn code(&self) -> u16 {
+        match self {
+            MgmtEvent::CommandComplete { .. } => MgmtEventCode::CommandComplete.into(),
+            MgmtEvent::CommandStatus { .. } => MgmtEventCode::CommandStatus.into(),
+            MgmtEvent::ControllerError(_) => MgmtEventCode::ControllerError.into(),
+            MgmtEvent::IndexAdded => MgmtEventCode::IndexAdded.into(),
+            MgmtEvent::IndexRemoved => MgmtEventCode::IndexRemoved.into(),
+            MgmtEvent::NewSettings(_) => MgmtEventCode::NewSettings.into(),
+            MgmtEvent::NewLongTermKey { .. } => MgmtEventCode::NewLongTermKey.into(),
+            MgmtEvent::DeviceConnected { .. } => MgmtEventCode::DeviceConnected.into(),
+            MgmtEvent::DeviceDisconnected { .. } => MgmtEventCode::DeviceDisconnected.into(),
+            MgmtEvent::ConnectFailed { .. } => MgmtEventCode::ConnectFailed.into(),
+            MgmtEvent::UserConfirmationRequest { .. } => {
+                MgmtEventCode::UserConfirmationRequest.into()
+            }
+            MgmtEvent::AuthenticationFailed { .. } => MgmtEventCode::AuthenticationFailed.into(),
+            MgmtEvent::DeviceFound { .. } => MgmtEventCode::DeviceFound.into(),
+            MgmtEvent::Discovering { .. } => MgmtEventCode::Discovering.into(),
+            MgmtEvent::DeviceUnpaired(_) => MgmtEventCode::DeviceUnpaired.into(),
+            MgmtEvent::PasskeyNotify { .. } => MgmtEventCode::PasskeyNotify.into(),
+            MgmtEvent::NewIdentityResolvingKey { .. } => {
+                MgmtEventCode::NewIdentityResolvingKey.into()
+            }
+            MgmtEvent::AdvertisingAdded(_) => MgmtEventCode::AdvertisingAdded.into(),
+            MgmtEvent::AdvertisingRemoved(_) => MgmtEventCode::AdvertisingRemoved.into(),
+            MgmtEvent::Other { code, .. } => *code,
+        }
+    }
+    /// Unpacks the event parameters of an event with event code `code`.
+    pub fn unpack_from(code: u16, buf: &[u8]) -> Result<MgmtEvent, PackError> {
+        const A: usize = MgmtAddress::BYTE_LEN;
+        let code = match MgmtEventCode::try_from(code) {
+            Ok(code) => code,
+            Err(_) => {
+                return Ok(MgmtEvent::Other {
+                    code,
+                    parameters: buf.into(),
+                })
+            }
+        };
+        Ok(match code {
+            MgmtEventCode::CommandComplete => {
+                PackError::atleast_length(3, buf)?;
+                MgmtEvent::CommandComplete {
+                    opcode: u16_at(buf, 0),
+                    status: status_at(buf, 2)?,
+                    parameters: buf[3..].into(),
+                }
+            }
+            MgmtEventCode::CommandStatus => {
+                PackError::expect_length(3, buf)?;
+                MgmtEvent::CommandStatus {
+                    opcode: u16_at(buf, 0),
+                    status: status_at(buf, 2)?,
+                }
+            }
+            MgmtEventCode::ControllerError => {
+                PackError::expect_length(1, buf)?;
+                MgmtEvent::ControllerError(buf[0])
+            }
+            MgmtEventCode::IndexAdded => {
+                PackError::expect_length(0, buf)?;
+                MgmtEvent::IndexAdded
+            }
+            MgmtEventCode::IndexRemoved => {
+                PackError::expect_length(0, buf)?;
+                MgmtEvent::IndexRemoved
+            }
+            MgmtEventCode::NewSettings => MgmtEvent::NewSettings(Settings::unpack_from(buf)?),
+            MgmtEventCode::NewLongTermKey => {
+                PackError::expect_length(1 + LongTermKey::BYTE_LEN, buf)?;
+                MgmtEvent::NewLongTermKey {
+                    store_hint: buf[0] != 0,
+                    key: LongTermKey::unpack_from(&buf[1..])?,
+                }
+            }
+            MgmtEventCode::DeviceConnected => {
+                PackError::atleast_length(A, buf)?;
+                let (flags, eir_data) = unpack_eir(&buf[A..])?;
+                MgmtEvent::DeviceConnected {
+                    address: MgmtAddress::unpack_from(&buf[..A])?,
+                    flags,
+                    eir_data,
+                }
+            }
+            MgmtEventCode::DeviceDisconnected => {
+                PackError::expect_length(A + 1, buf)?;
+                MgmtEvent::DeviceDisconnected {
+                    address: MgmtAddress::unpack_from(&buf[..A])?,
+                    reason: buf[A],
+                }
+            }
+            MgmtEventCode::ConnectFailed => {
+                PackError::expect_length(A + 1, buf)?;
+                MgmtEvent::ConnectFailed {
+                    address: MgmtAddress::unpack_from(&buf[..A])?,
+                    status: status_at(buf, A)?,
+                }
+            }
+            MgmtEventCode::UserConfirmationRequest => {
+                PackError::expect_length(A + 5, buf)?;
+                MgmtEvent::UserConfirmationRequest {
+                    address: MgmtAddress::unpack_from(&buf[..A])?,
+                    confirm_hint: buf[A] != 0,
+                    value: u32_at(buf, A + 1),
+                }
+            }
+            MgmtEventCode::AuthenticationFailed => {
+                PackError::expect_length(A + 1, buf)?;
+                MgmtEvent::AuthenticationFailed {
+                    address: MgmtAddress::unpack_from(&buf[..A])?,
+                    status: status_at(buf, A)?,
+                }
+            }
+            MgmtEventCode::DeviceFound => {
+                PackError::atleast_length(A + 1, buf)?;
+                let (flags, eir_data) = unpack_eir(&buf[A + 1..])?;
+                MgmtEvent::DeviceFound {
+                    address: MgmtAddress::unpack_from(&buf[..A])?,
+                    rssi: RSSI::try_from(buf[A]).ok(),
+                    flags,
+                    eir_data,
+                }
+            }
+            MgmtEventCode::Discovering => {
+                PackError::expect_length(2, buf)?;
+                MgmtEvent::Discovering {
+                    address_types: MgmtAddressTypes(buf[0]),
+                    is_discovering: buf[1] != 0,
+                }
+            }
+            MgmtEventCode::DeviceUnpaired => {
+                MgmtEvent::DeviceUnpaired(MgmtAddress::unpack_from(buf)?)
+            }
+            MgmtEventCode::PasskeyNotify => {
+                PackError::expect_length(A + 5, buf)?;
+                MgmtEvent::PasskeyNotify {
+                    address: MgmtAddress::unpack_from(&buf[..A])?,
+                    passkey: u32_at(buf, A),
+                    entered: buf[A + 4],
+                }
+            }
+            MgmtEventCode::NewIdentityResolvingKey => {
+                PackError::expect_length(1 + 6 + IdentityResolvingKey::BYTE_LEN, buf)?;
+                MgmtEvent::NewIdentityResolvingKey {
+                    store_hint: buf[0] != 0,
+                    random_address: crate::BTAddress::unpack_from(&buf[1..7])?,
+                    key: IdentityResolvingKey::unpack_from(&buf[7..])?,
+                }
+            }
+            MgmtEventCode::AdvertisingAdded => {
+                PackError::expect_length(1, buf)?;
+                MgmtEvent::AdvertisingAdded(buf[0])
+            }
+            MgmtEventCode::AdvertisingRemoved => {
+                PackError::expect_length(1, buf)?;
+                MgmtEvent::AdvertisingRemoved(buf[0])
+            }
+        })
+    }
+}
+/// Mgmt event with the index of the controller it came from.
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct MgmtEventPacket {
+    pub index: ControllerIndex,
+    pub event: MgmtEvent,
+}
+impl MgmtEventPacket {
+    /// Unpacks a whole event packet (header and parameters).
+    pub fn unpack_from(buf: &[u8]) -> Result<MgmtEventPacket, PackError> {
+        PackError::atleast_length(HEADER_LEN, buf)?;
+        let header = MgmtHeader::unpack_from(&buf[..HEADER_LEN])?;
+        let parameters = &buf[HEADER_LEN..];
+        PackError::expect_length(usize::from(header.len), parameters)?;
+        Ok(MgmtEventPacket {
+            index: header.index,
+            event: MgmtEvent::unpack_from(header.code, parameters)?,
+        })
+    }
+}
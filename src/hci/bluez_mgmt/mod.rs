@@ -0,0 +1,298 @@
+//! BlueZ Management (mgmt) API. Controls the adapters through the kernel over the
+//! `HCIChannel::Control` socket instead of taking exclusive access to them like the `Raw` and
+//! `User` channels do. Lets the host cooperate with `bluetoothd`.
+//!
+//! Every mgmt packet has a 6 byte header (opcode or event code, controller index and parameter
+//! length, all Little Endian) followed by the parameters.
+pub mod client;
+pub mod commands;
+pub mod events;
+
+pub use client::MgmtClient;
+
+use crate::error::IOError;
+use crate::{ConversionError, PackError};
+use core::convert::TryFrom;
+
+/// Mgmt packet header length.
+pub const HEADER_LEN: usize = 6;
+/// Max mgmt packet parameters length.
+pub const MAX_PARAMETERS_LEN: usize = u16::MAX as usize;
+
+/// Index of the controller a mgmt packet is for. `ControllerIndex::NONE` for packets not about a
+/// controller (ex: `ReadControllerIndexList`).
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash, Default)]
+pub struct ControllerIndex(pub u16);
+impl ControllerIndex {
+    pub const NONE: ControllerIndex = ControllerIndex(0xFFFF);
+}
+impl From<ControllerIndex> for u16 {
+    fn from(i: ControllerIndex) -> Self {
+        i.0
+    }
+}
+/// Mgmt command opcodes.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+#[repr(u16)]
+pub enum MgmtOpcode {
+    ReadVersionInformation = 0x0001,
+    ReadControllerIndexList = 0x0003,
+    ReadControllerInformation = 0x0004,
+    SetPowered = 0x0005,
+    SetDiscoverable = 0x0006,
+    SetConnectable = 0x0007,
+    SetBondable = 0x0009,
+    SetLE = 0x000D,
+    LoadLongTermKeys = 0x0013,
+    Disconnect = 0x0014,
+    PairDevice = 0x0019,
+    CancelPairDevice = 0x001A,
+    UnpairDevice = 0x001B,
+    UserConfirmationReply = 0x001C,
+    UserConfirmationNegativeReply = 0x001D,
+    StartDiscovery = 0x0023,
+    StopDiscovery = 0x0024,
+    SetAdvertising = 0x0029,
+    LoadIdentityResolvingKeys = 0x0030,
+    AddAdvertising = 0x003E,
+    RemoveAdvertising = 0x003F,
+}
+impl From<MgmtOpcode> for u16 {
+    fn from(o: MgmtOpcode) -> Self {
+        o as u16
+    }
+}
+impl TryFrom<u16> for MgmtOpcode {
+    type Error = ConversionError;
+
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
+        match value {
+            0x0001 => Ok(MgmtOpcode::ReadVersionInformation),
+            0x0003 => Ok(MgmtOpcode::ReadControllerIndexList),
+            0x0004 => Ok(MgmtOpcode::ReadControllerInformation),
+            0x0005 => Ok(MgmtOpcode::SetPowered),
+            0x0006 => Ok(MgmtOpcode::SetDiscoverable),
+            0x0007 => Ok(MgmtOpcode::SetConnectable),
+            0x0009 => Ok(MgmtOpcode::SetBondable),
+            0x000D => Ok(MgmtOpcode::SetLE),
+            0x0013 => Ok(MgmtOpcode::LoadLongTermKeys),
+            0x0014 => Ok(MgmtOpcode::Disconnect),
+            0x0019 => Ok(MgmtOpcode::PairDevice),
+            0x001A => Ok(MgmtOpcode::CancelPairDevice),
+            0x001B => Ok(MgmtOpcode::UnpairDevice),
+            0x001C => Ok(MgmtOpcode::UserConfirmationReply),
+            0x001D => Ok(MgmtOpcode::UserConfirmationNegativeReply),
+            0x0023 => Ok(MgmtOpcode::StartDiscovery),
+            0x0024 => Ok(MgmtOpcode::StopDiscovery),
+            0x0029 => Ok(MgmtOpcode::SetAdvertising),
+            0x0030 => Ok(MgmtOpcode::LoadIdentityResolvingKeys),
+            0x003E => Ok(MgmtOpcode::AddAdvertising),
+            0x003F => Ok(MgmtOpcode::RemoveAdvertising),
+            _ => Err(ConversionError(())),
+        }
+    }
+}
+/// Status returned in the `CommandComplete` and `CommandStatus` mgmt events.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+#[repr(u8)]
+pub enum MgmtStatus {
+    Success = 0x00,
+    UnknownCommand = 0x01,
+    NotConnected = 0x02,
+    Failed = 0x03,
+    ConnectFailed = 0x04,
+    AuthenticationFailed = 0x05,
+    NotPaired = 0x06,
+    NoResources = 0x07,
+    Timeout = 0x08,
+    AlreadyConnected = 0x09,
+    Busy = 0x0A,
+    Rejected = 0x0B,
+    NotSupported = 0x0C,
+    InvalidParameters = 0x0D,
+    Disconnected = 0x0E,
+    NotPowered = 0x0F,
+    Cancelled = 0x10,
+    InvalidIndex = 0x11,
+    RFKilled = 0x12,
+    AlreadyPaired = 0x13,
+    PermissionDenied = 0x14,
+}
+impl MgmtStatus {
+    /// Returns `Ok(())` if `Success` or `Err(MgmtError::Status(self))` if not.
+    pub fn error(self) -> Result<(), MgmtError> {
+        match self {
+            MgmtStatus::Success => Ok(()),
+            status => Err(MgmtError::Status(status)),
+        }
+    }
+}
+impl From<MgmtStatus> for u8 {
+    fn from(s: MgmtStatus) -> Self {
+        s as u8
+    }
+}
+impl TryFrom<u8> for MgmtStatus {
+    type Error = ConversionError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x00 => Ok(MgmtStatus::Success),
+            0x01 => Ok(MgmtStatus::UnknownCommand),
+            0x02 => Ok(MgmtStatus::NotConnected),
+            0x03 => Ok(MgmtStatus::Failed),
+            0x04 => Ok(MgmtStatus::ConnectFailed),
+            0x05 => Ok(MgmtStatus::AuthenticationFailed),
+            0x06 => Ok(MgmtStatus::NotPaired),
+            0x07 => Ok(MgmtStatus::NoResources),
+            0x08 => Ok(MgmtStatus::Timeout),
+            0x09 => Ok(MgmtStatus::AlreadyConnected),
+            0x0A => Ok(MgmtStatus::Busy),
+            0x0B => Ok(MgmtStatus::Rejected),
+            0x0C => Ok(MgmtStatus::NotSupported),
+            0x0D => Ok(MgmtStatus::InvalidParameters),
+            0x0E => Ok(MgmtStatus::Disconnected),
+            0x0F => Ok(MgmtStatus::NotPowered),
+            0x10 => Ok(MgmtStatus::Cancelled),
+            0x11 => Ok(MgmtStatus::InvalidIndex),
+            0x12 => Ok(MgmtStatus::RFKilled),
+            0x13 => Ok(MgmtStatus::AlreadyPaired),
+            0x14 => Ok(MgmtStatus::PermissionDenied),
+            _ => Err(ConversionError(())),
+        }
+    }
+}
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub enum MgmtError {
+    IOError(IOError),
+    PackError(PackError),
+    Status(MgmtStatus),
+}
+impl core::fmt::Display for MgmtError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "bluez mgmt error {:?}", self)
+    }
+}
+impl crate::error::Error for MgmtError {}
+impl From<IOError> for MgmtError {
+    fn from(e: IOError) -> Self {
+        MgmtError::IOError(e)
+    }
+}
+impl From<PackError> for MgmtError {
+    fn from(e: PackError) -> Self {
+        MgmtError::PackError(e)
+    }
+}
+/// Return parameters of a `MgmtCommand` (in the `CommandComplete` event).
+pub trait MgmtReturn {
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized;
+}
+impl MgmtReturn for () {
+    fn unpack_from(_buf: &[u8]) -> Result<Self, PackError> {
+        // Some commands return the command parameters again. They are ignored.
+        Ok(())
+    }
+}
+/// Mgmt command. Similar to the HCI `Command` but with a mgmt opcode.
+pub trait MgmtCommand {
+    type Return: MgmtReturn;
+    const OPCODE: MgmtOpcode;
+    fn byte_len(&self) -> usize;
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError>;
+    /// Packs the command (header and parameters) into a new buffer.
+    fn pack_packet(&self, index: ControllerIndex) -> Result<Vec<u8>, PackError> {
+        let len = self.byte_len();
+        if len > MAX_PARAMETERS_LEN {
+            return Err(PackError::InvalidFields);
+        }
+        let mut buf = vec![0_u8; HEADER_LEN + len];
+        MgmtHeader {
+            code: Self::OPCODE.into(),
+            index,
+            len: len as u16,
+        }
+        .pack_into(&mut buf[..HEADER_LEN])?;
+        self.pack_into(&mut buf[HEADER_LEN..])?;
+        Ok(buf)
+    }
+}
+/// Header of every mgmt packet. `code` is the command opcode or event code.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct MgmtHeader {
+    pub code: u16,
+    pub index: ControllerIndex,
+    pub len: u16,
+}
+impl MgmtHeader {
+    pub const BYTE_LEN: usize = HEADER_LEN;
+    pub fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        buf[0..2].copy_from_slice(&self.code.to_le_bytes());
+        buf[2..4].copy_from_slice(&self.index.0.to_le_bytes());
+        buf[4..6].copy_from_slice(&self.len.to_le_bytes());
+        Ok(())
+    }
+    pub fn unpack_from(buf: &[u8]) -> Result<Self, PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        Ok(MgmtHeader {
+            code: u16::from_le_bytes([buf[0], buf[1]]),
+            index: ControllerIndex(u16::from_le_bytes([buf[2], buf[3]])),
+            len: u16::from_le_bytes([buf[4], buf[5]]),
+        })
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hci::bluez_mgmt::commands::{ReadControllerIndexList, SetPowered};
+    use crate::hci::bluez_mgmt::events::{MgmtEvent, MgmtEventPacket};
+
+    #[test]
+    fn header() {
+        let header = MgmtHeader {
+            code: MgmtOpcode::SetPowered.into(),
+            index: ControllerIndex(1),
+            len: 0x0102,
+        };
+        let bytes = [0x05, 0x00, 0x01, 0x00, 0x02, 0x01];
+        let mut buf = [0_u8; HEADER_LEN];
+        header.pack_into(&mut buf).unwrap();
+        assert_eq!(buf, bytes);
+        assert_eq!(MgmtHeader::unpack_from(&bytes), Ok(header));
+        assert!(MgmtHeader::unpack_from(&bytes[..HEADER_LEN - 1]).is_err());
+    }
+    #[test]
+    fn command_packets() {
+        assert_eq!(
+            SetPowered { is_enabled: true }.pack_packet(ControllerIndex(0)),
+            Ok(vec![0x05, 0x00, 0x00, 0x00, 0x01, 0x00, 0x01])
+        );
+        assert_eq!(
+            ReadControllerIndexList.pack_packet(ControllerIndex::NONE),
+            Ok(vec![0x03, 0x00, 0xFF, 0xFF, 0x00, 0x00])
+        );
+    }
+    #[test]
+    fn event_packet() {
+        // Command Complete for Set Powered, Success, current settings Powered | LE.
+        let bytes = [
+            0x01, 0x00, 0x00, 0x00, 0x07, 0x00, 0x05, 0x00, 0x00, 0x01, 0x02, 0x00, 0x00,
+        ];
+        assert_eq!(
+            MgmtEventPacket::unpack_from(&bytes),
+            Ok(MgmtEventPacket {
+                index: ControllerIndex(0),
+                event: MgmtEvent::CommandComplete {
+                    opcode: MgmtOpcode::SetPowered.into(),
+                    status: MgmtStatus::Success,
+                    parameters: vec![0x01, 0x02, 0x00, 0x00].into_boxed_slice(),
+                },
+            })
+        );
+        // Parameter length doesn't match the header.
+        assert!(MgmtEventPacket::unpack_from(&bytes[..bytes.len() - 1]).is_err());
+    }
+}
@@ -0,0 +1,508 @@
+//! BlueZ monitor channel (`HCIChannel::Monitor`). The kernel copies every HCI packet of every
+//! adapter (and some adapter index changes and log messages) to the monitor channel. Doesn't
+//! interfere with the adapters so it works as a passive tracer like `btmon`.
+//!
+//! Each monitor frame has a 6 byte header (opcode, adapter index and data length, all Little
+//! Endian) followed by the data. HCI packets don't include the packet type byte, the opcode tells
+//! the type and direction instead.
+use crate::error::IOError;
+use crate::hci::acl::ACLPacket;
+use crate::hci::bluez_socket::{handle_libc_error, AdapterID, HCIChannel, HCISocket};
+use crate::hci::command::CommandPacket;
+use crate::hci::event::EventPacket;
+use crate::hci::packet::{PacketDirection, PacketType, RawPacket};
+use crate::{BTAddress, ConversionError, PackError, Stream};
+use core::convert::TryFrom;
+use core::time::Duration;
+use std::os::unix::io::{AsRawFd, RawFd};
+
+/// Monitor frame header length.
+pub const HEADER_LEN: usize = 6;
+/// Max monitor frame data length.
+pub const MAX_DATA_LEN: usize = u16::MAX as usize;
+
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+#[repr(u16)]
+pub enum MonitorOpcode {
+    NewIndex = 0,
+    DeleteIndex = 1,
+    Command = 2,
+    Event = 3,
+    ACLTx = 4,
+    ACLRx = 5,
+    SCOTx = 6,
+    SCORx = 7,
+    OpenIndex = 8,
+    CloseIndex = 9,
+    IndexInfo = 10,
+    VendorDiagnostic = 11,
+    SystemNote = 12,
+    UserLogging = 13,
+    ControlOpen = 14,
+    ControlClose = 15,
+    ControlCommand = 16,
+    ControlEvent = 17,
+    ISOTx = 18,
+    ISORx = 19,
+}
+impl From<MonitorOpcode> for u16 {
+    fn from(o: MonitorOpcode) -> Self {
+        o as u16
+    }
+}
+impl TryFrom<u16> for MonitorOpcode {
+    type Error = ConversionError;
+
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(MonitorOpcode::NewIndex),
+            1 => Ok(MonitorOpcode::DeleteIndex),
+            2 => Ok(MonitorOpcode::Command),
+            3 => Ok(MonitorOpcode::Event),
+            4 => Ok(MonitorOpcode::ACLTx),
+            5 => Ok(MonitorOpcode::ACLRx),
+            6 => Ok(MonitorOpcode::SCOTx),
+            7 => Ok(MonitorOpcode::SCORx),
+            8 => Ok(MonitorOpcode::OpenIndex),
+            9 => Ok(MonitorOpcode::CloseIndex),
+            10 => Ok(MonitorOpcode::IndexInfo),
+            11 => Ok(MonitorOpcode::VendorDiagnostic),
+            12 => Ok(MonitorOpcode::SystemNote),
+            13 => Ok(MonitorOpcode::UserLogging),
+            14 => Ok(MonitorOpcode::ControlOpen),
+            15 => Ok(MonitorOpcode::ControlClose),
+            16 => Ok(MonitorOpcode::ControlCommand),
+            17 => Ok(MonitorOpcode::ControlEvent),
+            18 => Ok(MonitorOpcode::ISOTx),
+            19 => Ok(MonitorOpcode::ISORx),
+            _ => Err(ConversionError(())),
+        }
+    }
+}
+/// Decoded monitor frame data.
+#[derive(Debug)]
+pub enum MonitorPacket {
+    NewIndex {
+        adapter_type: u8,
+        bus: u8,
+        address: BTAddress,
+        name: String,
+    },
+    DeleteIndex,
+    OpenIndex,
+    CloseIndex,
+    IndexInfo {
+        address: BTAddress,
+        manufacturer: u16,
+    },
+    Command(CommandPacket<Box<[u8]>>),
+    Event(EventPacket<Box<[u8]>>),
+    ACL(PacketDirection, ACLPacket<Box<[u8]>>),
+    SCO(PacketDirection, Box<[u8]>),
+    ISO(PacketDirection, Box<[u8]>),
+    SystemNote(String),
+    UserLogging {
+        priority: u8,
+        ident: String,
+        message: String,
+    },
+    /// Frames not decoded (vendor diagnostics, control channel tracing, etc).
+    Other(Box<[u8]>),
+}
+/// Strings in monitor frames are NUL terminated (or fill the whole field).
+fn unpack_str(buf: &[u8]) -> String {
+    let end = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    String::from_utf8_lossy(&buf[..end]).into_owned()
+}
+impl MonitorPacket {
+    pub const NEW_INDEX_LEN: usize = 16;
+    pub const INDEX_INFO_LEN: usize = 8;
+    pub fn unpack_from(opcode: MonitorOpcode, buf: &[u8]) -> Result<MonitorPacket, PackError> {
+        let raw = |packet_type| RawPacket { packet_type, buf };
+        Ok(match opcode {
+            MonitorOpcode::NewIndex => {
+                PackError::expect_length(Self::NEW_INDEX_LEN, buf)?;
+                MonitorPacket::NewIndex {
+                    adapter_type: buf[0],
+                    bus: buf[1],
+                    address: BTAddress::unpack_from(&buf[2..8])?,
+                    name: unpack_str(&buf[8..]),
+                }
+            }
+            MonitorOpcode::DeleteIndex => MonitorPacket::DeleteIndex,
+            MonitorOpcode::OpenIndex => MonitorPacket::OpenIndex,
+            MonitorOpcode::CloseIndex => MonitorPacket::CloseIndex,
+            MonitorOpcode::IndexInfo => {
+                PackError::expect_length(Self::INDEX_INFO_LEN, buf)?;
+                MonitorPacket::IndexInfo {
+                    address: BTAddress::unpack_from(&buf[..6])?,
+                    manufacturer: u16::from_le_bytes([buf[6], buf[7]]),
+                }
+            }
+            MonitorOpcode::Command => {
+                let packet = CommandPacket::try_from(raw(PacketType::Command))?;
+                MonitorPacket::Command(CommandPacket {
+                    opcode: packet.opcode,
+                    parameters: packet.parameters.into(),
+                })
+            }
+            MonitorOpcode::Event => MonitorPacket::Event(
+                EventPacket::try_from(raw(PacketType::Event))?.to_new_storage(),
+            ),
+            MonitorOpcode::ACLTx | MonitorOpcode::ACLRx => MonitorPacket::ACL(
+                if opcode == MonitorOpcode::ACLTx {
+                    PacketDirection::Tx
+                } else {
+                    PacketDirection::Rx
+                },
+                ACLPacket::unpack_from(buf)?.to_new_storage(),
+            ),
+            MonitorOpcode::SCOTx => MonitorPacket::SCO(PacketDirection::Tx, buf.into()),
+            MonitorOpcode::SCORx => MonitorPacket::SCO(PacketDirection::Rx, buf.into()),
+            MonitorOpcode::ISOTx => MonitorPacket::ISO(PacketDirection::Tx, buf.into()),
+            MonitorOpcode::ISORx => MonitorPacket::ISO(PacketDirection::Rx, buf.into()),
+            MonitorOpcode::SystemNote => MonitorPacket::SystemNote(unpack_str(buf)),
+            MonitorOpcode::UserLogging => {
+                PackError::atleast_length(2, buf)?;
+                let ident_end = 2 + usize::from(buf[1]);
+                PackError::atleast_length(ident_end, buf)?;
+                MonitorPacket::UserLogging {
+                    priority: buf[0],
+                    ident: unpack_str(&buf[2..ident_end]),
+                    message: unpack_str(&buf[ident_end..]),
+                }
+            }
+            MonitorOpcode::VendorDiagnostic
+            | MonitorOpcode::ControlOpen
+            | MonitorOpcode::ControlClose
+            | MonitorOpcode::ControlCommand
+            | MonitorOpcode::ControlEvent => MonitorPacket::Other(buf.into()),
+        })
+    }
+}
+/// One frame from the monitor channel. `timestamp` is since the Unix Epoch.
+#[derive(Debug)]
+pub struct MonitorFrame {
+    pub opcode: MonitorOpcode,
+    pub index: AdapterID,
+    pub timestamp: Duration,
+    pub packet: MonitorPacket,
+}
+impl MonitorFrame {
+    /// Unpacks a whole frame (header and data). `timestamp` isn't part of the frame.
+    pub fn unpack_from(buf: &[u8], timestamp: Duration) -> Result<MonitorFrame, PackError> {
+        PackError::atleast_length(HEADER_LEN, buf)?;
+        let opcode = MonitorOpcode::try_from(u16::from_le_bytes([buf[0], buf[1]]))
+            .map_err(|_| PackError::bad_index(0))?;
+        let index = AdapterID(u16::from_le_bytes([buf[2], buf[3]]));
+        let data = &buf[HEADER_LEN..];
+        PackError::expect_length(usize::from(u16::from_le_bytes([buf[4], buf[5]])), data)?;
+        Ok(MonitorFrame {
+            opcode,
+            index,
+            timestamp,
+            packet: MonitorPacket::unpack_from(opcode, data)?,
+        })
+    }
+}
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub enum MonitorError {
+    IOError(IOError),
+    PackError(PackError),
+}
+impl core::fmt::Display for MonitorError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "bluez monitor error {:?}", self)
+    }
+}
+impl crate::error::Error for MonitorError {}
+impl From<IOError> for MonitorError {
+    fn from(e: IOError) -> Self {
+        MonitorError::IOError(e)
+    }
+}
+impl From<PackError> for MonitorError {
+    fn from(e: PackError) -> Self {
+        MonitorError::PackError(e)
+    }
+}
+/// Reads one datagram into `buf` with `recvmsg`. Returns its length and the kernel timestamp
+/// (if any).
+fn recv_timestamped(fd: RawFd, buf: &mut [u8]) -> std::io::Result<(usize, Option<Duration>)> {
+    // Big enough for a `SCM_TIMESTAMP` control message (`cmsghdr` + `timeval`) and aligned.
+    let mut control = [0_u64; 8];
+    let mut iov = libc::iovec {
+        iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+        iov_len: buf.len(),
+    };
+    let mut msg: libc::msghdr = unsafe { core::mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+    msg.msg_controllen = core::mem::size_of_val(&control) as _;
+    let len = unsafe { libc::recvmsg(fd, &mut msg, 0) };
+    if len < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    let mut timestamp = None;
+    unsafe {
+        let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+        while !cmsg.is_null() {
+            if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_TIMESTAMP {
+                let tv = core::ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const libc::timeval);
+                timestamp = Some(
+                    Duration::from_secs(tv.tv_sec as u64)
+                        + Duration::from_micros(tv.tv_usec as u64),
+                );
+            }
+            cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+        }
+    }
+    Ok((len as usize, timestamp))
+}
+/// Async reader for the monitor channel. Frames are timestamped by the kernel (`SO_TIMESTAMP`)
+/// or with the time they were read if the socket doesn't give a timestamp.
+#[derive(Debug)]
+pub struct MonitorReader {
+    socket: tokio::net::UnixStream,
+    buf: Box<[u8]>,
+}
+impl MonitorReader {
+    /// Opens the monitor channel. Usually requires `CAP_NET_RAW`. Must be called inside a tokio
+    /// runtime.
+    pub fn open() -> Result<MonitorReader, IOError> {
+        let socket = HCISocket::bind_channel(AdapterID::NONE, HCIChannel::Monitor)?;
+        let enable: libc::c_int = 1;
+        handle_libc_error(unsafe {
+            libc::setsockopt(
+                socket.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_TIMESTAMP,
+                &enable as *const libc::c_int as *const libc::c_void,
+                core::mem::size_of::<libc::c_int>() as libc::socklen_t,
+            )
+        })?;
+        socket.set_nonblocking(true)?;
+        Ok(Self::from_stream(tokio::net::UnixStream::from_std(socket)?))
+    }
+    /// Wraps a socket that delivers one monitor frame per read (ex: the monitor channel or a
+    /// `SOCK_SEQPACKET` socket).
+    pub fn from_stream(socket: tokio::net::UnixStream) -> MonitorReader {
+        MonitorReader {
+            socket,
+            buf: vec![0_u8; HEADER_LEN + MAX_DATA_LEN].into_boxed_slice(),
+        }
+    }
+    /// Reads the next monitor frame.
+    pub async fn read_frame(&mut self) -> Result<MonitorFrame, MonitorError> {
+        let fd = self.socket.as_raw_fd();
+        loop {
+            self.socket.readable().await.map_err(IOError::from)?;
+            let buf = &mut self.buf;
+            match self
+                .socket
+                .try_io(tokio::io::Interest::READABLE, || recv_timestamped(fd, buf))
+            {
+                Ok((0, _)) => return Err(MonitorError::IOError(IOError::Closed)),
+                Ok((len, timestamp)) => {
+                    let timestamp = timestamp.unwrap_or_else(|| {
+                        std::time::SystemTime::now()
+                            .duration_since(std::time::UNIX_EPOCH)
+                            .unwrap_or_default()
+                    });
+                    return Ok(MonitorFrame::unpack_from(&self.buf[..len], timestamp)?);
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {}
+                Err(e) => return Err(MonitorError::IOError(e.into())),
+            }
+        }
+    }
+    /// Returns a Stream of every monitor frame.
+    pub fn frame_stream(&mut self) -> impl Stream<Item = Result<MonitorFrame, MonitorError>> + '_ {
+        futures_util::stream::unfold(self, |reader| async move {
+            let frame = reader.read_frame().await;
+            Some((frame, reader))
+        })
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hci::event::EventCode;
+    use crate::hci::le::LEControllerOpcode;
+    use crate::hci::Opcode;
+    use std::os::unix::io::FromRawFd;
+
+    fn frame(opcode: MonitorOpcode, index: u16, data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(&u16::from(opcode).to_le_bytes());
+        out.extend_from_slice(&index.to_le_bytes());
+        out.extend_from_slice(&(data.len() as u16).to_le_bytes());
+        out.extend_from_slice(data);
+        out
+    }
+    /// `SOCK_SEQPACKET` pair so frame boundaries are kept like on the monitor channel.
+    fn seqpacket_pair() -> (
+        std::os::unix::net::UnixStream,
+        std::os::unix::net::UnixStream,
+    ) {
+        let mut fds = [0; 2];
+        assert_eq!(
+            unsafe {
+                libc::socketpair(
+                    libc::AF_UNIX,
+                    libc::SOCK_SEQPACKET | libc::SOCK_CLOEXEC,
+                    0,
+                    fds.as_mut_ptr(),
+                )
+            },
+            0
+        );
+        unsafe {
+            (
+                std::os::unix::net::UnixStream::from_raw_fd(fds[0]),
+                std::os::unix::net::UnixStream::from_raw_fd(fds[1]),
+            )
+        }
+    }
+    #[test]
+    fn replays_canned_frames() {
+        let (writer, reader) = seqpacket_pair();
+        reader.set_nonblocking(true).unwrap();
+        let frames = [
+            frame(
+                MonitorOpcode::NewIndex,
+                0,
+                &[0, 1, 1, 2, 3, 4, 5, 6, b'h', b'c', b'i', b'0', 0, 0, 0, 0],
+            ),
+            // LE Set Scan Enable (enabled, no duplicates filter).
+            frame(MonitorOpcode::Command, 0, &[0x0C, 0x20, 2, 1, 0]),
+            // Command Complete for LE Set Scan Enable, Success.
+            frame(MonitorOpcode::Event, 0, &[0x0E, 4, 1, 0x0C, 0x20, 0]),
+            frame(MonitorOpcode::ACLRx, 0, &[0x40, 0x20, 2, 0, 0xAB, 0xCD]),
+        ];
+        for f in frames.iter() {
+            assert_eq!(
+                unsafe {
+                    libc::send(
+                        writer.as_raw_fd(),
+                        f.as_ptr() as *const libc::c_void,
+                        f.len(),
+                        0,
+                    )
+                },
+                f.len() as isize
+            );
+        }
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_io()
+            .build()
+            .unwrap();
+        runtime.block_on(async move {
+            let mut monitor =
+                MonitorReader::from_stream(tokio::net::UnixStream::from_std(reader).unwrap());
+            match monitor.read_frame().await.unwrap().packet {
+                MonitorPacket::NewIndex {
+                    address, name, bus, ..
+                } => {
+                    assert_eq!(address, BTAddress([1, 2, 3, 4, 5, 6]));
+                    assert_eq!(name, "hci0");
+                    assert_eq!(bus, 1);
+                }
+                p => panic!("unexpected {:?}", p),
+            }
+            match monitor.read_frame().await.unwrap().packet {
+                MonitorPacket::Command(c) => {
+                    assert_eq!(c.opcode, Opcode::from(LEControllerOpcode::SetScanEnable));
+                    assert_eq!(&c.parameters[..], &[1, 0]);
+                }
+                p => panic!("unexpected {:?}", p),
+            }
+            match monitor.read_frame().await.unwrap().packet {
+                MonitorPacket::Event(e) => {
+                    assert_eq!(e.event_code, EventCode::CommandComplete);
+                    assert_eq!(
+                        e.command_return_info(),
+                        Some((1, Opcode::from(LEControllerOpcode::SetScanEnable)))
+                    );
+                }
+                p => panic!("unexpected {:?}", p),
+            }
+            let acl = monitor.read_frame().await.unwrap();
+            assert_eq!(acl.opcode, MonitorOpcode::ACLRx);
+            match acl.packet {
+                MonitorPacket::ACL(PacketDirection::Rx, p) => {
+                    assert_eq!(u16::from(p.handle), 0x040);
+                    assert_eq!(&p.data[..], &[0xAB, 0xCD]);
+                }
+                p => panic!("unexpected {:?}", p),
+            }
+        });
+    }
+    #[test]
+    fn decodes_index_info_and_user_logging() {
+        let info = MonitorFrame::unpack_from(
+            &frame(MonitorOpcode::IndexInfo, 1, &[1, 2, 3, 4, 5, 6, 0x0F, 0x00]),
+            Duration::from_secs(1),
+        )
+        .unwrap();
+        assert_eq!(info.index, AdapterID(1));
+        match info.packet {
+            MonitorPacket::IndexInfo {
+                address,
+                manufacturer,
+            } => {
+                assert_eq!(address, BTAddress([1, 2, 3, 4, 5, 6]));
+                assert_eq!(manufacturer, 0x000F);
+            }
+            p => panic!("unexpected {:?}", p),
+        }
+        // Priority 6 (info), 5 byte ident then the message.
+        let logging = MonitorFrame::unpack_from(
+            &frame(
+                MonitorOpcode::UserLogging,
+                0xFFFF,
+                &[6, 5, b'b', b't', b'l', b'e', 0, b'h', b'i', 0],
+            ),
+            Duration::from_secs(1),
+        )
+        .unwrap();
+        match logging.packet {
+            MonitorPacket::UserLogging {
+                priority,
+                ident,
+                message,
+            } => {
+                assert_eq!(priority, 6);
+                assert_eq!(ident, "btle");
+                assert_eq!(message, "hi");
+            }
+            p => panic!("unexpected {:?}", p),
+        }
+        // Ident length past the end of the frame.
+        assert!(MonitorFrame::unpack_from(
+            &frame(MonitorOpcode::UserLogging, 0xFFFF, &[6, 5, b'b']),
+            Duration::from_secs(1),
+        )
+        .is_err());
+    }
+    #[test]
+    fn rejects_bad_headers() {
+        // Header length is one more than the data.
+        let mut bad_len = frame(MonitorOpcode::SystemNote, 0, b"note");
+        bad_len[4] = 5;
+        assert_eq!(
+            MonitorFrame::unpack_from(&bad_len, Duration::from_secs(1)).err(),
+            Some(PackError::BadLength {
+                expected: 5,
+                got: 4
+            })
+        );
+        let mut unknown = frame(MonitorOpcode::SystemNote, 0, b"note");
+        unknown[..2].copy_from_slice(&0x1234_u16.to_le_bytes());
+        assert_eq!(
+            MonitorFrame::unpack_from(&unknown, Duration::from_secs(1)).err(),
+            Some(PackError::bad_index(0))
+        );
+    }
+}
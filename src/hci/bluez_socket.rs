@@ -113,6 +113,10 @@ pub struct HCIDevInfo {
 pub struct HCIDevListReq {}
 #[derive(Copy, Clone, PartialOrd, PartialEq, Ord, Eq, Hash, Debug)]
 pub struct AdapterID(pub u16);
+impl AdapterID {
+    /// Not bound to any adapter (`HCI_DEV_NONE`). Used by the `Control` and `Monitor` channels.
+    pub const NONE: AdapterID = AdapterID(0xFFFF);
+}
 
 #[derive(Copy, Clone, Debug)]
 #[repr(C)]
@@ -129,7 +133,7 @@ pub struct HCISocket(UnixStream);
 /// Turns an libc `ERRNO` error number into a `IOError`.
 pub fn handle_libc_error(i: RawFd) -> Result<i32, IOError> {
     if i < 0 {
-        Err(handle_errno(nix::errno::errno()))
+        Err(handle_errno(nix::errno::Errno::last_raw()))
     } else {
         Ok(i)
     }
@@ -156,6 +160,15 @@ impl HCISocket {
     /// Creates an `HCISocket` based on a `libc` file_descriptor (`i32`). Returns an error if could
     /// not bind to the `adapter_id`.
     pub fn new_channel(adapter_id: AdapterID, channel: HCIChannel) -> Result<HCISocket, IOError> {
+        let out = HCISocket(Self::bind_channel(adapter_id, channel)?);
+        let mut filter = Filter::all_events();
+        filter.enable_type(PacketType::Command);
+        out.set_socket_filter(&filter)?;
+        Ok(out)
+    }
+    /// Opens an `AF_BLUETOOTH` socket bound to `channel` of `adapter_id` without setting an HCI
+    /// filter. Only the `Raw` channel supports HCI filters.
+    pub fn bind_channel(adapter_id: AdapterID, channel: HCIChannel) -> Result<UnixStream, IOError> {
         let adapter_fd = handle_libc_error(unsafe {
             libc::socket(
                 libc::AF_BLUETOOTH,
@@ -163,6 +176,8 @@ impl HCISocket {
                 BTProtocol::HCI.into(),
             )
         })?;
+        // Owns the fd right away so it gets closed if `bind` fails.
+        let stream = unsafe { UnixStream::from_raw_fd(adapter_fd) };
         let address = SockaddrHCI {
             family: libc::AF_BLUETOOTH as u16,
             dev: adapter_id.0,
@@ -170,17 +185,12 @@ impl HCISocket {
         };
         handle_libc_error(unsafe {
             libc::bind(
-                adapter_fd,
+                stream.as_raw_fd(),
                 &address as *const SockaddrHCI as *const libc::sockaddr,
                 std::mem::size_of::<SockaddrHCI>() as u32,
             )
         })?;
-        let stream = unsafe { UnixStream::from_raw_fd(adapter_fd) };
-        let out = HCISocket(stream);
-        let mut filter = Filter::all_events();
-        filter.enable_type(PacketType::Command);
-        out.set_socket_filter(&filter)?;
-        Ok(out)
+        Ok(stream)
     }
     pub unsafe fn new_unchecked(stream: UnixStream) -> HCISocket {
         Self(stream)
@@ -227,9 +237,8 @@ impl HCISocket {
 }
 fn hci_to_socket_error(err: nix::Error) -> IOError {
     match err {
-        nix::Error::Sys(i) => handle_errno(i as i32),
-        nix::Error::InvalidPath | nix::Error::InvalidUtf8 => panic!("bad nix path"),
-        nix::Error::UnsupportedOperation => IOError::NotImplemented,
+        nix::Error::ENOTSUP => IOError::NotImplemented,
+        e => handle_errno(e as i32),
     }
 }
 #[derive(Debug)]
@@ -318,9 +327,11 @@ impl HCIReader for AsyncHCISocket {
         cx: &mut Context<'_>,
         buf: &mut [u8],
     ) -> Poll<Result<usize, Error>> {
-        use tokio::io::AsyncRead;
+        use tokio::io::{AsyncRead, ReadBuf};
+        let mut read_buf = ReadBuf::new(buf);
         Pin::new(&mut self.0)
-            .poll_read(cx, buf)
+            .poll_read(cx, &mut read_buf)
+            .map_ok(|()| read_buf.filled().len())
             .map_err(|e| Error::IOError(e.into()))
     }
 }
@@ -0,0 +1,460 @@
+//! btsnoop capture files (the format `btmon -w`, Android and Wireshark use). Only the HCI UART
+//! (H4) datalink is supported so every record is the packet type byte followed by the packet.
+//!
+//! [`SnoopAdapter`] wraps any [`Adapter`] and records every packet passing through it.
+//! [`BTSnoopReader`] reads a capture back into [`BTSnoopRecord`]s.
+use crate::bytes::Storage;
+use crate::error::IOError;
+use crate::hci::acl::ACLPacket;
+use crate::hci::adapter::{self, Adapter};
+use crate::hci::command::CommandPacket;
+use crate::hci::event::EventPacket;
+use crate::hci::iso::ISOPacket;
+use crate::hci::packet::{PacketDirection, PacketType, RawPacket};
+use crate::hci::StreamError;
+use crate::{LocalBoxFuture, PackError, Stream};
+use core::convert::{TryFrom, TryInto};
+use core::time::Duration;
+use std::io::{Read, Write};
+
+/// `"btsnoop\0"`
+pub const MAGIC: [u8; 8] = *b"btsnoop\0";
+pub const VERSION: u32 = 1;
+/// HCI UART (H4) datalink type.
+pub const DATALINK_H4: u32 = 1002;
+pub const FILE_HEADER_LEN: usize = 16;
+pub const RECORD_HEADER_LEN: usize = 24;
+/// Max record data length. The packet type byte and the largest HCI packet (ACL data with a
+/// 16-bit length).
+pub const MAX_RECORD_LEN: usize = 1 + 4 + u16::MAX as usize;
+/// btsnoop timestamps are microseconds since midnight, January 1st, 0 AD. This is the Unix Epoch
+/// in btsnoop time, the same offset Wireshark and Android use.
+pub const UNIX_EPOCH_MICROS: i64 = 0x00DC_DDB3_0F2F_8000;
+
+/// Record flags. Bit 0 is the direction (`0` sent, `1` received) and bit 1 is set for Commands and
+/// Events.
+fn record_flags(direction: PacketDirection, packet_type: PacketType) -> u32 {
+    let received = match direction {
+        PacketDirection::Tx => 0,
+        PacketDirection::Rx => 1,
+    };
+    let command_or_event = match packet_type {
+        PacketType::Command | PacketType::Event => 1 << 1,
+        _ => 0,
+    };
+    received | command_or_event
+}
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub enum BTSnoopError {
+    IOError(IOError),
+    PackError(PackError),
+    /// File doesn't start with the btsnoop magic and version 1.
+    BadHeader,
+    /// Datalink isn't HCI UART (H4).
+    UnsupportedDatalink(u32),
+}
+impl core::fmt::Display for BTSnoopError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "btsnoop error {:?}", self)
+    }
+}
+impl crate::error::Error for BTSnoopError {}
+impl From<IOError> for BTSnoopError {
+    fn from(e: IOError) -> Self {
+        BTSnoopError::IOError(e)
+    }
+}
+impl From<std::io::Error> for BTSnoopError {
+    fn from(e: std::io::Error) -> Self {
+        BTSnoopError::IOError(e.into())
+    }
+}
+impl From<PackError> for BTSnoopError {
+    fn from(e: PackError) -> Self {
+        BTSnoopError::PackError(e)
+    }
+}
+/// Returns the time since the Unix Epoch.
+fn now() -> Duration {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+}
+/// Writes HCI packets to a btsnoop capture.
+#[derive(Debug)]
+pub struct BTSnoopWriter<W: Write> {
+    writer: W,
+}
+impl<W: Write> BTSnoopWriter<W> {
+    /// Writes the btsnoop file header to `writer`.
+    pub fn new(mut writer: W) -> Result<Self, BTSnoopError> {
+        let mut header = [0_u8; FILE_HEADER_LEN];
+        header[..8].copy_from_slice(&MAGIC);
+        header[8..12].copy_from_slice(&VERSION.to_be_bytes());
+        header[12..16].copy_from_slice(&DATALINK_H4.to_be_bytes());
+        writer.write_all(&header)?;
+        Ok(BTSnoopWriter { writer })
+    }
+    /// Writes `packet` as a record. `timestamp` is since the Unix Epoch.
+    pub fn write_packet(
+        &mut self,
+        direction: PacketDirection,
+        packet: RawPacket<&[u8]>,
+        timestamp: Duration,
+    ) -> Result<(), BTSnoopError> {
+        let len = u32::try_from(packet.total_len()).map_err(|_| PackError::InvalidFields)?;
+        let timestamp = i64::try_from(timestamp.as_micros())
+            .map_err(|_| PackError::InvalidFields)?
+            + UNIX_EPOCH_MICROS;
+        let mut header = [0_u8; RECORD_HEADER_LEN];
+        header[0..4].copy_from_slice(&len.to_be_bytes());
+        header[4..8].copy_from_slice(&len.to_be_bytes());
+        header[8..12].copy_from_slice(&record_flags(direction, packet.packet_type).to_be_bytes());
+        // Cumulative drops stay 0.
+        header[16..24].copy_from_slice(&timestamp.to_be_bytes());
+        self.writer.write_all(&header)?;
+        self.writer.write_all(&[packet.packet_type.into()])?;
+        self.writer.write_all(packet.buf)?;
+        Ok(())
+    }
+    /// Writes `packet` as a record timestamped with the current time.
+    pub fn write_packet_now(
+        &mut self,
+        direction: PacketDirection,
+        packet: RawPacket<&[u8]>,
+    ) -> Result<(), BTSnoopError> {
+        self.write_packet(direction, packet, now())
+    }
+    pub fn flush(&mut self) -> Result<(), BTSnoopError> {
+        Ok(self.writer.flush()?)
+    }
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+/// One packet from a btsnoop capture. `timestamp` is since the Unix Epoch.
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct BTSnoopRecord {
+    pub direction: PacketDirection,
+    pub timestamp: Duration,
+    pub cumulative_drops: u32,
+    pub packet: RawPacket<Box<[u8]>>,
+}
+/// Reads HCI packets from a btsnoop capture. Also an `Iterator` of the records.
+#[derive(Debug)]
+pub struct BTSnoopReader<R: Read> {
+    reader: R,
+}
+impl<R: Read> BTSnoopReader<R> {
+    /// Reads and checks the btsnoop file header.
+    pub fn new(mut reader: R) -> Result<Self, BTSnoopError> {
+        let mut header = [0_u8; FILE_HEADER_LEN];
+        reader.read_exact(&mut header)?;
+        let version = u32::from_be_bytes(header[8..12].try_into().expect("hardcoded length"));
+        if header[..8] != MAGIC || version != VERSION {
+            return Err(BTSnoopError::BadHeader);
+        }
+        let datalink = u32::from_be_bytes(header[12..16].try_into().expect("hardcoded length"));
+        if datalink != DATALINK_H4 {
+            return Err(BTSnoopError::UnsupportedDatalink(datalink));
+        }
+        Ok(BTSnoopReader { reader })
+    }
+    /// Reads the next record. Returns `Ok(None)` at the end of the capture.
+    pub fn read_record(&mut self) -> Result<Option<BTSnoopRecord>, BTSnoopError> {
+        let mut header = [0_u8; RECORD_HEADER_LEN];
+        // Only a clean EOF before a record header is the end of the capture.
+        let mut filled = 0;
+        while filled < RECORD_HEADER_LEN {
+            match self.reader.read(&mut header[filled..]) {
+                Ok(0) if filled == 0 => return Ok(None),
+                Ok(0) => return Err(BTSnoopError::IOError(IOError::InvalidData)),
+                Ok(len) => filled += len,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => (),
+                Err(e) => return Err(e.into()),
+            }
+        }
+        let u32_at = |i: usize| u32::from_be_bytes(header[i..i + 4].try_into().expect("in range"));
+        let original_len = u32_at(0) as usize;
+        let included_len = u32_at(4) as usize;
+        let flags = u32_at(8);
+        let cumulative_drops = u32_at(12);
+        let timestamp = i64::from_be_bytes(header[16..24].try_into().expect("in range"));
+        // Check the untrusted length before allocating for it.
+        if included_len == 0 {
+            return Err(BTSnoopError::PackError(PackError::BadLength {
+                expected: 1,
+                got: 0,
+            }));
+        }
+        if included_len > original_len.min(MAX_RECORD_LEN) {
+            return Err(BTSnoopError::PackError(PackError::BadLength {
+                expected: original_len.min(MAX_RECORD_LEN),
+                got: included_len,
+            }));
+        }
+        let mut data = vec![0_u8; included_len];
+        self.reader.read_exact(&mut data)?;
+        let packet_type = PacketType::try_from(data[0]).map_err(|_| PackError::bad_index(0))?;
+        Ok(Some(BTSnoopRecord {
+            direction: if flags & 1 == 0 {
+                PacketDirection::Tx
+            } else {
+                PacketDirection::Rx
+            },
+            timestamp: Duration::from_micros(
+                u64::try_from(timestamp - UNIX_EPOCH_MICROS).unwrap_or(0),
+            ),
+            cumulative_drops,
+            packet: RawPacket {
+                packet_type,
+                buf: data[1..].into(),
+            },
+        }))
+    }
+    /// Turns the reader into a Stream of records.
+    pub fn into_stream(self) -> impl Stream<Item = Result<BTSnoopRecord, BTSnoopError>> {
+        futures_util::stream::iter(self)
+    }
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+impl<R: Read> Iterator for BTSnoopReader<R> {
+    type Item = Result<BTSnoopRecord, BTSnoopError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_record().transpose()
+    }
+}
+/// Wraps an [`Adapter`] and records every packet written to or read from it to a btsnoop
+/// capture. A failed capture write fails the adapter call with `adapter::Error::IOError`.
+#[derive(Debug)]
+pub struct SnoopAdapter<A: Adapter, W: Write> {
+    adapter: A,
+    writer: BTSnoopWriter<W>,
+}
+impl<A: Adapter, W: Write> SnoopAdapter<A, W> {
+    pub fn new(adapter: A, writer: BTSnoopWriter<W>) -> Self {
+        SnoopAdapter { adapter, writer }
+    }
+    pub fn adapter(&self) -> &A {
+        &self.adapter
+    }
+    pub fn adapter_mut(&mut self) -> &mut A {
+        &mut self.adapter
+    }
+    pub fn writer_mut(&mut self) -> &mut BTSnoopWriter<W> {
+        &mut self.writer
+    }
+    pub fn into_inner(self) -> (A, BTSnoopWriter<W>) {
+        (self.adapter, self.writer)
+    }
+    fn record(
+        &mut self,
+        direction: PacketDirection,
+        packet: RawPacket<&[u8]>,
+    ) -> Result<(), adapter::Error> {
+        self.writer
+            .write_packet_now(direction, packet)
+            .map_err(|e| match e {
+                BTSnoopError::IOError(e) => adapter::Error::IOError(e),
+                BTSnoopError::PackError(e) => StreamError::CommandError(e).into(),
+                _ => adapter::Error::IOError(IOError::Other),
+            })
+    }
+}
+impl<A: Adapter, W: Write> Adapter for SnoopAdapter<A, W> {
+    fn write_command<'s, 'p: 's>(
+        &'s mut self,
+        packet: CommandPacket<&'p [u8]>,
+    ) -> LocalBoxFuture<'s, Result<(), adapter::Error>> {
+        Box::pin(async move {
+            let raw = packet.to_raw_packet::<Box<[u8]>>();
+            self.record(PacketDirection::Tx, raw.as_ref())?;
+            self.adapter.write_command(packet).await
+        })
+    }
+
+    fn read_event<'s, 'p: 's, S: Storage<u8> + 'p>(
+        &'s mut self,
+    ) -> LocalBoxFuture<'s, Result<EventPacket<S>, adapter::Error>> {
+        Box::pin(async move {
+            let event = self.adapter.read_event::<S>().await?;
+            let raw = event.to_raw_packet::<Box<[u8]>>();
+            self.record(PacketDirection::Rx, raw.as_ref())?;
+            Ok(event)
+        })
+    }
+
+    fn write_acl<'s, 'p: 's>(
+        &'s mut self,
+        packet: ACLPacket<&'p [u8]>,
+    ) -> LocalBoxFuture<'s, Result<(), adapter::Error>> {
+        Box::pin(async move {
+            let raw = packet
+                .to_raw_packet::<Box<[u8]>>()
+                .map_err(StreamError::DataError)?;
+            self.record(PacketDirection::Tx, raw.as_ref())?;
+            self.adapter.write_acl(packet).await
+        })
+    }
+
+    fn read_acl<'s, 'p: 's, S: Storage<u8> + 'p>(
+        &'s mut self,
+    ) -> LocalBoxFuture<'s, Result<ACLPacket<S>, adapter::Error>> {
+        Box::pin(async move {
+            let acl = self.adapter.read_acl::<S>().await?;
+            let raw = acl
+                .to_raw_packet::<Box<[u8]>>()
+                .map_err(StreamError::DataError)?;
+            self.record(PacketDirection::Rx, raw.as_ref())?;
+            Ok(acl)
+        })
+    }
+
+    fn write_iso<'s, 'p: 's>(
+        &'s mut self,
+        packet: ISOPacket<&'p [u8]>,
+    ) -> LocalBoxFuture<'s, Result<(), adapter::Error>> {
+        Box::pin(async move {
+            let raw = packet
+                .to_raw_packet::<Box<[u8]>>()
+                .map_err(StreamError::DataError)?;
+            self.record(PacketDirection::Tx, raw.as_ref())?;
+            self.adapter.write_iso(packet).await
+        })
+    }
+
+    fn read_iso<'s, 'p: 's, S: Storage<u8> + 'p>(
+        &'s mut self,
+    ) -> LocalBoxFuture<'s, Result<ISOPacket<S>, adapter::Error>> {
+        Box::pin(async move {
+            let iso = self.adapter.read_iso::<S>().await?;
+            let raw = iso
+                .to_raw_packet::<Box<[u8]>>()
+                .map_err(StreamError::DataError)?;
+            self.record(PacketDirection::Rx, raw.as_ref())?;
+            Ok(iso)
+        })
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet(packet_type: PacketType, buf: &[u8]) -> RawPacket<Box<[u8]>> {
+        RawPacket {
+            packet_type,
+            buf: buf.into(),
+        }
+    }
+    #[test]
+    fn round_trip() {
+        let records = [
+            BTSnoopRecord {
+                direction: PacketDirection::Tx,
+                timestamp: Duration::from_micros(1_600_000_000_000_001),
+                cumulative_drops: 0,
+                // HCI Reset.
+                packet: packet(PacketType::Command, &[0x03, 0x0C, 0x00]),
+            },
+            BTSnoopRecord {
+                direction: PacketDirection::Rx,
+                timestamp: Duration::from_micros(1_600_000_000_000_002),
+                cumulative_drops: 0,
+                // Command Complete for HCI Reset, Success.
+                packet: packet(PacketType::Event, &[0x0E, 0x04, 0x01, 0x03, 0x0C, 0x00]),
+            },
+            BTSnoopRecord {
+                direction: PacketDirection::Rx,
+                timestamp: Duration::from_micros(1_600_000_000_000_003),
+                cumulative_drops: 0,
+                packet: packet(PacketType::ACLData, &[0x40, 0x20, 0x02, 0x00, 0xAB, 0xCD]),
+            },
+        ];
+        let mut writer = BTSnoopWriter::new(Vec::new()).unwrap();
+        for record in records.iter() {
+            writer
+                .write_packet(record.direction, record.packet.as_ref(), record.timestamp)
+                .unwrap();
+        }
+        let capture = writer.into_inner();
+        assert_eq!(&capture[..8], b"btsnoop\0");
+        // Reset record: lengths, flags (sent command) and the H4 packet type.
+        assert_eq!(
+            &capture[FILE_HEADER_LEN..FILE_HEADER_LEN + 12],
+            &[0, 0, 0, 4, 0, 0, 0, 4, 0, 0, 0, 2]
+        );
+        assert_eq!(capture[FILE_HEADER_LEN + RECORD_HEADER_LEN], 0x01);
+        let read = BTSnoopReader::new(&capture[..])
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(read, records);
+    }
+    #[test]
+    fn absolute_timestamps() {
+        let mut writer = BTSnoopWriter::new(Vec::new()).unwrap();
+        let reset = packet(PacketType::Command, &[0x03, 0x0C, 0x00]);
+        writer
+            .write_packet(PacketDirection::Tx, reset.as_ref(), Duration::from_secs(0))
+            .unwrap();
+        // 2020-09-13 12:26:40 UTC.
+        writer
+            .write_packet(
+                PacketDirection::Tx,
+                reset.as_ref(),
+                Duration::from_secs(1_600_000_000),
+            )
+            .unwrap();
+        let capture = writer.into_inner();
+        let timestamp = |record: usize| {
+            let start = FILE_HEADER_LEN + record * (RECORD_HEADER_LEN + 4) + 16;
+            &capture[start..start + 8]
+        };
+        assert_eq!(
+            timestamp(0),
+            &[0x00, 0xDC, 0xDD, 0xB3, 0x0F, 0x2F, 0x80, 0x00]
+        );
+        assert_eq!(
+            timestamp(1),
+            &[0x00, 0xE2, 0x8C, 0xE4, 0x16, 0xD3, 0x80, 0x00]
+        );
+    }
+    #[test]
+    fn rejects_bad_lengths() {
+        let mut writer = BTSnoopWriter::new(Vec::new()).unwrap();
+        writer
+            .write_packet(
+                PacketDirection::Tx,
+                packet(PacketType::Command, &[0x03, 0x0C, 0x00]).as_ref(),
+                Duration::from_secs(1),
+            )
+            .unwrap();
+        let capture = writer.into_inner();
+        let read_first = |capture: &[u8]| BTSnoopReader::new(capture).unwrap().read_record();
+        // Included length bigger than the original length.
+        let mut bad = capture.clone();
+        bad[FILE_HEADER_LEN + 4..FILE_HEADER_LEN + 8].copy_from_slice(&5_u32.to_be_bytes());
+        assert!(matches!(
+            read_first(&bad),
+            Err(BTSnoopError::PackError(PackError::BadLength {
+                expected: 4,
+                got: 5
+            }))
+        ));
+        // Bigger than any HCI packet.
+        bad[FILE_HEADER_LEN..FILE_HEADER_LEN + 8].copy_from_slice(&[0xFF; 8]);
+        assert!(matches!(
+            read_first(&bad),
+            Err(BTSnoopError::PackError(PackError::BadLength {
+                expected: MAX_RECORD_LEN,
+                ..
+            }))
+        ));
+        // Truncated record.
+        assert!(read_first(&capture[..capture.len() - 1]).is_err());
+        assert_eq!(read_first(&capture[..FILE_HEADER_LEN]), Ok(None));
+    }
+}
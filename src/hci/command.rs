@@ -55,6 +55,20 @@ impl<Storage: AsRef<[u8]>> core::fmt::Debug for CommandPacket<Storage> {
             .finish()
     }
 }
+impl<'a> TryFrom<RawPacket<&'a [u8]>> for CommandPacket<&'a [u8]> {
+    type Error = PackError;
+
+    fn try_from(packet: RawPacket<&'a [u8]>) -> Result<Self, Self::Error> {
+        if packet.packet_type != PacketType::Command {
+            return Err(PackError::BadOpcode);
+        }
+        PackError::atleast_length(OPCODE_LEN + 1, packet.buf)?;
+        let opcode = Opcode::unpack(&packet.buf[..OPCODE_LEN])?;
+        let parameters = &packet.buf[OPCODE_LEN + 1..];
+        PackError::expect_length(usize::from(packet.buf[OPCODE_LEN]), parameters)?;
+        Ok(CommandPacket { opcode, parameters })
+    }
+}
 pub struct CommandHeader {
     pub opcode: Opcode,
     pub len: u8,
@@ -2,6 +2,7 @@
 use crate::bytes::{StaticBuf, Storage};
 use crate::hci::packet::{PacketType, RawPacket};
 use crate::hci::{ErrorCode, Opcode, EVENT_CODE_LEN, OPCODE_LEN};
+use crate::le::connection::ConnectionHandle;
 use crate::ConversionError;
 use crate::PackError;
 use core::convert::{TryFrom, TryInto};
@@ -74,6 +75,8 @@ pub enum EventCode {
     AMPTestEnd = 0x4A,
     AMPReceiverReport = 0x4B,
     LEMeta = 0x3E,
+    /// Vendor specific event (ex: Intel and Realtek debug or boot events).
+    VendorSpecific = 0xFF,
 }
 impl From<EventCode> for u8 {
     fn from(code: EventCode) -> Self {
@@ -154,6 +157,7 @@ impl TryFrom<u8> for EventCode {
             0x4A => Ok(EventCode::AMPTestEnd),
             0x4B => Ok(EventCode::AMPReceiverReport),
             0x3E => Ok(EventCode::LEMeta),
+            0xFF => Ok(EventCode::VendorSpecific),
             _ => Err(ConversionError(())),
         }
     }
@@ -218,6 +222,7 @@ impl From<FullHCIBuffer> for [u8; MAX_HCI_PACKET_SIZE] {
 }
 pub type StaticHCIBuffer = StaticBuf<u8, FullHCIBuffer>;
 /// Unprocessed HCI Event Packet
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
 pub struct EventPacket<Storage> {
     pub event_code: EventCode,
     pub parameters: Storage,
@@ -262,6 +267,25 @@ impl<Storage: AsRef<[u8]>> EventPacket<Storage> {
             parameters: NewStorage::from_slice(self.parameters().as_ref()),
         }
     }
+    /// Returns `(num_command_packets, opcode)` if the event is a `CommandComplete` or
+    /// `CommandStatus` event. `num_command_packets` is the amount of commands the controller can
+    /// currently accept (the command credit).
+    pub fn command_return_info(&self) -> Option<(u8, Opcode)> {
+        let parameters = self.parameters();
+        match self.event_code {
+            EventCode::CommandComplete if parameters.len() >= COMMAND_COMPLETE_HEADER_LEN => {
+                Some((
+                    parameters[0],
+                    Opcode::unpack(&parameters[1..1 + OPCODE_LEN]).ok()?,
+                ))
+            }
+            EventCode::CommandStatus if parameters.len() >= COMMAND_STATUS_LEN => Some((
+                parameters[1],
+                Opcode::unpack(&parameters[2..2 + OPCODE_LEN]).ok()?,
+            )),
+            _ => None,
+        }
+    }
 }
 impl<'a> TryFrom<RawPacket<&'a [u8]>> for EventPacket<&'a [u8]> {
     type Error = PackError;
@@ -336,6 +360,38 @@ impl ReturnParameters for StatusReturn {
         })
     }
 }
+/// Return parameters of commands that only return a status and the `ConnectionHandle` the
+/// command was for.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
+pub struct ConnectionHandleReturn {
+    pub status: ErrorCode,
+    pub handle: ConnectionHandle,
+}
+impl ConnectionHandleReturn {
+    pub const BYTE_LEN: usize = ErrorCode::BYTE_LEN + ConnectionHandle::BYTE_LEN;
+}
+impl ReturnParameters for ConnectionHandleReturn {
+    fn byte_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        buf[0] = self.status.into();
+        self.handle.pack_into(&mut buf[1..3])
+    }
+
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        Ok(ConnectionHandleReturn {
+            status: ErrorCode::try_from(buf[0]).map_err(|_| PackError::bad_index(0))?,
+            handle: ConnectionHandle::unpack_from(&buf[1..3])?,
+        })
+    }
+}
 #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
 pub struct CommandComplete<Params: ReturnParameters> {
     pub num_command_packets: u8,
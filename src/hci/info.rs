@@ -0,0 +1,573 @@
+//! HCI Informational Parameters commands. Read fixed information about the local controller
+//! (version, supported commands and features, BD_ADDR) and collect it into a [`ControllerInfo`].
+use crate::hci::adapter;
+use crate::hci::command::Command;
+use crate::hci::event::{CommandComplete, ReturnParameters};
+use crate::hci::le::info::LESupportedStates;
+use crate::hci::le::LEControllerOpcode;
+use crate::hci::{ErrorCode, Opcode, Version, OCF, OGF};
+use crate::le::features::{LEFeature, LEFeatures};
+use crate::{BTAddress, ConversionError, PackError, BT_ADDRESS_LEN};
+use core::convert::TryFrom;
+
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+#[repr(u16)]
+pub enum InformationalOpcode {
+    ReadLocalVersionInformation = 0x0001,
+    ReadLocalSupportedCommands = 0x0002,
+    ReadLocalSupportedFeatures = 0x0003,
+    ReadBufferSize = 0x0005,
+    ReadBDADDR = 0x0009,
+}
+impl TryFrom<OCF> for InformationalOpcode {
+    type Error = ConversionError;
+
+    fn try_from(ocf: OCF) -> Result<Self, Self::Error> {
+        match u16::from(ocf) {
+            0x0001 => Ok(InformationalOpcode::ReadLocalVersionInformation),
+            0x0002 => Ok(InformationalOpcode::ReadLocalSupportedCommands),
+            0x0003 => Ok(InformationalOpcode::ReadLocalSupportedFeatures),
+            0x0005 => Ok(InformationalOpcode::ReadBufferSize),
+            0x0009 => Ok(InformationalOpcode::ReadBDADDR),
+            _ => Err(ConversionError(())),
+        }
+    }
+}
+impl From<InformationalOpcode> for OCF {
+    fn from(opcode: InformationalOpcode) -> Self {
+        OCF::new(opcode as u16)
+    }
+}
+impl From<InformationalOpcode> for Opcode {
+    fn from(opcode: InformationalOpcode) -> Self {
+        Opcode(OGF::InformationalParameters, opcode.into())
+    }
+}
+/// Implements `Command` for a parameterless command returning `$ret`.
+macro_rules! parameterless_command {
+    ($name:ident, $opcode:expr, $ret:ty) => {
+        impl $name {
+            pub const OPCODE: InformationalOpcode = $opcode;
+        }
+        impl Command for $name {
+            type Return = CommandComplete<$ret>;
+
+            fn opcode() -> Opcode {
+                Self::OPCODE.into()
+            }
+
+            fn byte_len(&self) -> usize {
+                0
+            }
+
+            fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+                PackError::expect_length(0, buf)
+            }
+
+            fn unpack_from(buf: &[u8]) -> Result<Self, PackError>
+            where
+                Self: Sized,
+            {
+                PackError::expect_length(0, buf)?;
+                Ok($name())
+            }
+        }
+    };
+}
+/// Version information of the local controller. `hci_version` and `lmp_version` are kept raw
+/// so controllers newer than [`Version`] can still be read.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash, Default)]
+pub struct LocalVersion {
+    pub hci_version: u8,
+    pub hci_subversion: u16,
+    pub lmp_version: u8,
+    /// Company identifier of the controller manufacturer.
+    pub manufacturer_name: u16,
+    pub lmp_subversion: u16,
+}
+impl LocalVersion {
+    pub const BYTE_LEN: usize = 1 + 2 + 1 + 2 + 2;
+    pub fn hci_version(&self) -> Option<Version> {
+        Version::try_from(self.hci_version).ok()
+    }
+    pub fn lmp_version(&self) -> Option<Version> {
+        Version::try_from(self.lmp_version).ok()
+    }
+    pub fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        buf[0] = self.hci_version;
+        buf[1..3].copy_from_slice(&self.hci_subversion.to_le_bytes());
+        buf[3] = self.lmp_version;
+        buf[4..6].copy_from_slice(&self.manufacturer_name.to_le_bytes());
+        buf[6..8].copy_from_slice(&self.lmp_subversion.to_le_bytes());
+        Ok(())
+    }
+    pub fn unpack_from(buf: &[u8]) -> Result<Self, PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        Ok(LocalVersion {
+            hci_version: buf[0],
+            hci_subversion: u16::from_le_bytes([buf[1], buf[2]]),
+            lmp_version: buf[3],
+            manufacturer_name: u16::from_le_bytes([buf[4], buf[5]]),
+            lmp_subversion: u16::from_le_bytes([buf[6], buf[7]]),
+        })
+    }
+}
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash, Default)]
+pub struct ReadLocalVersionInformation();
+parameterless_command!(
+    ReadLocalVersionInformation,
+    InformationalOpcode::ReadLocalVersionInformation,
+    LocalVersionInformation
+);
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct LocalVersionInformation {
+    pub status: ErrorCode,
+    pub version: LocalVersion,
+}
+impl LocalVersionInformation {
+    pub const BYTE_LEN: usize = ErrorCode::BYTE_LEN + LocalVersion::BYTE_LEN;
+}
+impl ReturnParameters for LocalVersionInformation {
+    fn byte_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        buf[0] = self.status.into();
+        self.version.pack_into(&mut buf[1..])
+    }
+
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        Ok(LocalVersionInformation {
+            status: ErrorCode::try_from(buf[0]).map_err(|_| PackError::bad_index(0))?,
+            version: LocalVersion::unpack_from(&buf[1..])?,
+        })
+    }
+}
+/// 64 octet bit field of the HCI commands the controller supports.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub struct SupportedCommands(pub [u8; SupportedCommands::BYTE_LEN]);
+impl SupportedCommands {
+    pub const BYTE_LEN: usize = 64;
+    pub const fn zeroed() -> SupportedCommands {
+        SupportedCommands([0_u8; Self::BYTE_LEN])
+    }
+    /// If the command at `octet` and `bit` is supported. Returns `false` if out of range.
+    pub fn get_bit(&self, octet: usize, bit: u8) -> bool {
+        bit < 8 && self.0.get(octet).map_or(false, |o| o & (1 << bit) != 0)
+    }
+    /// If the command with `opcode` is supported. Returns `None` if the `opcode` isn't in the
+    /// known list.
+    pub fn supports(&self, opcode: Opcode) -> Option<bool> {
+        let (octet, bit) = command_bit(opcode)?;
+        Some(self.get_bit(octet, bit))
+    }
+}
+impl Default for SupportedCommands {
+    fn default() -> Self {
+        Self::zeroed()
+    }
+}
+impl core::fmt::Debug for SupportedCommands {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_tuple("SupportedCommands")
+            .field(&&self.0[..])
+            .finish()
+    }
+}
+/// Octet and bit of the command with `opcode` in [`SupportedCommands`].
+fn command_bit(opcode: Opcode) -> Option<(usize, u8)> {
+    match opcode.0 {
+        OGF::HCIControlBaseband => match u16::from(opcode.1) {
+            0x0001 => Some((5, 6)),
+            0x0003 => Some((5, 7)),
+            0x0031 => Some((10, 5)),
+            0x0033 => Some((10, 6)),
+            0x0035 => Some((10, 7)),
+            _ => None,
+        },
+        OGF::InformationalParameters => match InformationalOpcode::try_from(opcode.1).ok()? {
+            InformationalOpcode::ReadLocalVersionInformation => Some((14, 3)),
+            InformationalOpcode::ReadLocalSupportedFeatures => Some((14, 5)),
+            InformationalOpcode::ReadBufferSize => Some((14, 7)),
+            InformationalOpcode::ReadBDADDR => Some((15, 1)),
+            // Always supported.
+            InformationalOpcode::ReadLocalSupportedCommands => None,
+        },
+        OGF::LEController => le_command_bit(LEControllerOpcode::try_from(opcode.1).ok()?),
+        _ => None,
+    }
+}
+fn le_command_bit(opcode: LEControllerOpcode) -> Option<(usize, u8)> {
+    use LEControllerOpcode as O;
+    Some(match opcode {
+        O::SetEventMask => (25, 0),
+        O::ReadBufferSizeV1 => (25, 1),
+        O::ReadLocalSupportedFeatures => (25, 2),
+        O::SetRandomAddress => (25, 4),
+        O::SetAdvertisingParameters => (25, 5),
+        O::ReadAdvertisingChannelTxPower => (25, 6),
+        O::SetAdvertisingData => (25, 7),
+        O::SetScanResponseData => (26, 0),
+        O::SetAdvertisingEnable => (26, 1),
+        O::SetScanParameters => (26, 2),
+        O::SetScanEnable => (26, 3),
+        O::CreateConnection => (26, 4),
+        O::CreateConnectionCancel => (26, 5),
+        O::ReadWhitelistSize => (26, 6),
+        O::ClearWhitelist => (26, 7),
+        O::AddDeviceToWhitelist => (27, 0),
+        O::RemoveDeviceFromWhitelist => (27, 1),
+        O::ConnectionUpdate => (27, 2),
+        O::SetHostChannelClassification => (27, 3),
+        O::ReadChannelMap => (27, 4),
+        O::ReadRemoteUsedFeatures => (27, 5),
+        O::Encrypt => (27, 6),
+        O::Rand => (27, 7),
+        O::StartEncryption => (28, 0),
+        O::LongTermKeyRequestReply => (28, 1),
+        O::LongTermKeyRequestNegativeReply => (28, 2),
+        O::ReadSupportedState => (28, 3),
+        O::ReceiverTestV1 => (28, 4),
+        O::TransmitterTestV1 => (28, 5),
+        O::TestEnd => (28, 6),
+        O::SetDataLength => (33, 6),
+        O::ReadSuggestedDefaultDataLength => (33, 7),
+        O::WriteSuggestedDefaultDataLength => (34, 0),
+        O::ReadMaximumDataLength => (35, 3),
+        O::ReadPHY => (35, 4),
+        O::SetDefaultPHY => (35, 5),
+        O::SetPHY => (35, 6),
+        O::ReceiverTestV2 => (35, 7),
+        O::TransmitterTestV2 => (36, 0),
+        O::ReceiverTestV3 => (39, 3),
+        O::TransmitterTestV3 => (39, 4),
+        O::SetConnectionlessCTETransmitParameters => (39, 5),
+        O::SetConnectionlessCTETransmitEnable => (39, 6),
+        O::SetConnectionlessIQSamplingEnable => (39, 7),
+        O::SetConnectionCTEReceiveParameters => (40, 0),
+        O::SetConnectionCTETransmitParameters => (40, 1),
+        O::ConnectionCTERequestEnable => (40, 2),
+        O::ConnectionCTEResponseEnable => (40, 3),
+        O::ReadBufferSizeV2 => (41, 5),
+        O::SetCIGParameters => (41, 7),
+        O::CreateCIS => (42, 1),
+        O::RemoveCIG => (42, 2),
+        O::AcceptCISRequest => (42, 3),
+        O::RejectCISRequest => (42, 4),
+        O::CreateBIG => (42, 5),
+        O::TerminateBIG => (42, 7),
+        O::BIGCreateSync => (43, 0),
+        O::BIGTerminateSync => (43, 1),
+        O::RequestPeerSCA => (43, 2),
+        O::SetupISODataPath => (43, 3),
+        O::RemoveISODataPath => (43, 4),
+        O::EnhancedReadTransmitPowerLevel => (44, 3),
+        O::ReadRemoteTransmitPowerLevel => (44, 4),
+        O::SetPathLossReportingParameters => (44, 5),
+        O::SetPathLossReportingEnable => (44, 6),
+        O::SetTransmitPowerReportingEnable => (44, 7),
+    })
+}
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash, Default)]
+pub struct ReadLocalSupportedCommands();
+parameterless_command!(
+    ReadLocalSupportedCommands,
+    InformationalOpcode::ReadLocalSupportedCommands,
+    LocalSupportedCommands
+);
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct LocalSupportedCommands {
+    pub status: ErrorCode,
+    pub commands: SupportedCommands,
+}
+impl LocalSupportedCommands {
+    pub const BYTE_LEN: usize = ErrorCode::BYTE_LEN + SupportedCommands::BYTE_LEN;
+}
+impl ReturnParameters for LocalSupportedCommands {
+    fn byte_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        buf[0] = self.status.into();
+        buf[1..].copy_from_slice(&self.commands.0);
+        Ok(())
+    }
+
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        let mut commands = SupportedCommands::zeroed();
+        commands.0.copy_from_slice(&buf[1..]);
+        Ok(LocalSupportedCommands {
+            status: ErrorCode::try_from(buf[0]).map_err(|_| PackError::bad_index(0))?,
+            commands,
+        })
+    }
+}
+/// Bit index of some LMP features in [`LMPFeatures`]. Mostly the ones telling if the
+/// controller supports LE and/or BR/EDR.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+#[repr(u8)]
+pub enum LMPFeature {
+    Encryption = 2,
+    BREDRNotSupported = 37,
+    LESupportedController = 38,
+    ExtendedInquiryResponse = 48,
+    SimultaneousLEAndBREDRController = 49,
+    SecureSimplePairingController = 51,
+    ExtendedFeatures = 63,
+}
+impl From<LMPFeature> for u8 {
+    fn from(f: LMPFeature) -> Self {
+        f as u8
+    }
+}
+/// 64-bit LMP features (page 0) bit field.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash, Default)]
+pub struct LMPFeatures(pub u64);
+impl LMPFeatures {
+    pub const BYTE_LEN: usize = 8;
+    pub fn get(self, feature: LMPFeature) -> bool {
+        self.0 & (1 << u8::from(feature)) != 0
+    }
+}
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash, Default)]
+pub struct ReadLocalSupportedFeatures();
+parameterless_command!(
+    ReadLocalSupportedFeatures,
+    InformationalOpcode::ReadLocalSupportedFeatures,
+    LocalSupportedFeatures
+);
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct LocalSupportedFeatures {
+    pub status: ErrorCode,
+    pub features: LMPFeatures,
+}
+impl LocalSupportedFeatures {
+    pub const BYTE_LEN: usize = ErrorCode::BYTE_LEN + LMPFeatures::BYTE_LEN;
+}
+impl ReturnParameters for LocalSupportedFeatures {
+    fn byte_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        buf[0] = self.status.into();
+        buf[1..].copy_from_slice(&self.features.0.to_le_bytes());
+        Ok(())
+    }
+
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        let mut features = [0_u8; LMPFeatures::BYTE_LEN];
+        features.copy_from_slice(&buf[1..]);
+        Ok(LocalSupportedFeatures {
+            status: ErrorCode::try_from(buf[0]).map_err(|_| PackError::bad_index(0))?,
+            features: LMPFeatures(u64::from_le_bytes(features)),
+        })
+    }
+}
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash, Default)]
+pub struct ReadBDADDR();
+parameterless_command!(ReadBDADDR, InformationalOpcode::ReadBDADDR, BDADDRReturn);
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct BDADDRReturn {
+    pub status: ErrorCode,
+    /// Public device address of the controller.
+    pub address: BTAddress,
+}
+impl BDADDRReturn {
+    pub const BYTE_LEN: usize = ErrorCode::BYTE_LEN + BT_ADDRESS_LEN;
+}
+impl ReturnParameters for BDADDRReturn {
+    fn byte_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        buf[0] = self.status.into();
+        self.address.pack_into(&mut buf[1..])
+    }
+
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        Ok(BDADDRReturn {
+            status: ErrorCode::try_from(buf[0]).map_err(|_| PackError::bad_index(0))?,
+            address: BTAddress::unpack_from(&buf[1..])?,
+        })
+    }
+}
+/// Snapshot of the local controller information. Lets higher layers check if a command or
+/// feature is supported before using it.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct ControllerInfo {
+    pub version: LocalVersion,
+    pub commands: SupportedCommands,
+    pub lmp_features: LMPFeatures,
+    pub address: BTAddress,
+    pub le_features: LEFeatures,
+    pub le_states: LESupportedStates,
+}
+impl ControllerInfo {
+    /// Returns `adapter::Error::UnsupportedCommand` if the controller reports it doesn't support
+    /// the command with `opcode`. Commands not in the known list are assumed supported.
+    pub fn require_command(&self, opcode: Opcode) -> Result<(), adapter::Error> {
+        match self.commands.supports(opcode) {
+            Some(false) => Err(adapter::Error::UnsupportedCommand(opcode)),
+            _ => Ok(()),
+        }
+    }
+    /// Returns `adapter::Error::UnsupportedFeature` if the controller doesn't support `feature`.
+    pub fn require_le_feature(&self, feature: LEFeature) -> Result<(), adapter::Error> {
+        if self.le_features.get(feature) {
+            Ok(())
+        } else {
+            Err(adapter::Error::UnsupportedFeature(feature))
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hci::baseband::{Reset, SetEventMask};
+    use crate::hci::le::connection::RequestPeerSCA;
+    use crate::hci::le::cte::{
+        ConnectionCTERequestEnable, ConnectionCTEResponseEnable, SetConnectionCTEReceiveParameters,
+        SetConnectionCTETransmitParameters, SetConnectionlessCTETransmitEnable,
+        SetConnectionlessCTETransmitParameters, SetConnectionlessIQSamplingEnable,
+    };
+    use crate::hci::le::phy::SetPHY;
+    use crate::hci::test_packets::{assert_command, assert_return};
+
+    #[test]
+    fn local_version_information() {
+        assert_command(&ReadLocalVersionInformation(), &[0x01, 0x10, 0x00]);
+        let version = LocalVersion {
+            hci_version: 0x0B,
+            hci_subversion: 0x1234,
+            lmp_version: 0x0B,
+            manufacturer_name: 0x0002,
+            lmp_subversion: 0x0100,
+        };
+        assert_return(
+            &LocalVersionInformation {
+                status: ErrorCode::Ok,
+                version,
+            },
+            &[0x00, 0x0B, 0x34, 0x12, 0x0B, 0x02, 0x00, 0x00, 0x01],
+        );
+        assert_eq!(version.hci_version(), Some(Version::Bluetooth5v2));
+    }
+    #[test]
+    fn local_supported_commands() {
+        assert_command(&ReadLocalSupportedCommands(), &[0x02, 0x10, 0x00]);
+        let mut commands = SupportedCommands::zeroed();
+        commands.0[5] = 0x80;
+        commands.0[43] = 0x04;
+        let mut bytes = [0_u8; LocalSupportedCommands::BYTE_LEN];
+        bytes[1 + 5] = 0x80;
+        bytes[1 + 43] = 0x04;
+        assert_return(
+            &LocalSupportedCommands {
+                status: ErrorCode::Ok,
+                commands,
+            },
+            &bytes[..],
+        );
+    }
+    #[test]
+    fn local_supported_features() {
+        assert_command(&ReadLocalSupportedFeatures(), &[0x03, 0x10, 0x00]);
+        let features = LMPFeatures(1 << 37 | 1 << 38);
+        assert_return(
+            &LocalSupportedFeatures {
+                status: ErrorCode::Ok,
+                features,
+            },
+            &[0x00, 0x00, 0x00, 0x00, 0x00, 0x60, 0x00, 0x00, 0x00],
+        );
+        assert!(features.get(LMPFeature::LESupportedController));
+        assert!(!features.get(LMPFeature::Encryption));
+    }
+    #[test]
+    fn read_bd_addr() {
+        assert_command(&ReadBDADDR(), &[0x09, 0x10, 0x00]);
+        assert_return(
+            &BDADDRReturn {
+                status: ErrorCode::Ok,
+                address: BTAddress([0x11, 0x22, 0x33, 0x44, 0x55, 0x66]),
+            },
+            &[0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66],
+        );
+    }
+    /// Octet and bit of the Supported Commands table (Core Spec v5.2 Vol 4 Part E 6.27).
+    #[test]
+    fn command_bits() {
+        let table = [
+            (SetEventMask::opcode(), 5, 6),
+            (Reset::opcode(), 5, 7),
+            (ReadLocalVersionInformation::opcode(), 14, 3),
+            (ReadLocalSupportedFeatures::opcode(), 14, 5),
+            (InformationalOpcode::ReadBufferSize.into(), 14, 7),
+            (ReadBDADDR::opcode(), 15, 1),
+            (LEControllerOpcode::SetEventMask.into(), 25, 0),
+            (LEControllerOpcode::ReadSupportedState.into(), 28, 3),
+            (LEControllerOpcode::SetDataLength.into(), 33, 6),
+            (LEControllerOpcode::ReadMaximumDataLength.into(), 35, 3),
+            (SetPHY::opcode(), 35, 6),
+            (LEControllerOpcode::ReceiverTestV3.into(), 39, 3),
+            (LEControllerOpcode::TransmitterTestV3.into(), 39, 4),
+            (SetConnectionlessCTETransmitParameters::opcode(), 39, 5),
+            (SetConnectionlessCTETransmitEnable::opcode(), 39, 6),
+            (SetConnectionlessIQSamplingEnable::opcode(), 39, 7),
+            (SetConnectionCTEReceiveParameters::opcode(), 40, 0),
+            (SetConnectionCTETransmitParameters::opcode(), 40, 1),
+            (ConnectionCTERequestEnable::opcode(), 40, 2),
+            (ConnectionCTEResponseEnable::opcode(), 40, 3),
+            (RequestPeerSCA::opcode(), 43, 2),
+        ];
+        for &(opcode, octet, bit) in table.iter() {
+            assert_eq!(command_bit(opcode), Some((octet, bit)), "{:?}", opcode);
+            let mut commands = SupportedCommands::zeroed();
+            assert_eq!(commands.supports(opcode), Some(false));
+            commands.0[octet] = 1 << bit;
+            assert_eq!(commands.supports(opcode), Some(true));
+        }
+        assert_eq!(command_bit(ReadLocalSupportedCommands::opcode()), None);
+        let info = ControllerInfo {
+            version: LocalVersion::default(),
+            commands: SupportedCommands::zeroed(),
+            lmp_features: LMPFeatures::default(),
+            address: BTAddress::ZEROED,
+            le_features: LEFeatures::default(),
+            le_states: LESupportedStates::default(),
+        };
+        assert_eq!(
+            info.require_command(Reset::opcode()),
+            Err(adapter::Error::UnsupportedCommand(Reset::opcode()))
+        );
+        assert_eq!(
+            info.require_command(ReadLocalSupportedCommands::opcode()),
+            Ok(())
+        );
+    }
+}
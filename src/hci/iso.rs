@@ -0,0 +1,433 @@
+//! HCI ISO Data packets. Carry (fragments of) Isochronous SDUs for CIS and BIS connections.
+use crate::bytes::Storage;
+use crate::hci::packet::{PacketType, RawPacket};
+use crate::le::connection::ConnectionHandle;
+use crate::{ConversionError, PackError};
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::convert::{TryFrom, TryInto};
+
+/// ISO Packet Boundary Flag. Tells if the ISO packet is a whole SDU or a fragment of one.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+#[repr(u8)]
+pub enum ISOPacketBoundary {
+    FirstFragment = 0b00,
+    ContinuationFragment = 0b01,
+    Complete = 0b10,
+    LastFragment = 0b11,
+}
+impl ISOPacketBoundary {
+    /// If the packet starts an SDU (and has an [`ISOSDUHeader`]).
+    pub fn is_start(self) -> bool {
+        match self {
+            ISOPacketBoundary::FirstFragment | ISOPacketBoundary::Complete => true,
+            ISOPacketBoundary::ContinuationFragment | ISOPacketBoundary::LastFragment => false,
+        }
+    }
+    /// If the packet ends an SDU.
+    pub fn is_end(self) -> bool {
+        match self {
+            ISOPacketBoundary::Complete | ISOPacketBoundary::LastFragment => true,
+            ISOPacketBoundary::FirstFragment | ISOPacketBoundary::ContinuationFragment => false,
+        }
+    }
+}
+impl From<ISOPacketBoundary> for u8 {
+    fn from(b: ISOPacketBoundary) -> Self {
+        b as u8
+    }
+}
+impl TryFrom<u8> for ISOPacketBoundary {
+    type Error = ConversionError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0b00 => Ok(ISOPacketBoundary::FirstFragment),
+            0b01 => Ok(ISOPacketBoundary::ContinuationFragment),
+            0b10 => Ok(ISOPacketBoundary::Complete),
+            0b11 => Ok(ISOPacketBoundary::LastFragment),
+            _ => Err(ConversionError(())),
+        }
+    }
+}
+/// Status of a received SDU. Always `Valid` for packets sent by the host.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+#[repr(u8)]
+pub enum ISOPacketStatus {
+    Valid = 0b00,
+    PossiblyInvalid = 0b01,
+    Lost = 0b10,
+}
+impl Default for ISOPacketStatus {
+    fn default() -> Self {
+        ISOPacketStatus::Valid
+    }
+}
+impl From<ISOPacketStatus> for u8 {
+    fn from(s: ISOPacketStatus) -> Self {
+        s as u8
+    }
+}
+impl TryFrom<u8> for ISOPacketStatus {
+    type Error = ConversionError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0b00 => Ok(ISOPacketStatus::Valid),
+            0b01 => Ok(ISOPacketStatus::PossiblyInvalid),
+            0b10 => Ok(ISOPacketStatus::Lost),
+            _ => Err(ConversionError(())),
+        }
+    }
+}
+/// HCI ISO Data packet header.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct ISOHeader {
+    pub handle: ConnectionHandle,
+    pub boundary: ISOPacketBoundary,
+    pub has_timestamp: bool,
+    pub data_load_len: u16,
+}
+impl ISOHeader {
+    pub const BYTE_LEN: usize = 4;
+    pub const MAX_DATA_LOAD_LEN: u16 = 0x3FFF;
+    pub fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        if self.data_load_len > Self::MAX_DATA_LOAD_LEN {
+            return Err(PackError::InvalidFields);
+        }
+        let handle = u16::from(self.handle)
+            | (u16::from(u8::from(self.boundary)) << 12)
+            | (u16::from(self.has_timestamp) << 14);
+        buf[..2].copy_from_slice(&handle.to_le_bytes());
+        buf[2..4].copy_from_slice(&self.data_load_len.to_le_bytes());
+        Ok(())
+    }
+    pub fn unpack_from(buf: &[u8]) -> Result<Self, PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        let handle = u16::from_le_bytes([buf[0], buf[1]]);
+        Ok(ISOHeader {
+            handle: ConnectionHandle::new_checked(handle & 0x0FFF)
+                .ok_or(PackError::bad_index(0))?,
+            boundary: ISOPacketBoundary::try_from(((handle >> 12) & 0b11) as u8)
+                .map_err(|_| PackError::bad_index(1))?,
+            has_timestamp: handle & (1 << 14) != 0,
+            data_load_len: u16::from_le_bytes([buf[2], buf[3]]) & Self::MAX_DATA_LOAD_LEN,
+        })
+    }
+}
+/// Header of the first (or only) ISO Data packet of an SDU.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash, Default)]
+pub struct ISOSDUHeader {
+    pub sequence_number: u16,
+    /// Length of the whole SDU (not just this fragment).
+    pub sdu_len: u16,
+    pub status: ISOPacketStatus,
+}
+impl ISOSDUHeader {
+    pub const BYTE_LEN: usize = 4;
+    pub const MAX_SDU_LEN: u16 = 0x0FFF;
+    pub fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        if self.sdu_len > Self::MAX_SDU_LEN {
+            return Err(PackError::InvalidFields);
+        }
+        buf[..2].copy_from_slice(&self.sequence_number.to_le_bytes());
+        let len = self.sdu_len | (u16::from(u8::from(self.status)) << 14);
+        buf[2..4].copy_from_slice(&len.to_le_bytes());
+        Ok(())
+    }
+    pub fn unpack_from(buf: &[u8]) -> Result<Self, PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        let len = u16::from_le_bytes([buf[2], buf[3]]);
+        Ok(ISOSDUHeader {
+            sequence_number: u16::from_le_bytes([buf[0], buf[1]]),
+            sdu_len: len & Self::MAX_SDU_LEN,
+            status: ISOPacketStatus::try_from((len >> 14) as u8)
+                .map_err(|_| PackError::bad_index(3))?,
+        })
+    }
+}
+/// HCI ISO Data packet. `sdu_header` is only present if `boundary.is_start()`.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct ISOPacket<Buf> {
+    pub handle: ConnectionHandle,
+    pub boundary: ISOPacketBoundary,
+    /// Time stamp in microseconds.
+    pub timestamp: Option<u32>,
+    pub sdu_header: Option<ISOSDUHeader>,
+    pub data: Buf,
+}
+impl<Buf: AsRef<[u8]>> ISOPacket<Buf> {
+    /// ISO Data packet holding a whole SDU.
+    pub fn new_complete(
+        handle: ConnectionHandle,
+        sequence_number: u16,
+        timestamp: Option<u32>,
+        data: Buf,
+    ) -> Self {
+        let sdu_len = data
+            .as_ref()
+            .len()
+            .try_into()
+            .expect("ISO SDU bigger than an u16");
+        ISOPacket {
+            handle,
+            boundary: ISOPacketBoundary::Complete,
+            timestamp,
+            sdu_header: Some(ISOSDUHeader {
+                sequence_number,
+                sdu_len,
+                status: ISOPacketStatus::Valid,
+            }),
+            data,
+        }
+    }
+    pub fn as_ref(&self) -> ISOPacket<&[u8]> {
+        ISOPacket {
+            handle: self.handle,
+            boundary: self.boundary,
+            timestamp: self.timestamp,
+            sdu_header: self.sdu_header,
+            data: self.data.as_ref(),
+        }
+    }
+    pub fn sequence_number(&self) -> Option<u16> {
+        self.sdu_header.map(|h| h.sequence_number)
+    }
+    fn data_load_len(&self) -> usize {
+        self.timestamp.map_or(0, |_| 4)
+            + self.sdu_header.map_or(0, |_| ISOSDUHeader::BYTE_LEN)
+            + self.data.as_ref().len()
+    }
+    pub fn header(&self) -> ISOHeader {
+        ISOHeader {
+            handle: self.handle,
+            boundary: self.boundary,
+            has_timestamp: self.timestamp.is_some(),
+            data_load_len: self
+                .data_load_len()
+                .try_into()
+                .expect("ISO data load bigger than an u16"),
+        }
+    }
+    pub fn byte_len(&self) -> usize {
+        ISOHeader::BYTE_LEN + self.data_load_len()
+    }
+    pub fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(self.byte_len(), buf)?;
+        if self.data_load_len() > usize::from(ISOHeader::MAX_DATA_LOAD_LEN)
+            || self.sdu_header.is_some() != self.boundary.is_start()
+        {
+            return Err(PackError::InvalidFields);
+        }
+        self.header().pack_into(&mut buf[..ISOHeader::BYTE_LEN])?;
+        let mut index = ISOHeader::BYTE_LEN;
+        if let Some(timestamp) = self.timestamp {
+            buf[index..index + 4].copy_from_slice(&timestamp.to_le_bytes());
+            index += 4;
+        }
+        if let Some(sdu_header) = self.sdu_header {
+            sdu_header.pack_into(&mut buf[index..index + ISOSDUHeader::BYTE_LEN])?;
+            index += ISOSDUHeader::BYTE_LEN;
+        }
+        buf[index..].copy_from_slice(self.data.as_ref());
+        Ok(())
+    }
+    pub fn to_raw_packet<NewStorage: Storage<u8>>(
+        &self,
+    ) -> Result<RawPacket<NewStorage>, PackError> {
+        let mut buf = NewStorage::with_size(self.byte_len());
+        self.pack_into(buf.as_mut())?;
+        Ok(RawPacket {
+            packet_type: PacketType::ISOData,
+            buf,
+        })
+    }
+    pub fn to_new_storage<NewStorage: Storage<u8>>(&self) -> ISOPacket<NewStorage> {
+        ISOPacket {
+            handle: self.handle,
+            boundary: self.boundary,
+            timestamp: self.timestamp,
+            sdu_header: self.sdu_header,
+            data: NewStorage::from_slice(self.data.as_ref()),
+        }
+    }
+}
+impl<'a> ISOPacket<&'a [u8]> {
+    pub fn unpack_from(buf: &'a [u8]) -> Result<Self, PackError> {
+        if buf.len() < ISOHeader::BYTE_LEN {
+            return Err(PackError::BadLength {
+                expected: ISOHeader::BYTE_LEN,
+                got: buf.len(),
+            });
+        }
+        let header = ISOHeader::unpack_from(&buf[..ISOHeader::BYTE_LEN])?;
+        let data_load = &buf[ISOHeader::BYTE_LEN..];
+        PackError::expect_length(usize::from(header.data_load_len), data_load)?;
+        let mut index = 0;
+        let timestamp = if header.has_timestamp {
+            let timestamp = data_load
+                .get(..4)
+                .ok_or(PackError::bad_index(ISOHeader::BYTE_LEN))?;
+            index += 4;
+            Some(u32::from_le_bytes(
+                timestamp.try_into().expect("hardcoded length"),
+            ))
+        } else {
+            None
+        };
+        let sdu_header = if header.boundary.is_start() {
+            let sdu_header = data_load
+                .get(index..index + ISOSDUHeader::BYTE_LEN)
+                .ok_or(PackError::bad_index(ISOHeader::BYTE_LEN + index))?;
+            index += ISOSDUHeader::BYTE_LEN;
+            Some(ISOSDUHeader::unpack_from(sdu_header)?)
+        } else {
+            None
+        };
+        Ok(ISOPacket {
+            handle: header.handle,
+            boundary: header.boundary,
+            timestamp,
+            sdu_header,
+            data: &data_load[index..],
+        })
+    }
+}
+/// Whole Isochronous SDU reassembled from ISO Data packets by [`ISOSDUReassembler`].
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct ISOSDU {
+    pub handle: ConnectionHandle,
+    pub sequence_number: u16,
+    /// Time stamp in microseconds.
+    pub timestamp: Option<u32>,
+    pub status: ISOPacketStatus,
+    pub data: Box<[u8]>,
+}
+struct PartialSDU {
+    sequence_number: u16,
+    timestamp: Option<u32>,
+    status: ISOPacketStatus,
+    sdu_len: usize,
+    data: Vec<u8>,
+}
+/// Reassembles fragmented ISO Data packets into [`ISOSDU`]s. Keeps one SDU in progress per
+/// `ConnectionHandle` (CIS or BIS).
+#[derive(Default)]
+pub struct ISOSDUReassembler {
+    partial: BTreeMap<ConnectionHandle, PartialSDU>,
+}
+impl ISOSDUReassembler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Adds a ISO Data packet. Returns the whole SDU once its last fragment arrives. A start
+    /// fragment drops any unfinished SDU on the same handle. Returns `PackError::InvalidFields`
+    /// for a continuation without a start fragment or an SDU longer than its `sdu_len`.
+    pub fn push<Buf: AsRef<[u8]>>(
+        &mut self,
+        packet: &ISOPacket<Buf>,
+    ) -> Result<Option<ISOSDU>, PackError> {
+        let data = packet.data.as_ref();
+        // Either way, the SDU in progress (if any) is taken out.
+        let unfinished = self.partial.remove(&packet.handle);
+        let mut partial = match packet.sdu_header {
+            Some(sdu_header) => PartialSDU {
+                sequence_number: sdu_header.sequence_number,
+                timestamp: packet.timestamp,
+                status: sdu_header.status,
+                sdu_len: usize::from(sdu_header.sdu_len),
+                data: Vec::with_capacity(usize::from(sdu_header.sdu_len)),
+            },
+            None => unfinished.ok_or(PackError::InvalidFields)?,
+        };
+        partial.data.extend_from_slice(data);
+        if partial.data.len() > partial.sdu_len {
+            return Err(PackError::InvalidFields);
+        }
+        if packet.boundary.is_end() {
+            Ok(Some(ISOSDU {
+                handle: packet.handle,
+                sequence_number: partial.sequence_number,
+                timestamp: partial.timestamp,
+                status: partial.status,
+                data: partial.data.into_boxed_slice(),
+            }))
+        } else {
+            self.partial.insert(packet.handle, partial);
+            Ok(None)
+        }
+    }
+    /// Drops the unfinished SDU (if any) on `handle`. Should be called when the CIS or BIS is
+    /// disconnected.
+    pub fn clear(&mut self, handle: ConnectionHandle) {
+        self.partial.remove(&handle);
+    }
+}
+impl<'a> TryFrom<RawPacket<&'a [u8]>> for ISOPacket<&'a [u8]> {
+    type Error = PackError;
+
+    fn try_from(packet: RawPacket<&'a [u8]>) -> Result<Self, Self::Error> {
+        if packet.packet_type != PacketType::ISOData {
+            Err(PackError::BadOpcode)
+        } else {
+            ISOPacket::unpack_from(packet.buf)
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet(
+        boundary: ISOPacketBoundary,
+        sequence_number: u16,
+        sdu_len: u16,
+        data: &[u8],
+    ) -> ISOPacket<&[u8]> {
+        ISOPacket {
+            handle: ConnectionHandle::new(0x0060),
+            boundary,
+            timestamp: None,
+            sdu_header: if boundary.is_start() {
+                Some(ISOSDUHeader {
+                    sequence_number,
+                    sdu_len,
+                    status: ISOPacketStatus::Valid,
+                })
+            } else {
+                None
+            },
+            data,
+        }
+    }
+
+    #[test]
+    fn complete_drops_unfinished_sdu() {
+        let mut reassembler = ISOSDUReassembler::new();
+        let first = packet(ISOPacketBoundary::FirstFragment, 1, 4, &[1, 2]);
+        assert_eq!(reassembler.push(&first), Ok(None));
+        let complete = packet(ISOPacketBoundary::Complete, 2, 2, &[3, 4]);
+        let sdu = reassembler.push(&complete).unwrap().unwrap();
+        assert_eq!(
+            (sdu.sequence_number, sdu.data.as_ref()),
+            (2, &[3_u8, 4][..])
+        );
+        // The SDU started before the complete packet is gone.
+        let last = packet(ISOPacketBoundary::LastFragment, 0, 0, &[5, 6]);
+        assert_eq!(reassembler.push(&last), Err(PackError::InvalidFields));
+
+        assert_eq!(reassembler.push(&first), Ok(None));
+        let restart = packet(ISOPacketBoundary::FirstFragment, 3, 3, &[7]);
+        assert_eq!(reassembler.push(&restart), Ok(None));
+        let continuation = packet(ISOPacketBoundary::ContinuationFragment, 0, 0, &[8]);
+        assert_eq!(reassembler.push(&continuation), Ok(None));
+        let sdu = reassembler.push(&packet(ISOPacketBoundary::LastFragment, 0, 0, &[9]));
+        let sdu = sdu.unwrap().unwrap();
+        assert_eq!(
+            (sdu.sequence_number, sdu.data.as_ref()),
+            (3, &[7_u8, 8, 9][..])
+        );
+    }
+}
@@ -0,0 +1,203 @@
+//! HCI LE data channel commands and events.
+use crate::channel::ChannelMap;
+use crate::hci::command::Command;
+use crate::hci::event::{CommandComplete, ReturnParameters, StatusReturn};
+use crate::hci::le::{LEControllerOpcode, MetaEvent, MetaEventCode};
+use crate::hci::{ErrorCode, Opcode};
+use crate::le::connection::ConnectionHandle;
+use crate::le::link::channel_selection::ChannelSelectionAlgorithm;
+use crate::PackError;
+use core::convert::TryFrom;
+
+/// Tells the controller which data channels the host knows are bad (cleared) so they can be
+/// avoided. At least 2 channels must be left.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct SetHostChannelClassification(pub ChannelMap);
+impl SetHostChannelClassification {
+    pub const BYTE_LEN: usize = ChannelMap::BYTE_LEN;
+    pub const OPCODE: LEControllerOpcode = LEControllerOpcode::SetHostChannelClassification;
+    pub const MIN_USED_CHANNELS: u8 = 2;
+}
+impl Command for SetHostChannelClassification {
+    type Return = CommandComplete<StatusReturn>;
+
+    fn opcode() -> Opcode {
+        Self::OPCODE.into()
+    }
+
+    fn byte_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        if self.0.used_count() < Self::MIN_USED_CHANNELS {
+            return Err(PackError::InvalidFields);
+        }
+        self.0.pack_into(buf)
+    }
+
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        Ok(SetHostChannelClassification(ChannelMap::unpack_from(buf)?))
+    }
+}
+/// Reads the current data `ChannelMap` of a connection.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct ReadChannelMap {
+    pub handle: ConnectionHandle,
+}
+impl ReadChannelMap {
+    pub const BYTE_LEN: usize = ConnectionHandle::BYTE_LEN;
+    pub const OPCODE: LEControllerOpcode = LEControllerOpcode::ReadChannelMap;
+}
+impl Command for ReadChannelMap {
+    type Return = CommandComplete<ReadChannelMapReturn>;
+
+    fn opcode() -> Opcode {
+        Self::OPCODE.into()
+    }
+
+    fn byte_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        self.handle.pack_into(buf)
+    }
+
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        Ok(ReadChannelMap {
+            handle: ConnectionHandle::unpack_from(buf)?,
+        })
+    }
+}
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct ReadChannelMapReturn {
+    pub status: ErrorCode,
+    pub handle: ConnectionHandle,
+    pub channel_map: ChannelMap,
+}
+impl ReadChannelMapReturn {
+    pub const BYTE_LEN: usize =
+        ErrorCode::BYTE_LEN + ConnectionHandle::BYTE_LEN + ChannelMap::BYTE_LEN;
+}
+impl ReturnParameters for ReadChannelMapReturn {
+    fn byte_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        buf[0] = self.status.into();
+        self.handle.pack_into(&mut buf[1..3])?;
+        self.channel_map.pack_into(&mut buf[3..])
+    }
+
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        Ok(ReadChannelMapReturn {
+            status: ErrorCode::try_from(buf[0]).map_err(|_| PackError::bad_index(0))?,
+            handle: ConnectionHandle::unpack_from(&buf[1..3])?,
+            channel_map: ChannelMap::unpack_from(&buf[3..])?,
+        })
+    }
+}
+/// Channel Selection Algorithm event. Sent after a connection is established to tell which
+/// algorithm it uses.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct ChannelSelectionAlgorithmEvent {
+    pub handle: ConnectionHandle,
+    pub algorithm: ChannelSelectionAlgorithm,
+}
+impl ChannelSelectionAlgorithmEvent {
+    pub const BYTE_LEN: usize = ConnectionHandle::BYTE_LEN + ChannelSelectionAlgorithm::BYTE_LEN;
+}
+impl MetaEvent for ChannelSelectionAlgorithmEvent {
+    const META_CODE: MetaEventCode = MetaEventCode::ChannelSelectionAlgorithm;
+
+    fn meta_byte_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+
+    fn meta_unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        Ok(ChannelSelectionAlgorithmEvent {
+            handle: ConnectionHandle::unpack_from(&buf[0..2])?,
+            algorithm: ChannelSelectionAlgorithm::try_from(buf[2])
+                .map_err(|_| PackError::bad_index(2))?,
+        })
+    }
+
+    fn meta_pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        self.handle.pack_into(&mut buf[0..2])?;
+        buf[2] = self.algorithm.into();
+        Ok(())
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel::Index;
+    use crate::hci::test_packets::{assert_command, assert_meta_event, assert_return};
+
+    #[test]
+    fn set_host_channel_classification() {
+        assert_command(
+            &SetHostChannelClassification(ChannelMap::ALL),
+            &[0x14, 0x20, 0x05, 0xFF, 0xFF, 0xFF, 0xFF, 0x1F],
+        );
+        // Reserved bits 37 to 39 are ignored.
+        assert_eq!(
+            SetHostChannelClassification::unpack_from(&[0xFF; 5]),
+            Ok(SetHostChannelClassification(ChannelMap::ALL))
+        );
+        let mut one_channel = ChannelMap::zeroed();
+        one_channel.enable(Index::new(10));
+        let mut buf = [0_u8; SetHostChannelClassification::BYTE_LEN];
+        assert_eq!(
+            SetHostChannelClassification(one_channel).pack_into(&mut buf),
+            Err(PackError::InvalidFields)
+        );
+    }
+    #[test]
+    fn read_channel_map() {
+        let handle = ConnectionHandle::new(0x0040);
+        assert_command(&ReadChannelMap { handle }, &[0x15, 0x20, 0x02, 0x40, 0x00]);
+        let mut channel_map = ChannelMap::zeroed();
+        channel_map.enable(Index::new(0));
+        channel_map.enable(Index::new(1));
+        channel_map.enable(Index::new(36));
+        let ret = ReadChannelMapReturn {
+            status: ErrorCode::Ok,
+            handle,
+            channel_map,
+        };
+        assert_return(&ret, &[0x00, 0x40, 0x00, 0x03, 0x00, 0x00, 0x00, 0x10]);
+        assert_eq!(
+            ReadChannelMapReturn::unpack_from(&[0x00, 0x40, 0x00, 0x03, 0x00, 0x00, 0x00, 0xF0]),
+            Ok(ret)
+        );
+    }
+    #[test]
+    fn channel_selection_algorithm() {
+        assert_meta_event(
+            &ChannelSelectionAlgorithmEvent {
+                handle: ConnectionHandle::new(0x0040),
+                algorithm: ChannelSelectionAlgorithm::Algorithm2,
+            },
+            &[0x14, 0x40, 0x00, 0x01],
+        );
+    }
+}
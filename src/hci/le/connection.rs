@@ -1,12 +1,14 @@
 use crate::hci::command::Command;
 use crate::hci::event::{CommandComplete, CommandStatus, ReturnParameters};
-use crate::hci::le::{LEControllerOpcode, MetaEventCode};
+use crate::hci::le::{LEControllerOpcode, MetaEvent, MetaEventCode};
 use crate::hci::{ErrorCode, Opcode};
 use crate::le::advertiser::PeerAddressType;
 use crate::le::connection::{
-    CELength, ConnectionHandle, ConnectionInterval, ConnectionLatency, InitiatorFilterPolicy,
-    MasterClockAccuracy, Role, SupervisionTimeout,
+    CELength, Connection, ConnectionHandle, ConnectionInterval, ConnectionLatency, DataLength,
+    InitiatorFilterPolicy, MasterClockAccuracy, Role, SupervisionTimeout,
 };
+use crate::le::features::LEFeatures;
+use crate::le::phy::PHY;
 use crate::le::scan::{OwnAddressType, ScanInterval, ScanWindow};
 use crate::{BTAddress, PackError, BT_ADDRESS_LEN};
 use core::convert::{TryFrom, TryInto};
@@ -248,3 +250,307 @@ impl ConnectionCompleteEvent {
         + SupervisionTimeout::BYTE_LEN
         + MasterClockAccuracy::BYTE_LEN;
 }
+impl ConnectionCompleteEvent {
+    /// State of the new connection. Only valid if `status` is `ErrorCode::Ok`.
+    pub fn connection(&self) -> Connection {
+        Connection {
+            handle: self.connection_handle,
+            role: self.role,
+            peer_address_type: self.peer_address_type,
+            peer_address: self.peer_address,
+            interval: self.connection_interval,
+            latency: self.connection_latency,
+            supervision_timeout: self.supervision_timeout,
+            tx_phy: PHY::LE1M,
+            rx_phy: PHY::LE1M,
+            data_length: DataLength::DEFAULT,
+            remote_features: None,
+            remote_version: None,
+            peer_sca: match self.role {
+                Role::Slave => Some(self.master_clock_accuracy),
+                Role::Master => None,
+            },
+        }
+    }
+}
+impl MetaEvent for ConnectionCompleteEvent {
+    const META_CODE: MetaEventCode = MetaEventCode::ConnectionComplete;
+
+    fn meta_byte_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+
+    fn meta_unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        let status = ErrorCode::try_from(buf[0]).map_err(|_| PackError::bad_index(0))?;
+        let ok = status.is_ok();
+        let u16_at = |i: usize| u16::from_le_bytes([buf[i], buf[i + 1]]);
+        Ok(ConnectionCompleteEvent {
+            status,
+            connection_handle: ConnectionHandle::unpack_from(&buf[1..3])?,
+            role: Role::try_from(buf[3]).map_err(|_| PackError::bad_index(3))?,
+            peer_address_type: PeerAddressType::try_from(buf[4])
+                .map_err(|_| PackError::bad_index(4))?,
+            peer_address: BTAddress::unpack_from(&buf[5..11])?,
+            // The controller may zero the connection parameters on an error.
+            connection_interval: match ConnectionInterval::new_checked(u16_at(11)) {
+                Some(interval) => interval,
+                None if !ok => ConnectionInterval::MIN,
+                None => return Err(PackError::bad_index(11)),
+            },
+            connection_latency: ConnectionLatency::new_checked(u16_at(13))
+                .ok_or(PackError::bad_index(13))?,
+            supervision_timeout: match SupervisionTimeout::new_checked(u16_at(15)) {
+                Some(timeout) => timeout,
+                None if !ok => SupervisionTimeout::MIN,
+                None => return Err(PackError::bad_index(15)),
+            },
+            master_clock_accuracy: MasterClockAccuracy::try_from(buf[17])
+                .map_err(|_| PackError::bad_index(17))?,
+        })
+    }
+
+    fn meta_pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        buf[0] = self.status.into();
+        self.connection_handle.pack_into(&mut buf[1..3])?;
+        buf[3] = self.role.into();
+        buf[4] = self.peer_address_type.into();
+        self.peer_address.pack_into(&mut buf[5..11])?;
+        buf[11..13].copy_from_slice(&u16::from(self.connection_interval).to_le_bytes());
+        buf[13..15].copy_from_slice(&u16::from(self.connection_latency).to_le_bytes());
+        buf[15..17].copy_from_slice(&u16::from(self.supervision_timeout).to_le_bytes());
+        buf[17] = self.master_clock_accuracy.into();
+        Ok(())
+    }
+}
+/// Reads the LE features of the peer. The controller returns a `CommandStatus` and then a
+/// [`ReadRemoteFeaturesComplete`] event.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct ReadRemoteFeatures {
+    pub handle: ConnectionHandle,
+}
+impl ReadRemoteFeatures {
+    pub const BYTE_LEN: usize = ConnectionHandle::BYTE_LEN;
+    pub const OPCODE: LEControllerOpcode = LEControllerOpcode::ReadRemoteUsedFeatures;
+}
+impl Command for ReadRemoteFeatures {
+    type Return = CommandStatus;
+
+    fn opcode() -> Opcode {
+        Self::OPCODE.into()
+    }
+
+    fn byte_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        self.handle.pack_into(buf)
+    }
+
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        Ok(ReadRemoteFeatures {
+            handle: ConnectionHandle::unpack_from(buf)?,
+        })
+    }
+}
+/// Read Remote Features Complete event.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct ReadRemoteFeaturesComplete {
+    pub status: ErrorCode,
+    pub handle: ConnectionHandle,
+    pub features: LEFeatures,
+}
+impl ReadRemoteFeaturesComplete {
+    pub const BYTE_LEN: usize =
+        ErrorCode::BYTE_LEN + ConnectionHandle::BYTE_LEN + LEFeatures::BYTE_LEN;
+}
+impl MetaEvent for ReadRemoteFeaturesComplete {
+    const META_CODE: MetaEventCode = MetaEventCode::ReadRemoteFeatures;
+
+    fn meta_byte_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+
+    fn meta_unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        Ok(ReadRemoteFeaturesComplete {
+            status: ErrorCode::try_from(buf[0]).map_err(|_| PackError::bad_index(0))?,
+            handle: ConnectionHandle::unpack_from(&buf[1..3])?,
+            features: LEFeatures::unpack_from(&buf[3..])?,
+        })
+    }
+
+    fn meta_pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        buf[0] = self.status.into();
+        self.handle.pack_into(&mut buf[1..3])?;
+        self.features.pack_into(&mut buf[3..])
+    }
+}
+/// Requests the sleep clock accuracy of the peer. The result is reported with a
+/// [`RequestPeerSCAComplete`] event.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct RequestPeerSCA {
+    pub handle: ConnectionHandle,
+}
+impl RequestPeerSCA {
+    pub const BYTE_LEN: usize = ConnectionHandle::BYTE_LEN;
+    pub const OPCODE: LEControllerOpcode = LEControllerOpcode::RequestPeerSCA;
+}
+impl Command for RequestPeerSCA {
+    type Return = CommandStatus;
+
+    fn opcode() -> Opcode {
+        Self::OPCODE.into()
+    }
+
+    fn byte_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        self.handle.pack_into(buf)
+    }
+
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        Ok(RequestPeerSCA {
+            handle: ConnectionHandle::unpack_from(buf)?,
+        })
+    }
+}
+/// Request Peer SCA Complete event. `peer_clock_accuracy` is only valid if `status` is
+/// `ErrorCode::Ok`.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct RequestPeerSCAComplete {
+    pub status: ErrorCode,
+    pub handle: ConnectionHandle,
+    pub peer_clock_accuracy: MasterClockAccuracy,
+}
+impl RequestPeerSCAComplete {
+    pub const BYTE_LEN: usize =
+        ErrorCode::BYTE_LEN + ConnectionHandle::BYTE_LEN + MasterClockAccuracy::BYTE_LEN;
+}
+impl MetaEvent for RequestPeerSCAComplete {
+    const META_CODE: MetaEventCode = MetaEventCode::RequestPeerSCAComplete;
+
+    fn meta_byte_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+
+    fn meta_unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        let status = ErrorCode::try_from(buf[0]).map_err(|_| PackError::bad_index(0))?;
+        Ok(RequestPeerSCAComplete {
+            status,
+            handle: ConnectionHandle::unpack_from(&buf[1..3])?,
+            peer_clock_accuracy: match MasterClockAccuracy::try_from(buf[3]) {
+                Ok(sca) => sca,
+                Err(_) if status != ErrorCode::Ok => MasterClockAccuracy::PPM500,
+                Err(_) => return Err(PackError::bad_index(3)),
+            },
+        })
+    }
+
+    fn meta_pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        buf[0] = self.status.into();
+        self.handle.pack_into(&mut buf[1..3])?;
+        buf[3] = self.peer_clock_accuracy.into();
+        Ok(())
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hci::test_packets::{assert_command, assert_meta_event};
+    use crate::le::features::LEFeature;
+
+    #[test]
+    fn connection_complete() {
+        let event = ConnectionCompleteEvent {
+            status: ErrorCode::Ok,
+            connection_handle: ConnectionHandle::new(0x0040),
+            role: Role::Slave,
+            peer_address_type: PeerAddressType::Random,
+            peer_address: BTAddress([0x11, 0x22, 0x33, 0x44, 0x55, 0xC6]),
+            connection_interval: ConnectionInterval::new(0x0018),
+            connection_latency: ConnectionLatency::new(0x0000),
+            supervision_timeout: SupervisionTimeout::new(0x0048),
+            master_clock_accuracy: MasterClockAccuracy::PPM50,
+        };
+        assert_meta_event(
+            &event,
+            &[
+                0x01, 0x00, 0x40, 0x00, 0x01, 0x01, 0x11, 0x22, 0x33, 0x44, 0x55, 0xC6, 0x18, 0x00,
+                0x00, 0x00, 0x48, 0x00, 0x05,
+            ],
+        );
+        let connection = event.connection();
+        assert_eq!(connection.interval, event.connection_interval);
+        assert_eq!(
+            (connection.tx_phy, connection.rx_phy),
+            (PHY::LE1M, PHY::LE1M)
+        );
+    }
+    #[test]
+    fn read_remote_features() {
+        let handle = ConnectionHandle::new(0x0040);
+        assert_command(
+            &ReadRemoteFeatures { handle },
+            &[0x16, 0x20, 0x02, 0x40, 0x00],
+        );
+        let mut features = LEFeatures::zeroed();
+        features.enable(LEFeature::Encryption);
+        features.enable(LEFeature::LE2MPHY);
+        features.enable(LEFeature::ChannelSelectionAlgorithm2);
+        assert_meta_event(
+            &ReadRemoteFeaturesComplete {
+                status: ErrorCode::Ok,
+                handle,
+                features,
+            },
+            &[
+                0x04, 0x00, 0x40, 0x00, 0x01, 0x41, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            ],
+        );
+    }
+    #[test]
+    fn request_peer_sca() {
+        let handle = ConnectionHandle::new(0x0040);
+        assert_command(&RequestPeerSCA { handle }, &[0x6D, 0x20, 0x02, 0x40, 0x00]);
+        assert_meta_event(
+            &RequestPeerSCAComplete {
+                status: ErrorCode::Ok,
+                handle,
+                peer_clock_accuracy: MasterClockAccuracy::PPM20,
+            },
+            &[0x1F, 0x00, 0x40, 0x00, 0x07],
+        );
+        // The accuracy is ignored if the request failed.
+        assert_eq!(
+            RequestPeerSCAComplete::meta_unpack_from(&[0x1A, 0x40, 0x00, 0xFF]),
+            Ok(RequestPeerSCAComplete {
+                status: ErrorCode::UnsupportedRemoteFeature,
+                handle,
+                peer_clock_accuracy: MasterClockAccuracy::PPM500,
+            })
+        );
+    }
+}
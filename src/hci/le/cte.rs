@@ -0,0 +1,678 @@
+//! HCI LE direction finding commands and events. Constant Tone Extensions (CTEs) can be sent
+//! with periodic advertising (connectionless) or requested over a connection. The receiver
+//! samples IQ data while switching antennas and reports it to the host for AoA/AoD positioning.
+use crate::channel::Index;
+use crate::hci::command::Command;
+use crate::hci::event::{CommandComplete, ConnectionHandleReturn, ReturnParameters, StatusReturn};
+use crate::hci::le::{LEControllerOpcode, MetaEvent, MetaEventCode};
+use crate::hci::{ErrorCode, Opcode};
+use crate::le::connection::ConnectionHandle;
+use crate::le::cte::{CTELength, CTEType, CTETypes, IQSample, SlotDuration, SwitchingPattern};
+use crate::le::phy::PHY;
+use crate::{ConversionError, PackError};
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+
+fn unpack_bool(buf: &[u8], index: usize) -> Result<bool, PackError> {
+    match buf[index] {
+        0 => Ok(false),
+        1 => Ok(true),
+        _ => Err(PackError::bad_index(index)),
+    }
+}
+fn u16_at(buf: &[u8], index: usize) -> u16 {
+    u16::from_le_bytes([buf[index], buf[index + 1]])
+}
+/// Max number of CTEs sent (or sampled) each periodic advertising interval.
+pub const MAX_CTE_COUNT: u8 = 0x10;
+/// Sets the CTE sent with the periodic advertising of `advertising_handle`. `cte_count` CTEs
+/// (1 to 16) are sent each periodic advertising interval.
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct SetConnectionlessCTETransmitParameters {
+    pub advertising_handle: u8,
+    pub cte_length: CTELength,
+    pub cte_type: CTEType,
+    pub cte_count: u8,
+    pub switching_pattern: SwitchingPattern,
+}
+impl SetConnectionlessCTETransmitParameters {
+    pub const OPCODE: LEControllerOpcode =
+        LEControllerOpcode::SetConnectionlessCTETransmitParameters;
+    pub const MIN_BYTE_LEN: usize = 4 + 1;
+}
+impl Command for SetConnectionlessCTETransmitParameters {
+    type Return = CommandComplete<StatusReturn>;
+
+    fn opcode() -> Opcode {
+        Self::OPCODE.into()
+    }
+
+    fn byte_len(&self) -> usize {
+        4 + self.switching_pattern.byte_len()
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(self.byte_len(), buf)?;
+        if self.cte_count == 0 || self.cte_count > MAX_CTE_COUNT {
+            return Err(PackError::InvalidFields);
+        }
+        buf[0] = self.advertising_handle;
+        buf[1] = self.cte_length.into();
+        buf[2] = self.cte_type.into();
+        buf[3] = self.cte_count;
+        self.switching_pattern.pack_into(&mut buf[4..])
+    }
+
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        if buf.len() < Self::MIN_BYTE_LEN {
+            return Err(PackError::BadLength {
+                expected: Self::MIN_BYTE_LEN,
+                got: buf.len(),
+            });
+        }
+        Ok(SetConnectionlessCTETransmitParameters {
+            advertising_handle: buf[0],
+            cte_length: CTELength::new_checked(buf[1]).ok_or(PackError::bad_index(1))?,
+            cte_type: CTEType::try_from(buf[2]).map_err(|_| PackError::bad_index(2))?,
+            cte_count: buf[3],
+            switching_pattern: SwitchingPattern::unpack_from(&buf[4..])?,
+        })
+    }
+}
+/// Starts or stops sending CTEs with the periodic advertising of `advertising_handle`.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct SetConnectionlessCTETransmitEnable {
+    pub advertising_handle: u8,
+    pub is_enabled: bool,
+}
+impl SetConnectionlessCTETransmitEnable {
+    pub const OPCODE: LEControllerOpcode = LEControllerOpcode::SetConnectionlessCTETransmitEnable;
+    pub const BYTE_LEN: usize = 2;
+}
+impl Command for SetConnectionlessCTETransmitEnable {
+    type Return = CommandComplete<StatusReturn>;
+
+    fn opcode() -> Opcode {
+        Self::OPCODE.into()
+    }
+
+    fn byte_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        buf[0] = self.advertising_handle;
+        buf[1] = self.is_enabled.into();
+        Ok(())
+    }
+
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        Ok(SetConnectionlessCTETransmitEnable {
+            advertising_handle: buf[0],
+            is_enabled: unpack_bool(buf, 1)?,
+        })
+    }
+}
+/// Starts or stops sampling the CTEs of the periodic advertising train synchronized to with
+/// `sync_handle`. `max_sampled_ctes` is the max CTEs sampled each interval (0 samples all).
+/// Samples are reported with [`ConnectionlessIQReport`].
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct SetConnectionlessIQSamplingEnable {
+    pub sync_handle: u16,
+    pub is_enabled: bool,
+    pub slot_durations: SlotDuration,
+    pub max_sampled_ctes: u8,
+    pub switching_pattern: SwitchingPattern,
+}
+impl SetConnectionlessIQSamplingEnable {
+    pub const OPCODE: LEControllerOpcode = LEControllerOpcode::SetConnectionlessIQSamplingEnable;
+    pub const MIN_BYTE_LEN: usize = 5 + 1;
+}
+impl Command for SetConnectionlessIQSamplingEnable {
+    type Return = CommandComplete<SyncHandleReturn>;
+
+    fn opcode() -> Opcode {
+        Self::OPCODE.into()
+    }
+
+    fn byte_len(&self) -> usize {
+        5 + self.switching_pattern.byte_len()
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(self.byte_len(), buf)?;
+        if self.max_sampled_ctes > MAX_CTE_COUNT {
+            return Err(PackError::InvalidFields);
+        }
+        buf[0..2].copy_from_slice(&self.sync_handle.to_le_bytes());
+        buf[2] = self.is_enabled.into();
+        buf[3] = self.slot_durations.into();
+        buf[4] = self.max_sampled_ctes;
+        self.switching_pattern.pack_into(&mut buf[5..])
+    }
+
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        if buf.len() < Self::MIN_BYTE_LEN {
+            return Err(PackError::BadLength {
+                expected: Self::MIN_BYTE_LEN,
+                got: buf.len(),
+            });
+        }
+        Ok(SetConnectionlessIQSamplingEnable {
+            sync_handle: u16_at(buf, 0),
+            is_enabled: unpack_bool(buf, 2)?,
+            slot_durations: SlotDuration::try_from(buf[3]).map_err(|_| PackError::bad_index(3))?,
+            max_sampled_ctes: buf[4],
+            switching_pattern: SwitchingPattern::unpack_from(&buf[5..])?,
+        })
+    }
+}
+/// Return parameters of commands that return a status and the periodic advertising sync handle
+/// the command was for.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct SyncHandleReturn {
+    pub status: ErrorCode,
+    pub sync_handle: u16,
+}
+impl SyncHandleReturn {
+    pub const BYTE_LEN: usize = ErrorCode::BYTE_LEN + 2;
+}
+impl ReturnParameters for SyncHandleReturn {
+    fn byte_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        buf[0] = self.status.into();
+        buf[1..3].copy_from_slice(&self.sync_handle.to_le_bytes());
+        Ok(())
+    }
+
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        Ok(SyncHandleReturn {
+            status: ErrorCode::try_from(buf[0]).map_err(|_| PackError::bad_index(0))?,
+            sync_handle: u16_at(buf, 1),
+        })
+    }
+}
+/// Starts or stops sampling the CTEs received on a connection. Samples are reported with
+/// [`ConnectionIQReport`].
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct SetConnectionCTEReceiveParameters {
+    pub handle: ConnectionHandle,
+    pub is_enabled: bool,
+    pub slot_durations: SlotDuration,
+    pub switching_pattern: SwitchingPattern,
+}
+impl SetConnectionCTEReceiveParameters {
+    pub const OPCODE: LEControllerOpcode = LEControllerOpcode::SetConnectionCTEReceiveParameters;
+    pub const MIN_BYTE_LEN: usize = ConnectionHandle::BYTE_LEN + 2 + 1;
+}
+impl Command for SetConnectionCTEReceiveParameters {
+    type Return = CommandComplete<ConnectionHandleReturn>;
+
+    fn opcode() -> Opcode {
+        Self::OPCODE.into()
+    }
+
+    fn byte_len(&self) -> usize {
+        ConnectionHandle::BYTE_LEN + 2 + self.switching_pattern.byte_len()
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(self.byte_len(), buf)?;
+        self.handle.pack_into(&mut buf[0..2])?;
+        buf[2] = self.is_enabled.into();
+        buf[3] = self.slot_durations.into();
+        self.switching_pattern.pack_into(&mut buf[4..])
+    }
+
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        if buf.len() < Self::MIN_BYTE_LEN {
+            return Err(PackError::BadLength {
+                expected: Self::MIN_BYTE_LEN,
+                got: buf.len(),
+            });
+        }
+        Ok(SetConnectionCTEReceiveParameters {
+            handle: ConnectionHandle::unpack_from(&buf[0..2])?,
+            is_enabled: unpack_bool(buf, 2)?,
+            slot_durations: SlotDuration::try_from(buf[3]).map_err(|_| PackError::bad_index(3))?,
+            switching_pattern: SwitchingPattern::unpack_from(&buf[4..])?,
+        })
+    }
+}
+/// Sets the CTE types the controller may send on a connection when the peer requests a CTE.
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct SetConnectionCTETransmitParameters {
+    pub handle: ConnectionHandle,
+    pub cte_types: CTETypes,
+    pub switching_pattern: SwitchingPattern,
+}
+impl SetConnectionCTETransmitParameters {
+    pub const OPCODE: LEControllerOpcode = LEControllerOpcode::SetConnectionCTETransmitParameters;
+    pub const MIN_BYTE_LEN: usize = ConnectionHandle::BYTE_LEN + CTETypes::BYTE_LEN + 1;
+}
+impl Command for SetConnectionCTETransmitParameters {
+    type Return = CommandComplete<ConnectionHandleReturn>;
+
+    fn opcode() -> Opcode {
+        Self::OPCODE.into()
+    }
+
+    fn byte_len(&self) -> usize {
+        ConnectionHandle::BYTE_LEN + CTETypes::BYTE_LEN + self.switching_pattern.byte_len()
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(self.byte_len(), buf)?;
+        if self.cte_types.is_empty() {
+            return Err(PackError::InvalidFields);
+        }
+        self.handle.pack_into(&mut buf[0..2])?;
+        buf[2] = self.cte_types.into();
+        self.switching_pattern.pack_into(&mut buf[3..])
+    }
+
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        if buf.len() < Self::MIN_BYTE_LEN {
+            return Err(PackError::BadLength {
+                expected: Self::MIN_BYTE_LEN,
+                got: buf.len(),
+            });
+        }
+        Ok(SetConnectionCTETransmitParameters {
+            handle: ConnectionHandle::unpack_from(&buf[0..2])?,
+            cte_types: CTETypes::try_from(buf[2]).map_err(|_| PackError::bad_index(2))?,
+            switching_pattern: SwitchingPattern::unpack_from(&buf[3..])?,
+        })
+    }
+}
+/// Starts or stops requesting CTEs from the peer of a connection. `interval` is the number of
+/// connection events between requests (0 only requests once).
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct ConnectionCTERequestEnable {
+    pub handle: ConnectionHandle,
+    pub is_enabled: bool,
+    pub interval: u16,
+    pub requested_cte_length: CTELength,
+    pub requested_cte_type: CTEType,
+}
+impl ConnectionCTERequestEnable {
+    pub const OPCODE: LEControllerOpcode = LEControllerOpcode::ConnectionCTERequestEnable;
+    pub const BYTE_LEN: usize = ConnectionHandle::BYTE_LEN + 1 + 2 + 1 + CTEType::BYTE_LEN;
+}
+impl Command for ConnectionCTERequestEnable {
+    type Return = CommandComplete<ConnectionHandleReturn>;
+
+    fn opcode() -> Opcode {
+        Self::OPCODE.into()
+    }
+
+    fn byte_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        self.handle.pack_into(&mut buf[0..2])?;
+        buf[2] = self.is_enabled.into();
+        buf[3..5].copy_from_slice(&self.interval.to_le_bytes());
+        buf[5] = self.requested_cte_length.into();
+        buf[6] = self.requested_cte_type.into();
+        Ok(())
+    }
+
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        Ok(ConnectionCTERequestEnable {
+            handle: ConnectionHandle::unpack_from(&buf[0..2])?,
+            is_enabled: unpack_bool(buf, 2)?,
+            interval: u16_at(buf, 3),
+            requested_cte_length: CTELength::new_checked(buf[5]).ok_or(PackError::bad_index(5))?,
+            requested_cte_type: CTEType::try_from(buf[6]).map_err(|_| PackError::bad_index(6))?,
+        })
+    }
+}
+/// Allows or stops the controller responding to CTE requests from the peer of a connection.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct ConnectionCTEResponseEnable {
+    pub handle: ConnectionHandle,
+    pub is_enabled: bool,
+}
+impl ConnectionCTEResponseEnable {
+    pub const OPCODE: LEControllerOpcode = LEControllerOpcode::ConnectionCTEResponseEnable;
+    pub const BYTE_LEN: usize = ConnectionHandle::BYTE_LEN + 1;
+}
+impl Command for ConnectionCTEResponseEnable {
+    type Return = CommandComplete<ConnectionHandleReturn>;
+
+    fn opcode() -> Opcode {
+        Self::OPCODE.into()
+    }
+
+    fn byte_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        self.handle.pack_into(&mut buf[0..2])?;
+        buf[2] = self.is_enabled.into();
+        Ok(())
+    }
+
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        Ok(ConnectionCTEResponseEnable {
+            handle: ConnectionHandle::unpack_from(&buf[0..2])?,
+            is_enabled: unpack_bool(buf, 2)?,
+        })
+    }
+}
+/// Status of the packet an IQ report was sampled from.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+#[repr(u8)]
+pub enum PacketStatus {
+    CRCCorrect = 0x00,
+    /// CRC was incorrect. The `Length` and `CTETime` fields of the packet were still used.
+    CRCIncorrectLengthUsed = 0x01,
+    /// CRC was incorrect. The controller determined the CTE length some other way.
+    CRCIncorrect = 0x02,
+    /// Not enough resources to sample. The report has no samples.
+    InsufficientResources = 0xFF,
+}
+impl From<PacketStatus> for u8 {
+    fn from(s: PacketStatus) -> Self {
+        s as u8
+    }
+}
+impl TryFrom<u8> for PacketStatus {
+    type Error = ConversionError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x00 => Ok(PacketStatus::CRCCorrect),
+            0x01 => Ok(PacketStatus::CRCIncorrectLengthUsed),
+            0x02 => Ok(PacketStatus::CRCIncorrect),
+            0xFF => Ok(PacketStatus::InsufficientResources),
+            _ => Err(ConversionError(())),
+        }
+    }
+}
+/// Fields shared by both IQ reports, starting at the `Channel_Index` field.
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct IQReport {
+    pub channel: Index,
+    /// RSSI of the packet in units of 0.1 dBm.
+    pub rssi: i16,
+    pub rssi_antenna_id: u8,
+    pub cte_type: CTEType,
+    pub slot_durations: SlotDuration,
+    pub packet_status: PacketStatus,
+    /// Periodic advertising event counter or connection event counter of the packet.
+    pub event_counter: u16,
+    pub samples: Vec<IQSample>,
+}
+impl IQReport {
+    pub const MIN_BYTE_LEN: usize = 1 + 2 + 1 + 1 + 1 + 1 + 2 + 1;
+    pub fn byte_len(&self) -> usize {
+        Self::MIN_BYTE_LEN + self.samples.len() * IQSample::BYTE_LEN
+    }
+    /// Angle of arrival/departure can only be estimated from valid samples of correct packets.
+    pub fn is_usable(&self) -> bool {
+        self.packet_status == PacketStatus::CRCCorrect
+            && !self.samples.is_empty()
+            && self.samples.iter().all(|s| s.is_valid())
+    }
+    pub fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(self.byte_len(), buf)?;
+        buf[0] = self.channel.into();
+        buf[1..3].copy_from_slice(&self.rssi.to_le_bytes());
+        buf[3] = self.rssi_antenna_id;
+        buf[4] = self.cte_type.into();
+        buf[5] = self.slot_durations.into();
+        buf[6] = self.packet_status.into();
+        buf[7..9].copy_from_slice(&self.event_counter.to_le_bytes());
+        buf[9] = u8::try_from(self.samples.len()).map_err(|_| PackError::InvalidFields)?;
+        for (sample, b) in self.samples.iter().zip(buf[10..].chunks_exact_mut(2)) {
+            b[0] = sample.i as u8;
+            b[1] = sample.q as u8;
+        }
+        Ok(())
+    }
+    pub fn unpack_from(buf: &[u8]) -> Result<IQReport, PackError> {
+        if buf.len() < Self::MIN_BYTE_LEN {
+            return Err(PackError::BadLength {
+                expected: Self::MIN_BYTE_LEN,
+                got: buf.len(),
+            });
+        }
+        let sample_count = usize::from(buf[9]);
+        PackError::expect_length(Self::MIN_BYTE_LEN + sample_count * IQSample::BYTE_LEN, buf)?;
+        Ok(IQReport {
+            channel: Index::new_checked(buf[0]).ok_or(PackError::bad_index(0))?,
+            rssi: i16::from_le_bytes([buf[1], buf[2]]),
+            rssi_antenna_id: buf[3],
+            cte_type: CTEType::try_from(buf[4]).map_err(|_| PackError::bad_index(4))?,
+            slot_durations: SlotDuration::try_from(buf[5]).map_err(|_| PackError::bad_index(5))?,
+            packet_status: PacketStatus::try_from(buf[6]).map_err(|_| PackError::bad_index(6))?,
+            event_counter: u16_at(buf, 7),
+            samples: buf[10..]
+                .chunks_exact(2)
+                .map(|b| IQSample {
+                    i: b[0] as i8,
+                    q: b[1] as i8,
+                })
+                .collect(),
+        })
+    }
+}
+/// IQ samples of a CTE received with periodic advertising.
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct ConnectionlessIQReport {
+    pub sync_handle: u16,
+    pub report: IQReport,
+}
+impl MetaEvent for ConnectionlessIQReport {
+    const META_CODE: MetaEventCode = MetaEventCode::ConnectionlessIQReport;
+
+    fn meta_byte_len(&self) -> usize {
+        2 + self.report.byte_len()
+    }
+
+    fn meta_unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        if buf.len() < 2 {
+            return Err(PackError::BadLength {
+                expected: 2 + IQReport::MIN_BYTE_LEN,
+                got: buf.len(),
+            });
+        }
+        Ok(ConnectionlessIQReport {
+            sync_handle: u16_at(buf, 0),
+            report: IQReport::unpack_from(&buf[2..])?,
+        })
+    }
+
+    fn meta_pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(self.meta_byte_len(), buf)?;
+        buf[0..2].copy_from_slice(&self.sync_handle.to_le_bytes());
+        self.report.pack_into(&mut buf[2..])
+    }
+}
+/// IQ samples of a CTE received on a connection.
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct ConnectionIQReport {
+    pub handle: ConnectionHandle,
+    pub rx_phy: PHY,
+    pub report: IQReport,
+}
+impl ConnectionIQReport {
+    pub const HEADER_LEN: usize = ConnectionHandle::BYTE_LEN + PHY::BYTE_LEN;
+}
+impl MetaEvent for ConnectionIQReport {
+    const META_CODE: MetaEventCode = MetaEventCode::ConnectionIQReport;
+
+    fn meta_byte_len(&self) -> usize {
+        Self::HEADER_LEN + self.report.byte_len()
+    }
+
+    fn meta_unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        if buf.len() < Self::HEADER_LEN {
+            return Err(PackError::BadLength {
+                expected: Self::HEADER_LEN + IQReport::MIN_BYTE_LEN,
+                got: buf.len(),
+            });
+        }
+        Ok(ConnectionIQReport {
+            handle: ConnectionHandle::unpack_from(&buf[0..2])?,
+            rx_phy: PHY::try_from(buf[2]).map_err(|_| PackError::bad_index(2))?,
+            report: IQReport::unpack_from(&buf[Self::HEADER_LEN..])?,
+        })
+    }
+
+    fn meta_pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(self.meta_byte_len(), buf)?;
+        self.handle.pack_into(&mut buf[0..2])?;
+        buf[2] = self.rx_phy.into();
+        self.report.pack_into(&mut buf[Self::HEADER_LEN..])
+    }
+}
+/// Sent when the peer didn't respond to a CTE request or responded without a CTE.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct CTERequestFailed {
+    pub status: ErrorCode,
+    pub handle: ConnectionHandle,
+}
+impl CTERequestFailed {
+    pub const BYTE_LEN: usize = ErrorCode::BYTE_LEN + ConnectionHandle::BYTE_LEN;
+}
+impl MetaEvent for CTERequestFailed {
+    const META_CODE: MetaEventCode = MetaEventCode::CTERequestFailed;
+
+    fn meta_byte_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+
+    fn meta_unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        Ok(CTERequestFailed {
+            status: ErrorCode::try_from(buf[0]).map_err(|_| PackError::bad_index(0))?,
+            handle: ConnectionHandle::unpack_from(&buf[1..3])?,
+        })
+    }
+
+    fn meta_pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        buf[0] = self.status.into();
+        self.handle.pack_into(&mut buf[1..3])
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hci::test_packets::assert_meta_event;
+
+    #[test]
+    fn connectionless_iq_report() {
+        let event = ConnectionlessIQReport {
+            sync_handle: 0x0001,
+            report: IQReport {
+                channel: Index::new(5),
+                rssi: -600,
+                rssi_antenna_id: 1,
+                cte_type: CTEType::AoA,
+                slot_durations: SlotDuration::Slot1us,
+                packet_status: PacketStatus::CRCCorrect,
+                event_counter: 0x0010,
+                samples: vec![
+                    IQSample { i: 16, q: -16 },
+                    IQSample {
+                        i: IQSample::INVALID,
+                        q: 5,
+                    },
+                ],
+            },
+        };
+        let bytes = [
+            0x15, 0x01, 0x00, 0x05, 0xA8, 0xFD, 0x01, 0x00, 0x01, 0x00, 0x10, 0x00, 0x02, 0x10,
+            0xF0, 0x80, 0x05,
+        ];
+        assert_meta_event(&event, &bytes);
+        // The second sample isn't valid.
+        assert!(!event.report.is_usable());
+        // `Sample_Count` says there are more samples than sent.
+        assert!(ConnectionlessIQReport::meta_unpack_from(&bytes[1..bytes.len() - 2]).is_err());
+    }
+    #[test]
+    fn connection_iq_report() {
+        let event = ConnectionIQReport {
+            handle: ConnectionHandle::new(0x0040),
+            rx_phy: PHY::LE2M,
+            report: IQReport {
+                channel: Index::new(10),
+                rssi: -420,
+                rssi_antenna_id: 0,
+                cte_type: CTEType::AoD2us,
+                slot_durations: SlotDuration::Slot2us,
+                packet_status: PacketStatus::InsufficientResources,
+                event_counter: 0x1234,
+                samples: Vec::new(),
+            },
+        };
+        assert_meta_event(
+            &event,
+            &[
+                0x16, 0x40, 0x00, 0x02, 0x0A, 0x5C, 0xFE, 0x00, 0x02, 0x02, 0xFF, 0x34, 0x12, 0x00,
+            ],
+        );
+        assert!(!event.report.is_usable());
+    }
+    #[test]
+    fn cte_request_failed() {
+        assert_meta_event(
+            &CTERequestFailed {
+                status: ErrorCode::UnsupportedRemoteFeature,
+                handle: ConnectionHandle::new(0x0040),
+            },
+            &[0x17, 0x1A, 0x40, 0x00],
+        );
+    }
+}
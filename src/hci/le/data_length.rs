@@ -0,0 +1,369 @@
+//! HCI LE Data Length Extension (DLE) commands and events. Lets connections use Link Layer
+//! payloads of up to 251 bytes instead of 27.
+use crate::hci::command::Command;
+use crate::hci::event::{CommandComplete, ConnectionHandleReturn, ReturnParameters, StatusReturn};
+use crate::hci::le::{LEControllerOpcode, MetaEvent, MetaEventCode};
+use crate::hci::{ErrorCode, Opcode};
+use crate::le::connection::{Connection, ConnectionHandle, DataLength};
+use crate::PackError;
+use core::convert::TryFrom;
+
+fn u16_at(buf: &[u8], index: usize) -> u16 {
+    u16::from_le_bytes([buf[index], buf[index + 1]])
+}
+/// Suggests the max TX payload octets and time (in microseconds) of a connection. The
+/// controller may use other values. A [`DataLengthChange`] event is sent if they change.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct SetDataLength {
+    pub handle: ConnectionHandle,
+    pub tx_octets: u16,
+    pub tx_time: u16,
+}
+impl SetDataLength {
+    pub const BYTE_LEN: usize = ConnectionHandle::BYTE_LEN + 2 + 2;
+    pub const OPCODE: LEControllerOpcode = LEControllerOpcode::SetDataLength;
+}
+impl Command for SetDataLength {
+    type Return = CommandComplete<ConnectionHandleReturn>;
+
+    fn opcode() -> Opcode {
+        Self::OPCODE.into()
+    }
+
+    fn byte_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        if !DataLength::is_valid_octets(self.tx_octets) || !DataLength::is_valid_time(self.tx_time)
+        {
+            return Err(PackError::InvalidFields);
+        }
+        self.handle.pack_into(&mut buf[0..2])?;
+        buf[2..4].copy_from_slice(&self.tx_octets.to_le_bytes());
+        buf[4..6].copy_from_slice(&self.tx_time.to_le_bytes());
+        Ok(())
+    }
+
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        Ok(SetDataLength {
+            handle: ConnectionHandle::unpack_from(&buf[0..2])?,
+            tx_octets: u16_at(buf, 2),
+            tx_time: u16_at(buf, 4),
+        })
+    }
+}
+/// Reads the max TX payload octets and time the host suggests for new connections.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash, Default)]
+pub struct ReadSuggestedDefaultDataLength();
+impl ReadSuggestedDefaultDataLength {
+    pub const OPCODE: LEControllerOpcode = LEControllerOpcode::ReadSuggestedDefaultDataLength;
+}
+impl Command for ReadSuggestedDefaultDataLength {
+    type Return = CommandComplete<SuggestedDefaultDataLength>;
+
+    fn opcode() -> Opcode {
+        Self::OPCODE.into()
+    }
+
+    fn byte_len(&self) -> usize {
+        0
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(0, buf)
+    }
+
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        PackError::expect_length(0, buf)?;
+        Ok(ReadSuggestedDefaultDataLength())
+    }
+}
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct SuggestedDefaultDataLength {
+    pub status: ErrorCode,
+    pub max_tx_octets: u16,
+    pub max_tx_time: u16,
+}
+impl SuggestedDefaultDataLength {
+    pub const BYTE_LEN: usize = ErrorCode::BYTE_LEN + 2 + 2;
+}
+impl ReturnParameters for SuggestedDefaultDataLength {
+    fn byte_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        buf[0] = self.status.into();
+        buf[1..3].copy_from_slice(&self.max_tx_octets.to_le_bytes());
+        buf[3..5].copy_from_slice(&self.max_tx_time.to_le_bytes());
+        Ok(())
+    }
+
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        Ok(SuggestedDefaultDataLength {
+            status: ErrorCode::try_from(buf[0]).map_err(|_| PackError::bad_index(0))?,
+            max_tx_octets: u16_at(buf, 1),
+            max_tx_time: u16_at(buf, 3),
+        })
+    }
+}
+/// Sets the max TX payload octets and time the controller should use for new connections.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct WriteSuggestedDefaultDataLength {
+    pub max_tx_octets: u16,
+    pub max_tx_time: u16,
+}
+impl WriteSuggestedDefaultDataLength {
+    pub const BYTE_LEN: usize = 2 + 2;
+    pub const OPCODE: LEControllerOpcode = LEControllerOpcode::WriteSuggestedDefaultDataLength;
+}
+impl Command for WriteSuggestedDefaultDataLength {
+    type Return = CommandComplete<StatusReturn>;
+
+    fn opcode() -> Opcode {
+        Self::OPCODE.into()
+    }
+
+    fn byte_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        if !DataLength::is_valid_octets(self.max_tx_octets)
+            || !DataLength::is_valid_time(self.max_tx_time)
+        {
+            return Err(PackError::InvalidFields);
+        }
+        buf[0..2].copy_from_slice(&self.max_tx_octets.to_le_bytes());
+        buf[2..4].copy_from_slice(&self.max_tx_time.to_le_bytes());
+        Ok(())
+    }
+
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        Ok(WriteSuggestedDefaultDataLength {
+            max_tx_octets: u16_at(buf, 0),
+            max_tx_time: u16_at(buf, 2),
+        })
+    }
+}
+/// Reads the max payload octets and time the controller supports.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash, Default)]
+pub struct ReadMaximumDataLength();
+impl ReadMaximumDataLength {
+    pub const OPCODE: LEControllerOpcode = LEControllerOpcode::ReadMaximumDataLength;
+}
+impl Command for ReadMaximumDataLength {
+    type Return = CommandComplete<MaximumDataLength>;
+
+    fn opcode() -> Opcode {
+        Self::OPCODE.into()
+    }
+
+    fn byte_len(&self) -> usize {
+        0
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(0, buf)
+    }
+
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        PackError::expect_length(0, buf)?;
+        Ok(ReadMaximumDataLength())
+    }
+}
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct MaximumDataLength {
+    pub status: ErrorCode,
+    pub supported: DataLength,
+}
+impl MaximumDataLength {
+    pub const BYTE_LEN: usize = ErrorCode::BYTE_LEN + 2 * 4;
+}
+impl ReturnParameters for MaximumDataLength {
+    fn byte_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        buf[0] = self.status.into();
+        pack_data_length(&self.supported, &mut buf[1..])
+    }
+
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        Ok(MaximumDataLength {
+            status: ErrorCode::try_from(buf[0]).map_err(|_| PackError::bad_index(0))?,
+            supported: unpack_data_length(&buf[1..])?,
+        })
+    }
+}
+/// `DataLength` in the TX octets, TX time, RX octets, RX time order used by HCI.
+fn pack_data_length(data_length: &DataLength, buf: &mut [u8]) -> Result<(), PackError> {
+    PackError::expect_length(8, buf)?;
+    buf[0..2].copy_from_slice(&data_length.max_tx_octets.to_le_bytes());
+    buf[2..4].copy_from_slice(&data_length.max_tx_time.to_le_bytes());
+    buf[4..6].copy_from_slice(&data_length.max_rx_octets.to_le_bytes());
+    buf[6..8].copy_from_slice(&data_length.max_rx_time.to_le_bytes());
+    Ok(())
+}
+fn unpack_data_length(buf: &[u8]) -> Result<DataLength, PackError> {
+    PackError::expect_length(8, buf)?;
+    Ok(DataLength {
+        max_tx_octets: u16_at(buf, 0),
+        max_tx_time: u16_at(buf, 2),
+        max_rx_octets: u16_at(buf, 4),
+        max_rx_time: u16_at(buf, 6),
+    })
+}
+/// Data Length Change event. Sent when the max payload octets or time of a connection change
+/// (in either direction).
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct DataLengthChange {
+    pub handle: ConnectionHandle,
+    pub data_length: DataLength,
+}
+impl DataLengthChange {
+    pub const BYTE_LEN: usize = ConnectionHandle::BYTE_LEN + 2 * 4;
+    /// Updates the `DataLength` of `connection` if the event is for its handle. Returns if
+    /// `connection` was updated.
+    pub fn update_connection(&self, connection: &mut Connection) -> bool {
+        if self.handle == connection.handle {
+            connection.data_length = self.data_length;
+            true
+        } else {
+            false
+        }
+    }
+}
+impl MetaEvent for DataLengthChange {
+    const META_CODE: MetaEventCode = MetaEventCode::DataLengthChange;
+
+    fn meta_byte_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+
+    fn meta_unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        Ok(DataLengthChange {
+            handle: ConnectionHandle::unpack_from(&buf[0..2])?,
+            data_length: unpack_data_length(&buf[2..])?,
+        })
+    }
+
+    fn meta_pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        self.handle.pack_into(&mut buf[0..2])?;
+        pack_data_length(&self.data_length, &mut buf[2..])
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hci::test_packets::{assert_command, assert_meta_event, assert_return};
+
+    const HANDLE: u16 = 0x0040;
+
+    #[test]
+    fn set_data_length() {
+        assert_command(
+            &SetDataLength {
+                handle: ConnectionHandle::new(HANDLE),
+                tx_octets: 251,
+                tx_time: 2120,
+            },
+            &[0x22, 0x20, 0x06, 0x40, 0x00, 0xFB, 0x00, 0x48, 0x08],
+        );
+        let invalid = SetDataLength {
+            handle: ConnectionHandle::new(HANDLE),
+            tx_octets: 252,
+            tx_time: 2120,
+        };
+        assert_eq!(
+            invalid.pack_into(&mut [0_u8; SetDataLength::BYTE_LEN]),
+            Err(PackError::InvalidFields)
+        );
+    }
+    #[test]
+    fn suggested_default_data_length() {
+        assert_command(&ReadSuggestedDefaultDataLength(), &[0x23, 0x20, 0x00]);
+        assert_return(
+            &SuggestedDefaultDataLength {
+                status: ErrorCode::Ok,
+                max_tx_octets: 27,
+                max_tx_time: 328,
+            },
+            &[0x00, 0x1B, 0x00, 0x48, 0x01],
+        );
+        assert_command(
+            &WriteSuggestedDefaultDataLength {
+                max_tx_octets: 251,
+                max_tx_time: 2120,
+            },
+            &[0x24, 0x20, 0x04, 0xFB, 0x00, 0x48, 0x08],
+        );
+    }
+    #[test]
+    fn maximum_data_length() {
+        assert_command(&ReadMaximumDataLength(), &[0x2F, 0x20, 0x00]);
+        assert_return(
+            &MaximumDataLength {
+                status: ErrorCode::Ok,
+                supported: DataLength {
+                    max_tx_octets: 251,
+                    max_tx_time: 17040,
+                    max_rx_octets: 251,
+                    max_rx_time: 17040,
+                },
+            },
+            &[0x00, 0xFB, 0x00, 0x90, 0x42, 0xFB, 0x00, 0x90, 0x42],
+        );
+    }
+    #[test]
+    fn data_length_change() {
+        let change = DataLengthChange {
+            handle: ConnectionHandle::new(HANDLE),
+            data_length: DataLength {
+                max_tx_octets: 251,
+                max_tx_time: 2120,
+                max_rx_octets: 27,
+                max_rx_time: 328,
+            },
+        };
+        assert_meta_event(
+            &change,
+            &[
+                0x07, 0x40, 0x00, 0xFB, 0x00, 0x48, 0x08, 0x1B, 0x00, 0x48, 0x01,
+            ],
+        );
+    }
+}
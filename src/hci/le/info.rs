@@ -0,0 +1,184 @@
+//! HCI LE controller information commands. LE features and the supported combinations of Link
+//! Layer states.
+use crate::hci::command::Command;
+use crate::hci::event::{CommandComplete, ReturnParameters};
+use crate::hci::le::LEControllerOpcode;
+use crate::hci::{ErrorCode, Opcode};
+use crate::le::features::LEFeatures;
+use crate::PackError;
+use core::convert::TryFrom;
+
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash, Default)]
+pub struct ReadLocalSupportedFeatures();
+impl ReadLocalSupportedFeatures {
+    pub const OPCODE: LEControllerOpcode = LEControllerOpcode::ReadLocalSupportedFeatures;
+}
+impl Command for ReadLocalSupportedFeatures {
+    type Return = CommandComplete<LELocalSupportedFeatures>;
+
+    fn opcode() -> Opcode {
+        Self::OPCODE.into()
+    }
+
+    fn byte_len(&self) -> usize {
+        0
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(0, buf)
+    }
+
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        PackError::expect_length(0, buf)?;
+        Ok(ReadLocalSupportedFeatures())
+    }
+}
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct LELocalSupportedFeatures {
+    pub status: ErrorCode,
+    pub features: LEFeatures,
+}
+impl LELocalSupportedFeatures {
+    pub const BYTE_LEN: usize = ErrorCode::BYTE_LEN + LEFeatures::BYTE_LEN;
+}
+impl ReturnParameters for LELocalSupportedFeatures {
+    fn byte_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        buf[0] = self.status.into();
+        self.features.pack_into(&mut buf[1..])
+    }
+
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        Ok(LELocalSupportedFeatures {
+            status: ErrorCode::try_from(buf[0]).map_err(|_| PackError::bad_index(0))?,
+            features: LEFeatures::unpack_from(&buf[1..])?,
+        })
+    }
+}
+/// 64-bit bit field of the Link Layer state combinations (advertising, scanning, initiating,
+/// master or slave at the same time) the controller supports. See the Core Spec (Vol 4, Part E,
+/// 7.8.27) for the meaning of each bit.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash, Default)]
+pub struct LESupportedStates(pub u64);
+impl LESupportedStates {
+    pub const BYTE_LEN: usize = 8;
+    /// Highest state combination bit defined.
+    pub const MAX_BIT: u8 = 41;
+    /// If state combination `bit` is supported. Returns `false` if `bit > 63`.
+    pub fn get(self, bit: u8) -> bool {
+        bit < 64 && self.0 & (1 << bit) != 0
+    }
+    /// Iterates over every supported state combination bit.
+    pub fn supported(self) -> impl Iterator<Item = u8> {
+        (0..64_u8).filter(move |bit| self.get(*bit))
+    }
+}
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash, Default)]
+pub struct ReadSupportedStates();
+impl ReadSupportedStates {
+    pub const OPCODE: LEControllerOpcode = LEControllerOpcode::ReadSupportedState;
+}
+impl Command for ReadSupportedStates {
+    type Return = CommandComplete<SupportedStates>;
+
+    fn opcode() -> Opcode {
+        Self::OPCODE.into()
+    }
+
+    fn byte_len(&self) -> usize {
+        0
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(0, buf)
+    }
+
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        PackError::expect_length(0, buf)?;
+        Ok(ReadSupportedStates())
+    }
+}
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct SupportedStates {
+    pub status: ErrorCode,
+    pub states: LESupportedStates,
+}
+impl SupportedStates {
+    pub const BYTE_LEN: usize = ErrorCode::BYTE_LEN + LESupportedStates::BYTE_LEN;
+}
+impl ReturnParameters for SupportedStates {
+    fn byte_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        buf[0] = self.status.into();
+        buf[1..].copy_from_slice(&self.states.0.to_le_bytes());
+        Ok(())
+    }
+
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        let mut states = [0_u8; LESupportedStates::BYTE_LEN];
+        states.copy_from_slice(&buf[1..]);
+        Ok(SupportedStates {
+            status: ErrorCode::try_from(buf[0]).map_err(|_| PackError::bad_index(0))?,
+            states: LESupportedStates(u64::from_le_bytes(states)),
+        })
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hci::test_packets::{assert_command, assert_return};
+    use crate::le::features::LEFeature;
+
+    #[test]
+    fn local_supported_features() {
+        assert_command(&ReadLocalSupportedFeatures(), &[0x03, 0x20, 0x00]);
+        let mut features = LEFeatures::zeroed();
+        features.enable(LEFeature::Encryption);
+        features.enable(LEFeature::DataPacketLengthExtension);
+        features.enable(LEFeature::LE2MPHY);
+        assert_return(
+            &LELocalSupportedFeatures {
+                status: ErrorCode::Ok,
+                features,
+            },
+            &[0x00, 0x21, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+        );
+    }
+    #[test]
+    fn supported_states() {
+        assert_command(&ReadSupportedStates(), &[0x1C, 0x20, 0x00]);
+        // Every state combination defined by the Core Spec v5.2.
+        let states = LESupportedStates((1 << (LESupportedStates::MAX_BIT + 1)) - 1);
+        assert_return(
+            &SupportedStates {
+                status: ErrorCode::Ok,
+                states,
+            },
+            &[0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x03, 0x00, 0x00],
+        );
+        assert_eq!(states.supported().count(), 42);
+        assert!(!states.get(64));
+    }
+}
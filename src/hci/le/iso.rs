@@ -0,0 +1,1682 @@
+//! HCI LE Isochronous Channels commands and events. Connected Isochronous Streams (CIS) are
+//! grouped into Connected Isochronous Groups (CIG) and Broadcast Isochronous Streams (BIS) into
+//! Broadcast Isochronous Groups (BIG). Both carry ISO Data packets
+//! ([`crate::hci::iso::ISOPacket`]) once an ISO Data Path is set up.
+use crate::hci::command::Command;
+use crate::hci::event::{CommandComplete, CommandStatus, ConnectionHandleReturn, ReturnParameters};
+use crate::hci::le::phy::unpack_event_phy;
+use crate::hci::le::{LEControllerOpcode, MetaEvent, MetaEventCode};
+use crate::hci::{ErrorCode, Opcode};
+use crate::le::advertisement_structures::broadcast_code::BroadcastCode;
+use crate::le::connection::{ConnectionHandle, MasterClockAccuracy};
+use crate::le::phy::{PHYMask, PHY};
+use crate::{ConversionError, PackError};
+use core::convert::{TryFrom, TryInto};
+
+/// Max value of the 24-bit fields (SDU intervals, delays, latencies) in ISO commands and events.
+pub const MAX_U24: u32 = 0x00FF_FFFF;
+pub(crate) fn pack_u24(value: u32, buf: &mut [u8]) -> Result<(), PackError> {
+    PackError::expect_length(3, buf)?;
+    if value > MAX_U24 {
+        return Err(PackError::InvalidFields);
+    }
+    buf.copy_from_slice(&value.to_le_bytes()[..3]);
+    Ok(())
+}
+pub(crate) fn unpack_u24(buf: &[u8]) -> Result<u32, PackError> {
+    PackError::expect_length(3, buf)?;
+    Ok(u32::from_le_bytes([buf[0], buf[1], buf[2], 0]))
+}
+/// How the CISes in a CIG are scheduled.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+#[repr(u8)]
+pub enum Packing {
+    Sequential = 0x00,
+    Interleaved = 0x01,
+}
+impl From<Packing> for u8 {
+    fn from(p: Packing) -> Self {
+        p as u8
+    }
+}
+impl TryFrom<u8> for Packing {
+    type Error = ConversionError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x00 => Ok(Packing::Sequential),
+            0x01 => Ok(Packing::Interleaved),
+            _ => Err(ConversionError(())),
+        }
+    }
+}
+/// If SDUs are sent framed (segmented with headers) or unframed.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+#[repr(u8)]
+pub enum Framing {
+    Unframed = 0x00,
+    Framed = 0x01,
+}
+impl From<Framing> for u8 {
+    fn from(f: Framing) -> Self {
+        f as u8
+    }
+}
+impl TryFrom<u8> for Framing {
+    type Error = ConversionError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x00 => Ok(Framing::Unframed),
+            0x01 => Ok(Framing::Framed),
+            _ => Err(ConversionError(())),
+        }
+    }
+}
+/// Parameters of a single CIS in a CIG. See [`SetCIGParameters`].
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash, Default)]
+pub struct CISParameters {
+    pub cis_id: u8,
+    pub max_sdu_m_to_s: u16,
+    pub max_sdu_s_to_m: u16,
+    pub phy_m_to_s: PHYMask,
+    pub phy_s_to_m: PHYMask,
+    /// Max number of retransmissions.
+    pub rtn_m_to_s: u8,
+    pub rtn_s_to_m: u8,
+}
+impl CISParameters {
+    pub const BYTE_LEN: usize = 1 + 2 + 2 + 1 + 1 + 1 + 1;
+    pub fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        buf[0] = self.cis_id;
+        buf[1..3].copy_from_slice(&self.max_sdu_m_to_s.to_le_bytes());
+        buf[3..5].copy_from_slice(&self.max_sdu_s_to_m.to_le_bytes());
+        buf[5] = self.phy_m_to_s.into();
+        buf[6] = self.phy_s_to_m.into();
+        buf[7] = self.rtn_m_to_s;
+        buf[8] = self.rtn_s_to_m;
+        Ok(())
+    }
+    pub fn unpack_from(buf: &[u8]) -> Result<Self, PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        Ok(CISParameters {
+            cis_id: buf[0],
+            max_sdu_m_to_s: u16::from_le_bytes([buf[1], buf[2]]),
+            max_sdu_s_to_m: u16::from_le_bytes([buf[3], buf[4]]),
+            phy_m_to_s: PHYMask::try_from(buf[5]).map_err(|_| PackError::bad_index(5))?,
+            phy_s_to_m: PHYMask::try_from(buf[6]).map_err(|_| PackError::bad_index(6))?,
+            rtn_m_to_s: buf[7],
+            rtn_s_to_m: buf[8],
+        })
+    }
+}
+/// Creates (or modifies) a CIG. Returns the `ConnectionHandle` for each CIS.
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct SetCIGParameters {
+    pub cig_id: u8,
+    /// SDU interval in microseconds (24-bit).
+    pub sdu_interval_m_to_s: u32,
+    pub sdu_interval_s_to_m: u32,
+    pub worst_case_sca: MasterClockAccuracy,
+    pub packing: Packing,
+    pub framing: Framing,
+    /// Max transport latency in milliseconds.
+    pub max_transport_latency_m_to_s: u16,
+    pub max_transport_latency_s_to_m: u16,
+    pub cis: Box<[CISParameters]>,
+}
+impl SetCIGParameters {
+    pub const OPCODE: LEControllerOpcode = LEControllerOpcode::SetCIGParameters;
+    pub const HEADER_LEN: usize = 1 + 3 + 3 + 1 + 1 + 1 + 2 + 2 + 1;
+    pub const MAX_CIS_COUNT: usize = 0x1F;
+}
+impl Command for SetCIGParameters {
+    type Return = CommandComplete<SetCIGParametersReturn>;
+
+    fn opcode() -> Opcode {
+        Self::OPCODE.into()
+    }
+
+    fn byte_len(&self) -> usize {
+        Self::HEADER_LEN + self.cis.len() * CISParameters::BYTE_LEN
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(self.byte_len(), buf)?;
+        if self.cis.len() > Self::MAX_CIS_COUNT {
+            return Err(PackError::InvalidFields);
+        }
+        buf[0] = self.cig_id;
+        pack_u24(self.sdu_interval_m_to_s, &mut buf[1..4])?;
+        pack_u24(self.sdu_interval_s_to_m, &mut buf[4..7])?;
+        buf[7] = self.worst_case_sca.into();
+        buf[8] = self.packing.into();
+        buf[9] = self.framing.into();
+        buf[10..12].copy_from_slice(&self.max_transport_latency_m_to_s.to_le_bytes());
+        buf[12..14].copy_from_slice(&self.max_transport_latency_s_to_m.to_le_bytes());
+        buf[14] = self.cis.len().try_into().expect("checked above");
+        for (cis, chunk) in self
+            .cis
+            .iter()
+            .zip(buf[Self::HEADER_LEN..].chunks_exact_mut(CISParameters::BYTE_LEN))
+        {
+            cis.pack_into(chunk)?;
+        }
+        Ok(())
+    }
+
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        if buf.len() < Self::HEADER_LEN {
+            return Err(PackError::BadLength {
+                expected: Self::HEADER_LEN,
+                got: buf.len(),
+            });
+        }
+        let cis_count = usize::from(buf[14]);
+        PackError::expect_length(Self::HEADER_LEN + cis_count * CISParameters::BYTE_LEN, buf)?;
+        Ok(SetCIGParameters {
+            cig_id: buf[0],
+            sdu_interval_m_to_s: unpack_u24(&buf[1..4])?,
+            sdu_interval_s_to_m: unpack_u24(&buf[4..7])?,
+            worst_case_sca: MasterClockAccuracy::try_from(buf[7])
+                .map_err(|_| PackError::bad_index(7))?,
+            packing: Packing::try_from(buf[8]).map_err(|_| PackError::bad_index(8))?,
+            framing: Framing::try_from(buf[9]).map_err(|_| PackError::bad_index(9))?,
+            max_transport_latency_m_to_s: u16::from_le_bytes([buf[10], buf[11]]),
+            max_transport_latency_s_to_m: u16::from_le_bytes([buf[12], buf[13]]),
+            cis: buf[Self::HEADER_LEN..]
+                .chunks_exact(CISParameters::BYTE_LEN)
+                .map(CISParameters::unpack_from)
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct SetCIGParametersReturn {
+    pub status: ErrorCode,
+    pub cig_id: u8,
+    /// `ConnectionHandle` of each CIS (in the same order as [`SetCIGParameters::cis`]).
+    pub handles: Box<[ConnectionHandle]>,
+}
+impl ReturnParameters for SetCIGParametersReturn {
+    fn byte_len(&self) -> usize {
+        ErrorCode::BYTE_LEN + 1 + 1 + self.handles.len() * ConnectionHandle::BYTE_LEN
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(self.byte_len(), buf)?;
+        buf[0] = self.status.into();
+        buf[1] = self.cig_id;
+        pack_handles(&self.handles, &mut buf[2..])
+    }
+
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        let status = ErrorCode::try_from(*buf.get(0).ok_or(PackError::BadLength {
+            expected: 1,
+            got: 0,
+        })?)
+        .map_err(|_| PackError::bad_index(0))?;
+        if !status.is_ok() {
+            // The controller may only return the status on an error.
+            return Ok(SetCIGParametersReturn {
+                status,
+                cig_id: buf.get(1).copied().unwrap_or_default(),
+                handles: Box::new([]),
+            });
+        }
+        if buf.len() < 2 {
+            return Err(PackError::BadLength {
+                expected: 2,
+                got: buf.len(),
+            });
+        }
+        Ok(SetCIGParametersReturn {
+            status,
+            cig_id: buf[1],
+            handles: unpack_handles(&buf[2..])?,
+        })
+    }
+}
+/// CIS to create and the ACL connection to create it on. See [`CreateCIS`].
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash, Default)]
+pub struct CISConnection {
+    pub cis_handle: ConnectionHandle,
+    pub acl_handle: ConnectionHandle,
+}
+impl CISConnection {
+    pub const BYTE_LEN: usize = ConnectionHandle::BYTE_LEN * 2;
+}
+/// Creates one or more CIS. The controller returns a `CommandStatus` and then a
+/// [`CISEstablished`] event for each CIS.
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct CreateCIS {
+    pub cis: Box<[CISConnection]>,
+}
+impl CreateCIS {
+    pub const OPCODE: LEControllerOpcode = LEControllerOpcode::CreateCIS;
+}
+impl Command for CreateCIS {
+    type Return = CommandStatus;
+
+    fn opcode() -> Opcode {
+        Self::OPCODE.into()
+    }
+
+    fn byte_len(&self) -> usize {
+        1 + self.cis.len() * CISConnection::BYTE_LEN
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(self.byte_len(), buf)?;
+        buf[0] = self
+            .cis
+            .len()
+            .try_into()
+            .map_err(|_| PackError::InvalidFields)?;
+        for (cis, chunk) in self
+            .cis
+            .iter()
+            .zip(buf[1..].chunks_exact_mut(CISConnection::BYTE_LEN))
+        {
+            cis.cis_handle.pack_into(&mut chunk[..2])?;
+            cis.acl_handle.pack_into(&mut chunk[2..])?;
+        }
+        Ok(())
+    }
+
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        let cis_count = usize::from(*buf.get(0).ok_or(PackError::BadLength {
+            expected: 1,
+            got: 0,
+        })?);
+        PackError::expect_length(1 + cis_count * CISConnection::BYTE_LEN, buf)?;
+        Ok(CreateCIS {
+            cis: buf[1..]
+                .chunks_exact(CISConnection::BYTE_LEN)
+                .map(|chunk| {
+                    Ok(CISConnection {
+                        cis_handle: ConnectionHandle::unpack_from(&chunk[..2])?,
+                        acl_handle: ConnectionHandle::unpack_from(&chunk[2..])?,
+                    })
+                })
+                .collect::<Result<_, PackError>>()?,
+        })
+    }
+}
+/// Removes a CIG. All of its CISes must be disconnected first.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct RemoveCIG {
+    pub cig_id: u8,
+}
+impl RemoveCIG {
+    pub const BYTE_LEN: usize = 1;
+    pub const OPCODE: LEControllerOpcode = LEControllerOpcode::RemoveCIG;
+}
+impl Command for RemoveCIG {
+    type Return = CommandComplete<RemoveCIGReturn>;
+
+    fn opcode() -> Opcode {
+        Self::OPCODE.into()
+    }
+
+    fn byte_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        buf[0] = self.cig_id;
+        Ok(())
+    }
+
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        Ok(RemoveCIG { cig_id: buf[0] })
+    }
+}
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct RemoveCIGReturn {
+    pub status: ErrorCode,
+    pub cig_id: u8,
+}
+impl RemoveCIGReturn {
+    pub const BYTE_LEN: usize = ErrorCode::BYTE_LEN + 1;
+}
+impl ReturnParameters for RemoveCIGReturn {
+    fn byte_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        buf[0] = self.status.into();
+        buf[1] = self.cig_id;
+        Ok(())
+    }
+
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        Ok(RemoveCIGReturn {
+            status: ErrorCode::try_from(buf[0]).map_err(|_| PackError::bad_index(0))?,
+            cig_id: buf[1],
+        })
+    }
+}
+/// Accepts a [`CISRequest`] from the master. The controller returns a `CommandStatus` and then
+/// a [`CISEstablished`] event.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct AcceptCISRequest {
+    pub handle: ConnectionHandle,
+}
+impl AcceptCISRequest {
+    pub const BYTE_LEN: usize = ConnectionHandle::BYTE_LEN;
+    pub const OPCODE: LEControllerOpcode = LEControllerOpcode::AcceptCISRequest;
+}
+impl Command for AcceptCISRequest {
+    type Return = CommandStatus;
+
+    fn opcode() -> Opcode {
+        Self::OPCODE.into()
+    }
+
+    fn byte_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        self.handle.pack_into(buf)
+    }
+
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        Ok(AcceptCISRequest {
+            handle: ConnectionHandle::unpack_from(buf)?,
+        })
+    }
+}
+/// Rejects a [`CISRequest`] from the master with `reason`.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct RejectCISRequest {
+    pub handle: ConnectionHandle,
+    pub reason: ErrorCode,
+}
+impl RejectCISRequest {
+    pub const BYTE_LEN: usize = ConnectionHandle::BYTE_LEN + ErrorCode::BYTE_LEN;
+    pub const OPCODE: LEControllerOpcode = LEControllerOpcode::RejectCISRequest;
+}
+impl Command for RejectCISRequest {
+    type Return = CommandComplete<ConnectionHandleReturn>;
+
+    fn opcode() -> Opcode {
+        Self::OPCODE.into()
+    }
+
+    fn byte_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        self.handle.pack_into(&mut buf[..2])?;
+        buf[2] = self.reason.into();
+        Ok(())
+    }
+
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        Ok(RejectCISRequest {
+            handle: ConnectionHandle::unpack_from(&buf[..2])?,
+            reason: ErrorCode::try_from(buf[2]).map_err(|_| PackError::bad_index(2))?,
+        })
+    }
+}
+/// Direction of an ISO Data Path. `Input` is Host to Controller and `Output` is Controller to
+/// Host.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+#[repr(u8)]
+pub enum DataPathDirection {
+    Input = 0x00,
+    Output = 0x01,
+}
+impl From<DataPathDirection> for u8 {
+    fn from(d: DataPathDirection) -> Self {
+        d as u8
+    }
+}
+impl TryFrom<u8> for DataPathDirection {
+    type Error = ConversionError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x00 => Ok(DataPathDirection::Input),
+            0x01 => Ok(DataPathDirection::Output),
+            _ => Err(ConversionError(())),
+        }
+    }
+}
+/// Codec used on an ISO Data Path. Vendor codecs use `coding_format` `0xFF`.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash, Default)]
+pub struct CodecID {
+    pub coding_format: u8,
+    pub company_id: u16,
+    pub vendor_codec_id: u16,
+}
+impl CodecID {
+    pub const BYTE_LEN: usize = 5;
+    /// Transparent codec. The controller passes the SDUs through as is.
+    pub const TRANSPARENT: CodecID = CodecID {
+        coding_format: 0x03,
+        company_id: 0,
+        vendor_codec_id: 0,
+    };
+    pub fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        buf[0] = self.coding_format;
+        buf[1..3].copy_from_slice(&self.company_id.to_le_bytes());
+        buf[3..5].copy_from_slice(&self.vendor_codec_id.to_le_bytes());
+        Ok(())
+    }
+    pub fn unpack_from(buf: &[u8]) -> Result<Self, PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        Ok(CodecID {
+            coding_format: buf[0],
+            company_id: u16::from_le_bytes([buf[1], buf[2]]),
+            vendor_codec_id: u16::from_le_bytes([buf[3], buf[4]]),
+        })
+    }
+}
+/// Sets up the ISO Data Path for a CIS or BIS. Use [`SetupISODataPath::HCI_DATA_PATH`] to send
+/// and receive the SDUs as HCI ISO Data packets.
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct SetupISODataPath {
+    pub handle: ConnectionHandle,
+    pub direction: DataPathDirection,
+    pub data_path_id: u8,
+    pub codec_id: CodecID,
+    /// Controller delay in microseconds (24-bit).
+    pub controller_delay: u32,
+    pub codec_configuration: Box<[u8]>,
+}
+impl SetupISODataPath {
+    pub const OPCODE: LEControllerOpcode = LEControllerOpcode::SetupISODataPath;
+    pub const HEADER_LEN: usize = ConnectionHandle::BYTE_LEN + 1 + 1 + CodecID::BYTE_LEN + 3 + 1;
+    pub const HCI_DATA_PATH: u8 = 0x00;
+    /// ISO Data Path over HCI using the transparent codec.
+    pub fn hci(handle: ConnectionHandle, direction: DataPathDirection) -> SetupISODataPath {
+        SetupISODataPath {
+            handle,
+            direction,
+            data_path_id: Self::HCI_DATA_PATH,
+            codec_id: CodecID::TRANSPARENT,
+            controller_delay: 0,
+            codec_configuration: Box::new([]),
+        }
+    }
+}
+impl Command for SetupISODataPath {
+    type Return = CommandComplete<ConnectionHandleReturn>;
+
+    fn opcode() -> Opcode {
+        Self::OPCODE.into()
+    }
+
+    fn byte_len(&self) -> usize {
+        Self::HEADER_LEN + self.codec_configuration.len()
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(self.byte_len(), buf)?;
+        self.handle.pack_into(&mut buf[..2])?;
+        buf[2] = self.direction.into();
+        buf[3] = self.data_path_id;
+        self.codec_id.pack_into(&mut buf[4..9])?;
+        pack_u24(self.controller_delay, &mut buf[9..12])?;
+        buf[12] = self
+            .codec_configuration
+            .len()
+            .try_into()
+            .map_err(|_| PackError::InvalidFields)?;
+        buf[Self::HEADER_LEN..].copy_from_slice(&self.codec_configuration);
+        Ok(())
+    }
+
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        if buf.len() < Self::HEADER_LEN {
+            return Err(PackError::BadLength {
+                expected: Self::HEADER_LEN,
+                got: buf.len(),
+            });
+        }
+        PackError::expect_length(Self::HEADER_LEN + usize::from(buf[12]), buf)?;
+        Ok(SetupISODataPath {
+            handle: ConnectionHandle::unpack_from(&buf[..2])?,
+            direction: DataPathDirection::try_from(buf[2]).map_err(|_| PackError::bad_index(2))?,
+            data_path_id: buf[3],
+            codec_id: CodecID::unpack_from(&buf[4..9])?,
+            controller_delay: unpack_u24(&buf[9..12])?,
+            codec_configuration: buf[Self::HEADER_LEN..].into(),
+        })
+    }
+}
+/// Removes the input and/or output ISO Data Path of a CIS or BIS.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct RemoveISODataPath {
+    pub handle: ConnectionHandle,
+    pub input: bool,
+    pub output: bool,
+}
+impl RemoveISODataPath {
+    pub const BYTE_LEN: usize = ConnectionHandle::BYTE_LEN + 1;
+    pub const OPCODE: LEControllerOpcode = LEControllerOpcode::RemoveISODataPath;
+}
+impl Command for RemoveISODataPath {
+    type Return = CommandComplete<ConnectionHandleReturn>;
+
+    fn opcode() -> Opcode {
+        Self::OPCODE.into()
+    }
+
+    fn byte_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        self.handle.pack_into(&mut buf[..2])?;
+        buf[2] = u8::from(self.input) | (u8::from(self.output) << 1);
+        Ok(())
+    }
+
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        if buf[2] & !0b11 != 0 {
+            return Err(PackError::bad_index(2));
+        }
+        Ok(RemoveISODataPath {
+            handle: ConnectionHandle::unpack_from(&buf[..2])?,
+            input: buf[2] & 0b01 != 0,
+            output: buf[2] & 0b10 != 0,
+        })
+    }
+}
+/// CIS Established event. Sent to both the master and the slave once a CIS is established (or
+/// failed to be established).
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct CISEstablished {
+    pub status: ErrorCode,
+    pub handle: ConnectionHandle,
+    /// CIG synchronization delay in microseconds.
+    pub cig_sync_delay: u32,
+    /// CIS synchronization delay in microseconds.
+    pub cis_sync_delay: u32,
+    /// Transport latency in microseconds.
+    pub transport_latency_m_to_s: u32,
+    pub transport_latency_s_to_m: u32,
+    pub phy_m_to_s: PHY,
+    pub phy_s_to_m: PHY,
+    /// Max number of subevents in each interval.
+    pub nse: u8,
+    /// Burst number.
+    pub bn_m_to_s: u8,
+    pub bn_s_to_m: u8,
+    /// Flush timeout (in multiples of `iso_interval`).
+    pub ft_m_to_s: u8,
+    pub ft_s_to_m: u8,
+    pub max_pdu_m_to_s: u16,
+    pub max_pdu_s_to_m: u16,
+    /// ISO interval in 1.25 ms units.
+    pub iso_interval: u16,
+}
+impl CISEstablished {
+    pub const BYTE_LEN: usize =
+        ErrorCode::BYTE_LEN + ConnectionHandle::BYTE_LEN + 3 * 4 + 2 + 5 + 2 * 2 + 2;
+}
+impl MetaEvent for CISEstablished {
+    const META_CODE: MetaEventCode = MetaEventCode::CISEstablished;
+
+    fn meta_byte_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+
+    fn meta_unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        let status = ErrorCode::try_from(buf[0]).map_err(|_| PackError::bad_index(0))?;
+        Ok(CISEstablished {
+            status,
+            handle: ConnectionHandle::unpack_from(&buf[1..3])?,
+            cig_sync_delay: unpack_u24(&buf[3..6])?,
+            cis_sync_delay: unpack_u24(&buf[6..9])?,
+            transport_latency_m_to_s: unpack_u24(&buf[9..12])?,
+            transport_latency_s_to_m: unpack_u24(&buf[12..15])?,
+            phy_m_to_s: unpack_event_phy(status, buf[15], 15)?,
+            phy_s_to_m: unpack_event_phy(status, buf[16], 16)?,
+            nse: buf[17],
+            bn_m_to_s: buf[18],
+            bn_s_to_m: buf[19],
+            ft_m_to_s: buf[20],
+            ft_s_to_m: buf[21],
+            max_pdu_m_to_s: u16::from_le_bytes([buf[22], buf[23]]),
+            max_pdu_s_to_m: u16::from_le_bytes([buf[24], buf[25]]),
+            iso_interval: u16::from_le_bytes([buf[26], buf[27]]),
+        })
+    }
+
+    fn meta_pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        buf[0] = self.status.into();
+        self.handle.pack_into(&mut buf[1..3])?;
+        pack_u24(self.cig_sync_delay, &mut buf[3..6])?;
+        pack_u24(self.cis_sync_delay, &mut buf[6..9])?;
+        pack_u24(self.transport_latency_m_to_s, &mut buf[9..12])?;
+        pack_u24(self.transport_latency_s_to_m, &mut buf[12..15])?;
+        buf[15] = self.phy_m_to_s.into();
+        buf[16] = self.phy_s_to_m.into();
+        buf[17] = self.nse;
+        buf[18] = self.bn_m_to_s;
+        buf[19] = self.bn_s_to_m;
+        buf[20] = self.ft_m_to_s;
+        buf[21] = self.ft_s_to_m;
+        buf[22..24].copy_from_slice(&self.max_pdu_m_to_s.to_le_bytes());
+        buf[24..26].copy_from_slice(&self.max_pdu_s_to_m.to_le_bytes());
+        buf[26..28].copy_from_slice(&self.iso_interval.to_le_bytes());
+        Ok(())
+    }
+}
+/// CIS Request event. Sent to the slave when the master wants to create a CIS. Answer with
+/// [`AcceptCISRequest`] or [`RejectCISRequest`].
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct CISRequest {
+    pub acl_handle: ConnectionHandle,
+    pub cis_handle: ConnectionHandle,
+    pub cig_id: u8,
+    pub cis_id: u8,
+}
+impl CISRequest {
+    pub const BYTE_LEN: usize = ConnectionHandle::BYTE_LEN * 2 + 1 + 1;
+}
+impl MetaEvent for CISRequest {
+    const META_CODE: MetaEventCode = MetaEventCode::CISRequest;
+
+    fn meta_byte_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+
+    fn meta_unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        Ok(CISRequest {
+            acl_handle: ConnectionHandle::unpack_from(&buf[..2])?,
+            cis_handle: ConnectionHandle::unpack_from(&buf[2..4])?,
+            cig_id: buf[4],
+            cis_id: buf[5],
+        })
+    }
+
+    fn meta_pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        self.acl_handle.pack_into(&mut buf[..2])?;
+        self.cis_handle.pack_into(&mut buf[2..4])?;
+        buf[4] = self.cig_id;
+        buf[5] = self.cis_id;
+        Ok(())
+    }
+}
+/// Packs a count byte followed by each `ConnectionHandle`.
+fn pack_handles(handles: &[ConnectionHandle], buf: &mut [u8]) -> Result<(), PackError> {
+    PackError::expect_length(1 + handles.len() * ConnectionHandle::BYTE_LEN, buf)?;
+    buf[0] = handles
+        .len()
+        .try_into()
+        .map_err(|_| PackError::InvalidFields)?;
+    for (handle, chunk) in handles
+        .iter()
+        .zip(buf[1..].chunks_exact_mut(ConnectionHandle::BYTE_LEN))
+    {
+        handle.pack_into(chunk)?;
+    }
+    Ok(())
+}
+fn unpack_handles(buf: &[u8]) -> Result<Box<[ConnectionHandle]>, PackError> {
+    let count = usize::from(*buf.get(0).ok_or(PackError::BadLength {
+        expected: 1,
+        got: 0,
+    })?);
+    PackError::expect_length(1 + count * ConnectionHandle::BYTE_LEN, buf)?;
+    buf[1..]
+        .chunks_exact(ConnectionHandle::BYTE_LEN)
+        .map(ConnectionHandle::unpack_from)
+        .collect()
+}
+/// Creates a BIG (as a broadcaster) on a periodic advertising set. The controller returns a
+/// `CommandStatus` and then a [`CreateBIGComplete`] event.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct CreateBIG {
+    pub big_handle: u8,
+    pub advertising_handle: u8,
+    pub num_bis: u8,
+    /// SDU interval in microseconds (24-bit).
+    pub sdu_interval: u32,
+    pub max_sdu: u16,
+    /// Max transport latency in milliseconds.
+    pub max_transport_latency: u16,
+    /// Number of retransmissions.
+    pub rtn: u8,
+    pub phy: PHYMask,
+    pub packing: Packing,
+    pub framing: Framing,
+    /// Encrypts the BIG with this code if set.
+    pub broadcast_code: Option<BroadcastCode>,
+}
+impl CreateBIG {
+    pub const BYTE_LEN: usize = 1 + 1 + 1 + 3 + 2 + 2 + 1 + 1 + 1 + 1 + 1 + BroadcastCode::BYTE_LEN;
+    pub const OPCODE: LEControllerOpcode = LEControllerOpcode::CreateBIG;
+}
+impl Command for CreateBIG {
+    type Return = CommandStatus;
+
+    fn opcode() -> Opcode {
+        Self::OPCODE.into()
+    }
+
+    fn byte_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        buf[0] = self.big_handle;
+        buf[1] = self.advertising_handle;
+        buf[2] = self.num_bis;
+        pack_u24(self.sdu_interval, &mut buf[3..6])?;
+        buf[6..8].copy_from_slice(&self.max_sdu.to_le_bytes());
+        buf[8..10].copy_from_slice(&self.max_transport_latency.to_le_bytes());
+        buf[10] = self.rtn;
+        buf[11] = self.phy.into();
+        buf[12] = self.packing.into();
+        buf[13] = self.framing.into();
+        buf[14] = u8::from(self.broadcast_code.is_some());
+        buf[15..31].copy_from_slice(&self.broadcast_code.unwrap_or_default().0);
+        Ok(())
+    }
+
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        Ok(CreateBIG {
+            big_handle: buf[0],
+            advertising_handle: buf[1],
+            num_bis: buf[2],
+            sdu_interval: unpack_u24(&buf[3..6])?,
+            max_sdu: u16::from_le_bytes([buf[6], buf[7]]),
+            max_transport_latency: u16::from_le_bytes([buf[8], buf[9]]),
+            rtn: buf[10],
+            phy: PHYMask::try_from(buf[11]).map_err(|_| PackError::bad_index(11))?,
+            packing: Packing::try_from(buf[12]).map_err(|_| PackError::bad_index(12))?,
+            framing: Framing::try_from(buf[13]).map_err(|_| PackError::bad_index(13))?,
+            broadcast_code: match buf[14] {
+                0x00 => None,
+                0x01 => Some(BroadcastCode(
+                    buf[15..31].try_into().expect("length checked above"),
+                )),
+                _ => return Err(PackError::bad_index(14)),
+            },
+        })
+    }
+}
+/// Terminates a BIG (as a broadcaster). The controller returns a `CommandStatus` and then a
+/// [`TerminateBIGComplete`] event.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct TerminateBIG {
+    pub big_handle: u8,
+    pub reason: ErrorCode,
+}
+impl TerminateBIG {
+    pub const BYTE_LEN: usize = 1 + ErrorCode::BYTE_LEN;
+    pub const OPCODE: LEControllerOpcode = LEControllerOpcode::TerminateBIG;
+}
+impl Command for TerminateBIG {
+    type Return = CommandStatus;
+
+    fn opcode() -> Opcode {
+        Self::OPCODE.into()
+    }
+
+    fn byte_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        buf[0] = self.big_handle;
+        buf[1] = self.reason.into();
+        Ok(())
+    }
+
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        Ok(TerminateBIG {
+            big_handle: buf[0],
+            reason: ErrorCode::try_from(buf[1]).map_err(|_| PackError::bad_index(1))?,
+        })
+    }
+}
+/// Synchronizes to a BIG (as a receiver) found in the [`BIGInfoAdvertisingReport`] of a
+/// periodic advertising train. The controller returns a `CommandStatus` and then a
+/// [`BIGSyncEstablished`] event.
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct BIGCreateSync {
+    pub big_handle: u8,
+    /// Periodic advertising `Sync_Handle`.
+    pub sync_handle: u16,
+    pub broadcast_code: Option<BroadcastCode>,
+    /// Max number of subevents to receive per BIS in each interval. `0` lets the controller
+    /// decide.
+    pub mse: u8,
+    /// Sync timeout in 10 ms units.
+    pub big_sync_timeout: u16,
+    /// Indices (starting at 1) of the BISes to synchronize to.
+    pub bis: Box<[u8]>,
+}
+impl BIGCreateSync {
+    pub const HEADER_LEN: usize = 1 + 2 + 1 + BroadcastCode::BYTE_LEN + 1 + 2 + 1;
+    pub const OPCODE: LEControllerOpcode = LEControllerOpcode::BIGCreateSync;
+}
+impl Command for BIGCreateSync {
+    type Return = CommandStatus;
+
+    fn opcode() -> Opcode {
+        Self::OPCODE.into()
+    }
+
+    fn byte_len(&self) -> usize {
+        Self::HEADER_LEN + self.bis.len()
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(self.byte_len(), buf)?;
+        buf[0] = self.big_handle;
+        buf[1..3].copy_from_slice(&self.sync_handle.to_le_bytes());
+        buf[3] = u8::from(self.broadcast_code.is_some());
+        buf[4..20].copy_from_slice(&self.broadcast_code.unwrap_or_default().0);
+        buf[20] = self.mse;
+        buf[21..23].copy_from_slice(&self.big_sync_timeout.to_le_bytes());
+        buf[23] = self
+            .bis
+            .len()
+            .try_into()
+            .map_err(|_| PackError::InvalidFields)?;
+        buf[Self::HEADER_LEN..].copy_from_slice(&self.bis);
+        Ok(())
+    }
+
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        if buf.len() < Self::HEADER_LEN {
+            return Err(PackError::BadLength {
+                expected: Self::HEADER_LEN,
+                got: buf.len(),
+            });
+        }
+        PackError::expect_length(Self::HEADER_LEN + usize::from(buf[23]), buf)?;
+        Ok(BIGCreateSync {
+            big_handle: buf[0],
+            sync_handle: u16::from_le_bytes([buf[1], buf[2]]),
+            broadcast_code: match buf[3] {
+                0x00 => None,
+                0x01 => Some(BroadcastCode(
+                    buf[4..20].try_into().expect("length checked above"),
+                )),
+                _ => return Err(PackError::bad_index(3)),
+            },
+            mse: buf[20],
+            big_sync_timeout: u16::from_le_bytes([buf[21], buf[22]]),
+            bis: buf[Self::HEADER_LEN..].into(),
+        })
+    }
+}
+/// Stops synchronizing to a BIG (or cancels a pending [`BIGCreateSync`]).
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct BIGTerminateSync {
+    pub big_handle: u8,
+}
+impl BIGTerminateSync {
+    pub const BYTE_LEN: usize = 1;
+    pub const OPCODE: LEControllerOpcode = LEControllerOpcode::BIGTerminateSync;
+}
+impl Command for BIGTerminateSync {
+    type Return = CommandComplete<BIGTerminateSyncReturn>;
+
+    fn opcode() -> Opcode {
+        Self::OPCODE.into()
+    }
+
+    fn byte_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        buf[0] = self.big_handle;
+        Ok(())
+    }
+
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        Ok(BIGTerminateSync { big_handle: buf[0] })
+    }
+}
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct BIGTerminateSyncReturn {
+    pub status: ErrorCode,
+    pub big_handle: u8,
+}
+impl BIGTerminateSyncReturn {
+    pub const BYTE_LEN: usize = ErrorCode::BYTE_LEN + 1;
+}
+impl ReturnParameters for BIGTerminateSyncReturn {
+    fn byte_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        buf[0] = self.status.into();
+        buf[1] = self.big_handle;
+        Ok(())
+    }
+
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        Ok(BIGTerminateSyncReturn {
+            status: ErrorCode::try_from(buf[0]).map_err(|_| PackError::bad_index(0))?,
+            big_handle: buf[1],
+        })
+    }
+}
+/// Create BIG Complete event. `bis_handles` are the `ConnectionHandle`s of each BIS used to send
+/// ISO Data packets.
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct CreateBIGComplete {
+    pub status: ErrorCode,
+    pub big_handle: u8,
+    /// BIG synchronization delay in microseconds.
+    pub big_sync_delay: u32,
+    /// Transport latency in microseconds.
+    pub transport_latency_big: u32,
+    pub phy: PHY,
+    pub nse: u8,
+    pub bn: u8,
+    pub pto: u8,
+    pub irc: u8,
+    pub max_pdu: u16,
+    /// ISO interval in 1.25 ms units.
+    pub iso_interval: u16,
+    pub bis_handles: Box<[ConnectionHandle]>,
+}
+impl CreateBIGComplete {
+    pub const HEADER_LEN: usize = ErrorCode::BYTE_LEN + 1 + 3 + 3 + 1 + 4 + 2 + 2;
+}
+impl MetaEvent for CreateBIGComplete {
+    const META_CODE: MetaEventCode = MetaEventCode::CreateBIGComplete;
+
+    fn meta_byte_len(&self) -> usize {
+        Self::HEADER_LEN + 1 + self.bis_handles.len() * ConnectionHandle::BYTE_LEN
+    }
+
+    fn meta_unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        if buf.len() < Self::HEADER_LEN {
+            return Err(PackError::BadLength {
+                expected: Self::HEADER_LEN,
+                got: buf.len(),
+            });
+        }
+        let status = ErrorCode::try_from(buf[0]).map_err(|_| PackError::bad_index(0))?;
+        Ok(CreateBIGComplete {
+            status,
+            big_handle: buf[1],
+            big_sync_delay: unpack_u24(&buf[2..5])?,
+            transport_latency_big: unpack_u24(&buf[5..8])?,
+            phy: unpack_event_phy(status, buf[8], 8)?,
+            nse: buf[9],
+            bn: buf[10],
+            pto: buf[11],
+            irc: buf[12],
+            max_pdu: u16::from_le_bytes([buf[13], buf[14]]),
+            iso_interval: u16::from_le_bytes([buf[15], buf[16]]),
+            bis_handles: unpack_handles(&buf[Self::HEADER_LEN..])?,
+        })
+    }
+
+    fn meta_pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(self.meta_byte_len(), buf)?;
+        buf[0] = self.status.into();
+        buf[1] = self.big_handle;
+        pack_u24(self.big_sync_delay, &mut buf[2..5])?;
+        pack_u24(self.transport_latency_big, &mut buf[5..8])?;
+        buf[8] = self.phy.into();
+        buf[9] = self.nse;
+        buf[10] = self.bn;
+        buf[11] = self.pto;
+        buf[12] = self.irc;
+        buf[13..15].copy_from_slice(&self.max_pdu.to_le_bytes());
+        buf[15..17].copy_from_slice(&self.iso_interval.to_le_bytes());
+        pack_handles(&self.bis_handles, &mut buf[Self::HEADER_LEN..])
+    }
+}
+/// Terminate BIG Complete event. Sent once a BIG stops (terminated by the host or lost).
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct TerminateBIGComplete {
+    pub big_handle: u8,
+    pub reason: ErrorCode,
+}
+impl TerminateBIGComplete {
+    pub const BYTE_LEN: usize = 1 + ErrorCode::BYTE_LEN;
+}
+impl MetaEvent for TerminateBIGComplete {
+    const META_CODE: MetaEventCode = MetaEventCode::TerminateBIGComplete;
+
+    fn meta_byte_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+
+    fn meta_unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        Ok(TerminateBIGComplete {
+            big_handle: buf[0],
+            reason: ErrorCode::try_from(buf[1]).map_err(|_| PackError::bad_index(1))?,
+        })
+    }
+
+    fn meta_pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        buf[0] = self.big_handle;
+        buf[1] = self.reason.into();
+        Ok(())
+    }
+}
+/// BIG Sync Established event. `bis_handles` are the `ConnectionHandle`s of each BIS (in the
+/// same order as [`BIGCreateSync::bis`]) that ISO Data packets are received on.
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct BIGSyncEstablished {
+    pub status: ErrorCode,
+    pub big_handle: u8,
+    /// Transport latency in microseconds.
+    pub transport_latency_big: u32,
+    pub nse: u8,
+    pub bn: u8,
+    pub pto: u8,
+    pub irc: u8,
+    pub max_pdu: u16,
+    /// ISO interval in 1.25 ms units.
+    pub iso_interval: u16,
+    pub bis_handles: Box<[ConnectionHandle]>,
+}
+impl BIGSyncEstablished {
+    pub const HEADER_LEN: usize = ErrorCode::BYTE_LEN + 1 + 3 + 4 + 2 + 2;
+}
+impl MetaEvent for BIGSyncEstablished {
+    const META_CODE: MetaEventCode = MetaEventCode::BIGSyncEstablished;
+
+    fn meta_byte_len(&self) -> usize {
+        Self::HEADER_LEN + 1 + self.bis_handles.len() * ConnectionHandle::BYTE_LEN
+    }
+
+    fn meta_unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        if buf.len() < Self::HEADER_LEN {
+            return Err(PackError::BadLength {
+                expected: Self::HEADER_LEN,
+                got: buf.len(),
+            });
+        }
+        Ok(BIGSyncEstablished {
+            status: ErrorCode::try_from(buf[0]).map_err(|_| PackError::bad_index(0))?,
+            big_handle: buf[1],
+            transport_latency_big: unpack_u24(&buf[2..5])?,
+            nse: buf[5],
+            bn: buf[6],
+            pto: buf[7],
+            irc: buf[8],
+            max_pdu: u16::from_le_bytes([buf[9], buf[10]]),
+            iso_interval: u16::from_le_bytes([buf[11], buf[12]]),
+            bis_handles: unpack_handles(&buf[Self::HEADER_LEN..])?,
+        })
+    }
+
+    fn meta_pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(self.meta_byte_len(), buf)?;
+        buf[0] = self.status.into();
+        buf[1] = self.big_handle;
+        pack_u24(self.transport_latency_big, &mut buf[2..5])?;
+        buf[5] = self.nse;
+        buf[6] = self.bn;
+        buf[7] = self.pto;
+        buf[8] = self.irc;
+        buf[9..11].copy_from_slice(&self.max_pdu.to_le_bytes());
+        buf[11..13].copy_from_slice(&self.iso_interval.to_le_bytes());
+        pack_handles(&self.bis_handles, &mut buf[Self::HEADER_LEN..])
+    }
+}
+/// BIG Sync Lost event. Sent when the receiver loses (or terminates) the sync to a BIG.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct BIGSyncLost {
+    pub big_handle: u8,
+    pub reason: ErrorCode,
+}
+impl BIGSyncLost {
+    pub const BYTE_LEN: usize = 1 + ErrorCode::BYTE_LEN;
+}
+impl MetaEvent for BIGSyncLost {
+    const META_CODE: MetaEventCode = MetaEventCode::BIGSyncLost;
+
+    fn meta_byte_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+
+    fn meta_unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        Ok(BIGSyncLost {
+            big_handle: buf[0],
+            reason: ErrorCode::try_from(buf[1]).map_err(|_| PackError::bad_index(1))?,
+        })
+    }
+
+    fn meta_pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        buf[0] = self.big_handle;
+        buf[1] = self.reason.into();
+        Ok(())
+    }
+}
+/// BIGInfo Advertising Report event. The controller's decoded
+/// [`crate::le::advertisement_structures::big_info::BIGInfo`] of a periodic advertising train the
+/// host is synchronized to.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct BIGInfoAdvertisingReport {
+    /// Periodic advertising `Sync_Handle`.
+    pub sync_handle: u16,
+    pub num_bis: u8,
+    pub nse: u8,
+    /// ISO interval in 1.25 ms units.
+    pub iso_interval: u16,
+    pub bn: u8,
+    pub pto: u8,
+    pub irc: u8,
+    pub max_pdu: u16,
+    /// SDU interval in microseconds.
+    pub sdu_interval: u32,
+    pub max_sdu: u16,
+    pub phy: PHY,
+    pub framing: Framing,
+    pub encrypted: bool,
+}
+impl BIGInfoAdvertisingReport {
+    pub const BYTE_LEN: usize = 2 + 1 + 1 + 2 + 1 + 1 + 1 + 2 + 3 + 2 + 1 + 1 + 1;
+}
+impl MetaEvent for BIGInfoAdvertisingReport {
+    const META_CODE: MetaEventCode = MetaEventCode::BIGInfoAdvertisingReport;
+
+    fn meta_byte_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+
+    fn meta_unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        Ok(BIGInfoAdvertisingReport {
+            sync_handle: u16::from_le_bytes([buf[0], buf[1]]),
+            num_bis: buf[2],
+            nse: buf[3],
+            iso_interval: u16::from_le_bytes([buf[4], buf[5]]),
+            bn: buf[6],
+            pto: buf[7],
+            irc: buf[8],
+            max_pdu: u16::from_le_bytes([buf[9], buf[10]]),
+            sdu_interval: unpack_u24(&buf[11..14])?,
+            max_sdu: u16::from_le_bytes([buf[14], buf[15]]),
+            phy: PHY::try_from(buf[16]).map_err(|_| PackError::bad_index(16))?,
+            framing: Framing::try_from(buf[17]).map_err(|_| PackError::bad_index(17))?,
+            encrypted: match buf[18] {
+                0x00 => false,
+                0x01 => true,
+                _ => return Err(PackError::bad_index(18)),
+            },
+        })
+    }
+
+    fn meta_pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        buf[0..2].copy_from_slice(&self.sync_handle.to_le_bytes());
+        buf[2] = self.num_bis;
+        buf[3] = self.nse;
+        buf[4..6].copy_from_slice(&self.iso_interval.to_le_bytes());
+        buf[6] = self.bn;
+        buf[7] = self.pto;
+        buf[8] = self.irc;
+        buf[9..11].copy_from_slice(&self.max_pdu.to_le_bytes());
+        pack_u24(self.sdu_interval, &mut buf[11..14])?;
+        buf[14..16].copy_from_slice(&self.max_sdu.to_le_bytes());
+        buf[16] = self.phy.into();
+        buf[17] = self.framing.into();
+        buf[18] = u8::from(self.encrypted);
+        Ok(())
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hci::test_packets::{assert_command, assert_meta_event, assert_return};
+
+    #[test]
+    fn set_cig_parameters() {
+        let cis = |cis_id, max_sdu_s_to_m, rtn_s_to_m| CISParameters {
+            cis_id,
+            max_sdu_m_to_s: 40,
+            max_sdu_s_to_m,
+            phy_m_to_s: PHY::LE2M.into(),
+            phy_s_to_m: PHY::LE2M.into(),
+            rtn_m_to_s: 2,
+            rtn_s_to_m,
+        };
+        let mut command = SetCIGParameters {
+            cig_id: 0x01,
+            sdu_interval_m_to_s: 10_000,
+            sdu_interval_s_to_m: 10_000,
+            worst_case_sca: MasterClockAccuracy::PPM50,
+            packing: Packing::Sequential,
+            framing: Framing::Unframed,
+            max_transport_latency_m_to_s: 10,
+            max_transport_latency_s_to_m: 10,
+            cis: vec![cis(0, 40, 2), cis(1, 0, 0)].into_boxed_slice(),
+        };
+        assert_command(
+            &command,
+            &[
+                0x62, 0x20, 0x21, 0x01, 0x10, 0x27, 0x00, 0x10, 0x27, 0x00, 0x05, 0x00, 0x00, 0x0A,
+                0x00, 0x0A, 0x00, 0x02, // CIS 0
+                0x00, 0x28, 0x00, 0x28, 0x00, 0x02, 0x02, 0x02, 0x02, // CIS 1
+                0x01, 0x28, 0x00, 0x00, 0x00, 0x02, 0x02, 0x02, 0x00,
+            ],
+        );
+        // The CIS count doesn't match the parameters.
+        assert!(SetCIGParameters::unpack_from(&[
+            0x01, 0x10, 0x27, 0x00, 0x10, 0x27, 0x00, 0x05, 0x00, 0x00, 0x0A, 0x00, 0x0A, 0x00,
+            0x02, 0x00, 0x28, 0x00, 0x28, 0x00, 0x02, 0x02, 0x02, 0x02,
+        ])
+        .is_err());
+        command.sdu_interval_m_to_s = MAX_U24 + 1;
+        let mut buf = [0_u8; 33];
+        assert!(command.pack_into(&mut buf).is_err());
+    }
+    #[test]
+    fn set_cig_parameters_return() {
+        assert_return(
+            &SetCIGParametersReturn {
+                status: ErrorCode::Ok,
+                cig_id: 0x01,
+                handles: vec![ConnectionHandle::new(0x0060), ConnectionHandle::new(0x0061)]
+                    .into_boxed_slice(),
+            },
+            &[0x00, 0x01, 0x02, 0x60, 0x00, 0x61, 0x00],
+        );
+        // A failed command may only return the status.
+        assert_eq!(
+            SetCIGParametersReturn::unpack_from(&[0x12]),
+            Ok(SetCIGParametersReturn {
+                status: ErrorCode::InvalidHCICommandParameters,
+                cig_id: 0,
+                handles: Box::new([]),
+            })
+        );
+    }
+    #[test]
+    fn create_cis() {
+        assert_command(
+            &CreateCIS {
+                cis: vec![
+                    CISConnection {
+                        cis_handle: ConnectionHandle::new(0x0060),
+                        acl_handle: ConnectionHandle::new(0x0040),
+                    },
+                    CISConnection {
+                        cis_handle: ConnectionHandle::new(0x0061),
+                        acl_handle: ConnectionHandle::new(0x0041),
+                    },
+                ]
+                .into_boxed_slice(),
+            },
+            &[
+                0x64, 0x20, 0x09, 0x02, 0x60, 0x00, 0x40, 0x00, 0x61, 0x00, 0x41, 0x00,
+            ],
+        );
+        assert!(CreateCIS::unpack_from(&[0x02, 0x60, 0x00, 0x40, 0x00]).is_err());
+    }
+    #[test]
+    fn remove_cig() {
+        assert_command(&RemoveCIG { cig_id: 0x01 }, &[0x65, 0x20, 0x01, 0x01]);
+        assert_return(
+            &RemoveCIGReturn {
+                status: ErrorCode::Ok,
+                cig_id: 0x01,
+            },
+            &[0x00, 0x01],
+        );
+    }
+    #[test]
+    fn accept_and_reject_cis_request() {
+        let handle = ConnectionHandle::new(0x0060);
+        assert_command(
+            &AcceptCISRequest { handle },
+            &[0x66, 0x20, 0x02, 0x60, 0x00],
+        );
+        assert_command(
+            &RejectCISRequest {
+                handle,
+                reason: ErrorCode::HostRejectedDueToLimitedResources,
+            },
+            &[0x67, 0x20, 0x03, 0x60, 0x00, 0x0D],
+        );
+    }
+    #[test]
+    fn setup_iso_data_path() {
+        let handle = ConnectionHandle::new(0x0060);
+        let mut command = SetupISODataPath::hci(handle, DataPathDirection::Input);
+        assert_command(
+            &command,
+            &[
+                0x6E, 0x20, 0x0D, 0x60, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x00,
+            ],
+        );
+        assert_return(
+            &ConnectionHandleReturn {
+                status: ErrorCode::Ok,
+                handle,
+            },
+            &[0x00, 0x60, 0x00],
+        );
+        command.controller_delay = MAX_U24 + 1;
+        let mut buf = [0_u8; SetupISODataPath::HEADER_LEN];
+        assert!(command.pack_into(&mut buf).is_err());
+    }
+    #[test]
+    fn remove_iso_data_path() {
+        assert_command(
+            &RemoveISODataPath {
+                handle: ConnectionHandle::new(0x0060),
+                input: true,
+                output: true,
+            },
+            &[0x6F, 0x20, 0x03, 0x60, 0x00, 0x03],
+        );
+        assert_command(
+            &RemoveISODataPath {
+                handle: ConnectionHandle::new(0x0060),
+                input: false,
+                output: true,
+            },
+            &[0x6F, 0x20, 0x03, 0x60, 0x00, 0x02],
+        );
+        // Bits 2-7 are reserved.
+        assert!(RemoveISODataPath::unpack_from(&[0x60, 0x00, 0x04]).is_err());
+    }
+    #[test]
+    fn cis_established() {
+        assert_meta_event(
+            &CISEstablished {
+                status: ErrorCode::Ok,
+                handle: ConnectionHandle::new(0x0060),
+                cig_sync_delay: 0x0400,
+                cis_sync_delay: 0x0400,
+                transport_latency_m_to_s: 8_000,
+                transport_latency_s_to_m: 8_000,
+                phy_m_to_s: PHY::LE2M,
+                phy_s_to_m: PHY::LE2M,
+                nse: 2,
+                bn_m_to_s: 1,
+                bn_s_to_m: 1,
+                ft_m_to_s: 1,
+                ft_s_to_m: 1,
+                max_pdu_m_to_s: 40,
+                max_pdu_s_to_m: 40,
+                iso_interval: 8,
+            },
+            &[
+                0x19, 0x00, 0x60, 0x00, 0x00, 0x04, 0x00, 0x00, 0x04, 0x00, 0x40, 0x1F, 0x00, 0x40,
+                0x1F, 0x00, 0x02, 0x02, 0x02, 0x01, 0x01, 0x01, 0x01, 0x28, 0x00, 0x28, 0x00, 0x08,
+                0x00,
+            ],
+        );
+    }
+    #[test]
+    fn cis_request() {
+        assert_meta_event(
+            &CISRequest {
+                acl_handle: ConnectionHandle::new(0x0040),
+                cis_handle: ConnectionHandle::new(0x0060),
+                cig_id: 0x01,
+                cis_id: 0x00,
+            },
+            &[0x1A, 0x40, 0x00, 0x60, 0x00, 0x01, 0x00],
+        );
+    }
+    #[test]
+    fn create_big() {
+        let mut code = [0_u8; 16];
+        for (i, b) in code.iter_mut().enumerate() {
+            *b = i as u8 + 1;
+        }
+        let mut bytes = vec![
+            0x68, 0x20, 0x1F, 0x00, 0x01, 0x02, 0x10, 0x27, 0x00, 0x64, 0x00, 0x14, 0x00, 0x02,
+            0x02, 0x00, 0x00, 0x01,
+        ];
+        bytes.extend_from_slice(&code);
+        assert_command(
+            &CreateBIG {
+                big_handle: 0x00,
+                advertising_handle: 0x01,
+                num_bis: 2,
+                sdu_interval: 10_000,
+                max_sdu: 100,
+                max_transport_latency: 20,
+                rtn: 2,
+                phy: PHY::LE2M.into(),
+                packing: Packing::Sequential,
+                framing: Framing::Unframed,
+                broadcast_code: Some(BroadcastCode(code)),
+            },
+            &bytes,
+        );
+        // The encryption flag is either 0 or 1.
+        bytes[17] = 0x02;
+        assert!(CreateBIG::unpack_from(&bytes[3..]).is_err());
+    }
+    #[test]
+    fn terminate_big() {
+        assert_command(
+            &TerminateBIG {
+                big_handle: 0x00,
+                reason: ErrorCode::ConnectionTerminatedByLocalHost,
+            },
+            &[0x6A, 0x20, 0x02, 0x00, 0x16],
+        );
+        assert_meta_event(
+            &TerminateBIGComplete {
+                big_handle: 0x00,
+                reason: ErrorCode::ConnectionTerminatedByLocalHost,
+            },
+            &[0x1C, 0x00, 0x16],
+        );
+    }
+    #[test]
+    fn big_create_sync() {
+        let mut bytes = vec![0x6B, 0x20, 0x1A, 0x00, 0x01, 0x00, 0x00];
+        bytes.extend_from_slice(&[0x00; 16]);
+        bytes.extend_from_slice(&[0x00, 0x64, 0x00, 0x02, 0x01, 0x02]);
+        assert_command(
+            &BIGCreateSync {
+                big_handle: 0x00,
+                sync_handle: 0x0001,
+                broadcast_code: None,
+                mse: 0,
+                big_sync_timeout: 100,
+                bis: Box::new([1, 2]),
+            },
+            &bytes,
+        );
+        // Fewer BIS indices than Num_BIS.
+        assert!(BIGCreateSync::unpack_from(&bytes[3..bytes.len() - 1]).is_err());
+    }
+    #[test]
+    fn big_terminate_sync() {
+        assert_command(
+            &BIGTerminateSync { big_handle: 0x00 },
+            &[0x6C, 0x20, 0x01, 0x00],
+        );
+        assert_return(
+            &BIGTerminateSyncReturn {
+                status: ErrorCode::Ok,
+                big_handle: 0x00,
+            },
+            &[0x00, 0x00],
+        );
+    }
+    #[test]
+    fn create_big_complete() {
+        assert_meta_event(
+            &CreateBIGComplete {
+                status: ErrorCode::Ok,
+                big_handle: 0x00,
+                big_sync_delay: 0x0400,
+                transport_latency_big: 10_000,
+                phy: PHY::LE2M,
+                nse: 4,
+                bn: 2,
+                pto: 0,
+                irc: 2,
+                max_pdu: 100,
+                iso_interval: 8,
+                bis_handles: vec![ConnectionHandle::new(0x0100), ConnectionHandle::new(0x0101)]
+                    .into_boxed_slice(),
+            },
+            &[
+                0x1B, 0x00, 0x00, 0x00, 0x04, 0x00, 0x10, 0x27, 0x00, 0x02, 0x04, 0x02, 0x00, 0x02,
+                0x64, 0x00, 0x08, 0x00, 0x02, 0x00, 0x01, 0x01, 0x01,
+            ],
+        );
+    }
+    #[test]
+    fn big_sync_established_and_lost() {
+        assert_meta_event(
+            &BIGSyncEstablished {
+                status: ErrorCode::Ok,
+                big_handle: 0x00,
+                transport_latency_big: 10_000,
+                nse: 4,
+                bn: 2,
+                pto: 0,
+                irc: 2,
+                max_pdu: 100,
+                iso_interval: 8,
+                bis_handles: vec![ConnectionHandle::new(0x0100), ConnectionHandle::new(0x0101)]
+                    .into_boxed_slice(),
+            },
+            &[
+                0x1D, 0x00, 0x00, 0x10, 0x27, 0x00, 0x04, 0x02, 0x00, 0x02, 0x64, 0x00, 0x08, 0x00,
+                0x02, 0x00, 0x01, 0x01, 0x01,
+            ],
+        );
+        assert_meta_event(
+            &BIGSyncLost {
+                big_handle: 0x00,
+                reason: ErrorCode::ConnectionTimeout,
+            },
+            &[0x1E, 0x00, 0x08],
+        );
+    }
+    #[test]
+    fn big_info_advertising_report() {
+        let mut bytes = [
+            0x22, 0x01, 0x00, 0x02, 0x04, 0x08, 0x00, 0x02, 0x00, 0x02, 0x64, 0x00, 0x10, 0x27,
+            0x00, 0x64, 0x00, 0x02, 0x00, 0x01,
+        ];
+        assert_meta_event(
+            &BIGInfoAdvertisingReport {
+                sync_handle: 0x0001,
+                num_bis: 2,
+                nse: 4,
+                iso_interval: 8,
+                bn: 2,
+                pto: 0,
+                irc: 2,
+                max_pdu: 100,
+                sdu_interval: 10_000,
+                max_sdu: 100,
+                phy: PHY::LE2M,
+                framing: Framing::Unframed,
+                encrypted: true,
+            },
+            &bytes,
+        );
+        bytes[19] = 0x02;
+        assert!(BIGInfoAdvertisingReport::meta_unpack_from(&bytes[1..]).is_err());
+    }
+}
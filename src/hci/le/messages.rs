@@ -6,12 +6,51 @@ pub mod commands {
             ReadAdvertisingChannelTxPower, SetAdvertisingData, SetAdvertisingEnable,
             SetAdvertisingParameters,
         },
-        connection::{ReadBufferSizeV1, ReadBufferSizeV2},
+        channel::{ReadChannelMap, SetHostChannelClassification},
+        connection::{ReadBufferSizeV1, ReadBufferSizeV2, ReadRemoteFeatures, RequestPeerSCA},
+        cte::{
+            ConnectionCTERequestEnable, ConnectionCTEResponseEnable,
+            SetConnectionCTEReceiveParameters, SetConnectionCTETransmitParameters,
+            SetConnectionlessCTETransmitEnable, SetConnectionlessCTETransmitParameters,
+            SetConnectionlessIQSamplingEnable,
+        },
+        data_length::{
+            ReadMaximumDataLength, ReadSuggestedDefaultDataLength, SetDataLength,
+            WriteSuggestedDefaultDataLength,
+        },
+        info::{ReadLocalSupportedFeatures, ReadSupportedStates},
+        iso::{
+            AcceptCISRequest, BIGCreateSync, BIGTerminateSync, CreateBIG, CreateCIS,
+            RejectCISRequest, RemoveCIG, RemoveISODataPath, SetCIGParameters, SetupISODataPath,
+            TerminateBIG,
+        },
         mask::SetMetaEventMask,
+        phy::{ReadPHY, SetDefaultPHY, SetPHY},
+        power::{
+            EnhancedReadTransmitPowerLevel, ReadRemoteTransmitPowerLevel,
+            SetPathLossReportingEnable, SetPathLossReportingParameters,
+            SetTransmitPowerReportingEnable,
+        },
         random::Rand,
         scan::{SetScanEnable, SetScanParameters, SetScanResponseData},
+        test::{
+            ReceiverTestV1, ReceiverTestV2, ReceiverTestV3, TestEnd, TransmitterTestV1,
+            TransmitterTestV2, TransmitterTestV3,
+        },
     };
 }
 pub mod events {
-    pub use super::report::AdvertisingReport;
+    pub use super::{
+        channel::ChannelSelectionAlgorithmEvent,
+        connection::{ReadRemoteFeaturesComplete, RequestPeerSCAComplete},
+        cte::{CTERequestFailed, ConnectionIQReport, ConnectionlessIQReport},
+        data_length::DataLengthChange,
+        iso::{
+            BIGInfoAdvertisingReport, BIGSyncEstablished, BIGSyncLost, CISEstablished, CISRequest,
+            CreateBIGComplete, TerminateBIGComplete,
+        },
+        phy::PHYUpdateComplete,
+        power::{PathLossThreshold, TransmitPowerReporting},
+        report::AdvertisingReport,
+    };
 }
@@ -4,9 +4,17 @@ pub mod mask;
 pub mod messages;
 pub mod report;
 pub use messages::*;
+pub mod channel;
 pub mod connection;
+pub mod cte;
+pub mod data_length;
+pub mod info;
+pub mod iso;
+pub mod phy;
+pub mod power;
 pub mod random;
 pub mod scan;
+pub mod test;
 use crate::bytes::Storage;
 use crate::hci::event::{Event, EventCode, EventPacket};
 use crate::hci::{Opcode, OCF, OGF};
@@ -46,9 +54,44 @@ pub enum LEControllerOpcode {
     LongTermKeyRequestReply = 0x001A,
     LongTermKeyRequestNegativeReply = 0x001B,
     ReadSupportedState = 0x001C,
-    ReceiverTest = 0x001D,
-    TransmitterTest = 0x001E,
+    ReceiverTestV1 = 0x001D,
+    TransmitterTestV1 = 0x001E,
     TestEnd = 0x001F,
+    SetDataLength = 0x0022,
+    ReadSuggestedDefaultDataLength = 0x0023,
+    WriteSuggestedDefaultDataLength = 0x0024,
+    ReadMaximumDataLength = 0x002F,
+    ReadPHY = 0x0030,
+    SetDefaultPHY = 0x0031,
+    SetPHY = 0x0032,
+    ReceiverTestV2 = 0x0033,
+    TransmitterTestV2 = 0x0034,
+    ReceiverTestV3 = 0x004F,
+    TransmitterTestV3 = 0x0050,
+    SetConnectionlessCTETransmitParameters = 0x0051,
+    SetConnectionlessCTETransmitEnable = 0x0052,
+    SetConnectionlessIQSamplingEnable = 0x0053,
+    SetConnectionCTEReceiveParameters = 0x0054,
+    SetConnectionCTETransmitParameters = 0x0055,
+    ConnectionCTERequestEnable = 0x0056,
+    ConnectionCTEResponseEnable = 0x0057,
+    SetCIGParameters = 0x0062,
+    CreateCIS = 0x0064,
+    RemoveCIG = 0x0065,
+    AcceptCISRequest = 0x0066,
+    RejectCISRequest = 0x0067,
+    CreateBIG = 0x0068,
+    TerminateBIG = 0x006A,
+    BIGCreateSync = 0x006B,
+    BIGTerminateSync = 0x006C,
+    RequestPeerSCA = 0x006D,
+    SetupISODataPath = 0x006E,
+    RemoveISODataPath = 0x006F,
+    EnhancedReadTransmitPowerLevel = 0x0076,
+    ReadRemoteTransmitPowerLevel = 0x0077,
+    SetPathLossReportingParameters = 0x0078,
+    SetPathLossReportingEnable = 0x0079,
+    SetTransmitPowerReportingEnable = 0x007A,
 }
 impl TryFrom<OCF> for LEControllerOpcode {
     type Error = ConversionError;
@@ -83,9 +126,44 @@ impl TryFrom<OCF> for LEControllerOpcode {
             0x001A => Ok(LEControllerOpcode::LongTermKeyRequestReply),
             0x001B => Ok(LEControllerOpcode::LongTermKeyRequestNegativeReply),
             0x001C => Ok(LEControllerOpcode::ReadSupportedState),
-            0x001D => Ok(LEControllerOpcode::ReceiverTest),
-            0x001E => Ok(LEControllerOpcode::TransmitterTest),
+            0x001D => Ok(LEControllerOpcode::ReceiverTestV1),
+            0x001E => Ok(LEControllerOpcode::TransmitterTestV1),
             0x001F => Ok(LEControllerOpcode::TestEnd),
+            0x0022 => Ok(LEControllerOpcode::SetDataLength),
+            0x0023 => Ok(LEControllerOpcode::ReadSuggestedDefaultDataLength),
+            0x0024 => Ok(LEControllerOpcode::WriteSuggestedDefaultDataLength),
+            0x002F => Ok(LEControllerOpcode::ReadMaximumDataLength),
+            0x0030 => Ok(LEControllerOpcode::ReadPHY),
+            0x0031 => Ok(LEControllerOpcode::SetDefaultPHY),
+            0x0032 => Ok(LEControllerOpcode::SetPHY),
+            0x0033 => Ok(LEControllerOpcode::ReceiverTestV2),
+            0x0034 => Ok(LEControllerOpcode::TransmitterTestV2),
+            0x004F => Ok(LEControllerOpcode::ReceiverTestV3),
+            0x0050 => Ok(LEControllerOpcode::TransmitterTestV3),
+            0x0051 => Ok(LEControllerOpcode::SetConnectionlessCTETransmitParameters),
+            0x0052 => Ok(LEControllerOpcode::SetConnectionlessCTETransmitEnable),
+            0x0053 => Ok(LEControllerOpcode::SetConnectionlessIQSamplingEnable),
+            0x0054 => Ok(LEControllerOpcode::SetConnectionCTEReceiveParameters),
+            0x0055 => Ok(LEControllerOpcode::SetConnectionCTETransmitParameters),
+            0x0056 => Ok(LEControllerOpcode::ConnectionCTERequestEnable),
+            0x0057 => Ok(LEControllerOpcode::ConnectionCTEResponseEnable),
+            0x0062 => Ok(LEControllerOpcode::SetCIGParameters),
+            0x0064 => Ok(LEControllerOpcode::CreateCIS),
+            0x0065 => Ok(LEControllerOpcode::RemoveCIG),
+            0x0066 => Ok(LEControllerOpcode::AcceptCISRequest),
+            0x0067 => Ok(LEControllerOpcode::RejectCISRequest),
+            0x0068 => Ok(LEControllerOpcode::CreateBIG),
+            0x006A => Ok(LEControllerOpcode::TerminateBIG),
+            0x006B => Ok(LEControllerOpcode::BIGCreateSync),
+            0x006C => Ok(LEControllerOpcode::BIGTerminateSync),
+            0x006D => Ok(LEControllerOpcode::RequestPeerSCA),
+            0x006E => Ok(LEControllerOpcode::SetupISODataPath),
+            0x006F => Ok(LEControllerOpcode::RemoveISODataPath),
+            0x0076 => Ok(LEControllerOpcode::EnhancedReadTransmitPowerLevel),
+            0x0077 => Ok(LEControllerOpcode::ReadRemoteTransmitPowerLevel),
+            0x0078 => Ok(LEControllerOpcode::SetPathLossReportingParameters),
+            0x0079 => Ok(LEControllerOpcode::SetPathLossReportingEnable),
+            0x007A => Ok(LEControllerOpcode::SetTransmitPowerReportingEnable),
             _ => Err(ConversionError(())),
         }
     }
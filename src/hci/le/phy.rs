@@ -0,0 +1,350 @@
+//! HCI LE PHY commands and events. Reads and selects the PHYs (LE 1M, LE 2M or LE Coded) used by
+//! LE connections.
+use crate::hci::command::Command;
+use crate::hci::event::{CommandComplete, CommandStatus, ReturnParameters, StatusReturn};
+use crate::hci::le::{LEControllerOpcode, MetaEvent, MetaEventCode};
+use crate::hci::{ErrorCode, Opcode};
+use crate::le::connection::{Connection, ConnectionHandle};
+use crate::le::phy::{PHYMask, PHY};
+use crate::{ConversionError, PackError};
+use core::convert::TryFrom;
+
+/// Unpacks a `PHY` that may be `0x00` if the event `status` is an error.
+pub(crate) fn unpack_event_phy(
+    status: ErrorCode,
+    value: u8,
+    index: usize,
+) -> Result<PHY, PackError> {
+    match PHY::try_from(value) {
+        Ok(phy) => Ok(phy),
+        Err(_) if !status.is_ok() => Ok(PHY::default()),
+        Err(_) => Err(PackError::bad_index(index)),
+    }
+}
+/// `ALL_PHYS` byte and the TX and RX `PHYMask`s. `None` means the host has no preference.
+fn pack_preferred_phys(tx_phys: Option<PHYMask>, rx_phys: Option<PHYMask>, buf: &mut [u8]) {
+    buf[0] = u8::from(tx_phys.is_none()) | (u8::from(rx_phys.is_none()) << 1);
+    buf[1] = tx_phys.map_or(0, u8::from);
+    buf[2] = rx_phys.map_or(0, u8::from);
+}
+fn unpack_preferred_phys(
+    buf: &[u8],
+    index: usize,
+) -> Result<(Option<PHYMask>, Option<PHYMask>), PackError> {
+    let all_phys = buf[0];
+    if all_phys & !0b11 != 0 {
+        return Err(PackError::bad_index(index));
+    }
+    let tx = PHYMask::try_from(buf[1]).map_err(|_| PackError::bad_index(index + 1))?;
+    let rx = PHYMask::try_from(buf[2]).map_err(|_| PackError::bad_index(index + 2))?;
+    Ok((
+        if all_phys & 0b01 == 0 { Some(tx) } else { None },
+        if all_phys & 0b10 == 0 { Some(rx) } else { None },
+    ))
+}
+/// Coding preferred when transmitting on the LE Coded PHY. S=2 doubles the range of LE 1M and
+/// S=8 quadruples it (at 500 kb/s and 125 kb/s).
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+#[repr(u16)]
+pub enum CodedPHYOptions {
+    NoPreference = 0x0000,
+    S2 = 0x0001,
+    S8 = 0x0002,
+}
+impl CodedPHYOptions {
+    pub const BYTE_LEN: usize = 2;
+}
+impl Default for CodedPHYOptions {
+    fn default() -> Self {
+        CodedPHYOptions::NoPreference
+    }
+}
+impl From<CodedPHYOptions> for u16 {
+    fn from(o: CodedPHYOptions) -> Self {
+        o as u16
+    }
+}
+impl TryFrom<u16> for CodedPHYOptions {
+    type Error = ConversionError;
+
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
+        match value {
+            0x0000 => Ok(CodedPHYOptions::NoPreference),
+            0x0001 => Ok(CodedPHYOptions::S2),
+            0x0002 => Ok(CodedPHYOptions::S8),
+            _ => Err(ConversionError(())),
+        }
+    }
+}
+/// Reads the current TX and RX `PHY` of a connection.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct ReadPHY {
+    pub handle: ConnectionHandle,
+}
+impl ReadPHY {
+    pub const BYTE_LEN: usize = ConnectionHandle::BYTE_LEN;
+    pub const OPCODE: LEControllerOpcode = LEControllerOpcode::ReadPHY;
+}
+impl Command for ReadPHY {
+    type Return = CommandComplete<ReadPHYReturn>;
+
+    fn opcode() -> Opcode {
+        Self::OPCODE.into()
+    }
+
+    fn byte_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        self.handle.pack_into(buf)
+    }
+
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        Ok(ReadPHY {
+            handle: ConnectionHandle::unpack_from(buf)?,
+        })
+    }
+}
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct ReadPHYReturn {
+    pub status: ErrorCode,
+    pub handle: ConnectionHandle,
+    pub tx_phy: PHY,
+    pub rx_phy: PHY,
+}
+impl ReadPHYReturn {
+    pub const BYTE_LEN: usize =
+        ErrorCode::BYTE_LEN + ConnectionHandle::BYTE_LEN + PHY::BYTE_LEN * 2;
+}
+impl ReturnParameters for ReadPHYReturn {
+    fn byte_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        buf[0] = self.status.into();
+        self.handle.pack_into(&mut buf[1..3])?;
+        buf[3] = self.tx_phy.into();
+        buf[4] = self.rx_phy.into();
+        Ok(())
+    }
+
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        let status = ErrorCode::try_from(buf[0]).map_err(|_| PackError::bad_index(0))?;
+        Ok(ReadPHYReturn {
+            status,
+            handle: ConnectionHandle::unpack_from(&buf[1..3])?,
+            tx_phy: unpack_event_phy(status, buf[3], 3)?,
+            rx_phy: unpack_event_phy(status, buf[4], 4)?,
+        })
+    }
+}
+/// Sets the PHYs preferred for all future connections. `None` means the host has no
+/// preference and lets the controller pick.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash, Default)]
+pub struct SetDefaultPHY {
+    pub tx_phys: Option<PHYMask>,
+    pub rx_phys: Option<PHYMask>,
+}
+impl SetDefaultPHY {
+    pub const BYTE_LEN: usize = 1 + PHYMask::BYTE_LEN * 2;
+    pub const OPCODE: LEControllerOpcode = LEControllerOpcode::SetDefaultPHY;
+}
+impl Command for SetDefaultPHY {
+    type Return = CommandComplete<StatusReturn>;
+
+    fn opcode() -> Opcode {
+        Self::OPCODE.into()
+    }
+
+    fn byte_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        pack_preferred_phys(self.tx_phys, self.rx_phys, buf);
+        Ok(())
+    }
+
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        let (tx_phys, rx_phys) = unpack_preferred_phys(buf, 0)?;
+        Ok(SetDefaultPHY { tx_phys, rx_phys })
+    }
+}
+/// Requests a PHY change on a connection. The controller returns a `CommandStatus` and then a
+/// [`PHYUpdateComplete`] event (even if the PHYs didn't change).
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct SetPHY {
+    pub handle: ConnectionHandle,
+    pub tx_phys: Option<PHYMask>,
+    pub rx_phys: Option<PHYMask>,
+    pub phy_options: CodedPHYOptions,
+}
+impl SetPHY {
+    pub const BYTE_LEN: usize =
+        ConnectionHandle::BYTE_LEN + 1 + PHYMask::BYTE_LEN * 2 + CodedPHYOptions::BYTE_LEN;
+    pub const OPCODE: LEControllerOpcode = LEControllerOpcode::SetPHY;
+}
+impl Command for SetPHY {
+    type Return = CommandStatus;
+
+    fn opcode() -> Opcode {
+        Self::OPCODE.into()
+    }
+
+    fn byte_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        self.handle.pack_into(&mut buf[0..2])?;
+        pack_preferred_phys(self.tx_phys, self.rx_phys, &mut buf[2..5]);
+        buf[5..7].copy_from_slice(&u16::from(self.phy_options).to_le_bytes());
+        Ok(())
+    }
+
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        let (tx_phys, rx_phys) = unpack_preferred_phys(&buf[2..5], 2)?;
+        Ok(SetPHY {
+            handle: ConnectionHandle::unpack_from(&buf[0..2])?,
+            tx_phys,
+            rx_phys,
+            phy_options: CodedPHYOptions::try_from(u16::from_le_bytes([buf[5], buf[6]]))
+                .map_err(|_| PackError::bad_index(5))?,
+        })
+    }
+}
+/// PHY Update Complete event. Sent after a [`SetPHY`] or when the peer changes the PHYs.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct PHYUpdateComplete {
+    pub status: ErrorCode,
+    pub handle: ConnectionHandle,
+    pub tx_phy: PHY,
+    pub rx_phy: PHY,
+}
+impl PHYUpdateComplete {
+    pub const BYTE_LEN: usize =
+        ErrorCode::BYTE_LEN + ConnectionHandle::BYTE_LEN + PHY::BYTE_LEN * 2;
+    /// Updates the PHYs of `connection` if the update succeeded and is for its handle. Returns
+    /// if `connection` was updated.
+    pub fn update_connection(&self, connection: &mut Connection) -> bool {
+        if self.status.is_ok() && self.handle == connection.handle {
+            connection.tx_phy = self.tx_phy;
+            connection.rx_phy = self.rx_phy;
+            true
+        } else {
+            false
+        }
+    }
+}
+impl MetaEvent for PHYUpdateComplete {
+    const META_CODE: MetaEventCode = MetaEventCode::PHYUpdateCompleteEvent;
+
+    fn meta_byte_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+
+    fn meta_unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        let status = ErrorCode::try_from(buf[0]).map_err(|_| PackError::bad_index(0))?;
+        Ok(PHYUpdateComplete {
+            status,
+            handle: ConnectionHandle::unpack_from(&buf[1..3])?,
+            tx_phy: unpack_event_phy(status, buf[3], 3)?,
+            rx_phy: unpack_event_phy(status, buf[4], 4)?,
+        })
+    }
+
+    fn meta_pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        buf[0] = self.status.into();
+        self.handle.pack_into(&mut buf[1..3])?;
+        buf[3] = self.tx_phy.into();
+        buf[4] = self.rx_phy.into();
+        Ok(())
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hci::test_packets::{assert_command, assert_meta_event, assert_return};
+
+    const HANDLE: u16 = 0x0040;
+
+    #[test]
+    fn read_phy() {
+        let handle = ConnectionHandle::new(HANDLE);
+        assert_command(&ReadPHY { handle }, &[0x30, 0x20, 0x02, 0x40, 0x00]);
+        assert_return(
+            &ReadPHYReturn {
+                status: ErrorCode::Ok,
+                handle,
+                tx_phy: PHY::LE2M,
+                rx_phy: PHY::LECoded,
+            },
+            &[0x00, 0x40, 0x00, 0x02, 0x03],
+        );
+    }
+    #[test]
+    fn set_phy() {
+        let mut uncoded = PHYMask::from(PHY::LE1M);
+        uncoded.enable(PHY::LE2M);
+        // The host has no TX preference (`ALL_PHYS` bit 0).
+        assert_command(
+            &SetDefaultPHY {
+                tx_phys: None,
+                rx_phys: Some(uncoded),
+            },
+            &[0x31, 0x20, 0x03, 0x01, 0x00, 0x03],
+        );
+        assert_command(
+            &SetPHY {
+                handle: ConnectionHandle::new(HANDLE),
+                tx_phys: Some(PHY::LECoded.into()),
+                rx_phys: Some(PHY::LECoded.into()),
+                phy_options: CodedPHYOptions::S8,
+            },
+            &[0x32, 0x20, 0x07, 0x40, 0x00, 0x00, 0x04, 0x04, 0x02, 0x00],
+        );
+    }
+    #[test]
+    fn phy_update_complete() {
+        let handle = ConnectionHandle::new(HANDLE);
+        assert_meta_event(
+            &PHYUpdateComplete {
+                status: ErrorCode::Ok,
+                handle,
+                tx_phy: PHY::LE2M,
+                rx_phy: PHY::LE2M,
+            },
+            &[0x0C, 0x00, 0x40, 0x00, 0x02, 0x02],
+        );
+        // Failed updates may leave the PHYs zeroed.
+        let failed = PHYUpdateComplete::meta_unpack_from(&[0x1A, 0x40, 0x00, 0x00, 0x00]).unwrap();
+        assert_eq!(failed.status, ErrorCode::UnsupportedRemoteFeature);
+        assert_eq!(failed.tx_phy, PHY::default());
+        assert!(PHYUpdateComplete::meta_unpack_from(&[0x00, 0x40, 0x00, 0x00, 0x00]).is_err());
+    }
+}
@@ -0,0 +1,641 @@
+//! HCI LE Power Control commands and events. The host can read the local and remote transmit
+//! power of a connection and be told when the path loss crosses a threshold or either side
+//! changes its transmit power.
+use crate::hci::command::Command;
+use crate::hci::event::{CommandComplete, CommandStatus, ConnectionHandleReturn, ReturnParameters};
+use crate::hci::le::{LEControllerOpcode, MetaEvent, MetaEventCode};
+use crate::hci::{ErrorCode, Opcode};
+use crate::le::connection::ConnectionHandle;
+use crate::le::phy::TxPHY;
+use crate::{ConversionError, PackError};
+use core::convert::TryFrom;
+
+fn unpack_bool(buf: &[u8], index: usize) -> Result<bool, PackError> {
+    match buf[index] {
+        0 => Ok(false),
+        1 => Ok(true),
+        _ => Err(PackError::bad_index(index)),
+    }
+}
+fn unpack_phy(buf: &[u8], index: usize) -> Result<TxPHY, PackError> {
+    TxPHY::try_from(buf[index]).map_err(|_| PackError::bad_index(index))
+}
+/// Unpacks the `TxPHY` of a return or event. It's `None` (and may be any value) if `status` is
+/// an error.
+fn unpack_status_phy(
+    status: ErrorCode,
+    buf: &[u8],
+    index: usize,
+) -> Result<Option<TxPHY>, PackError> {
+    if status.is_ok() {
+        unpack_phy(buf, index).map(Some)
+    } else {
+        Ok(None)
+    }
+}
+/// Reads the current and max transmit power level (in dBm) of a connection on `phy`.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct EnhancedReadTransmitPowerLevel {
+    pub handle: ConnectionHandle,
+    pub phy: TxPHY,
+}
+impl EnhancedReadTransmitPowerLevel {
+    pub const BYTE_LEN: usize = ConnectionHandle::BYTE_LEN + TxPHY::BYTE_LEN;
+    pub const OPCODE: LEControllerOpcode = LEControllerOpcode::EnhancedReadTransmitPowerLevel;
+}
+impl Command for EnhancedReadTransmitPowerLevel {
+    type Return = CommandComplete<EnhancedReadTransmitPowerLevelReturn>;
+
+    fn opcode() -> Opcode {
+        Self::OPCODE.into()
+    }
+
+    fn byte_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        self.handle.pack_into(&mut buf[0..2])?;
+        buf[2] = self.phy.into();
+        Ok(())
+    }
+
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        Ok(EnhancedReadTransmitPowerLevel {
+            handle: ConnectionHandle::unpack_from(&buf[0..2])?,
+            phy: unpack_phy(buf, 2)?,
+        })
+    }
+}
+/// `phy` is `None` if the command failed.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct EnhancedReadTransmitPowerLevelReturn {
+    pub status: ErrorCode,
+    pub handle: ConnectionHandle,
+    pub phy: Option<TxPHY>,
+    pub current_tx_power_level: i8,
+    pub max_tx_power_level: i8,
+}
+impl EnhancedReadTransmitPowerLevelReturn {
+    pub const BYTE_LEN: usize =
+        ErrorCode::BYTE_LEN + ConnectionHandle::BYTE_LEN + TxPHY::BYTE_LEN + 2;
+}
+impl ReturnParameters for EnhancedReadTransmitPowerLevelReturn {
+    fn byte_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        buf[0] = self.status.into();
+        self.handle.pack_into(&mut buf[1..3])?;
+        buf[3] = self.phy.map_or(0, u8::from);
+        buf[4] = self.current_tx_power_level as u8;
+        buf[5] = self.max_tx_power_level as u8;
+        Ok(())
+    }
+
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        let status = ErrorCode::try_from(buf[0]).map_err(|_| PackError::bad_index(0))?;
+        Ok(EnhancedReadTransmitPowerLevelReturn {
+            status,
+            handle: ConnectionHandle::unpack_from(&buf[1..3])?,
+            phy: unpack_status_phy(status, buf, 3)?,
+            current_tx_power_level: buf[4] as i8,
+            max_tx_power_level: buf[5] as i8,
+        })
+    }
+}
+/// Reads the transmit power level the peer uses on `phy`. The result is reported with a
+/// [`TransmitPowerReporting`] event (with [`TransmitPowerReportingReason::ReadRemoteComplete`]).
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct ReadRemoteTransmitPowerLevel {
+    pub handle: ConnectionHandle,
+    pub phy: TxPHY,
+}
+impl ReadRemoteTransmitPowerLevel {
+    pub const BYTE_LEN: usize = ConnectionHandle::BYTE_LEN + TxPHY::BYTE_LEN;
+    pub const OPCODE: LEControllerOpcode = LEControllerOpcode::ReadRemoteTransmitPowerLevel;
+}
+impl Command for ReadRemoteTransmitPowerLevel {
+    type Return = CommandStatus;
+
+    fn opcode() -> Opcode {
+        Self::OPCODE.into()
+    }
+
+    fn byte_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        self.handle.pack_into(&mut buf[0..2])?;
+        buf[2] = self.phy.into();
+        Ok(())
+    }
+
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        Ok(ReadRemoteTransmitPowerLevel {
+            handle: ConnectionHandle::unpack_from(&buf[0..2])?,
+            phy: unpack_phy(buf, 2)?,
+        })
+    }
+}
+/// Sets the path loss (in dB) zones of a connection. The high zone is entered above
+/// `high_threshold + high_hysteresis` and left below `high_threshold - high_hysteresis` (same for
+/// the low zone). A zone must be stayed in for `min_time_spent` connection events before a
+/// [`PathLossThreshold`] event is sent.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct SetPathLossReportingParameters {
+    pub handle: ConnectionHandle,
+    pub high_threshold: u8,
+    pub high_hysteresis: u8,
+    pub low_threshold: u8,
+    pub low_hysteresis: u8,
+    pub min_time_spent: u16,
+}
+impl SetPathLossReportingParameters {
+    pub const BYTE_LEN: usize = ConnectionHandle::BYTE_LEN + 4 + 2;
+    pub const OPCODE: LEControllerOpcode = LEControllerOpcode::SetPathLossReportingParameters;
+    /// Threshold value meaning the zone isn't used.
+    pub const THRESHOLD_UNUSED: u8 = 0xFF;
+}
+impl Command for SetPathLossReportingParameters {
+    type Return = CommandComplete<ConnectionHandleReturn>;
+
+    fn opcode() -> Opcode {
+        Self::OPCODE.into()
+    }
+
+    fn byte_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        self.handle.pack_into(&mut buf[0..2])?;
+        buf[2] = self.high_threshold;
+        buf[3] = self.high_hysteresis;
+        buf[4] = self.low_threshold;
+        buf[5] = self.low_hysteresis;
+        buf[6..8].copy_from_slice(&self.min_time_spent.to_le_bytes());
+        Ok(())
+    }
+
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        Ok(SetPathLossReportingParameters {
+            handle: ConnectionHandle::unpack_from(&buf[0..2])?,
+            high_threshold: buf[2],
+            high_hysteresis: buf[3],
+            low_threshold: buf[4],
+            low_hysteresis: buf[5],
+            min_time_spent: u16::from_le_bytes([buf[6], buf[7]]),
+        })
+    }
+}
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct SetPathLossReportingEnable {
+    pub handle: ConnectionHandle,
+    pub is_enabled: bool,
+}
+impl SetPathLossReportingEnable {
+    pub const BYTE_LEN: usize = ConnectionHandle::BYTE_LEN + 1;
+    pub const OPCODE: LEControllerOpcode = LEControllerOpcode::SetPathLossReportingEnable;
+}
+impl Command for SetPathLossReportingEnable {
+    type Return = CommandComplete<ConnectionHandleReturn>;
+
+    fn opcode() -> Opcode {
+        Self::OPCODE.into()
+    }
+
+    fn byte_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        self.handle.pack_into(&mut buf[0..2])?;
+        buf[2] = self.is_enabled.into();
+        Ok(())
+    }
+
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        Ok(SetPathLossReportingEnable {
+            handle: ConnectionHandle::unpack_from(&buf[0..2])?,
+            is_enabled: unpack_bool(buf, 2)?,
+        })
+    }
+}
+/// Enables [`TransmitPowerReporting`] events when the local and/or remote transmit power
+/// changes.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct SetTransmitPowerReportingEnable {
+    pub handle: ConnectionHandle,
+    pub local_enabled: bool,
+    pub remote_enabled: bool,
+}
+impl SetTransmitPowerReportingEnable {
+    pub const BYTE_LEN: usize = ConnectionHandle::BYTE_LEN + 2;
+    pub const OPCODE: LEControllerOpcode = LEControllerOpcode::SetTransmitPowerReportingEnable;
+}
+impl Command for SetTransmitPowerReportingEnable {
+    type Return = CommandComplete<ConnectionHandleReturn>;
+
+    fn opcode() -> Opcode {
+        Self::OPCODE.into()
+    }
+
+    fn byte_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        self.handle.pack_into(&mut buf[0..2])?;
+        buf[2] = self.local_enabled.into();
+        buf[3] = self.remote_enabled.into();
+        Ok(())
+    }
+
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        Ok(SetTransmitPowerReportingEnable {
+            handle: ConnectionHandle::unpack_from(&buf[0..2])?,
+            local_enabled: unpack_bool(buf, 2)?,
+            remote_enabled: unpack_bool(buf, 3)?,
+        })
+    }
+}
+/// Path loss zone set by [`SetPathLossReportingParameters`].
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+#[repr(u8)]
+pub enum PathLossZone {
+    Low = 0x00,
+    Middle = 0x01,
+    High = 0x02,
+}
+impl From<PathLossZone> for u8 {
+    fn from(z: PathLossZone) -> Self {
+        z as u8
+    }
+}
+impl TryFrom<u8> for PathLossZone {
+    type Error = ConversionError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x00 => Ok(PathLossZone::Low),
+            0x01 => Ok(PathLossZone::Middle),
+            0x02 => Ok(PathLossZone::High),
+            _ => Err(ConversionError(())),
+        }
+    }
+}
+/// Sent when the path loss of a connection enters a new zone. `current_path_loss` (in dB) is
+/// `None` if unavailable.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct PathLossThreshold {
+    pub handle: ConnectionHandle,
+    pub current_path_loss: Option<u8>,
+    pub zone_entered: PathLossZone,
+}
+impl PathLossThreshold {
+    pub const BYTE_LEN: usize = ConnectionHandle::BYTE_LEN + 2;
+    pub const PATH_LOSS_UNAVAILABLE: u8 = 0xFF;
+}
+impl MetaEvent for PathLossThreshold {
+    const META_CODE: MetaEventCode = MetaEventCode::PathLossThreshold;
+
+    fn meta_byte_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+
+    fn meta_unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        Ok(PathLossThreshold {
+            handle: ConnectionHandle::unpack_from(&buf[0..2])?,
+            current_path_loss: match buf[2] {
+                Self::PATH_LOSS_UNAVAILABLE => None,
+                path_loss => Some(path_loss),
+            },
+            zone_entered: PathLossZone::try_from(buf[3]).map_err(|_| PackError::bad_index(3))?,
+        })
+    }
+
+    fn meta_pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        self.handle.pack_into(&mut buf[0..2])?;
+        buf[2] = self
+            .current_path_loss
+            .unwrap_or(Self::PATH_LOSS_UNAVAILABLE);
+        buf[3] = self.zone_entered.into();
+        Ok(())
+    }
+}
+/// Why a [`TransmitPowerReporting`] event was sent.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+#[repr(u8)]
+pub enum TransmitPowerReportingReason {
+    LocalChange = 0x00,
+    RemoteChange = 0x01,
+    ReadRemoteComplete = 0x02,
+}
+impl From<TransmitPowerReportingReason> for u8 {
+    fn from(r: TransmitPowerReportingReason) -> Self {
+        r as u8
+    }
+}
+impl TryFrom<u8> for TransmitPowerReportingReason {
+    type Error = ConversionError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x00 => Ok(TransmitPowerReportingReason::LocalChange),
+            0x01 => Ok(TransmitPowerReportingReason::RemoteChange),
+            0x02 => Ok(TransmitPowerReportingReason::ReadRemoteComplete),
+            _ => Err(ConversionError(())),
+        }
+    }
+}
+/// Transmit power level (in dBm) of the local controller or the peer on `phy`. `delta` is the
+/// change from the last reported level. `phy` is `None` if `status` is an error.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct TransmitPowerReporting {
+    pub status: ErrorCode,
+    pub handle: ConnectionHandle,
+    pub reason: TransmitPowerReportingReason,
+    pub phy: Option<TxPHY>,
+    pub tx_power_level: i8,
+    pub at_min_level: bool,
+    pub at_max_level: bool,
+    pub delta: i8,
+}
+impl TransmitPowerReporting {
+    pub const BYTE_LEN: usize = ErrorCode::BYTE_LEN + ConnectionHandle::BYTE_LEN + 1 + 1 + 3;
+    /// `tx_power_level` if the peer isn't managing its power level on `phy`.
+    pub const POWER_NOT_MANAGED: i8 = 0x7E;
+    /// `tx_power_level` or `delta` if it's unavailable.
+    pub const UNAVAILABLE: i8 = 0x7F;
+    const MIN_LEVEL_FLAG: u8 = 0x01;
+    const MAX_LEVEL_FLAG: u8 = 0x02;
+    /// Returns the transmit power level or `None` if it's unavailable or not managed.
+    pub fn tx_power(&self) -> Option<i8> {
+        match self.tx_power_level {
+            Self::POWER_NOT_MANAGED | Self::UNAVAILABLE => None,
+            level => Some(level),
+        }
+    }
+}
+impl MetaEvent for TransmitPowerReporting {
+    const META_CODE: MetaEventCode = MetaEventCode::TransmitPowerReporting;
+
+    fn meta_byte_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+
+    fn meta_unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        let status = ErrorCode::try_from(buf[0]).map_err(|_| PackError::bad_index(0))?;
+        Ok(TransmitPowerReporting {
+            status,
+            handle: ConnectionHandle::unpack_from(&buf[1..3])?,
+            reason: TransmitPowerReportingReason::try_from(buf[3])
+                .map_err(|_| PackError::bad_index(3))?,
+            phy: unpack_status_phy(status, buf, 4)?,
+            tx_power_level: buf[5] as i8,
+            at_min_level: buf[6] & Self::MIN_LEVEL_FLAG != 0,
+            at_max_level: buf[6] & Self::MAX_LEVEL_FLAG != 0,
+            delta: buf[7] as i8,
+        })
+    }
+
+    fn meta_pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        buf[0] = self.status.into();
+        self.handle.pack_into(&mut buf[1..3])?;
+        buf[3] = self.reason.into();
+        buf[4] = self.phy.map_or(0, u8::from);
+        buf[5] = self.tx_power_level as u8;
+        buf[6] = if self.at_min_level {
+            Self::MIN_LEVEL_FLAG
+        } else {
+            0
+        } | if self.at_max_level {
+            Self::MAX_LEVEL_FLAG
+        } else {
+            0
+        };
+        buf[7] = self.delta as u8;
+        Ok(())
+    }
+}
+/// Power control event of a connection.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub enum PowerControlEvent {
+    PathLoss(PathLossThreshold),
+    TransmitPower(TransmitPowerReporting),
+}
+impl PowerControlEvent {
+    pub fn handle(&self) -> ConnectionHandle {
+        match self {
+            PowerControlEvent::PathLoss(e) => e.handle,
+            PowerControlEvent::TransmitPower(e) => e.handle,
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hci::test_packets::{assert_command, assert_meta_event, assert_return};
+
+    #[test]
+    fn enhanced_read_transmit_power_level() {
+        let handle = ConnectionHandle::new(0x0040);
+        assert_command(
+            &EnhancedReadTransmitPowerLevel {
+                handle,
+                phy: TxPHY::LE2M,
+            },
+            &[0x76, 0x20, 0x03, 0x40, 0x00, 0x02],
+        );
+        assert_return(
+            &EnhancedReadTransmitPowerLevelReturn {
+                status: ErrorCode::Ok,
+                handle,
+                phy: Some(TxPHY::LE2M),
+                current_tx_power_level: -4,
+                max_tx_power_level: 20,
+            },
+            &[0x00, 0x40, 0x00, 0x02, 0xFC, 0x14],
+        );
+        // The PHY isn't valid if the command failed.
+        assert_return(
+            &EnhancedReadTransmitPowerLevelReturn {
+                status: ErrorCode::NoConnection,
+                handle,
+                phy: None,
+                current_tx_power_level: 0,
+                max_tx_power_level: 0,
+            },
+            &[0x02, 0x40, 0x00, 0x00, 0x00, 0x00],
+        );
+        assert!(EnhancedReadTransmitPowerLevelReturn::unpack_from(&[
+            0x00, 0x40, 0x00, 0x00, 0xFC, 0x14
+        ])
+        .is_err());
+    }
+    #[test]
+    fn read_remote_transmit_power_level() {
+        assert_command(
+            &ReadRemoteTransmitPowerLevel {
+                handle: ConnectionHandle::new(0x0040),
+                phy: TxPHY::LE1M,
+            },
+            &[0x77, 0x20, 0x03, 0x40, 0x00, 0x01],
+        );
+        assert!(ReadRemoteTransmitPowerLevel::unpack_from(&[0x40, 0x00, 0x05]).is_err());
+    }
+    #[test]
+    fn path_loss_reporting() {
+        let handle = ConnectionHandle::new(0x0040);
+        assert_command(
+            &SetPathLossReportingParameters {
+                handle,
+                high_threshold: 60,
+                high_hysteresis: 5,
+                low_threshold: 30,
+                low_hysteresis: 5,
+                min_time_spent: 16,
+            },
+            &[
+                0x78, 0x20, 0x08, 0x40, 0x00, 0x3C, 0x05, 0x1E, 0x05, 0x10, 0x00,
+            ],
+        );
+        assert_command(
+            &SetPathLossReportingEnable {
+                handle,
+                is_enabled: true,
+            },
+            &[0x79, 0x20, 0x03, 0x40, 0x00, 0x01],
+        );
+        assert!(SetPathLossReportingEnable::unpack_from(&[0x40, 0x00, 0x02]).is_err());
+        assert_return(
+            &ConnectionHandleReturn {
+                status: ErrorCode::Ok,
+                handle,
+            },
+            &[0x00, 0x40, 0x00],
+        );
+    }
+    #[test]
+    fn set_transmit_power_reporting_enable() {
+        assert_command(
+            &SetTransmitPowerReportingEnable {
+                handle: ConnectionHandle::new(0x0040),
+                local_enabled: true,
+                remote_enabled: false,
+            },
+            &[0x7A, 0x20, 0x04, 0x40, 0x00, 0x01, 0x00],
+        );
+    }
+    #[test]
+    fn path_loss_threshold() {
+        let handle = ConnectionHandle::new(0x0040);
+        assert_meta_event(
+            &PathLossThreshold {
+                handle,
+                current_path_loss: Some(50),
+                zone_entered: PathLossZone::Middle,
+            },
+            &[0x20, 0x40, 0x00, 0x32, 0x01],
+        );
+        assert_meta_event(
+            &PathLossThreshold {
+                handle,
+                current_path_loss: None,
+                zone_entered: PathLossZone::High,
+            },
+            &[0x20, 0x40, 0x00, 0xFF, 0x02],
+        );
+        assert!(PathLossThreshold::meta_unpack_from(&[0x40, 0x00, 0x32, 0x03]).is_err());
+    }
+    #[test]
+    fn transmit_power_reporting() {
+        let event = TransmitPowerReporting {
+            status: ErrorCode::Ok,
+            handle: ConnectionHandle::new(0x0040),
+            reason: TransmitPowerReportingReason::RemoteChange,
+            phy: Some(TxPHY::LE2M),
+            tx_power_level: -10,
+            at_min_level: false,
+            at_max_level: true,
+            delta: 3,
+        };
+        assert_meta_event(
+            &event,
+            &[0x21, 0x00, 0x40, 0x00, 0x01, 0x02, 0xF6, 0x02, 0x03],
+        );
+        assert_eq!(event.tx_power(), Some(-10));
+        let not_managed = TransmitPowerReporting {
+            phy: Some(TxPHY::LECodedS2),
+            tx_power_level: TransmitPowerReporting::POWER_NOT_MANAGED,
+            at_max_level: false,
+            delta: TransmitPowerReporting::UNAVAILABLE,
+            ..event
+        };
+        assert_meta_event(
+            &not_managed,
+            &[0x21, 0x00, 0x40, 0x00, 0x01, 0x04, 0x7E, 0x00, 0x7F],
+        );
+        assert_eq!(not_managed.tx_power(), None);
+        // A failed Read Remote Transmit Power Level may not have a valid PHY.
+        assert_meta_event(
+            &TransmitPowerReporting {
+                status: ErrorCode::NoConnection,
+                reason: TransmitPowerReportingReason::ReadRemoteComplete,
+                phy: None,
+                tx_power_level: 0,
+                at_max_level: false,
+                delta: 0,
+                ..event
+            },
+            &[0x21, 0x02, 0x40, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00],
+        );
+        assert!(TransmitPowerReporting::meta_unpack_from(&[
+            0x00, 0x40, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00
+        ])
+        .is_err());
+    }
+}
@@ -0,0 +1,512 @@
+//! HCI LE Direct Test Mode (DTM) commands. Used for RF production testing. The controller
+//! transmits or receives test packets on a single RF channel until [`TestEnd`] is sent.
+use crate::channel::RFChannel;
+use crate::hci::command::Command;
+use crate::hci::event::{CommandComplete, ReturnParameters, StatusReturn};
+use crate::hci::le::LEControllerOpcode;
+use crate::hci::{ErrorCode, Opcode};
+use crate::le::cte::{CTELength, CTEType, SlotDuration, SwitchingPattern};
+use crate::le::phy::{TxPHY, PHY};
+use crate::{ConversionError, PackError};
+use core::convert::TryFrom;
+
+fn unpack_channel(buf: &[u8], index: usize) -> Result<RFChannel, PackError> {
+    RFChannel::new_checked(buf[index]).ok_or(PackError::bad_index(index))
+}
+/// Test packet payload pattern.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+#[repr(u8)]
+pub enum PacketPayload {
+    PRBS9 = 0x00,
+    Repeated11110000 = 0x01,
+    Repeated10101010 = 0x02,
+    PRBS15 = 0x03,
+    AllOnes = 0x04,
+    AllZeros = 0x05,
+    Repeated00001111 = 0x06,
+    Repeated01010101 = 0x07,
+}
+impl Default for PacketPayload {
+    fn default() -> Self {
+        PacketPayload::PRBS9
+    }
+}
+impl From<PacketPayload> for u8 {
+    fn from(p: PacketPayload) -> Self {
+        p as u8
+    }
+}
+impl TryFrom<u8> for PacketPayload {
+    type Error = ConversionError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x00 => Ok(PacketPayload::PRBS9),
+            0x01 => Ok(PacketPayload::Repeated11110000),
+            0x02 => Ok(PacketPayload::Repeated10101010),
+            0x03 => Ok(PacketPayload::PRBS15),
+            0x04 => Ok(PacketPayload::AllOnes),
+            0x05 => Ok(PacketPayload::AllZeros),
+            0x06 => Ok(PacketPayload::Repeated00001111),
+            0x07 => Ok(PacketPayload::Repeated01010101),
+            _ => Err(ConversionError(())),
+        }
+    }
+}
+/// Modulation index the receiver should assume the transmitter uses.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+#[repr(u8)]
+pub enum ModulationIndex {
+    Standard = 0x00,
+    Stable = 0x01,
+}
+impl Default for ModulationIndex {
+    fn default() -> Self {
+        ModulationIndex::Standard
+    }
+}
+impl From<ModulationIndex> for u8 {
+    fn from(m: ModulationIndex) -> Self {
+        m as u8
+    }
+}
+impl TryFrom<u8> for ModulationIndex {
+    type Error = ConversionError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x00 => Ok(ModulationIndex::Standard),
+            0x01 => Ok(ModulationIndex::Stable),
+            _ => Err(ConversionError(())),
+        }
+    }
+}
+/// Constant Tone Extension sent (or expected) with every test packet.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct TestCTE {
+    pub length: CTELength,
+    pub cte_type: CTEType,
+}
+impl TestCTE {
+    pub const BYTE_LEN: usize = 2;
+    fn pack(cte: Option<TestCTE>, buf: &mut [u8]) {
+        match cte {
+            Some(cte) => {
+                buf[0] = cte.length.into();
+                buf[1] = cte.cte_type.into();
+            }
+            None => {
+                buf[0] = 0;
+                buf[1] = 0;
+            }
+        }
+    }
+    fn unpack(buf: &[u8], index: usize) -> Result<Option<TestCTE>, PackError> {
+        if buf[index] == 0 {
+            return Ok(None);
+        }
+        Ok(Some(TestCTE {
+            length: CTELength::new_checked(buf[index])
+                .ok_or_else(|| PackError::bad_index(index))?,
+            cte_type: CTEType::try_from(buf[index + 1])
+                .map_err(|_| PackError::bad_index(index + 1))?,
+        }))
+    }
+}
+macro_rules! status_command {
+    ($name:ident, $opcode:ident) => {
+        impl $name {
+            pub const OPCODE: LEControllerOpcode = LEControllerOpcode::$opcode;
+        }
+        impl Command for $name {
+            type Return = CommandComplete<StatusReturn>;
+
+            fn opcode() -> Opcode {
+                Self::OPCODE.into()
+            }
+
+            fn byte_len(&self) -> usize {
+                self.params_len()
+            }
+
+            fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+                PackError::expect_length(self.params_len(), buf)?;
+                self.pack_params(buf)
+            }
+
+            fn unpack_from(buf: &[u8]) -> Result<Self, PackError>
+            where
+                Self: Sized,
+            {
+                Self::unpack_params(buf)
+            }
+        }
+    };
+}
+/// Receives test packets on `rx_channel` (LE 1M PHY).
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash, Default)]
+pub struct ReceiverTestV1 {
+    pub rx_channel: RFChannel,
+}
+impl ReceiverTestV1 {
+    pub const BYTE_LEN: usize = 1;
+    fn params_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+    fn pack_params(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        buf[0] = self.rx_channel.into();
+        Ok(())
+    }
+    fn unpack_params(buf: &[u8]) -> Result<Self, PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        Ok(ReceiverTestV1 {
+            rx_channel: unpack_channel(buf, 0)?,
+        })
+    }
+}
+status_command!(ReceiverTestV1, ReceiverTestV1);
+/// Transmits `test_data_len` byte test packets on `tx_channel` (LE 1M PHY).
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash, Default)]
+pub struct TransmitterTestV1 {
+    pub tx_channel: RFChannel,
+    pub test_data_len: u8,
+    pub payload: PacketPayload,
+}
+impl TransmitterTestV1 {
+    pub const BYTE_LEN: usize = 3;
+    fn params_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+    fn pack_params(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        buf[0] = self.tx_channel.into();
+        buf[1] = self.test_data_len;
+        buf[2] = self.payload.into();
+        Ok(())
+    }
+    fn unpack_params(buf: &[u8]) -> Result<Self, PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        Ok(TransmitterTestV1 {
+            tx_channel: unpack_channel(buf, 0)?,
+            test_data_len: buf[1],
+            payload: PacketPayload::try_from(buf[2]).map_err(|_| PackError::bad_index(2))?,
+        })
+    }
+}
+status_command!(TransmitterTestV1, TransmitterTestV1);
+/// Receives test packets on `rx_channel` using `phy`. (Enhanced Receiver Test)
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash, Default)]
+pub struct ReceiverTestV2 {
+    pub rx_channel: RFChannel,
+    pub phy: PHY,
+    pub modulation_index: ModulationIndex,
+}
+impl ReceiverTestV2 {
+    pub const BYTE_LEN: usize = 3;
+    fn params_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+    fn pack_params(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        buf[0] = self.rx_channel.into();
+        buf[1] = self.phy.into();
+        buf[2] = self.modulation_index.into();
+        Ok(())
+    }
+    fn unpack_params(buf: &[u8]) -> Result<Self, PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        Ok(ReceiverTestV2 {
+            rx_channel: unpack_channel(buf, 0)?,
+            phy: PHY::try_from(buf[1]).map_err(|_| PackError::bad_index(1))?,
+            modulation_index: ModulationIndex::try_from(buf[2])
+                .map_err(|_| PackError::bad_index(2))?,
+        })
+    }
+}
+status_command!(ReceiverTestV2, ReceiverTestV2);
+/// Transmits test packets on `tx_channel` using `phy`. (Enhanced Transmitter Test)
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash, Default)]
+pub struct TransmitterTestV2 {
+    pub tx_channel: RFChannel,
+    pub test_data_len: u8,
+    pub payload: PacketPayload,
+    pub phy: TxPHY,
+}
+impl TransmitterTestV2 {
+    pub const BYTE_LEN: usize = 4;
+    fn params_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+    fn pack_params(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        buf[0] = self.tx_channel.into();
+        buf[1] = self.test_data_len;
+        buf[2] = self.payload.into();
+        buf[3] = self.phy.into();
+        Ok(())
+    }
+    fn unpack_params(buf: &[u8]) -> Result<Self, PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        Ok(TransmitterTestV2 {
+            tx_channel: unpack_channel(buf, 0)?,
+            test_data_len: buf[1],
+            payload: PacketPayload::try_from(buf[2]).map_err(|_| PackError::bad_index(2))?,
+            phy: TxPHY::try_from(buf[3]).map_err(|_| PackError::bad_index(3))?,
+        })
+    }
+}
+status_command!(TransmitterTestV2, TransmitterTestV2);
+/// Receives test packets with an expected Constant Tone Extension and samples IQ data.
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct ReceiverTestV3 {
+    pub rx_channel: RFChannel,
+    pub phy: PHY,
+    pub modulation_index: ModulationIndex,
+    pub expected_cte: Option<TestCTE>,
+    pub slot_durations: SlotDuration,
+    pub switching_pattern: SwitchingPattern,
+}
+impl ReceiverTestV3 {
+    pub const MIN_BYTE_LEN: usize = 3 + TestCTE::BYTE_LEN + 1 + 1;
+    fn params_len(&self) -> usize {
+        Self::MIN_BYTE_LEN - 1 + self.switching_pattern.byte_len()
+    }
+    fn pack_params(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        buf[0] = self.rx_channel.into();
+        buf[1] = self.phy.into();
+        buf[2] = self.modulation_index.into();
+        TestCTE::pack(self.expected_cte, &mut buf[3..5]);
+        buf[5] = self.slot_durations.into();
+        self.switching_pattern.pack_into(&mut buf[6..])
+    }
+    fn unpack_params(buf: &[u8]) -> Result<Self, PackError> {
+        PackError::expect_length(
+            Self::MIN_BYTE_LEN + usize::from(*buf.get(6).unwrap_or(&0)),
+            buf,
+        )?;
+        Ok(ReceiverTestV3 {
+            rx_channel: unpack_channel(buf, 0)?,
+            phy: PHY::try_from(buf[1]).map_err(|_| PackError::bad_index(1))?,
+            modulation_index: ModulationIndex::try_from(buf[2])
+                .map_err(|_| PackError::bad_index(2))?,
+            expected_cte: TestCTE::unpack(buf, 3)?,
+            slot_durations: SlotDuration::try_from(buf[5]).map_err(|_| PackError::bad_index(5))?,
+            switching_pattern: SwitchingPattern::unpack_from(&buf[6..])?,
+        })
+    }
+}
+status_command!(ReceiverTestV3, ReceiverTestV3);
+/// Transmits test packets with a Constant Tone Extension. `switching_pattern` is used
+/// for AoD.
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct TransmitterTestV3 {
+    pub tx_channel: RFChannel,
+    pub test_data_len: u8,
+    pub payload: PacketPayload,
+    pub phy: TxPHY,
+    pub cte: Option<TestCTE>,
+    pub switching_pattern: SwitchingPattern,
+}
+impl TransmitterTestV3 {
+    pub const MIN_BYTE_LEN: usize = 4 + TestCTE::BYTE_LEN + 1;
+    fn params_len(&self) -> usize {
+        Self::MIN_BYTE_LEN - 1 + self.switching_pattern.byte_len()
+    }
+    fn pack_params(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        buf[0] = self.tx_channel.into();
+        buf[1] = self.test_data_len;
+        buf[2] = self.payload.into();
+        buf[3] = self.phy.into();
+        TestCTE::pack(self.cte, &mut buf[4..6]);
+        self.switching_pattern.pack_into(&mut buf[6..])
+    }
+    fn unpack_params(buf: &[u8]) -> Result<Self, PackError> {
+        PackError::expect_length(
+            Self::MIN_BYTE_LEN + usize::from(*buf.get(6).unwrap_or(&0)),
+            buf,
+        )?;
+        Ok(TransmitterTestV3 {
+            tx_channel: unpack_channel(buf, 0)?,
+            test_data_len: buf[1],
+            payload: PacketPayload::try_from(buf[2]).map_err(|_| PackError::bad_index(2))?,
+            phy: TxPHY::try_from(buf[3]).map_err(|_| PackError::bad_index(3))?,
+            cte: TestCTE::unpack(buf, 4)?,
+            switching_pattern: SwitchingPattern::unpack_from(&buf[6..])?,
+        })
+    }
+}
+status_command!(TransmitterTestV3, TransmitterTestV3);
+/// Stops any running receiver or transmitter test.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash, Default)]
+pub struct TestEnd();
+impl TestEnd {
+    pub const OPCODE: LEControllerOpcode = LEControllerOpcode::TestEnd;
+}
+impl Command for TestEnd {
+    type Return = CommandComplete<TestEndReturn>;
+
+    fn opcode() -> Opcode {
+        Self::OPCODE.into()
+    }
+
+    fn byte_len(&self) -> usize {
+        0
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(0, buf)
+    }
+
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        PackError::expect_length(0, buf)?;
+        Ok(TestEnd())
+    }
+}
+/// `num_packets` is the number of packets received by a receiver test (0 for a transmitter
+/// test).
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct TestEndReturn {
+    pub status: ErrorCode,
+    pub num_packets: u16,
+}
+impl TestEndReturn {
+    pub const BYTE_LEN: usize = ErrorCode::BYTE_LEN + 2;
+}
+impl ReturnParameters for TestEndReturn {
+    fn byte_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        buf[0] = self.status.into();
+        buf[1..3].copy_from_slice(&self.num_packets.to_le_bytes());
+        Ok(())
+    }
+
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        Ok(TestEndReturn {
+            status: ErrorCode::try_from(buf[0]).map_err(|_| PackError::bad_index(0))?,
+            num_packets: u16::from_le_bytes([buf[1], buf[2]]),
+        })
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hci::test_packets::{assert_command, assert_return};
+
+    fn channel(channel: u8) -> RFChannel {
+        RFChannel::new_checked(channel).unwrap()
+    }
+
+    #[test]
+    fn v1_tests() {
+        assert_command(
+            &ReceiverTestV1 {
+                rx_channel: channel(19),
+            },
+            &[0x1D, 0x20, 0x01, 0x13],
+        );
+        assert_command(
+            &TransmitterTestV1 {
+                tx_channel: channel(0),
+                test_data_len: 37,
+                payload: PacketPayload::PRBS9,
+            },
+            &[0x1E, 0x20, 0x03, 0x00, 0x25, 0x00],
+        );
+        // RF channels stop at 39.
+        assert!(ReceiverTestV1::unpack_from(&[0x28]).is_err());
+    }
+    #[test]
+    fn v2_tests() {
+        assert_command(
+            &ReceiverTestV2 {
+                rx_channel: channel(39),
+                phy: PHY::LE2M,
+                modulation_index: ModulationIndex::Stable,
+            },
+            &[0x33, 0x20, 0x03, 0x27, 0x02, 0x01],
+        );
+        assert_command(
+            &TransmitterTestV2 {
+                tx_channel: channel(39),
+                test_data_len: 255,
+                payload: PacketPayload::Repeated10101010,
+                phy: TxPHY::LECodedS8,
+            },
+            &[0x34, 0x20, 0x04, 0x27, 0xFF, 0x02, 0x03],
+        );
+        assert!(TransmitterTestV2::unpack_from(&[0x27, 0xFF, 0x08, 0x03]).is_err());
+    }
+    #[test]
+    fn v3_tests() {
+        assert_command(
+            &ReceiverTestV3 {
+                rx_channel: channel(10),
+                phy: PHY::LE1M,
+                modulation_index: ModulationIndex::Standard,
+                expected_cte: Some(TestCTE {
+                    length: CTELength::MAX,
+                    cte_type: CTEType::AoA,
+                }),
+                slot_durations: SlotDuration::Slot1us,
+                switching_pattern: SwitchingPattern::new_checked(&[0, 1, 2]).unwrap(),
+            },
+            &[
+                0x4F, 0x20, 0x0A, 0x0A, 0x01, 0x00, 0x14, 0x00, 0x01, 0x03, 0x00, 0x01, 0x02,
+            ],
+        );
+        let transmitter = TransmitterTestV3 {
+            tx_channel: channel(10),
+            test_data_len: 37,
+            payload: PacketPayload::PRBS9,
+            phy: TxPHY::LE1M,
+            cte: Some(TestCTE {
+                length: CTELength::MIN,
+                cte_type: CTEType::AoD2us,
+            }),
+            switching_pattern: SwitchingPattern::new_checked(&[0, 1]).unwrap(),
+        };
+        assert_command(
+            &transmitter,
+            &[
+                0x50, 0x20, 0x09, 0x0A, 0x25, 0x00, 0x01, 0x02, 0x02, 0x02, 0x00, 0x01,
+            ],
+        );
+        // No CTE is a 0 length.
+        assert_command(
+            &TransmitterTestV3 {
+                cte: None,
+                switching_pattern: SwitchingPattern::empty(),
+                ..transmitter
+            },
+            &[0x50, 0x20, 0x07, 0x0A, 0x25, 0x00, 0x01, 0x00, 0x00, 0x00],
+        );
+        // CTE lengths stop at 160 µs.
+        assert!(
+            TransmitterTestV3::unpack_from(&[0x0A, 0x25, 0x00, 0x01, 0x15, 0x02, 0x00]).is_err()
+        );
+        // Fewer antenna IDs than the switching pattern length.
+        assert!(
+            TransmitterTestV3::unpack_from(&[0x0A, 0x25, 0x00, 0x01, 0x02, 0x02, 0x02, 0x00])
+                .is_err()
+        );
+    }
+    #[test]
+    fn test_end() {
+        assert_command(&TestEnd(), &[0x1F, 0x20, 0x00]);
+        assert_return(
+            &TestEndReturn {
+                status: ErrorCode::Ok,
+                num_packets: 10_000,
+            },
+            &[0x00, 0x10, 0x27],
+        );
+    }
+}
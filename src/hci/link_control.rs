@@ -1,5 +1,10 @@
 //! Link Controller module (WIP).
-use crate::hci::{Opcode, OCF, OGF};
+use crate::hci::command::Command;
+use crate::hci::event::{CommandStatus, Event, EventCode};
+use crate::hci::{ErrorCode, Opcode, Version, OCF, OGF};
+use crate::le::connection::{ConnectionHandle, RemoteVersion};
+use crate::PackError;
+use core::convert::TryFrom;
 
 #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
 #[repr(u16)]
@@ -42,3 +47,163 @@ impl From<LinkControlOpcode> for Opcode {
         Self(OGF::LinkControl, opcode.into())
     }
 }
+/// Reads the version of the peer. The controller returns a `CommandStatus` and then a
+/// [`ReadRemoteVersionInformationComplete`] event.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct ReadRemoteVersionInformation {
+    pub handle: ConnectionHandle,
+}
+impl ReadRemoteVersionInformation {
+    pub const BYTE_LEN: usize = ConnectionHandle::BYTE_LEN;
+    pub const OPCODE: LinkControlOpcode = LinkControlOpcode::ReadRemoteVersionInformation;
+}
+impl Command for ReadRemoteVersionInformation {
+    type Return = CommandStatus;
+
+    fn opcode() -> Opcode {
+        Self::OPCODE.into()
+    }
+
+    fn byte_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        self.handle.pack_into(buf)
+    }
+
+    fn unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        Ok(ReadRemoteVersionInformation {
+            handle: ConnectionHandle::unpack_from(buf)?,
+        })
+    }
+}
+/// Read Remote Version Information Complete event.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct ReadRemoteVersionInformationComplete {
+    pub status: ErrorCode,
+    pub handle: ConnectionHandle,
+    pub remote_version: RemoteVersion,
+}
+impl ReadRemoteVersionInformationComplete {
+    pub const BYTE_LEN: usize = ErrorCode::BYTE_LEN + ConnectionHandle::BYTE_LEN + 1 + 2 + 2;
+    /// `remote_version.version` as a `Version`. `None` if unknown.
+    pub fn version(&self) -> Option<Version> {
+        Version::try_from(self.remote_version.version).ok()
+    }
+}
+impl Event for ReadRemoteVersionInformationComplete {
+    const EVENT_CODE: EventCode = EventCode::ReadRemoteVersionInformationComplete;
+
+    fn event_byte_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+
+    fn event_unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        Ok(ReadRemoteVersionInformationComplete {
+            status: ErrorCode::try_from(buf[0]).map_err(|_| PackError::bad_index(0))?,
+            handle: ConnectionHandle::unpack_from(&buf[1..3])?,
+            remote_version: RemoteVersion {
+                version: buf[3],
+                company_id: u16::from_le_bytes([buf[4], buf[5]]),
+                subversion: u16::from_le_bytes([buf[6], buf[7]]),
+            },
+        })
+    }
+
+    fn event_pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        buf[0] = self.status.into();
+        self.handle.pack_into(&mut buf[1..3])?;
+        buf[3] = self.remote_version.version;
+        buf[4..6].copy_from_slice(&self.remote_version.company_id.to_le_bytes());
+        buf[6..8].copy_from_slice(&self.remote_version.subversion.to_le_bytes());
+        Ok(())
+    }
+}
+/// Sent when a connection is terminated. The controller discards the data packets left for the
+/// connection so their buffers are free again.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct DisconnectionComplete {
+    pub status: ErrorCode,
+    pub handle: ConnectionHandle,
+    pub reason: ErrorCode,
+}
+impl DisconnectionComplete {
+    pub const BYTE_LEN: usize = ErrorCode::BYTE_LEN + ConnectionHandle::BYTE_LEN + 1;
+}
+impl Event for DisconnectionComplete {
+    const EVENT_CODE: EventCode = EventCode::DisconnectionComplete;
+
+    fn event_byte_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+
+    fn event_unpack_from(buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        Ok(DisconnectionComplete {
+            status: ErrorCode::try_from(buf[0]).map_err(|_| PackError::bad_index(0))?,
+            handle: ConnectionHandle::unpack_from(&buf[1..3])?,
+            reason: ErrorCode::try_from(buf[3]).map_err(|_| PackError::bad_index(3))?,
+        })
+    }
+
+    fn event_pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        buf[0] = self.status.into();
+        self.handle.pack_into(&mut buf[1..3])?;
+        buf[3] = self.reason.into();
+        Ok(())
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hci::test_packets::{assert_command, assert_event};
+
+    #[test]
+    fn read_remote_version_information() {
+        let handle = ConnectionHandle::new(0x0040);
+        assert_command(
+            &ReadRemoteVersionInformation { handle },
+            &[0x1D, 0x04, 0x02, 0x40, 0x00],
+        );
+        let event = ReadRemoteVersionInformationComplete {
+            status: ErrorCode::Ok,
+            handle,
+            remote_version: RemoteVersion {
+                version: 0x0B,
+                company_id: 0x000F,
+                subversion: 0x2209,
+            },
+        };
+        assert_event(&event, &[0x00, 0x40, 0x00, 0x0B, 0x0F, 0x00, 0x09, 0x22]);
+        assert_eq!(
+            u8::from(EventCode::ReadRemoteVersionInformationComplete),
+            0x0C
+        );
+        assert_eq!(event.version(), Some(Version::Bluetooth5v2));
+    }
+    #[test]
+    fn disconnection_complete() {
+        assert_event(
+            &DisconnectionComplete {
+                status: ErrorCode::Ok,
+                handle: ConnectionHandle::new(0x0040),
+                reason: ErrorCode::OtherEndTerminatedConnectionUserEndedConnection,
+            },
+            &[0x00, 0x40, 0x00, 0x13],
+        );
+        assert_eq!(u8::from(EventCode::DisconnectionComplete), 0x05);
+    }
+}
@@ -1,26 +1,53 @@
 //! HCI Layer (where most the magic happens). Implements a Bluetooth Adapter for any controller
 //! supporting HCI streams.
 //! (HCI Layer is Little Endian).
+pub mod acl;
 pub mod adapter;
 pub mod adapters;
 pub mod baseband;
 #[cfg(all(unix, feature = "bluez_socket"))]
+pub mod bluez_mgmt;
+#[cfg(all(unix, feature = "bluez_socket"))]
+pub mod bluez_monitor;
+#[cfg(all(unix, feature = "bluez_socket"))]
 pub mod bluez_socket;
+#[cfg(feature = "std")]
+pub mod btsnoop;
 pub mod command;
 pub mod event;
+pub mod info;
+pub mod iso;
 pub mod le;
 pub mod link_control;
 pub mod packet;
+#[cfg(feature = "std")]
+pub mod pcapng;
 #[cfg(feature = "remote")]
 pub mod remote;
+#[cfg(feature = "std")]
+pub mod replay;
 pub mod stream;
+#[cfg(test)]
+pub(crate) mod test_packets;
+#[cfg(feature = "hci_uart")]
+pub mod uart;
 #[cfg(feature = "hci_usb")]
 pub mod usb;
+#[cfg(feature = "vendor")]
+pub mod vendor;
+#[cfg(all(target_os = "linux", feature = "vhci"))]
+pub mod vhci;
+#[cfg(feature = "std")]
+pub mod virtual_controller;
 
 #[derive(Copy, Clone, PartialOrd, PartialEq, Ord, Eq, Hash, Debug)]
 pub enum StreamError {
     EventError(PackError),
     CommandError(PackError),
+    /// Malformed ACL or ISO Data packet.
+    DataError(PackError),
+    /// Too many unread ACL or ISO Data packets are buffered. Read them to continue.
+    DataBufferFull,
     UnsupportedPacketType(u8),
     BadOpcode,
     BadEventCode,
@@ -78,6 +105,8 @@ pub enum Version {
     Bluetooth5v0 = 9,
     Bluetooth5v1 = 10,
     Bluetooth5v2 = 11,
+    Bluetooth5v3 = 12,
+    Bluetooth5v4 = 13,
 }
 impl From<Version> for u8 {
     fn from(v: Version) -> Self {
@@ -101,6 +130,8 @@ impl TryFrom<u8> for Version {
             9 => Ok(Version::Bluetooth5v0),
             10 => Ok(Version::Bluetooth5v1),
             11 => Ok(Version::Bluetooth5v2),
+            12 => Ok(Version::Bluetooth5v3),
+            13 => Ok(Version::Bluetooth5v4),
             _ => Err(ConversionError(())),
         }
     }
@@ -11,6 +11,7 @@ pub enum PacketType {
     ACLData = 0x02,
     SCOData = 0x03,
     Event = 0x04,
+    ISOData = 0x05,
     Vendor = 0xFF,
 }
 impl From<PacketType> for u8 {
@@ -32,11 +33,19 @@ impl TryFrom<u8> for PacketType {
             0x02 => Ok(PacketType::ACLData),
             0x03 => Ok(PacketType::SCOData),
             0x04 => Ok(PacketType::Event),
+            0x05 => Ok(PacketType::ISOData),
             0xFF => Ok(PacketType::Vendor),
             _ => Err(ConversionError(())),
         }
     }
 }
+/// Direction of a HCI Packet. `Tx` is Host to Controller (Commands and outgoing data) and `Rx` is
+/// Controller to Host (Events and incoming data).
+#[derive(Copy, Clone, PartialOrd, PartialEq, Ord, Eq, Hash, Debug)]
+pub enum PacketDirection {
+    Tx,
+    Rx,
+}
 /// Raw HCI Packet. Stores the [`PacketType`] + packet data buf (bytes).
 #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
 pub struct RawPacket<Buf> {
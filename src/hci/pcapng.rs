@@ -0,0 +1,441 @@
+//! pcapng capture writer. Each adapter gets its own interface (with a name and description) so
+//! captures of multiple adapters can go in one file. Supports HCI packets
+//! (`LINKTYPE_BLUETOOTH_HCI_H4_WITH_PHDR`) and LE Link Layer packets
+//! (`LINKTYPE_BLUETOOTH_LE_LL_WITH_PHDR`).
+//!
+//! Blocks are written in the host byte order like most pcapng writers. Timestamps use the default
+//! microsecond resolution.
+use crate::channel::{Index, RFChannel};
+use crate::error::IOError;
+use crate::hci::packet::{PacketDirection, RawPacket};
+use crate::le::report::{AddressType, EventType, ReportInfo};
+use crate::PackError;
+use core::convert::TryFrom;
+use core::time::Duration;
+use std::io::Write;
+
+/// pcapng link types (the subset this writer knows how to encode).
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+#[repr(u16)]
+pub enum LinkType {
+    /// 4 byte direction header then the H4 packet (packet type byte and packet).
+    BluetoothHCIH4WithPHDR = 201,
+    /// 10 byte RF header then the LE Link Layer packet (access address, PDU and CRC).
+    BluetoothLELLWithPHDR = 256,
+}
+impl From<LinkType> for u16 {
+    fn from(l: LinkType) -> Self {
+        l as u16
+    }
+}
+const SECTION_HEADER_BLOCK: u32 = 0x0A0D_0D0A;
+const INTERFACE_DESCRIPTION_BLOCK: u32 = 0x0000_0001;
+const ENHANCED_PACKET_BLOCK: u32 = 0x0000_0006;
+const BYTE_ORDER_MAGIC: u32 = 0x1A2B_3C4D;
+const OPTION_END: u16 = 0;
+const OPTION_IF_NAME: u16 = 2;
+const OPTION_IF_DESCRIPTION: u16 = 3;
+/// Advertising channel PDU access address.
+pub const ADVERTISING_ACCESS_ADDRESS: u32 = 0x8E89_BED6;
+
+/// LE LL RF header flags.
+pub mod ll_flags {
+    pub const DEWHITENED: u16 = 0x0001;
+    pub const SIGNAL_POWER_VALID: u16 = 0x0002;
+    pub const NOISE_POWER_VALID: u16 = 0x0004;
+    pub const DECRYPTED: u16 = 0x0008;
+    pub const REFERENCE_ACCESS_ADDRESS_VALID: u16 = 0x0010;
+    pub const ACCESS_ADDRESS_OFFENSES_VALID: u16 = 0x0020;
+    pub const CHANNEL_ALIASED: u16 = 0x0040;
+    pub const CRC_CHECKED: u16 = 0x0400;
+    pub const CRC_VALID: u16 = 0x0800;
+}
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub enum PcapNGError {
+    IOError(IOError),
+    PackError(PackError),
+    /// Interface doesn't exist or has a different `LinkType` than the packet.
+    WrongLinkType,
+}
+impl core::fmt::Display for PcapNGError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "pcapng error {:?}", self)
+    }
+}
+impl crate::error::Error for PcapNGError {}
+impl From<std::io::Error> for PcapNGError {
+    fn from(e: std::io::Error) -> Self {
+        PcapNGError::IOError(e.into())
+    }
+}
+impl From<PackError> for PcapNGError {
+    fn from(e: PackError) -> Self {
+        PcapNGError::PackError(e)
+    }
+}
+/// Metadata of one capture interface (usually one adapter).
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct Interface {
+    pub link_type: LinkType,
+    pub name: Option<String>,
+    pub description: Option<String>,
+}
+impl Interface {
+    pub fn new(link_type: LinkType) -> Interface {
+        Interface {
+            link_type,
+            name: None,
+            description: None,
+        }
+    }
+    pub fn with_name(mut self, name: impl Into<String>) -> Interface {
+        self.name = Some(name.into());
+        self
+    }
+    pub fn with_description(mut self, description: impl Into<String>) -> Interface {
+        self.description = Some(description.into());
+        self
+    }
+}
+/// Interface ID returned by [`PcapNGWriter::add_interface`].
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct InterfaceID(pub u32);
+
+fn padded_len(len: usize) -> usize {
+    (len + 3) & !3
+}
+fn push_option(buf: &mut Vec<u8>, code: u16, value: &[u8]) {
+    buf.extend_from_slice(&code.to_ne_bytes());
+    buf.extend_from_slice(&(value.len() as u16).to_ne_bytes());
+    buf.extend_from_slice(value);
+    buf.resize(padded_len(buf.len()), 0);
+}
+/// Writes a pcapng capture. Packets written to an interface must match its `LinkType`.
+#[derive(Debug)]
+pub struct PcapNGWriter<W: Write> {
+    writer: W,
+    interfaces: Vec<LinkType>,
+}
+impl<W: Write> PcapNGWriter<W> {
+    /// Writes the Section Header Block to `writer`.
+    pub fn new(writer: W) -> Result<Self, PcapNGError> {
+        let mut out = PcapNGWriter {
+            writer,
+            interfaces: Vec::new(),
+        };
+        let mut body = Vec::with_capacity(16);
+        body.extend_from_slice(&BYTE_ORDER_MAGIC.to_ne_bytes());
+        // Version 1.0
+        body.extend_from_slice(&1_u16.to_ne_bytes());
+        body.extend_from_slice(&0_u16.to_ne_bytes());
+        // Unknown section length.
+        body.extend_from_slice(&(-1_i64).to_ne_bytes());
+        out.write_block(SECTION_HEADER_BLOCK, &body)?;
+        Ok(out)
+    }
+    fn write_block(&mut self, block_type: u32, body: &[u8]) -> Result<(), PcapNGError> {
+        let padded = padded_len(body.len());
+        let total_len = u32::try_from(12 + padded).map_err(|_| PackError::InvalidFields)?;
+        self.writer.write_all(&block_type.to_ne_bytes())?;
+        self.writer.write_all(&total_len.to_ne_bytes())?;
+        self.writer.write_all(body)?;
+        self.writer.write_all(&[0_u8; 3][..padded - body.len()])?;
+        self.writer.write_all(&total_len.to_ne_bytes())?;
+        Ok(())
+    }
+    /// Writes an Interface Description Block. Returns the ID to write packets with.
+    pub fn add_interface(&mut self, interface: &Interface) -> Result<InterfaceID, PcapNGError> {
+        let mut body = Vec::new();
+        body.extend_from_slice(&u16::from(interface.link_type).to_ne_bytes());
+        // Reserved
+        body.extend_from_slice(&0_u16.to_ne_bytes());
+        // No snap length limit.
+        body.extend_from_slice(&0_u32.to_ne_bytes());
+        if let Some(name) = &interface.name {
+            push_option(&mut body, OPTION_IF_NAME, name.as_bytes());
+        }
+        if let Some(description) = &interface.description {
+            push_option(&mut body, OPTION_IF_DESCRIPTION, description.as_bytes());
+        }
+        if interface.name.is_some() || interface.description.is_some() {
+            push_option(&mut body, OPTION_END, &[]);
+        }
+        self.write_block(INTERFACE_DESCRIPTION_BLOCK, &body)?;
+        let id = InterfaceID(self.interfaces.len() as u32);
+        self.interfaces.push(interface.link_type);
+        Ok(id)
+    }
+    fn check_interface(&self, id: InterfaceID, link_type: LinkType) -> Result<(), PcapNGError> {
+        match self.interfaces.get(id.0 as usize) {
+            Some(&l) if l == link_type => Ok(()),
+            _ => Err(PcapNGError::WrongLinkType),
+        }
+    }
+    /// Writes an Enhanced Packet Block. `timestamp` is since the Unix Epoch.
+    fn write_packet_block(
+        &mut self,
+        id: InterfaceID,
+        timestamp: Duration,
+        data: &[u8],
+    ) -> Result<(), PcapNGError> {
+        let micros = u64::try_from(timestamp.as_micros()).map_err(|_| PackError::InvalidFields)?;
+        let len = u32::try_from(data.len()).map_err(|_| PackError::InvalidFields)?;
+        let mut body = Vec::with_capacity(20 + data.len());
+        body.extend_from_slice(&id.0.to_ne_bytes());
+        body.extend_from_slice(&((micros >> 32) as u32).to_ne_bytes());
+        body.extend_from_slice(&(micros as u32).to_ne_bytes());
+        body.extend_from_slice(&len.to_ne_bytes());
+        body.extend_from_slice(&len.to_ne_bytes());
+        body.extend_from_slice(data);
+        self.write_block(ENHANCED_PACKET_BLOCK, &body)
+    }
+    /// Writes a HCI packet to a `BluetoothHCIH4WithPHDR` interface.
+    pub fn write_hci_packet(
+        &mut self,
+        id: InterfaceID,
+        direction: PacketDirection,
+        packet: RawPacket<&[u8]>,
+        timestamp: Duration,
+    ) -> Result<(), PcapNGError> {
+        self.check_interface(id, LinkType::BluetoothHCIH4WithPHDR)?;
+        let mut data = Vec::with_capacity(4 + packet.total_len());
+        let direction: u32 = match direction {
+            PacketDirection::Tx => 0,
+            PacketDirection::Rx => 1,
+        };
+        data.extend_from_slice(&direction.to_be_bytes());
+        data.push(packet.packet_type.into());
+        data.extend_from_slice(packet.buf);
+        self.write_packet_block(id, timestamp, &data)
+    }
+    /// Writes a LE Link Layer packet (access address, PDU and CRC) to a `BluetoothLELLWithPHDR`
+    /// interface. `flags` are `ll_flags`. `SIGNAL_POWER_VALID` is added if `signal_power` is
+    /// `Some`.
+    pub fn write_ll_packet(
+        &mut self,
+        id: InterfaceID,
+        channel: RFChannel,
+        signal_power: Option<i8>,
+        flags: u16,
+        packet: &[u8],
+        timestamp: Duration,
+    ) -> Result<(), PcapNGError> {
+        self.check_interface(id, LinkType::BluetoothLELLWithPHDR)?;
+        let mut flags = flags;
+        if signal_power.is_some() {
+            flags |= ll_flags::SIGNAL_POWER_VALID;
+        }
+        let mut data = Vec::with_capacity(10 + packet.len());
+        data.push(channel.into());
+        data.push(signal_power.unwrap_or(0) as u8);
+        // Noise power and access address offenses.
+        data.extend_from_slice(&[0, 0]);
+        let reference_access_address = if packet.len() >= 4 {
+            u32::from_le_bytes([packet[0], packet[1], packet[2], packet[3]])
+        } else {
+            0
+        };
+        data.extend_from_slice(&reference_access_address.to_le_bytes());
+        data.extend_from_slice(&flags.to_le_bytes());
+        data.extend_from_slice(packet);
+        self.write_packet_block(id, timestamp, &data)
+    }
+    /// Writes an advertising report as a synthetic advertising channel LL packet. HCI reports
+    /// don't say which advertising channel (`Index` 37, 38 or 39) the advertisement was on or
+    /// include the CRC so the CRC is zeroed and marked unchecked. `ADV_DIRECT_IND` reports don't
+    /// have the target address so it is zeroed as well.
+    pub fn write_advertising_report<T: AsRef<[u8]>>(
+        &mut self,
+        id: InterfaceID,
+        report: &ReportInfo<T>,
+        channel: Index,
+        timestamp: Duration,
+    ) -> Result<(), PcapNGError> {
+        let pdu_type: u8 = match report.event_type {
+            EventType::AdvInd => 0b0000,
+            EventType::AdvDirectInd => 0b0001,
+            EventType::AdvNonconnInd => 0b0010,
+            EventType::ScanRsp => 0b0100,
+            EventType::AdvScanInd => 0b0110,
+        };
+        let tx_add = match report.address_type {
+            AddressType::PublicDevice | AddressType::PublicIdentity => 0,
+            AddressType::RandomDevice | AddressType::RandomIdentity => 1,
+        };
+        let mut payload = Vec::with_capacity(6 + 31);
+        payload.extend_from_slice(&report.address.0);
+        if report.event_type == EventType::AdvDirectInd {
+            payload.extend_from_slice(&[0_u8; 6]);
+        } else {
+            payload.extend_from_slice(report.data.as_ref());
+        }
+        let mut packet = Vec::with_capacity(4 + 2 + payload.len() + 3);
+        packet.extend_from_slice(&ADVERTISING_ACCESS_ADDRESS.to_le_bytes());
+        packet.push(pdu_type | (tx_add << 6));
+        packet.push(u8::try_from(payload.len()).map_err(|_| PackError::InvalidFields)?);
+        packet.extend_from_slice(&payload);
+        packet.extend_from_slice(&[0_u8; 3]);
+        self.write_ll_packet(
+            id,
+            RFChannel::from(channel),
+            report.rssi.map(i8::from),
+            ll_flags::DEWHITENED | ll_flags::REFERENCE_ACCESS_ADDRESS_VALID,
+            &packet,
+            timestamp,
+        )
+    }
+    pub fn flush(&mut self) -> Result<(), PcapNGError> {
+        Ok(self.writer.flush()?)
+    }
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hci::packet::PacketType;
+
+    fn u16_at(buf: &[u8], index: usize) -> u16 {
+        u16::from_ne_bytes([buf[index], buf[index + 1]])
+    }
+    fn u32_at(buf: &[u8], index: usize) -> u32 {
+        u32::from_ne_bytes([buf[index], buf[index + 1], buf[index + 2], buf[index + 3]])
+    }
+    /// Splits a capture into (block type, padded body) after checking the block framing.
+    fn blocks(mut capture: &[u8]) -> Vec<(u32, &[u8])> {
+        let mut out = Vec::new();
+        while !capture.is_empty() {
+            let total_len = u32_at(capture, 4) as usize;
+            assert_eq!(total_len % 4, 0, "blocks are 32-bit aligned");
+            assert_eq!(u32_at(capture, total_len - 4) as usize, total_len);
+            out.push((u32_at(capture, 0), &capture[8..total_len - 4]));
+            capture = &capture[total_len..];
+        }
+        out
+    }
+    /// Parses the options of an Interface Description Block body.
+    fn options(mut buf: &[u8]) -> Vec<(u16, &[u8])> {
+        let mut out = Vec::new();
+        while !buf.is_empty() {
+            let code = u16_at(buf, 0);
+            let len = usize::from(u16_at(buf, 2));
+            out.push((code, &buf[4..4 + len]));
+            buf = &buf[4 + padded_len(len)..];
+        }
+        out
+    }
+    /// Returns (interface ID, timestamp, captured data) of an Enhanced Packet Block body.
+    fn packet(body: &[u8]) -> (u32, Duration, &[u8]) {
+        let micros = (u64::from(u32_at(body, 4)) << 32) | u64::from(u32_at(body, 8));
+        let len = u32_at(body, 12) as usize;
+        assert_eq!(u32_at(body, 16) as usize, len);
+        assert_eq!(body.len(), 20 + padded_len(len));
+        assert!(body[20 + len..].iter().all(|&b| b == 0), "zero padding");
+        (
+            u32_at(body, 0),
+            Duration::from_micros(micros),
+            &body[20..20 + len],
+        )
+    }
+    #[test]
+    fn round_trip() {
+        let mut writer = PcapNGWriter::new(Vec::new()).unwrap();
+        let hci = writer
+            .add_interface(
+                &Interface::new(LinkType::BluetoothHCIH4WithPHDR)
+                    .with_name("hci0")
+                    .with_description("usb"),
+            )
+            .unwrap();
+        let ll = writer
+            .add_interface(&Interface::new(LinkType::BluetoothLELLWithPHDR))
+            .unwrap();
+        assert_eq!((hci, ll), (InterfaceID(0), InterfaceID(1)));
+        let timestamp = Duration::from_micros(0x0001_0000_0002);
+        // Command Complete for HCI Reset, Success.
+        let event = [0x0E, 0x04, 0x01, 0x03, 0x0C, 0x00];
+        writer
+            .write_hci_packet(
+                hci,
+                PacketDirection::Rx,
+                RawPacket {
+                    packet_type: PacketType::Event,
+                    buf: &event[..],
+                },
+                timestamp,
+            )
+            .unwrap();
+        // ADV_NONCONN_IND with only an address and a zeroed CRC.
+        let mut ll_packet = ADVERTISING_ACCESS_ADDRESS.to_le_bytes().to_vec();
+        ll_packet.extend_from_slice(&[0x02, 0x06, 1, 2, 3, 4, 5, 6, 0, 0, 0]);
+        writer
+            .write_ll_packet(
+                ll,
+                RFChannel::from(Index::new(37)),
+                Some(-60),
+                ll_flags::DEWHITENED,
+                &ll_packet,
+                timestamp,
+            )
+            .unwrap();
+        assert_eq!(
+            writer.write_ll_packet(
+                hci,
+                RFChannel::from(Index::new(37)),
+                None,
+                0,
+                &ll_packet,
+                timestamp
+            ),
+            Err(PcapNGError::WrongLinkType)
+        );
+        let capture = writer.into_inner();
+        let blocks = blocks(&capture);
+        assert_eq!(blocks.len(), 5);
+
+        let (block_type, shb) = blocks[0];
+        assert_eq!(block_type, SECTION_HEADER_BLOCK);
+        assert_eq!(u32_at(shb, 0), BYTE_ORDER_MAGIC);
+        assert_eq!((u16_at(shb, 4), u16_at(shb, 6)), (1, 0));
+        assert_eq!(&shb[8..16], &[0xFF; 8]);
+
+        let (block_type, idb) = blocks[1];
+        assert_eq!(block_type, INTERFACE_DESCRIPTION_BLOCK);
+        assert_eq!(u16_at(idb, 0), 201);
+        assert_eq!(u32_at(idb, 4), 0);
+        assert_eq!(
+            options(&idb[8..]),
+            vec![
+                (OPTION_IF_NAME, &b"hci0"[..]),
+                (OPTION_IF_DESCRIPTION, &b"usb"[..]),
+                (OPTION_END, &[][..]),
+            ]
+        );
+        let (block_type, idb) = blocks[2];
+        assert_eq!(block_type, INTERFACE_DESCRIPTION_BLOCK);
+        assert_eq!(u16_at(idb, 0), 256);
+        assert_eq!(idb.len(), 8);
+
+        let (block_type, epb) = blocks[3];
+        assert_eq!(block_type, ENHANCED_PACKET_BLOCK);
+        let (id, time, data) = packet(epb);
+        assert_eq!((id, time), (hci.0, timestamp));
+        // Big Endian direction (received), H4 packet type then the event.
+        assert_eq!(&data[..5], &[0, 0, 0, 1, 0x04]);
+        assert_eq!(&data[5..], &event);
+
+        let (block_type, epb) = blocks[4];
+        assert_eq!(block_type, ENHANCED_PACKET_BLOCK);
+        let (id, time, data) = packet(epb);
+        assert_eq!((id, time), (ll.0, timestamp));
+        // RF channel 0, -60 dBm, no noise or offenses, reference access address and flags.
+        assert_eq!(&data[..4], &[0, (-60_i8) as u8, 0, 0]);
+        assert_eq!(&data[4..8], &ADVERTISING_ACCESS_ADDRESS.to_le_bytes());
+        assert_eq!(
+            u16::from_le_bytes([data[8], data[9]]),
+            ll_flags::DEWHITENED | ll_flags::SIGNAL_POWER_VALID
+        );
+        assert_eq!(&data[10..], &ll_packet[..]);
+    }
+}
@@ -0,0 +1,58 @@
+//! Async HCI-over-TCP client.
+use crate::error::IOError;
+use crate::hci::adapter;
+use crate::hci::stream::{HCIReader, HCIWriter, Stream};
+use crate::hci::uart::h4::H4Transport;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use tokio::net::{TcpStream, ToSocketAddrs};
+
+/// Async client for a [`super::server::Server`] (or anything else speaking H4 over TCP). Wrap it
+/// in a [`Stream`] (see [`AsyncClient::into_stream`]) to use it as an `Adapter`. Must be used
+/// inside a tokio runtime.
+#[derive(Debug)]
+pub struct AsyncClient(H4Transport<TcpStream>);
+impl AsyncClient {
+    pub async fn connect<A: ToSocketAddrs>(addr: A) -> Result<AsyncClient, IOError> {
+        Ok(Self::new(TcpStream::connect(addr).await?))
+    }
+    pub fn new(stream: TcpStream) -> AsyncClient {
+        // HCI packets are small so don't wait to fill segments.
+        let _ = stream.set_nodelay(true);
+        AsyncClient(H4Transport::new(stream))
+    }
+    pub fn into_stream(self) -> Stream<AsyncClient, Box<AsyncClient>> {
+        Stream::new(Box::pin(self))
+    }
+    pub fn transport(&self) -> &H4Transport<TcpStream> {
+        &self.0
+    }
+    pub fn into_inner(self) -> TcpStream {
+        self.0.into_inner()
+    }
+}
+impl HCIReader for AsyncClient {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<Result<usize, adapter::Error>> {
+        Pin::new(&mut self.0).poll_read(cx, buf)
+    }
+}
+impl HCIWriter for AsyncClient {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, adapter::Error>> {
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), adapter::Error>> {
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+}
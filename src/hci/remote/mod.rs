@@ -0,0 +1,28 @@
+//! Remote HCI Controller. HCI packets are sent over TCP with H4 framing (packet type byte then
+//! the packet). [`server::Server`] exposes a local [`Adapter`](crate::hci::adapter::Adapter) and
+//! [`client::AsyncClient`] connects to it.
+pub mod client;
+pub mod server;
+
+use std::{io, net};
+/// Blocking client. Raw H4 byte stream.
+pub struct Client(pub net::TcpStream);
+impl io::Write for Client {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, io::Error> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> Result<(), io::Error> {
+        self.0.flush()
+    }
+}
+impl io::Read for Client {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, io::Error> {
+        self.0.read(buf)
+    }
+}
+impl Client {
+    pub fn new(stream: net::TcpStream) -> Self {
+        Self(stream)
+    }
+}
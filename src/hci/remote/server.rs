@@ -0,0 +1,289 @@
+//! HCI-over-TCP server. Shares one local [`Adapter`] with every connected client.
+use crate::error::IOError;
+use crate::hci::acl::ACLPacket;
+use crate::hci::adapter::{self, Adapter};
+use crate::hci::command::CommandPacket;
+use crate::hci::event::EventPacket;
+use crate::hci::iso::{ISOHeader, ISOPacket};
+use crate::hci::packet::{PacketType, RawPacket};
+use crate::hci::stream::{HCIReader, HCIWriter};
+use crate::hci::uart::h4::H4Transport;
+use crate::hci::StreamError;
+use core::convert::TryFrom;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use core::time::Duration;
+use futures_util::future::poll_fn;
+use std::net::SocketAddr;
+use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
+
+/// Biggest packet (including the packet type byte) read from a client.
+const READ_BUF_LEN: usize = 1 + ISOHeader::BYTE_LEN + ISOHeader::MAX_DATA_LOAD_LEN as usize;
+/// How long to stop accepting after a failed accept. Errors like running out of file
+/// descriptors would fail every accept right away otherwise.
+const ACCEPT_ERROR_DELAY: Duration = Duration::from_millis(100);
+#[derive(Debug)]
+struct Connection {
+    transport: H4Transport<TcpStream>,
+    addr: SocketAddr,
+}
+enum Incoming {
+    Accepted(TcpStream, SocketAddr),
+    AcceptFailed(IOError),
+    /// Client `index` sent a packet (stored in `read_buf`) or disconnected.
+    Client(usize, Result<usize, adapter::Error>),
+    Adapter(Result<RawPacket<Box<[u8]>>, adapter::Error>),
+}
+/// Exposes `adapter` over TCP with H4 framing. Commands and ACL/ISO Data from any client are
+/// forwarded to the adapter. Events and ACL/ISO Data from the adapter are sent to every client
+/// (clients must ignore Command Completes for commands they didn't send).
+///
+/// The adapter is read by polling `read_event`, `read_acl` and `read_iso` in turn and dropping
+/// the futures that aren't ready so the reads must be cancel safe (like `hci::stream::Stream`).
+/// Must be used inside a tokio runtime. Clients are written to one after the other so a stalled
+/// client holds up the rest.
+#[derive(Debug)]
+pub struct Server<A> {
+    adapter: A,
+    listener: TcpListener,
+    clients: Vec<Connection>,
+    read_buf: Box<[u8]>,
+    last_accept_error: Option<IOError>,
+}
+/// Polls `adapter` for the next Event, ACL or ISO packet. Adapters without an ACL or ISO path
+/// (`IOError::NotImplemented`) are only polled for events.
+fn poll_adapter<A: Adapter>(
+    adapter: &mut A,
+    cx: &mut Context<'_>,
+) -> Poll<Result<RawPacket<Box<[u8]>>, adapter::Error>> {
+    let event: Poll<Result<EventPacket<Box<[u8]>>, _>> = adapter.read_event().as_mut().poll(cx);
+    if let Poll::Ready(result) = event {
+        return Poll::Ready(result.map(|event| event.to_raw_packet()));
+    }
+    let acl: Poll<Result<ACLPacket<Box<[u8]>>, _>> = adapter.read_acl().as_mut().poll(cx);
+    match acl {
+        Poll::Ready(Err(adapter::Error::IOError(IOError::NotImplemented))) | Poll::Pending => (),
+        Poll::Ready(result) => {
+            return Poll::Ready(result.and_then(|acl| {
+                acl.to_raw_packet()
+                    .map_err(|e| StreamError::DataError(e).into())
+            }))
+        }
+    }
+    let iso: Poll<Result<ISOPacket<Box<[u8]>>, _>> = adapter.read_iso().as_mut().poll(cx);
+    match iso {
+        Poll::Ready(Err(adapter::Error::IOError(IOError::NotImplemented))) | Poll::Pending => {
+            Poll::Pending
+        }
+        Poll::Ready(result) => Poll::Ready(result.and_then(|iso| {
+            iso.to_raw_packet()
+                .map_err(|e| StreamError::DataError(e).into())
+        })),
+    }
+}
+async fn write_all<W: HCIWriter + Unpin>(
+    writer: &mut W,
+    mut buf: &[u8],
+) -> Result<(), adapter::Error> {
+    while !buf.is_empty() {
+        let len = poll_fn(|cx| Pin::new(&mut *writer).poll_write(cx, buf)).await?;
+        if len == 0 {
+            return Err(adapter::Error::IOError(IOError::Closed));
+        }
+        buf = &buf[len..];
+    }
+    poll_fn(|cx| Pin::new(&mut *writer).poll_flush(cx)).await
+}
+impl<A: Adapter> Server<A> {
+    pub async fn bind<T: ToSocketAddrs>(adapter: A, addr: T) -> Result<Server<A>, IOError> {
+        Ok(Self::new(adapter, TcpListener::bind(addr).await?))
+    }
+    pub fn new(adapter: A, listener: TcpListener) -> Server<A> {
+        Server {
+            adapter,
+            listener,
+            clients: Vec::new(),
+            read_buf: vec![0_u8; READ_BUF_LEN].into_boxed_slice(),
+            last_accept_error: None,
+        }
+    }
+    pub fn local_addr(&self) -> Result<SocketAddr, IOError> {
+        Ok(self.listener.local_addr()?)
+    }
+    /// Addresses of the connected clients.
+    pub fn clients(&self) -> impl Iterator<Item = SocketAddr> + '_ {
+        self.clients.iter().map(|connection| connection.addr)
+    }
+    /// Last error accepting a client. Failed accepts are skipped and the server keeps serving.
+    pub fn last_accept_error(&self) -> Option<IOError> {
+        self.last_accept_error
+    }
+    pub fn adapter_mut(&mut self) -> &mut A {
+        &mut self.adapter
+    }
+    pub fn into_adapter(self) -> A {
+        self.adapter
+    }
+    /// Serves clients until the adapter fails.
+    pub async fn run(&mut self) -> Result<(), adapter::Error> {
+        loop {
+            self.step().await?;
+        }
+    }
+    /// Handles the next connection, client packet or adapter packet.
+    pub async fn step(&mut self) -> Result<(), adapter::Error> {
+        match poll_fn(|cx| self.poll_incoming(cx)).await {
+            Incoming::Accepted(stream, addr) => {
+                let _ = stream.set_nodelay(true);
+                self.clients.push(Connection {
+                    transport: H4Transport::new(stream),
+                    addr,
+                });
+                Ok(())
+            }
+            Incoming::AcceptFailed(e) => {
+                self.last_accept_error = Some(e);
+                tokio::time::sleep(ACCEPT_ERROR_DELAY).await;
+                Ok(())
+            }
+            Incoming::Client(index, Ok(len)) => self.forward_to_adapter(index, len).await,
+            Incoming::Client(index, Err(_)) => {
+                self.clients.remove(index);
+                Ok(())
+            }
+            Incoming::Adapter(Ok(packet)) => {
+                self.broadcast(packet.as_ref()).await;
+                Ok(())
+            }
+            Incoming::Adapter(Err(e)) => Err(e),
+        }
+    }
+    fn poll_incoming(&mut self, cx: &mut Context<'_>) -> Poll<Incoming> {
+        match self.listener.poll_accept(cx) {
+            Poll::Ready(Ok((stream, addr))) => {
+                return Poll::Ready(Incoming::Accepted(stream, addr))
+            }
+            Poll::Ready(Err(e)) => return Poll::Ready(Incoming::AcceptFailed(e.into())),
+            Poll::Pending => (),
+        }
+        for (index, connection) in self.clients.iter_mut().enumerate() {
+            if let Poll::Ready(result) =
+                Pin::new(&mut connection.transport).poll_read(cx, &mut self.read_buf[..])
+            {
+                return Poll::Ready(Incoming::Client(index, result));
+            }
+        }
+        poll_adapter(&mut self.adapter, cx).map(Incoming::Adapter)
+    }
+    /// Sends the packet client `index` sent to the adapter. Clients sending malformed or
+    /// unsupported packets are disconnected. Packets the adapter has no path for are dropped.
+    async fn forward_to_adapter(&mut self, index: usize, len: usize) -> Result<(), adapter::Error> {
+        let raw = match RawPacket::try_from(&self.read_buf[..len]) {
+            Ok(raw) => raw,
+            Err(_) => {
+                self.clients.remove(index);
+                return Ok(());
+            }
+        };
+        let result = match raw.packet_type {
+            PacketType::Command => match CommandPacket::try_from(raw) {
+                Ok(packet) => self.adapter.write_command(packet).await,
+                Err(e) => Err(StreamError::CommandError(e).into()),
+            },
+            PacketType::ACLData => match ACLPacket::try_from(raw) {
+                Ok(packet) => self.adapter.write_acl(packet).await,
+                Err(e) => Err(StreamError::DataError(e).into()),
+            },
+            PacketType::ISOData => match ISOPacket::try_from(raw) {
+                Ok(packet) => self.adapter.write_iso(packet).await,
+                Err(e) => Err(StreamError::DataError(e).into()),
+            },
+            t => Err(StreamError::UnsupportedPacketType(t.into()).into()),
+        };
+        match result {
+            Err(adapter::Error::StreamError(_)) => {
+                self.clients.remove(index);
+                Ok(())
+            }
+            Err(adapter::Error::IOError(IOError::NotImplemented)) => Ok(()),
+            result => result,
+        }
+    }
+    /// Sends `packet` to every client. Clients that can't be written to are disconnected.
+    async fn broadcast(&mut self, packet: RawPacket<&[u8]>) {
+        let bytes: Box<[u8]> = packet.pack().expect("boxed slices have no max length");
+        let mut index = 0;
+        while index < self.clients.len() {
+            if write_all(&mut self.clients[index].transport, &bytes)
+                .await
+                .is_ok()
+            {
+                index += 1;
+            } else {
+                self.clients.remove(index);
+            }
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hci::event::EventCode;
+    use crate::hci::remote::client::AsyncClient;
+    use crate::hci::stream::Stream;
+    use futures_util::future::{select, Either};
+
+    const RESET: [u8; 4] = [0x01, 0x03, 0x0C, 0x00];
+    const RESET_COMPLETE: [u8; 7] = [0x04, 0x0E, 0x04, 0x01, 0x03, 0x0C, 0x00];
+    const ACL_IN: [u8; 8] = [0x02, 0x40, 0x20, 0x03, 0x00, 0x01, 0x02, 0x03];
+    const ACL_OUT: [u8; 7] = [0x02, 0x40, 0x00, 0x02, 0x00, 0xAA, 0xBB];
+
+    #[test]
+    fn shares_adapter_over_loopback() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_io()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            // The "controller" is the other end of a TCP connection speaking H4.
+            let controller_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let host_end = TcpStream::connect(controller_listener.local_addr().unwrap())
+                .await
+                .unwrap();
+            let (controller_end, _) = controller_listener.accept().await.unwrap();
+            let adapter = Stream::new(Box::pin(H4Transport::new(host_end)));
+            let mut controller = Stream::new(Box::pin(H4Transport::new(controller_end)));
+            let mut server = Server::bind(adapter, "127.0.0.1:0").await.unwrap();
+            let addr = server.local_addr().unwrap();
+
+            let test = async {
+                let mut a = AsyncClient::connect(addr).await?.into_stream();
+                let mut b = AsyncClient::connect(addr).await?.into_stream();
+                let mut buf = [0_u8; 16];
+
+                a.send_exact(&RESET[..]).await?;
+                let len = controller.read_bytes(&mut buf[..]).await?;
+                assert_eq!(&buf[..len], &RESET[..]);
+                controller.send_exact(&RESET_COMPLETE[..]).await?;
+                controller.send_exact(&ACL_IN[..]).await?;
+                for client in vec![&mut a, &mut b] {
+                    let event = client.read_event::<Box<[u8]>>().await?;
+                    assert_eq!(event.event_code(), EventCode::CommandComplete);
+                    assert_eq!(event.parameters(), &RESET_COMPLETE[3..]);
+                    let acl = client.read_acl::<Box<[u8]>>().await?;
+                    assert_eq!(acl.data.as_ref(), &ACL_IN[5..]);
+                }
+
+                b.send_exact(&ACL_OUT[..]).await?;
+                let len = controller.read_bytes(&mut buf[..]).await?;
+                assert_eq!(&buf[..len], &ACL_OUT[..]);
+                Ok::<_, adapter::Error>(())
+            };
+            match select(Box::pin(server.run()), Box::pin(test)).await {
+                Either::Left((result, _)) => panic!("server stopped: {:?}", result),
+                Either::Right((result, _)) => result.unwrap(),
+            }
+            assert_eq!(server.clients().count(), 2);
+        });
+    }
+}
@@ -0,0 +1,367 @@
+//! Replays a recorded HCI capture (ex: a btsnoop file from [`SnoopAdapter`]) as an [`Adapter`].
+//! Controller to Host packets are served in the recorded order and Host to Controller packets
+//! are checked against the recording. Turns captures into regression tests without hardware.
+//!
+//! [`SnoopAdapter`]: crate::hci::btsnoop::SnoopAdapter
+use crate::bytes::Storage;
+use crate::error::IOError;
+use crate::hci::acl::ACLPacket;
+use crate::hci::adapter::{self, Adapter};
+use crate::hci::btsnoop::{BTSnoopError, BTSnoopReader, BTSnoopRecord};
+use crate::hci::command::CommandPacket;
+use crate::hci::event::EventPacket;
+use crate::hci::iso::ISOPacket;
+use crate::hci::packet::{PacketDirection, PacketType, RawPacket};
+use crate::hci::StreamError;
+use crate::LocalBoxFuture;
+use alloc::collections::VecDeque;
+use core::convert::TryFrom;
+use std::io::Read;
+
+/// First place the host didn't follow the recording.
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub enum Divergence {
+    /// Host sent `got` but the recording has `expected` (record `record_index`) next.
+    UnexpectedPacket {
+        record_index: usize,
+        expected: RawPacket<Box<[u8]>>,
+        got: RawPacket<Box<[u8]>>,
+    },
+    /// Host sent `got` after every recorded Host to Controller packet was sent.
+    ExtraPacket { got: RawPacket<Box<[u8]>> },
+    /// Host tried to read a `packet_type` packet but the recording expects the host to send
+    /// record `record_index` first.
+    ReadBeforeSend {
+        record_index: usize,
+        packet_type: PacketType,
+    },
+    /// Host tried to read a `packet_type` packet but there are none left in the recording.
+    EndOfRecording { packet_type: PacketType },
+}
+/// [`Adapter`] that replays a recording. Errors with `IOError::InvalidData` at the first
+/// divergence (see [`ReplayAdapter::divergence`]) and with every call after it.
+#[derive(Clone, Debug, Default)]
+pub struct ReplayAdapter {
+    records: VecDeque<(usize, BTSnoopRecord)>,
+    divergence: Option<Divergence>,
+}
+impl ReplayAdapter {
+    pub fn new(records: impl IntoIterator<Item = BTSnoopRecord>) -> ReplayAdapter {
+        ReplayAdapter {
+            records: records.into_iter().enumerate().collect(),
+            divergence: None,
+        }
+    }
+    /// Reads the whole btsnoop capture into a `ReplayAdapter`.
+    pub fn from_btsnoop<R: Read>(reader: BTSnoopReader<R>) -> Result<ReplayAdapter, BTSnoopError> {
+        Ok(Self::new(reader.collect::<Result<Vec<_>, _>>()?))
+    }
+    /// First divergence from the recording (if any).
+    pub fn divergence(&self) -> Option<&Divergence> {
+        self.divergence.as_ref()
+    }
+    /// Records not replayed yet.
+    pub fn remaining(&self) -> impl Iterator<Item = &BTSnoopRecord> + '_ {
+        self.records.iter().map(|(_, record)| record)
+    }
+    /// Returns `true` if every record was replayed without diverging.
+    pub fn is_finished(&self) -> bool {
+        self.divergence.is_none() && self.records.is_empty()
+    }
+    fn diverge(&mut self, divergence: Divergence) -> adapter::Error {
+        if self.divergence.is_none() {
+            self.divergence = Some(divergence);
+        }
+        adapter::Error::IOError(IOError::InvalidData)
+    }
+    fn check(&self) -> Result<(), adapter::Error> {
+        match self.divergence {
+            Some(_) => Err(adapter::Error::IOError(IOError::InvalidData)),
+            None => Ok(()),
+        }
+    }
+    /// Matches `got` against the next recorded Host to Controller packet. Recorded Controller to
+    /// Host packets before it stay queued for reading.
+    fn expect_sent(&mut self, got: RawPacket<&[u8]>) -> Result<(), adapter::Error> {
+        self.check()?;
+        let position = self
+            .records
+            .iter()
+            .position(|(_, record)| record.direction == PacketDirection::Tx);
+        let position = match position {
+            Some(position) => position,
+            None => {
+                let got = got.clone_buf();
+                return Err(self.diverge(Divergence::ExtraPacket { got }));
+            }
+        };
+        let (record_index, record) = &self.records[position];
+        if record.packet.as_ref() == got {
+            self.records.remove(position);
+            Ok(())
+        } else {
+            let divergence = Divergence::UnexpectedPacket {
+                record_index: *record_index,
+                expected: record.packet.clone(),
+                got: got.clone_buf(),
+            };
+            Err(self.diverge(divergence))
+        }
+    }
+    /// Returns the next recorded Controller to Host `packet_type` packet. Recorded Controller to
+    /// Host packets of other types before it stay queued.
+    fn next_received(&mut self, packet_type: PacketType) -> Result<Box<[u8]>, adapter::Error> {
+        self.check()?;
+        let mut position = None;
+        for (i, (record_index, record)) in self.records.iter().enumerate() {
+            match record.direction {
+                PacketDirection::Tx => {
+                    let record_index = *record_index;
+                    return Err(self.diverge(Divergence::ReadBeforeSend {
+                        record_index,
+                        packet_type,
+                    }));
+                }
+                PacketDirection::Rx if record.packet.packet_type == packet_type => {
+                    position = Some(i);
+                    break;
+                }
+                PacketDirection::Rx => (),
+            }
+        }
+        match position.and_then(|position| self.records.remove(position)) {
+            Some((_, record)) => Ok(record.packet.buf),
+            None => Err(self.diverge(Divergence::EndOfRecording { packet_type })),
+        }
+    }
+}
+impl Adapter for ReplayAdapter {
+    fn write_command<'s, 'p: 's>(
+        &'s mut self,
+        packet: CommandPacket<&'p [u8]>,
+    ) -> LocalBoxFuture<'s, Result<(), adapter::Error>> {
+        let raw = packet.to_raw_packet::<Box<[u8]>>();
+        Box::pin(async move { self.expect_sent(raw.as_ref()) })
+    }
+
+    fn read_event<'s, 'p: 's, S: Storage<u8> + 'p>(
+        &'s mut self,
+    ) -> LocalBoxFuture<'s, Result<EventPacket<S>, adapter::Error>> {
+        Box::pin(async move {
+            let buf = self.next_received(PacketType::Event)?;
+            let event = EventPacket::try_from(RawPacket {
+                packet_type: PacketType::Event,
+                buf: buf.as_ref(),
+            })
+            .map_err(StreamError::EventError)?;
+            Ok(event.to_new_storage())
+        })
+    }
+
+    fn write_acl<'s, 'p: 's>(
+        &'s mut self,
+        packet: ACLPacket<&'p [u8]>,
+    ) -> LocalBoxFuture<'s, Result<(), adapter::Error>> {
+        let raw = packet.to_raw_packet::<Box<[u8]>>();
+        Box::pin(async move {
+            let raw = raw.map_err(StreamError::DataError)?;
+            self.expect_sent(raw.as_ref())
+        })
+    }
+
+    fn read_acl<'s, 'p: 's, S: Storage<u8> + 'p>(
+        &'s mut self,
+    ) -> LocalBoxFuture<'s, Result<ACLPacket<S>, adapter::Error>> {
+        Box::pin(async move {
+            let buf = self.next_received(PacketType::ACLData)?;
+            let acl = ACLPacket::unpack_from(buf.as_ref()).map_err(StreamError::DataError)?;
+            Ok(acl.to_new_storage())
+        })
+    }
+
+    fn write_iso<'s, 'p: 's>(
+        &'s mut self,
+        packet: ISOPacket<&'p [u8]>,
+    ) -> LocalBoxFuture<'s, Result<(), adapter::Error>> {
+        let raw = packet.to_raw_packet::<Box<[u8]>>();
+        Box::pin(async move {
+            let raw = raw.map_err(StreamError::DataError)?;
+            self.expect_sent(raw.as_ref())
+        })
+    }
+
+    fn read_iso<'s, 'p: 's, S: Storage<u8> + 'p>(
+        &'s mut self,
+    ) -> LocalBoxFuture<'s, Result<ISOPacket<S>, adapter::Error>> {
+        Box::pin(async move {
+            let buf = self.next_received(PacketType::ISOData)?;
+            let iso = ISOPacket::unpack_from(buf.as_ref()).map_err(StreamError::DataError)?;
+            Ok(iso.to_new_storage())
+        })
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hci::adapters::le::LEAdapter;
+    use crate::hci::adapters::{Adapter as HCIAdapter, DummyUnrecognizedEventHandler};
+    use crate::hci::btsnoop::BTSnoopWriter;
+    use core::time::Duration;
+
+    /// LE Set Advertising Enable (enabled).
+    const ADVERTISING_ON: [u8; 4] = [0x0A, 0x20, 0x01, 0x01];
+    /// LE Set Advertising Enable (disabled).
+    const ADVERTISING_OFF: [u8; 4] = [0x0A, 0x20, 0x01, 0x00];
+    /// Command Complete for LE Set Advertising Enable, Success.
+    const ADVERTISING_COMPLETE: [u8; 6] = [0x0E, 0x04, 0x01, 0x0A, 0x20, 0x00];
+
+    fn capture(packets: &[(PacketDirection, PacketType, &[u8])]) -> ReplayAdapter {
+        let mut writer = BTSnoopWriter::new(Vec::new()).unwrap();
+        for (i, &(direction, packet_type, buf)) in packets.iter().enumerate() {
+            writer
+                .write_packet(
+                    direction,
+                    RawPacket { packet_type, buf },
+                    Duration::from_millis(i as u64),
+                )
+                .unwrap();
+        }
+        let capture = writer.into_inner();
+        ReplayAdapter::from_btsnoop(BTSnoopReader::new(&capture[..]).unwrap()).unwrap()
+    }
+    fn le(
+        replay: ReplayAdapter,
+    ) -> LEAdapter<ReplayAdapter, DummyUnrecognizedEventHandler<Box<[u8]>>> {
+        HCIAdapter::new(replay).le()
+    }
+    fn block_on<F: core::future::Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+    fn command(buf: &[u8]) -> RawPacket<Box<[u8]>> {
+        RawPacket {
+            packet_type: PacketType::Command,
+            buf: buf.into(),
+        }
+    }
+    #[test]
+    fn replays_matching_host() {
+        // LE Rand returning 1 to 8.
+        let rand_complete = [0x0E, 0x0C, 0x01, 0x18, 0x20, 0x00, 1, 2, 3, 4, 5, 6, 7, 8];
+        let mut adapter = le(capture(&[
+            (PacketDirection::Tx, PacketType::Command, &ADVERTISING_ON),
+            (
+                PacketDirection::Rx,
+                PacketType::Event,
+                &ADVERTISING_COMPLETE,
+            ),
+            (
+                PacketDirection::Tx,
+                PacketType::Command,
+                &[0x18, 0x20, 0x00],
+            ),
+            (PacketDirection::Rx, PacketType::Event, &rand_complete),
+        ]));
+        block_on(async {
+            adapter.set_advertising_enable(true).await.unwrap();
+            assert_eq!(adapter.get_rand().await.unwrap(), [1, 2, 3, 4, 5, 6, 7, 8]);
+        });
+        assert!(adapter.adapter.adapter.is_finished());
+        assert_eq!(adapter.adapter.adapter.divergence(), None);
+    }
+    #[test]
+    fn unexpected_packet() {
+        let mut adapter = le(capture(&[
+            (PacketDirection::Tx, PacketType::Command, &ADVERTISING_ON),
+            (
+                PacketDirection::Rx,
+                PacketType::Event,
+                &ADVERTISING_COMPLETE,
+            ),
+        ]));
+        let invalid_data = Err(adapter::Error::IOError(IOError::InvalidData));
+        block_on(async {
+            assert_eq!(adapter.set_advertising_enable(false).await, invalid_data);
+            // Every call after the divergence fails.
+            assert_eq!(adapter.set_advertising_enable(true).await, invalid_data);
+        });
+        let replay = &adapter.adapter.adapter;
+        assert_eq!(
+            replay.divergence(),
+            Some(&Divergence::UnexpectedPacket {
+                record_index: 0,
+                expected: command(&ADVERTISING_ON),
+                got: command(&ADVERTISING_OFF),
+            })
+        );
+        assert_eq!(replay.remaining().count(), 2);
+        assert!(!replay.is_finished());
+    }
+    #[test]
+    fn extra_packet() {
+        let mut adapter = le(capture(&[
+            (PacketDirection::Tx, PacketType::Command, &ADVERTISING_ON),
+            (
+                PacketDirection::Rx,
+                PacketType::Event,
+                &ADVERTISING_COMPLETE,
+            ),
+        ]));
+        block_on(async {
+            adapter.set_advertising_enable(true).await.unwrap();
+            assert!(adapter.set_advertising_enable(false).await.is_err());
+        });
+        assert_eq!(
+            adapter.adapter.adapter.divergence(),
+            Some(&Divergence::ExtraPacket {
+                got: command(&ADVERTISING_OFF),
+            })
+        );
+    }
+    #[test]
+    fn read_before_send() {
+        let mut adapter = le(capture(&[
+            (
+                PacketDirection::Rx,
+                PacketType::Event,
+                &ADVERTISING_COMPLETE,
+            ),
+            (PacketDirection::Tx, PacketType::Command, &ADVERTISING_ON),
+            (
+                PacketDirection::Rx,
+                PacketType::Event,
+                &ADVERTISING_COMPLETE,
+            ),
+        ]));
+        block_on(async {
+            adapter.adapter.hci_read_event::<Box<[u8]>>().await.unwrap();
+            assert!(adapter.adapter.hci_read_event::<Box<[u8]>>().await.is_err());
+        });
+        assert_eq!(
+            adapter.adapter.adapter.divergence(),
+            Some(&Divergence::ReadBeforeSend {
+                record_index: 1,
+                packet_type: PacketType::Event,
+            })
+        );
+    }
+    #[test]
+    fn end_of_recording() {
+        let mut adapter = le(capture(&[(
+            PacketDirection::Tx,
+            PacketType::Command,
+            &ADVERTISING_ON,
+        )]));
+        block_on(async {
+            assert!(adapter.set_advertising_enable(true).await.is_err());
+        });
+        assert_eq!(
+            adapter.adapter.adapter.divergence(),
+            Some(&Divergence::EndOfRecording {
+                packet_type: PacketType::Event,
+            })
+        );
+    }
+}
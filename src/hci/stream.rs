@@ -2,11 +2,14 @@
 //! commands.
 use crate::bytes::Storage;
 use crate::error;
+use crate::hci::acl::ACLPacket;
 use crate::hci::command::CommandPacket;
-use crate::hci::event::{EventCode, EventPacket, StaticHCIBuffer, MAX_HCI_PACKET_SIZE};
+use crate::hci::event::{EventCode, EventPacket, StaticHCIBuffer};
+use crate::hci::iso::{ISOHeader, ISOPacket};
 use crate::hci::packet::{PacketType, RawPacket};
 use crate::hci::{adapter, Opcode, StreamError};
 use crate::PackError;
+use alloc::collections::VecDeque;
 use core::convert::{TryFrom, TryInto};
 use core::ops::Deref;
 use core::ops::DerefMut;
@@ -143,15 +146,34 @@ pub trait HCIReader: Unpin {
     ) -> Poll<Result<usize, adapter::Error>>;
 }
 /// HCI Stream. Wraps the `poll_read` and `poll_write` methods of [`HCIReader`] and [`HCIWriter`]
-/// to provide the [`Stream::read_packet`] and [`Stream::send_command`] functions.
+/// to provide the [`Stream::read_packet`] and [`Stream::send_command`] functions. Events, ACL
+/// Data and ISO Data packets share the same byte stream so packets of the other kinds are buffered
+/// until they are read. At most [`MAX_BUFFERED_DATA_PACKETS`] ACL and ISO Data packets are
+/// buffered each. Once either is full, the stream stops reading and reads fail with
+/// `StreamError::DataBufferFull` until the buffered packets are read.
 #[derive(Clone, Debug)]
 pub struct Stream<S: HCIReader, B: Deref<Target = S>> {
     pub stream: Pin<B>,
+    events: VecDeque<EventPacket<Box<[u8]>>>,
+    acl: VecDeque<ACLPacket<Box<[u8]>>>,
+    iso: VecDeque<ISOPacket<Box<[u8]>>>,
+    read_buf: Box<[u8]>,
 }
 pub const HCI_EVENT_READ_TRIES: usize = 50;
+/// Most ACL (or ISO) Data packets a [`Stream`] buffers while waiting for them to be read.
+pub const MAX_BUFFERED_DATA_PACKETS: usize = 64;
+/// Biggest packet (including the packet type byte) that can be read from the stream. ISO Data
+/// packets are the biggest.
+const READ_BUF_LEN: usize = 1 + ISOHeader::BYTE_LEN + ISOHeader::MAX_DATA_LOAD_LEN as usize;
 impl<S: HCIReader, B: Deref<Target = S> + DerefMut> Stream<S, B> {
     pub fn new(stream: Pin<B>) -> Self {
-        Self { stream }
+        Self {
+            stream,
+            events: VecDeque::new(),
+            acl: VecDeque::new(),
+            iso: VecDeque::new(),
+            read_buf: vec![0_u8; READ_BUF_LEN].into_boxed_slice(),
+        }
     }
     pub fn stream_pinned(&mut self) -> Pin<&mut S> {
         self.stream.as_mut()
@@ -169,15 +191,64 @@ impl<S: HCIReader, B: Deref<Target = S> + DerefMut> Stream<S, B> {
     pub async fn read_bytes(&mut self, buf: &mut [u8]) -> Result<usize, adapter::Error> {
         poll_fn(|cx| self.stream_pinned().poll_read(cx, buf)).await
     }
+    /// Reads the next packet from the stream and buffers it. Expects each read to return exactly
+    /// one packet (like a BlueZ HCI socket).
+    async fn read_into_buffers(&mut self) -> Result<(), adapter::Error> {
+        if self.acl.len() >= MAX_BUFFERED_DATA_PACKETS
+            || self.iso.len() >= MAX_BUFFERED_DATA_PACKETS
+        {
+            return Err(StreamError::DataBufferFull.into());
+        }
+        let stream = &mut self.stream;
+        let buf = &mut self.read_buf[..];
+        let len = poll_fn(|cx| stream.as_mut().poll_read(cx, buf)).await?;
+        let packet =
+            RawPacket::try_from(&self.read_buf[..len]).map_err(|_| StreamError::BadPacketCode)?;
+        match packet.packet_type {
+            PacketType::Event => self.events.push_back(
+                EventPacket::try_from(packet)
+                    .map_err(StreamError::EventError)?
+                    .to_new_storage(),
+            ),
+            PacketType::ACLData => self.acl.push_back(
+                ACLPacket::try_from(packet)
+                    .map_err(StreamError::DataError)?
+                    .to_new_storage(),
+            ),
+            PacketType::ISOData => self.iso.push_back(
+                ISOPacket::try_from(packet)
+                    .map_err(StreamError::DataError)?
+                    .to_new_storage(),
+            ),
+            t => return Err(StreamError::UnsupportedPacketType(t.into()).into()),
+        }
+        Ok(())
+    }
     pub async fn read_event<Buf: Storage<u8>>(
         &mut self,
     ) -> Result<EventPacket<Buf>, adapter::Error> {
-        let mut event_buf = StaticHCIBuffer::with_size(MAX_HCI_PACKET_SIZE);
-        let len = self.read_bytes(event_buf.as_mut()).await?;
-        let packet = RawPacket::try_from(&event_buf.as_ref()[..len])
-            .map_err(|_| StreamError::BadPacketCode)?;
-        let event_packet = EventPacket::try_from(packet).map_err(StreamError::EventError)?;
-        Ok(event_packet.to_new_storage())
+        loop {
+            if let Some(event) = self.events.pop_front() {
+                return Ok(event.to_new_storage());
+            }
+            self.read_into_buffers().await?;
+        }
+    }
+    pub async fn read_acl<Buf: Storage<u8>>(&mut self) -> Result<ACLPacket<Buf>, adapter::Error> {
+        loop {
+            if let Some(packet) = self.acl.pop_front() {
+                return Ok(packet.to_new_storage());
+            }
+            self.read_into_buffers().await?;
+        }
+    }
+    pub async fn read_iso<Buf: Storage<u8>>(&mut self) -> Result<ISOPacket<Buf>, adapter::Error> {
+        loop {
+            if let Some(packet) = self.iso.pop_front() {
+                return Ok(packet.to_new_storage());
+            }
+            self.read_into_buffers().await?;
+        }
     }
     pub async fn send_command_packet(
         &mut self,
@@ -189,6 +260,30 @@ impl<S: HCIReader, B: Deref<Target = S> + DerefMut> Stream<S, B> {
         let out = packet.pack_as_raw_packet::<StaticHCIBuffer>();
         self.send_exact(out.as_ref()).await
     }
+    pub async fn send_acl_packet(&mut self, packet: ACLPacket<&[u8]>) -> Result<(), adapter::Error>
+    where
+        S: HCIWriter,
+    {
+        let raw = packet
+            .to_raw_packet::<Box<[u8]>>()
+            .map_err(StreamError::CommandError)?;
+        let out = raw
+            .pack::<Box<[u8]>>()
+            .ok_or(adapter::Error::BadParameter)?;
+        self.send_exact(out.as_ref()).await
+    }
+    pub async fn send_iso_packet(&mut self, packet: ISOPacket<&[u8]>) -> Result<(), adapter::Error>
+    where
+        S: HCIWriter,
+    {
+        let raw = packet
+            .to_raw_packet::<Box<[u8]>>()
+            .map_err(StreamError::CommandError)?;
+        let out = raw
+            .pack::<Box<[u8]>>()
+            .ok_or(adapter::Error::BadParameter)?;
+        self.send_exact(out.as_ref()).await
+    }
 }
 impl<S: HCIWriter + HCIReader, B: Deref<Target = S> + DerefMut> adapter::Adapter for Stream<S, B> {
     fn write_command<'s, 'p: 's>(
@@ -203,4 +298,98 @@ impl<S: HCIWriter + HCIReader, B: Deref<Target = S> + DerefMut> adapter::Adapter
     ) -> LocalBoxFuture<'s, Result<EventPacket<Buf>, adapter::Error>> {
         Box::pin(self.read_event())
     }
+
+    fn write_acl<'s, 'p: 's>(
+        &'s mut self,
+        packet: ACLPacket<&'p [u8]>,
+    ) -> LocalBoxFuture<'s, Result<(), adapter::Error>> {
+        Box::pin(self.send_acl_packet(packet))
+    }
+
+    fn read_acl<'s, 'p: 's, Buf: Storage<u8> + 'p>(
+        &'s mut self,
+    ) -> LocalBoxFuture<'s, Result<ACLPacket<Buf>, adapter::Error>> {
+        Box::pin(self.read_acl())
+    }
+
+    fn write_iso<'s, 'p: 's>(
+        &'s mut self,
+        packet: ISOPacket<&'p [u8]>,
+    ) -> LocalBoxFuture<'s, Result<(), adapter::Error>> {
+        Box::pin(self.send_iso_packet(packet))
+    }
+
+    fn read_iso<'s, 'p: 's, Buf: Storage<u8> + 'p>(
+        &'s mut self,
+    ) -> LocalBoxFuture<'s, Result<ISOPacket<Buf>, adapter::Error>> {
+        Box::pin(self.read_iso())
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    /// Reader returning one queued packet per read.
+    struct Packets(VecDeque<Vec<u8>>);
+    impl HCIReader for Packets {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            buf: &mut [u8],
+        ) -> Poll<Result<usize, adapter::Error>> {
+            let packet = self.0.pop_front().ok_or(adapter::Error::ChannelClosed)?;
+            buf[..packet.len()].copy_from_slice(&packet);
+            Poll::Ready(Ok(packet.len()))
+        }
+    }
+
+    #[test]
+    fn stops_reading_when_data_buffer_is_full() {
+        let mut packets: VecDeque<Vec<u8>> = (0..=MAX_BUFFERED_DATA_PACKETS)
+            .map(|i| vec![0x02, 0x01, 0x00, 0x01, 0x00, i as u8])
+            .collect();
+        // Command Complete for `Opcode::nop()`.
+        packets.push_back(vec![0x04, 0x0E, 0x03, 0x01, 0x00, 0x00]);
+        let mut stream = Stream::new(Box::pin(Packets(packets)));
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            assert_eq!(
+                stream.read_event::<Box<[u8]>>().await.err(),
+                Some(adapter::Error::StreamError(StreamError::DataBufferFull))
+            );
+            // Nothing was dropped.
+            for i in 0..=MAX_BUFFERED_DATA_PACKETS {
+                let acl = stream.read_acl::<Box<[u8]>>().await.unwrap();
+                assert_eq!(acl.data.as_ref(), &[i as u8]);
+            }
+            let event = stream.read_event::<Box<[u8]>>().await.unwrap();
+            assert_eq!(event.event_code, EventCode::CommandComplete);
+        });
+    }
+    #[test]
+    fn malformed_data_packets_are_data_errors() {
+        let packets = vec![
+            // ACL length says 2 bytes but only 1 follows.
+            vec![0x02, 0x01, 0x00, 0x02, 0x00, 0xAB],
+            // ISO packet shorter than its header.
+            vec![0x05, 0x01, 0x00],
+        ];
+        let mut stream = Stream::new(Box::pin(Packets(packets.into_iter().collect())));
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            assert!(matches!(
+                stream.read_acl::<Box<[u8]>>().await,
+                Err(adapter::Error::StreamError(StreamError::DataError(_)))
+            ));
+            assert!(matches!(
+                stream.read_iso::<Box<[u8]>>().await,
+                Err(adapter::Error::StreamError(StreamError::DataError(_)))
+            ));
+        });
+    }
 }
@@ -0,0 +1,37 @@
+//! Test helpers checking packed commands and events against bytes from the Bluetooth Core Spec.
+use crate::hci::command::Command;
+use crate::hci::event::{Event, ReturnParameters};
+use crate::hci::le::MetaEvent;
+use crate::hci::Opcode;
+use core::fmt::Debug;
+
+/// `bytes` is the whole command: opcode, parameter length then the parameters.
+pub(crate) fn assert_command<C: Command + PartialEq + Debug>(command: &C, bytes: &[u8]) {
+    assert_eq!(Opcode::unpack(&bytes[..2]), Ok(C::opcode()), "opcode");
+    let mut buf = vec![0_u8; command.full_len()];
+    assert_eq!(command.pack_full(&mut buf), Ok(bytes.len()));
+    assert_eq!(&buf[..], bytes);
+    assert_eq!(&C::unpack_from(&bytes[3..]).unwrap(), command);
+}
+/// `bytes` are the Command Complete return parameters (status first).
+pub(crate) fn assert_return<R: ReturnParameters + PartialEq + Debug>(ret: &R, bytes: &[u8]) {
+    let mut buf = vec![0_u8; ret.byte_len()];
+    ret.pack_into(&mut buf).unwrap();
+    assert_eq!(&buf[..], bytes);
+    assert_eq!(&R::unpack_from(bytes).unwrap(), ret);
+}
+/// `bytes` are the event parameters.
+pub(crate) fn assert_event<E: Event + PartialEq + Debug>(event: &E, bytes: &[u8]) {
+    let mut buf = vec![0_u8; event.event_byte_len()];
+    event.event_pack_into(&mut buf).unwrap();
+    assert_eq!(&buf[..], bytes);
+    assert_eq!(&E::event_unpack_from(bytes).unwrap(), event);
+}
+/// `bytes` are the LE Meta event parameters: subevent code then the event parameters.
+pub(crate) fn assert_meta_event<E: MetaEvent + PartialEq + Debug>(event: &E, bytes: &[u8]) {
+    assert_eq!(bytes[0], u8::from(E::META_CODE), "subevent code");
+    let mut buf = vec![0_u8; event.meta_byte_len()];
+    event.meta_pack_into(&mut buf).unwrap();
+    assert_eq!(&buf[..], &bytes[1..]);
+    assert_eq!(&E::meta_unpack_from(&bytes[1..]).unwrap(), event);
+}
@@ -0,0 +1,402 @@
+//! H4 (UART) HCI transport. Every packet is sent as its [`PacketType`] byte followed by the
+//! packet. H4 has no framing or error detection so a garbled byte (line noise, a baud rate switch,
+//! a controller reset) puts the receiver out of sync. [`H4Transport`] resyncs by dropping bytes
+//! until the buffered bytes start with a plausible packet header.
+use crate::error::IOError;
+use crate::hci::adapter;
+use crate::hci::iso::ISOHeader;
+use crate::hci::packet::PacketType;
+use crate::hci::stream::{HCIReader, HCIWriter};
+use crate::hci::uart::UartControl;
+use crate::hci::MAX_ACL_SIZE;
+use crate::le::connection::ConnectionHandle;
+use core::convert::TryFrom;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// How many bytes are read from the byte stream at once.
+const READ_CHUNK_LEN: usize = 1024;
+/// Highest Event Code assigned by the Core Spec (v5.3). Codes above it (except the vendor
+/// specific code) are reserved.
+const MAX_ASSIGNED_EVENT_CODE: u8 = 0x59;
+/// Vendor specific Event Code.
+const VENDOR_EVENT_CODE: u8 = 0xFF;
+/// Any Event Code a controller can send, even if it isn't part of `EventCode`. `0x00` is never
+/// assigned and `0xFE` is reserved for Bluetooth Logo testing.
+fn is_possible_event_code(code: u8) -> bool {
+    matches!(code, 0x01..=MAX_ASSIGNED_EVENT_CODE | VENDOR_EVENT_CODE)
+}
+enum HeaderCheck {
+    /// Not enough bytes buffered to check the header.
+    Incomplete,
+    /// Can't be the start of a packet.
+    Invalid,
+    /// Plausible header. Total length of the packet (including the packet type byte).
+    Packet(usize),
+}
+/// H4 transport over any async byte stream (ex: a `SerialPort` or a `TcpStream`). Each
+/// [`HCIReader::poll_read`] returns exactly one whole packet (packet type byte first) like
+/// `hci::stream::Stream` expects.
+#[derive(Debug)]
+pub struct H4Transport<T> {
+    io: T,
+    rx: Vec<u8>,
+    max_acl_data_len: usize,
+    discarded_bytes: usize,
+}
+impl<T: AsyncRead + AsyncWrite + Unpin> H4Transport<T> {
+    pub fn new(io: T) -> H4Transport<T> {
+        H4Transport {
+            io,
+            rx: Vec::new(),
+            max_acl_data_len: MAX_ACL_SIZE,
+            discarded_bytes: 0,
+        }
+    }
+    /// Biggest ACL data length accepted while checking headers (default [`MAX_ACL_SIZE`]).
+    /// Lowering it to the controller's ACL buffer size makes resyncing stricter.
+    pub fn set_max_acl_data_len(&mut self, max_acl_data_len: usize) {
+        self.max_acl_data_len = max_acl_data_len;
+    }
+    /// Total amount of bytes dropped while resyncing.
+    pub fn discarded_bytes(&self) -> usize {
+        self.discarded_bytes
+    }
+    /// Drops any received bytes that haven't been returned as a packet yet.
+    pub fn clear_rx(&mut self) {
+        self.discarded_bytes += self.rx.len();
+        self.rx.clear();
+    }
+    pub fn get_ref(&self) -> &T {
+        &self.io
+    }
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.io
+    }
+    pub fn into_inner(self) -> T {
+        self.io
+    }
+    fn check_header(&self) -> HeaderCheck {
+        let bytes = self.rx.as_slice();
+        let packet_type = match bytes.first().map(|&b| PacketType::try_from(b)) {
+            None => return HeaderCheck::Incomplete,
+            Some(Ok(packet_type)) => packet_type,
+            Some(Err(_)) => return HeaderCheck::Invalid,
+        };
+        let header_len = match packet_type {
+            PacketType::Command | PacketType::SCOData => 3,
+            PacketType::Event => 2,
+            PacketType::ACLData | PacketType::ISOData => 4,
+            PacketType::Vendor => return HeaderCheck::Invalid,
+        };
+        if bytes.len() < 1 + header_len {
+            return HeaderCheck::Incomplete;
+        }
+        let header = &bytes[1..=header_len];
+        let handle = u16::from_le_bytes([header[0], header[1]]) & 0x0FFF;
+        let data_len = match packet_type {
+            PacketType::Command | PacketType::SCOData => usize::from(header[2]),
+            PacketType::Event => {
+                if !is_possible_event_code(header[0]) {
+                    return HeaderCheck::Invalid;
+                }
+                usize::from(header[1])
+            }
+            PacketType::ACLData => {
+                let data_len = usize::from(u16::from_le_bytes([header[2], header[3]]));
+                if ConnectionHandle::new_checked(handle).is_none()
+                    || data_len > self.max_acl_data_len
+                {
+                    return HeaderCheck::Invalid;
+                }
+                data_len
+            }
+            PacketType::ISOData => {
+                let data_len = u16::from_le_bytes([header[2], header[3]]);
+                if ConnectionHandle::new_checked(handle).is_none()
+                    || data_len > ISOHeader::MAX_DATA_LOAD_LEN
+                {
+                    return HeaderCheck::Invalid;
+                }
+                usize::from(data_len)
+            }
+            PacketType::Vendor => unreachable!("vendor packets are rejected above"),
+        };
+        HeaderCheck::Packet(1 + header_len + data_len)
+    }
+    /// Resyncs and returns the length of the first buffered packet if it is complete.
+    fn buffered_packet_len(&mut self) -> Option<usize> {
+        loop {
+            match self.check_header() {
+                HeaderCheck::Incomplete => return None,
+                HeaderCheck::Invalid => {
+                    self.rx.remove(0);
+                    self.discarded_bytes += 1;
+                }
+                HeaderCheck::Packet(len) if len <= self.rx.len() => return Some(len),
+                HeaderCheck::Packet(_) => return None,
+            }
+        }
+    }
+    fn poll_fill(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), IOError>> {
+        let start = self.rx.len();
+        self.rx.resize(start + READ_CHUNK_LEN, 0);
+        let mut read_buf = ReadBuf::new(&mut self.rx[start..]);
+        let result = Pin::new(&mut self.io).poll_read(cx, &mut read_buf);
+        let filled = read_buf.filled().len();
+        self.rx.truncate(start + filled);
+        match result {
+            Poll::Ready(Ok(())) if filled == 0 => Poll::Ready(Err(IOError::Closed)),
+            Poll::Ready(Ok(())) => Poll::Ready(Ok(())),
+            Poll::Ready(Err(e)) => Poll::Ready(Err(e.into())),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+impl<T: UartControl + AsyncRead + AsyncWrite + Unpin> H4Transport<T> {
+    /// Changes the baud rate of the UART. Send the (vendor specific) command that switches the
+    /// controller first. Anything garbled during the switch is dropped by the resync. Blocks
+    /// the thread like [`UartControl::set_baud_rate`].
+    pub fn set_baud_rate(&mut self, baud_rate: u32) -> Result<(), IOError> {
+        self.io.set_baud_rate(baud_rate)
+    }
+}
+impl<T: AsyncRead + AsyncWrite + Unpin> HCIReader for H4Transport<T> {
+    /// Returns one whole packet. Errors with `IOError::Overflow` (and drops the packet) if it
+    /// doesn't fit in `buf`.
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<Result<usize, adapter::Error>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(len) = this.buffered_packet_len() {
+                let packet = this.rx.drain(..len);
+                if len > buf.len() {
+                    this.discarded_bytes += len;
+                    return Poll::Ready(Err(adapter::Error::IOError(IOError::Overflow)));
+                }
+                for (out, b) in buf.iter_mut().zip(packet) {
+                    *out = b;
+                }
+                return Poll::Ready(Ok(len));
+            }
+            match this.poll_fill(cx) {
+                Poll::Ready(Ok(())) => (),
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(adapter::Error::IOError(e))),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+impl<T: AsyncRead + AsyncWrite + Unpin> HCIWriter for H4Transport<T> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, adapter::Error>> {
+        Pin::new(&mut self.io)
+            .poll_write(cx, buf)
+            .map_err(|e| adapter::Error::IOError(e.into()))
+    }
+
+    fn poll_flush(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), adapter::Error>> {
+        Pin::new(&mut self.io)
+            .poll_flush(cx)
+            .map_err(|e| adapter::Error::IOError(e.into()))
+    }
+}
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::hci::event::EventCode;
+    use crate::hci::stream::Stream;
+    use crate::hci::uart::SerialPort;
+    use futures_util::future::poll_fn;
+    use std::collections::VecDeque;
+    use std::fs::File;
+    use std::io::{Read, Write};
+    use std::os::unix::io::FromRawFd;
+
+    /// Byte stream returning one chunk per read then EOF. Writes are dropped.
+    struct Chunks(VecDeque<Vec<u8>>);
+    impl AsyncRead for Chunks {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<std::io::Result<()>> {
+            if let Some(chunk) = self.0.pop_front() {
+                buf.put_slice(&chunk);
+            }
+            Poll::Ready(Ok(()))
+        }
+    }
+    impl AsyncWrite for Chunks {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<std::io::Result<usize>> {
+            Poll::Ready(Ok(buf.len()))
+        }
+        fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+        fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+    fn h4_over(chunks: &[&[u8]]) -> H4Transport<Chunks> {
+        H4Transport::new(Chunks(chunks.iter().map(|c| c.to_vec()).collect()))
+    }
+    /// Reads the next packet into a `len` byte buffer.
+    fn read_packet(
+        transport: &mut H4Transport<Chunks>,
+        len: usize,
+    ) -> Result<Vec<u8>, adapter::Error> {
+        let mut buf = vec![0_u8; len];
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let read = runtime.block_on(poll_fn(|cx| {
+            Pin::new(&mut *transport).poll_read(cx, &mut buf)
+        }))?;
+        buf.truncate(read);
+        Ok(buf)
+    }
+
+    fn open_pty() -> (File, File) {
+        let mut master = -1;
+        let mut slave = -1;
+        let r = unsafe {
+            libc::openpty(
+                &mut master,
+                &mut slave,
+                core::ptr::null_mut(),
+                core::ptr::null(),
+                core::ptr::null(),
+            )
+        };
+        assert_eq!(r, 0, "openpty failed");
+        unsafe { (File::from_raw_fd(master), File::from_raw_fd(slave)) }
+    }
+    #[test]
+    fn resyncs_over_pty() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_io()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let (mut master, slave) = open_pty();
+            let mut port = SerialPort::from_file(slave).unwrap();
+            port.configure(115_200, false).unwrap();
+            let mut stream = Stream::new(Box::pin(H4Transport::new(port)));
+
+            // Garbage (the 0x04 is followed by an invalid event code) then an HCI_Reset Command
+            // Complete event and an ACL packet.
+            master
+                .write_all(&[
+                    0xAA, 0x55, 0x04, 0x00, 0xFE, 0x04, 0x0E, 0x04, 0x01, 0x03, 0x0C, 0x00, 0x02,
+                    0x40, 0x20, 0x03, 0x00, 0x01, 0x02, 0x03,
+                ])
+                .unwrap();
+            let event = stream.read_event::<Box<[u8]>>().await.unwrap();
+            assert_eq!(event.event_code(), EventCode::CommandComplete);
+            assert_eq!(event.parameters(), &[0x01, 0x03, 0x0C, 0x00][..]);
+            let acl = stream.read_acl::<Box<[u8]>>().await.unwrap();
+            assert_eq!(acl.data.as_ref(), &[0x01, 0x02, 0x03][..]);
+            let transport = stream.stream.as_mut().get_mut();
+            assert_eq!(transport.discarded_bytes(), 5);
+
+            transport.set_baud_rate(921_600).unwrap();
+            assert_eq!(transport.get_ref().baud_rate().unwrap(), Some(921_600));
+            assert_eq!(transport.set_baud_rate(1234), Err(IOError::InvalidArgument));
+
+            // HCI_Reset
+            stream.send_exact(&[0x01, 0x03, 0x0C, 0x00]).await.unwrap();
+            let mut sent = [0_u8; 4];
+            master.read_exact(&mut sent).unwrap();
+            assert_eq!(sent, [0x01, 0x03, 0x0C, 0x00]);
+        });
+    }
+    #[test]
+    fn waits_for_truncated_headers() {
+        // HCI_Reset Command Complete and an ACL packet split inside their headers.
+        let mut transport = h4_over(&[
+            &[0x04],
+            &[0x0E],
+            &[0x04, 0x01, 0x03],
+            &[0x0C, 0x00, 0x02, 0x40, 0x20],
+            &[0x01],
+            &[0x00, 0xAB],
+        ]);
+        assert_eq!(
+            read_packet(&mut transport, 64),
+            Ok(vec![0x04, 0x0E, 0x04, 0x01, 0x03, 0x0C, 0x00])
+        );
+        assert_eq!(
+            read_packet(&mut transport, 64),
+            Ok(vec![0x02, 0x40, 0x20, 0x01, 0x00, 0xAB])
+        );
+        assert_eq!(transport.discarded_bytes(), 0);
+        // Closed in the middle of a header.
+        let mut transport = h4_over(&[&[0x02, 0x40]]);
+        assert_eq!(
+            read_packet(&mut transport, 64),
+            Err(adapter::Error::IOError(IOError::Closed))
+        );
+    }
+    #[test]
+    fn accepts_events_missing_from_event_code() {
+        // Hardware Error, Authenticated Payload Timeout Expired and a vendor event.
+        let packets: [&[u8]; 3] = [
+            &[0x04, 0x10, 0x01, 0x00],
+            &[0x04, 0x57, 0x02, 0x40, 0x00],
+            &[0x04, 0xFF, 0x01, 0xAA],
+        ];
+        let mut transport = h4_over(&packets);
+        for packet in packets.iter() {
+            assert_eq!(read_packet(&mut transport, 64).as_deref(), Ok(*packet));
+        }
+        // `0x00` and `0xFE` are never sent.
+        let mut transport = h4_over(&[&[0x04, 0x00, 0x04, 0xFE, 0x04, 0x10, 0x01, 0x00]]);
+        assert_eq!(
+            read_packet(&mut transport, 64),
+            Ok(vec![0x04, 0x10, 0x01, 0x00])
+        );
+        assert_eq!(transport.discarded_bytes(), 4);
+    }
+    #[test]
+    fn drops_oversized_acl_headers() {
+        // ACL data longer than the controller's buffer then a Hardware Error event.
+        let mut transport = h4_over(&[&[0x02, 0x40, 0x00, 0x1C, 0x00, 0x04, 0x10, 0x01, 0x00]]);
+        transport.set_max_acl_data_len(27);
+        assert_eq!(
+            read_packet(&mut transport, 64),
+            Ok(vec![0x04, 0x10, 0x01, 0x00])
+        );
+        assert_eq!(transport.discarded_bytes(), 5);
+    }
+    #[test]
+    fn drops_packets_bigger_than_the_buffer() {
+        let mut transport = h4_over(&[
+            &[0x02, 0x40, 0x00, 0x04, 0x00, 0x01, 0x02, 0x03, 0x04],
+            &[0x04, 0x10, 0x01, 0x00],
+        ]);
+        assert_eq!(
+            read_packet(&mut transport, 8),
+            Err(adapter::Error::IOError(IOError::Overflow))
+        );
+        assert_eq!(transport.discarded_bytes(), 9);
+        assert_eq!(
+            read_packet(&mut transport, 8),
+            Ok(vec![0x04, 0x10, 0x01, 0x00])
+        );
+    }
+}
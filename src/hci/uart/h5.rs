@@ -0,0 +1,1071 @@
+//! H5 (Three-wire UART) HCI transport. Bluetooth Core v5.2 Vol 4 Part D.
+//!
+//! Packets are SLIP framed, have a checksummed 4 byte header and optionally a CRC. Commands, ACL,
+//! ISO and Event packets are sent reliably (sequence numbers, acknowledgements and retransmission
+//! with a sliding window). The link is set up with a SYNC/CONFIG handshake before any HCI packet
+//! is sent. OOF software flow control isn't supported.
+use crate::error::IOError;
+use crate::hci::adapter;
+use crate::hci::packet::PacketType;
+use crate::hci::stream::{HCIReader, HCIWriter};
+use crate::hci::uart::UartControl;
+use crate::hci::StreamError;
+use crate::{ConversionError, PackError};
+use alloc::collections::VecDeque;
+use core::convert::TryFrom;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use core::time::Duration;
+use futures_util::future::poll_fn;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::time::{Instant, Sleep};
+
+pub const HEADER_LEN: usize = 4;
+pub const CRC_LEN: usize = 2;
+pub const MAX_PAYLOAD_LEN: usize = 0x0FFF;
+pub const MAX_WINDOW_SIZE: u8 = 7;
+/// Biggest unescaped frame (header + payload + CRC).
+const MAX_FRAME_LEN: usize = HEADER_LEN + MAX_PAYLOAD_LEN + CRC_LEN;
+const READ_CHUNK_LEN: usize = 1024;
+const SEQ_MODULO: u8 = 8;
+const SLIP_DELIMITER: u8 = 0xC0;
+const SLIP_ESCAPE: u8 = 0xDB;
+const SLIP_ESCAPED_DELIMITER: u8 = 0xDC;
+const SLIP_ESCAPED_ESCAPE: u8 = 0xDD;
+
+/// SLIP encodes `frame` (adding the delimiters) into `out`.
+pub fn slip_encode(frame: &[u8], out: &mut Vec<u8>) {
+    out.push(SLIP_DELIMITER);
+    for &b in frame {
+        match b {
+            SLIP_DELIMITER => out.extend_from_slice(&[SLIP_ESCAPE, SLIP_ESCAPED_DELIMITER]),
+            SLIP_ESCAPE => out.extend_from_slice(&[SLIP_ESCAPE, SLIP_ESCAPED_ESCAPE]),
+            b => out.push(b),
+        }
+    }
+    out.push(SLIP_DELIMITER);
+}
+/// 16-bit CCITT CRC (x^16 + x^12 + x^5 + 1, initial value 0xFFFF) used for the Data Integrity
+/// Check. Bytes are processed LSB first and the result is bit reversed so it can be sent MSB first.
+pub fn crc16(bytes: &[u8]) -> u16 {
+    let mut crc = 0xFFFF_u16;
+    for &b in bytes {
+        crc ^= u16::from(b);
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0x8408
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    crc.reverse_bits()
+}
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+#[repr(u8)]
+pub enum H5PacketType {
+    Ack = 0,
+    Command = 1,
+    ACLData = 2,
+    SCOData = 3,
+    Event = 4,
+    ISOData = 5,
+    Vendor = 14,
+    LinkControl = 15,
+}
+impl H5PacketType {
+    /// Returns `true` if packets of this type are sent reliably.
+    pub fn is_reliable(self) -> bool {
+        match self {
+            H5PacketType::Command
+            | H5PacketType::ACLData
+            | H5PacketType::Event
+            | H5PacketType::ISOData
+            | H5PacketType::Vendor => true,
+            H5PacketType::Ack | H5PacketType::SCOData | H5PacketType::LinkControl => false,
+        }
+    }
+    /// HCI [`PacketType`] carried by this H5 packet type (if any).
+    pub fn packet_type(self) -> Option<PacketType> {
+        match self {
+            H5PacketType::Command => Some(PacketType::Command),
+            H5PacketType::ACLData => Some(PacketType::ACLData),
+            H5PacketType::SCOData => Some(PacketType::SCOData),
+            H5PacketType::Event => Some(PacketType::Event),
+            H5PacketType::ISOData => Some(PacketType::ISOData),
+            H5PacketType::Vendor => Some(PacketType::Vendor),
+            H5PacketType::Ack | H5PacketType::LinkControl => None,
+        }
+    }
+}
+impl From<PacketType> for H5PacketType {
+    fn from(packet_type: PacketType) -> Self {
+        match packet_type {
+            PacketType::Command => H5PacketType::Command,
+            PacketType::ACLData => H5PacketType::ACLData,
+            PacketType::SCOData => H5PacketType::SCOData,
+            PacketType::Event => H5PacketType::Event,
+            PacketType::ISOData => H5PacketType::ISOData,
+            PacketType::Vendor => H5PacketType::Vendor,
+        }
+    }
+}
+impl From<H5PacketType> for u8 {
+    fn from(packet_type: H5PacketType) -> Self {
+        packet_type as u8
+    }
+}
+impl TryFrom<u8> for H5PacketType {
+    type Error = ConversionError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(H5PacketType::Ack),
+            1 => Ok(H5PacketType::Command),
+            2 => Ok(H5PacketType::ACLData),
+            3 => Ok(H5PacketType::SCOData),
+            4 => Ok(H5PacketType::Event),
+            5 => Ok(H5PacketType::ISOData),
+            14 => Ok(H5PacketType::Vendor),
+            15 => Ok(H5PacketType::LinkControl),
+            _ => Err(ConversionError(())),
+        }
+    }
+}
+/// H5 packet header. `seq` and `ack` are 3 bit sequence numbers. `ack` is the next `seq` the
+/// sender expects to receive.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct H5Header {
+    pub seq: u8,
+    pub ack: u8,
+    /// Data Integrity Check (CRC) follows the payload.
+    pub crc: bool,
+    pub reliable: bool,
+    pub packet_type: H5PacketType,
+    pub payload_len: u16,
+}
+impl H5Header {
+    pub fn pack(&self) -> [u8; HEADER_LEN] {
+        let mut out = [0_u8; HEADER_LEN];
+        out[0] = (self.seq & 0x07)
+            | ((self.ack & 0x07) << 3)
+            | (u8::from(self.crc) << 6)
+            | (u8::from(self.reliable) << 7);
+        out[1] = u8::from(self.packet_type) | ((self.payload_len & 0x0F) as u8) << 4;
+        out[2] = (self.payload_len >> 4) as u8;
+        out[3] = !out[0].wrapping_add(out[1]).wrapping_add(out[2]);
+        out
+    }
+    /// Unpacks the first [`HEADER_LEN`] bytes of `buf` and checks the header checksum.
+    pub fn unpack(buf: &[u8]) -> Result<H5Header, PackError> {
+        PackError::atleast_length(HEADER_LEN, buf)?;
+        if buf[0]
+            .wrapping_add(buf[1])
+            .wrapping_add(buf[2])
+            .wrapping_add(buf[3])
+            != 0xFF
+        {
+            return Err(PackError::bad_index(3));
+        }
+        Ok(H5Header {
+            seq: buf[0] & 0x07,
+            ack: (buf[0] >> 3) & 0x07,
+            crc: buf[0] & (1 << 6) != 0,
+            reliable: buf[0] & (1 << 7) != 0,
+            packet_type: H5PacketType::try_from(buf[1] & 0x0F)
+                .map_err(|_| PackError::bad_index(1))?,
+            payload_len: u16::from(buf[1] >> 4) | (u16::from(buf[2]) << 4),
+        })
+    }
+}
+/// Link Control messages (payloads of [`H5PacketType::LinkControl`] packets). `Config` and
+/// `ConfigResponse` carry a configuration field (see [`H5Config::config_field`]).
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub enum LinkMessage {
+    Sync,
+    SyncResponse,
+    Config(u8),
+    ConfigResponse(u8),
+    Wakeup,
+    Woken,
+    Sleep,
+}
+impl LinkMessage {
+    pub fn byte_len(self) -> usize {
+        match self {
+            LinkMessage::Config(_) | LinkMessage::ConfigResponse(_) => 3,
+            _ => 2,
+        }
+    }
+    pub fn pack_into(self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(self.byte_len(), buf)?;
+        let (code, field) = match self {
+            LinkMessage::Sync => ([0x01, 0x7E], None),
+            LinkMessage::SyncResponse => ([0x02, 0x7D], None),
+            LinkMessage::Config(field) => ([0x03, 0xFC], Some(field)),
+            LinkMessage::ConfigResponse(field) => ([0x04, 0x7B], Some(field)),
+            LinkMessage::Wakeup => ([0x05, 0xFA], None),
+            LinkMessage::Woken => ([0x06, 0xF9], None),
+            LinkMessage::Sleep => ([0x07, 0x78], None),
+        };
+        buf[..2].copy_from_slice(&code[..]);
+        if let Some(field) = field {
+            buf[2] = field;
+        }
+        Ok(())
+    }
+    pub fn unpack_from(buf: &[u8]) -> Result<LinkMessage, PackError> {
+        PackError::atleast_length(2, buf)?;
+        let field = || buf.get(2).copied().ok_or(PackError::bad_index(2));
+        let message = match (buf[0], buf[1]) {
+            (0x01, 0x7E) => LinkMessage::Sync,
+            (0x02, 0x7D) => LinkMessage::SyncResponse,
+            (0x03, 0xFC) => LinkMessage::Config(field()?),
+            (0x04, 0x7B) => LinkMessage::ConfigResponse(field()?),
+            (0x05, 0xFA) => LinkMessage::Wakeup,
+            (0x06, 0xF9) => LinkMessage::Woken,
+            (0x07, 0x78) => LinkMessage::Sleep,
+            _ => return Err(PackError::bad_index(0)),
+        };
+        PackError::expect_length(message.byte_len(), buf)?;
+        Ok(message)
+    }
+}
+/// Local H5 settings. The sliding window size and CRC use are negotiated with the peer during
+/// the CONFIG handshake.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct H5Config {
+    /// Max unacknowledged reliable packets (1 to [`MAX_WINDOW_SIZE`]).
+    pub window_size: u8,
+    /// Send a Data Integrity Check (CRC) with every packet.
+    pub crc: bool,
+    /// How often SYNC/CONFIG messages are resent while setting up the link.
+    pub link_timeout: Duration,
+    /// How long to wait for an acknowledgement before resending the unacknowledged packets.
+    pub retransmit_timeout: Duration,
+}
+impl H5Config {
+    /// Configuration field sent with CONFIG and CONFIG RESPONSE.
+    pub fn config_field(&self) -> u8 {
+        (self.window_size & 0x07) | (u8::from(self.crc) << 4)
+    }
+}
+impl Default for H5Config {
+    fn default() -> Self {
+        H5Config {
+            window_size: 4,
+            crc: true,
+            link_timeout: Duration::from_millis(250),
+            retransmit_timeout: Duration::from_millis(250),
+        }
+    }
+}
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub enum LinkState {
+    /// Sending SYNC.
+    Uninitialized,
+    /// Got SYNC RESPONSE, sending CONFIG.
+    Initialized,
+    /// Got CONFIG RESPONSE, HCI packets can be sent.
+    Active,
+}
+/// Length of the H4 formatted packet (packet type byte first) at the start of `bytes` once enough
+/// of its header is buffered.
+fn h4_packet_len(bytes: &[u8]) -> Result<Option<usize>, adapter::Error> {
+    let packet_type = match bytes.first() {
+        Some(&b) => PacketType::try_from(b).map_err(|_| StreamError::BadPacketCode)?,
+        None => return Ok(None),
+    };
+    let header_len = match packet_type {
+        PacketType::Command | PacketType::SCOData => 3,
+        PacketType::Event => 2,
+        PacketType::ACLData | PacketType::ISOData => 4,
+        PacketType::Vendor => {
+            return Err(StreamError::UnsupportedPacketType(packet_type.into()).into())
+        }
+    };
+    if bytes.len() < 1 + header_len {
+        return Ok(None);
+    }
+    let data_len = match packet_type {
+        PacketType::Command | PacketType::SCOData => usize::from(bytes[3]),
+        PacketType::Event => usize::from(bytes[2]),
+        _ => usize::from(u16::from_le_bytes([bytes[3], bytes[4]]) & 0x3FFF),
+    };
+    if header_len + data_len > MAX_PAYLOAD_LEN {
+        return Err(adapter::Error::BadParameter);
+    }
+    Ok(Some(1 + header_len + data_len))
+}
+/// Polls `timer`. If it fired, resets it to `period` from now and returns `true`.
+fn poll_timer(timer: &mut Pin<Box<Sleep>>, cx: &mut Context<'_>, period: Duration) -> bool {
+    if timer.as_mut().poll(cx).is_pending() {
+        return false;
+    }
+    timer.as_mut().reset(Instant::now() + period);
+    // Registers the waker for the new deadline.
+    let _ = timer.as_mut().poll(cx);
+    true
+}
+/// H5 transport over any async byte stream (ex: a `SerialPort`). Must be used inside a
+/// tokio runtime with time enabled.
+///
+/// All the link work (handshake, acknowledgements, retransmissions) is done while the transport
+/// is polled through [`HCIReader`]/[`HCIWriter`] or [`H5Transport::establish`]. Each
+/// [`HCIReader::poll_read`] returns exactly one HCI packet (packet type byte first) and written
+/// H4 formatted packets are queued until the link is active and the window has room.
+/// [`HCIWriter::poll_flush`] waits until every written packet is acknowledged.
+#[derive(Debug)]
+pub struct H5Transport<T> {
+    io: T,
+    config: H5Config,
+    state: LinkState,
+    window_size: u8,
+    crc: bool,
+    slip: Vec<u8>,
+    escaping: bool,
+    frame_error: bool,
+    received: VecDeque<Box<[u8]>>,
+    tx_h4: Vec<u8>,
+    tx_queue: VecDeque<(H5PacketType, Box<[u8]>)>,
+    unacked: VecDeque<(u8, H5PacketType, Box<[u8]>)>,
+    tx_raw: Vec<u8>,
+    tx_seq: u8,
+    rx_ack: u8,
+    ack_pending: bool,
+    link_timer: Option<Pin<Box<Sleep>>>,
+    retransmit_timer: Option<Pin<Box<Sleep>>>,
+    peer_reset: bool,
+    dropped_frames: usize,
+    retransmitted_frames: usize,
+}
+impl<T: AsyncRead + AsyncWrite + Unpin> H5Transport<T> {
+    pub fn new(io: T) -> H5Transport<T> {
+        Self::with_config(io, H5Config::default())
+    }
+    /// # Panics
+    /// Panics if `config.window_size` isn't 1 to [`MAX_WINDOW_SIZE`].
+    pub fn with_config(io: T, config: H5Config) -> H5Transport<T> {
+        assert!(
+            config.window_size >= 1 && config.window_size <= MAX_WINDOW_SIZE,
+            "bad H5 window size"
+        );
+        H5Transport {
+            io,
+            config,
+            state: LinkState::Uninitialized,
+            window_size: 1,
+            crc: false,
+            slip: Vec::new(),
+            escaping: false,
+            frame_error: false,
+            received: VecDeque::new(),
+            tx_h4: Vec::new(),
+            tx_queue: VecDeque::new(),
+            unacked: VecDeque::new(),
+            tx_raw: Vec::new(),
+            tx_seq: 0,
+            rx_ack: 0,
+            ack_pending: false,
+            link_timer: None,
+            retransmit_timer: None,
+            peer_reset: false,
+            dropped_frames: 0,
+            retransmitted_frames: 0,
+        }
+    }
+    pub fn state(&self) -> LinkState {
+        self.state
+    }
+    /// Negotiated sliding window size and CRC use (only valid once [`LinkState::Active`]).
+    pub fn negotiated(&self) -> (u8, bool) {
+        (self.window_size, self.crc)
+    }
+    /// Frames dropped because of bad SLIP escapes, header checksums, lengths or CRCs.
+    pub fn dropped_frames(&self) -> usize {
+        self.dropped_frames
+    }
+    /// Reliable frames resent because they weren't acknowledged in time.
+    pub fn retransmitted_frames(&self) -> usize {
+        self.retransmitted_frames
+    }
+    pub fn get_ref(&self) -> &T {
+        &self.io
+    }
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.io
+    }
+    pub fn into_inner(self) -> T {
+        self.io
+    }
+    /// Drives the link until the SYNC/CONFIG handshake is done.
+    pub async fn establish(&mut self) -> Result<(), adapter::Error> {
+        poll_fn(|cx| {
+            self.poll_drive(cx)?;
+            if self.state == LinkState::Active {
+                Poll::Ready(Ok(()))
+            } else {
+                Poll::Pending
+            }
+        })
+        .await
+    }
+    fn reset_link(&mut self) {
+        self.state = LinkState::Uninitialized;
+        self.window_size = 1;
+        self.crc = false;
+        self.tx_seq = 0;
+        self.rx_ack = 0;
+        self.ack_pending = false;
+        self.tx_queue.clear();
+        self.unacked.clear();
+        self.link_timer = None;
+        self.retransmit_timer = None;
+    }
+    fn send_frame(&mut self, packet_type: H5PacketType, seq: u8, payload: &[u8]) {
+        let header = H5Header {
+            seq,
+            ack: self.rx_ack,
+            crc: self.crc,
+            reliable: packet_type.is_reliable(),
+            packet_type,
+            payload_len: payload.len() as u16,
+        };
+        let mut frame = Vec::with_capacity(HEADER_LEN + payload.len() + CRC_LEN);
+        frame.extend_from_slice(&header.pack()[..]);
+        frame.extend_from_slice(payload);
+        if self.crc {
+            let crc = crc16(&frame);
+            frame.extend_from_slice(&crc.to_be_bytes()[..]);
+        }
+        slip_encode(&frame, &mut self.tx_raw);
+        self.ack_pending = false;
+    }
+    fn send_link_message(&mut self, message: LinkMessage) {
+        let mut buf = [0_u8; 3];
+        let len = message.byte_len();
+        message
+            .pack_into(&mut buf[..len])
+            .expect("hardcoded array length");
+        self.send_frame(H5PacketType::LinkControl, 0, &buf[..len]);
+    }
+    fn handle_link_message(&mut self, message: LinkMessage) {
+        match (message, self.state) {
+            (LinkMessage::Sync, LinkState::Active) => {
+                // The peer restarted so everything in flight is lost.
+                self.reset_link();
+                self.peer_reset = true;
+                self.send_link_message(LinkMessage::SyncResponse);
+            }
+            (LinkMessage::Sync, _) => self.send_link_message(LinkMessage::SyncResponse),
+            (LinkMessage::SyncResponse, LinkState::Uninitialized) => {
+                self.state = LinkState::Initialized;
+                // Send CONFIG right away.
+                self.link_timer = None;
+            }
+            (LinkMessage::Config(_), LinkState::Initialized | LinkState::Active) => {
+                self.send_link_message(LinkMessage::ConfigResponse(self.config.config_field()));
+            }
+            (LinkMessage::ConfigResponse(field), LinkState::Initialized) => {
+                self.window_size = (field & 0x07).max(1).min(self.config.window_size);
+                self.crc = self.config.crc && field & (1 << 4) != 0;
+                self.state = LinkState::Active;
+                self.link_timer = None;
+            }
+            (LinkMessage::Wakeup, _) => self.send_link_message(LinkMessage::Woken),
+            _ => (),
+        }
+    }
+    /// Removes the packets acknowledged by `ack` from the unacknowledged queue.
+    fn process_ack(&mut self, ack: u8) {
+        let valid = ack == self.tx_seq || self.unacked.iter().any(|&(seq, _, _)| seq == ack);
+        if !valid {
+            return;
+        }
+        let before = self.unacked.len();
+        while matches!(self.unacked.front(), Some(&(seq, _, _)) if seq != ack) {
+            self.unacked.pop_front();
+        }
+        if self.unacked.is_empty() {
+            self.retransmit_timer = None;
+        } else if self.unacked.len() != before {
+            if let Some(timer) = self.retransmit_timer.as_mut() {
+                timer
+                    .as_mut()
+                    .reset(Instant::now() + self.config.retransmit_timeout);
+            }
+        }
+    }
+    fn handle_frame(&mut self, frame: &[u8]) {
+        let header = match H5Header::unpack(frame) {
+            Ok(header) => header,
+            Err(_) => {
+                self.dropped_frames += 1;
+                return;
+            }
+        };
+        let payload_end = HEADER_LEN + usize::from(header.payload_len);
+        let frame_len = payload_end + if header.crc { CRC_LEN } else { 0 };
+        if frame.len() != frame_len
+            || (header.crc
+                && crc16(&frame[..payload_end])
+                    != u16::from_be_bytes([frame[payload_end], frame[payload_end + 1]]))
+        {
+            self.dropped_frames += 1;
+            return;
+        }
+        let payload = &frame[HEADER_LEN..payload_end];
+        if header.packet_type == H5PacketType::LinkControl {
+            if let Ok(message) = LinkMessage::unpack_from(payload) {
+                self.handle_link_message(message);
+            }
+            return;
+        }
+        if self.state != LinkState::Active {
+            return;
+        }
+        self.process_ack(header.ack);
+        if header.reliable {
+            // Out of order or repeated packets are dropped but still acknowledged so the peer
+            // knows where to resend from.
+            self.ack_pending = true;
+            if header.seq != self.rx_ack {
+                return;
+            }
+            self.rx_ack = (self.rx_ack + 1) % SEQ_MODULO;
+        }
+        if let Some(packet_type) = header.packet_type.packet_type() {
+            let mut packet = Vec::with_capacity(1 + payload.len());
+            packet.push(u8::from(packet_type));
+            packet.extend_from_slice(payload);
+            self.received.push_back(packet.into_boxed_slice());
+        }
+    }
+    fn push_rx_byte(&mut self, b: u8) {
+        match b {
+            SLIP_DELIMITER => {
+                let frame = core::mem::take(&mut self.slip);
+                if self.frame_error {
+                    self.dropped_frames += 1;
+                } else if !frame.is_empty() {
+                    self.handle_frame(&frame);
+                }
+                self.frame_error = false;
+                self.escaping = false;
+            }
+            _ if self.escaping => {
+                self.escaping = false;
+                match b {
+                    SLIP_ESCAPED_DELIMITER => self.slip.push(SLIP_DELIMITER),
+                    SLIP_ESCAPED_ESCAPE => self.slip.push(SLIP_ESCAPE),
+                    _ => self.frame_error = true,
+                }
+            }
+            SLIP_ESCAPE => self.escaping = true,
+            b => self.slip.push(b),
+        }
+        if self.slip.len() > MAX_FRAME_LEN {
+            self.slip.clear();
+            self.frame_error = true;
+        }
+    }
+    fn poll_receive(&mut self, cx: &mut Context<'_>) -> Result<(), IOError> {
+        let mut chunk = [0_u8; READ_CHUNK_LEN];
+        loop {
+            let mut read_buf = ReadBuf::new(&mut chunk[..]);
+            match Pin::new(&mut self.io).poll_read(cx, &mut read_buf) {
+                Poll::Ready(Ok(())) => {
+                    let len = read_buf.filled().len();
+                    if len == 0 {
+                        return Err(IOError::Closed);
+                    }
+                    for &b in &chunk[..len] {
+                        self.push_rx_byte(b);
+                    }
+                }
+                Poll::Ready(Err(e)) => return Err(e.into()),
+                Poll::Pending => return Ok(()),
+            }
+        }
+    }
+    fn send_queued(&mut self) {
+        if self.state != LinkState::Active {
+            return;
+        }
+        while let Some((packet_type, _)) = self.tx_queue.front() {
+            let reliable = packet_type.is_reliable();
+            if reliable && self.unacked.len() >= usize::from(self.window_size) {
+                break;
+            }
+            let (packet_type, payload) = self.tx_queue.pop_front().expect("front checked above");
+            if reliable {
+                let seq = self.tx_seq;
+                self.tx_seq = (self.tx_seq + 1) % SEQ_MODULO;
+                self.send_frame(packet_type, seq, &payload);
+                self.unacked.push_back((seq, packet_type, payload));
+                if self.retransmit_timer.is_none() {
+                    self.retransmit_timer =
+                        Some(Box::pin(tokio::time::sleep(self.config.retransmit_timeout)));
+                }
+            } else {
+                self.send_frame(packet_type, 0, &payload);
+            }
+        }
+    }
+    fn poll_timers(&mut self, cx: &mut Context<'_>) {
+        if self.state == LinkState::Active {
+            let fired = match self.retransmit_timer.as_mut() {
+                Some(timer) => poll_timer(timer, cx, self.config.retransmit_timeout),
+                None => false,
+            };
+            if fired {
+                // Go-back-N: resend every unacknowledged packet.
+                let unacked = core::mem::take(&mut self.unacked);
+                for (seq, packet_type, payload) in &unacked {
+                    self.send_frame(*packet_type, *seq, payload);
+                }
+                self.retransmitted_frames += unacked.len();
+                self.unacked = unacked;
+            }
+        } else {
+            let timer = self
+                .link_timer
+                .get_or_insert_with(|| Box::pin(tokio::time::sleep(Duration::from_secs(0))));
+            if poll_timer(timer, cx, self.config.link_timeout) {
+                match self.state {
+                    LinkState::Uninitialized => self.send_link_message(LinkMessage::Sync),
+                    _ => self.send_link_message(LinkMessage::Config(self.config.config_field())),
+                }
+            }
+        }
+    }
+    fn poll_transmit(&mut self, cx: &mut Context<'_>) -> Result<(), IOError> {
+        while !self.tx_raw.is_empty() {
+            match Pin::new(&mut self.io).poll_write(cx, &self.tx_raw) {
+                Poll::Ready(Ok(0)) => return Err(IOError::Closed),
+                Poll::Ready(Ok(len)) => {
+                    self.tx_raw.drain(..len);
+                }
+                Poll::Ready(Err(e)) => return Err(e.into()),
+                Poll::Pending => return Ok(()),
+            }
+        }
+        match Pin::new(&mut self.io).poll_flush(cx) {
+            Poll::Ready(Err(e)) => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+    /// Does all the pending link work. Never blocks but registers `cx` to be woken when there is
+    /// more to do.
+    fn poll_drive(&mut self, cx: &mut Context<'_>) -> Result<(), adapter::Error> {
+        self.poll_receive(cx)?;
+        // Timers go after the receive so new deadlines from acknowledgements get registered.
+        self.poll_timers(cx);
+        self.send_queued();
+        if self.state == LinkState::Active && self.ack_pending {
+            self.send_frame(H5PacketType::Ack, 0, &[]);
+        }
+        self.poll_transmit(cx)?;
+        if core::mem::take(&mut self.peer_reset) {
+            return Err(adapter::Error::IOError(IOError::NotConnected));
+        }
+        Ok(())
+    }
+}
+impl<T: UartControl + AsyncRead + AsyncWrite + Unpin> H5Transport<T> {
+    /// Changes the baud rate of the UART (after the controller was told to switch).
+    pub fn set_baud_rate(&mut self, baud_rate: u32) -> Result<(), IOError> {
+        self.io.set_baud_rate(baud_rate)
+    }
+}
+impl<T: AsyncRead + AsyncWrite + Unpin> HCIReader for H5Transport<T> {
+    /// Returns one whole packet. Errors with `IOError::NotConnected` once if the peer restarted
+    /// (sent SYNC while the link was active). Errors with `IOError::Overflow` if the next packet
+    /// doesn't fit in `buf` but keeps it so it can be read with a bigger buffer.
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<Result<usize, adapter::Error>> {
+        let this = self.get_mut();
+        this.poll_drive(cx)?;
+        match this.received.front() {
+            Some(packet) if packet.len() > buf.len() => {
+                Poll::Ready(Err(adapter::Error::IOError(IOError::Overflow)))
+            }
+            Some(_) => {
+                let packet = this.received.pop_front().expect("front checked above");
+                buf[..packet.len()].copy_from_slice(&packet);
+                Poll::Ready(Ok(packet.len()))
+            }
+            None => Poll::Pending,
+        }
+    }
+}
+impl<T: AsyncRead + AsyncWrite + Unpin> HCIWriter for H5Transport<T> {
+    /// Takes H4 formatted packets (packet type byte first). Returns `Pending` while the send
+    /// queue is full.
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, adapter::Error>> {
+        let this = self.get_mut();
+        this.poll_drive(cx)?;
+        if this.tx_queue.len() >= usize::from(this.config.window_size) {
+            return Poll::Pending;
+        }
+        this.tx_h4.extend_from_slice(buf);
+        loop {
+            match h4_packet_len(&this.tx_h4) {
+                Ok(Some(len)) if len <= this.tx_h4.len() => {
+                    let packet: Vec<u8> = this.tx_h4.drain(..len).collect();
+                    let packet_type = PacketType::try_from(packet[0]).expect("checked by len");
+                    this.tx_queue
+                        .push_back((packet_type.into(), packet[1..].into()));
+                }
+                Ok(_) => break,
+                Err(e) => {
+                    this.tx_h4.clear();
+                    return Poll::Ready(Err(e));
+                }
+            }
+        }
+        this.poll_drive(cx)?;
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), adapter::Error>> {
+        let this = self.get_mut();
+        this.poll_drive(cx)?;
+        if this.tx_queue.is_empty() && this.unacked.is_empty() && this.tx_raw.is_empty() {
+            Poll::Ready(Ok(()))
+        } else {
+            Poll::Pending
+        }
+    }
+}
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::hci::event::EventCode;
+    use crate::hci::stream::Stream;
+    use futures_util::future::join;
+    use tokio::net::UnixStream;
+
+    /// Byte stream that corrupts the next write once `corrupt_next` is set.
+    struct Corrupting {
+        io: UnixStream,
+        corrupt_next: bool,
+    }
+    impl AsyncRead for Corrupting {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<std::io::Result<()>> {
+            Pin::new(&mut self.io).poll_read(cx, buf)
+        }
+    }
+    impl AsyncWrite for Corrupting {
+        fn poll_write(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<std::io::Result<usize>> {
+            if core::mem::take(&mut self.corrupt_next) {
+                let mut corrupted = buf.to_vec();
+                corrupted[2] ^= 0x01;
+                Pin::new(&mut self.io).poll_write(cx, &corrupted)
+            } else {
+                Pin::new(&mut self.io).poll_write(cx, buf)
+            }
+        }
+
+        fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            Pin::new(&mut self.io).poll_flush(cx)
+        }
+
+        fn poll_shutdown(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+        ) -> Poll<std::io::Result<()>> {
+            Pin::new(&mut self.io).poll_shutdown(cx)
+        }
+    }
+    #[test]
+    fn header_and_link_messages() {
+        let header = H5Header {
+            seq: 5,
+            ack: 2,
+            crc: true,
+            reliable: true,
+            packet_type: H5PacketType::ACLData,
+            payload_len: 0x123,
+        };
+        let packed = header.pack();
+        assert_eq!(
+            packed.iter().fold(0_u8, |sum, &b| sum.wrapping_add(b)),
+            0xFF
+        );
+        assert_eq!(H5Header::unpack(&packed[..]), Ok(header));
+        let mut buf = [0_u8; 3];
+        LinkMessage::Config(0x14).pack_into(&mut buf).unwrap();
+        assert_eq!(buf, [0x03, 0xFC, 0x14]);
+        assert_eq!(
+            LinkMessage::unpack_from(&buf[..]),
+            Ok(LinkMessage::Config(0x14))
+        );
+        let mut encoded = Vec::new();
+        slip_encode(&[0x01, SLIP_DELIMITER, SLIP_ESCAPE], &mut encoded);
+        assert_eq!(encoded, [0xC0, 0x01, 0xDB, 0xDC, 0xDB, 0xDD, 0xC0]);
+    }
+    #[test]
+    fn loopback_with_retransmit() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_io()
+            .enable_time()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let (a, b) = UnixStream::pair().unwrap();
+            let config = H5Config {
+                window_size: 4,
+                crc: true,
+                link_timeout: Duration::from_millis(20),
+                retransmit_timeout: Duration::from_millis(20),
+            };
+            let mut host = H5Transport::with_config(
+                Corrupting {
+                    io: a,
+                    corrupt_next: false,
+                },
+                config,
+            );
+            let mut peer = H5Transport::with_config(
+                b,
+                H5Config {
+                    window_size: 2,
+                    ..config
+                },
+            );
+            let (host_result, peer_result) = join(host.establish(), peer.establish()).await;
+            host_result.unwrap();
+            peer_result.unwrap();
+            assert_eq!(host.negotiated(), (2, true));
+            assert_eq!(peer.negotiated(), (2, true));
+
+            // The HCI_Reset command gets corrupted and has to be resent.
+            host.get_mut().corrupt_next = true;
+            let mut host = Stream::new(Box::pin(host));
+            let mut peer = Stream::new(Box::pin(peer));
+            let reset = [0x01, 0x03, 0x0C, 0x00];
+            let command_complete = [0x04, 0x0E, 0x04, 0x01, 0x03, 0x0C, 0x00];
+            let (event, command) = join(
+                async {
+                    host.send_exact(&reset[..]).await?;
+                    host.read_event::<Box<[u8]>>().await
+                },
+                async {
+                    let mut buf = [0_u8; 16];
+                    let len = peer.read_bytes(&mut buf[..]).await?;
+                    peer.send_exact(&command_complete[..]).await?;
+                    Ok::<_, adapter::Error>(buf[..len].to_vec())
+                },
+            )
+            .await;
+            assert_eq!(command.unwrap(), reset);
+            let event = event.unwrap();
+            assert_eq!(event.event_code(), EventCode::CommandComplete);
+            assert_eq!(event.parameters(), &command_complete[3..]);
+            assert!(host.stream.retransmitted_frames() >= 1);
+            assert!(peer.stream.dropped_frames() >= 1);
+        });
+    }
+    fn run<F: Future>(f: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_io()
+            .enable_time()
+            .build()
+            .unwrap()
+            .block_on(f)
+    }
+    /// Unescaped frame as the controller would send it.
+    fn peer_frame(
+        packet_type: H5PacketType,
+        seq: u8,
+        ack: u8,
+        crc: bool,
+        payload: &[u8],
+    ) -> Vec<u8> {
+        let header = H5Header {
+            seq,
+            ack,
+            crc,
+            reliable: packet_type.is_reliable(),
+            packet_type,
+            payload_len: payload.len() as u16,
+        };
+        let mut frame = header.pack().to_vec();
+        frame.extend_from_slice(payload);
+        if crc {
+            let crc = crc16(&frame);
+            frame.extend_from_slice(&crc.to_be_bytes()[..]);
+        }
+        frame
+    }
+    fn link_frame(message: LinkMessage) -> Vec<u8> {
+        let mut buf = [0_u8; 3];
+        let len = message.byte_len();
+        message.pack_into(&mut buf[..len]).unwrap();
+        peer_frame(H5PacketType::LinkControl, 0, 0, false, &buf[..len])
+    }
+    /// Transport that finished the handshake with a peer sending `field` in CONFIG RESPONSE.
+    fn active(io: UnixStream, field: u8) -> H5Transport<UnixStream> {
+        let mut h5 = H5Transport::new(io);
+        h5.handle_frame(&link_frame(LinkMessage::SyncResponse));
+        h5.handle_frame(&link_frame(LinkMessage::ConfigResponse(field)));
+        assert_eq!(h5.state(), LinkState::Active);
+        h5
+    }
+    #[test]
+    fn crc16_known_answer() {
+        // CRC-16/MCRF4XX check value (0x6F91) sent bit reversed.
+        assert_eq!(crc16(b"123456789"), 0x6F91_u16.reverse_bits());
+        assert_eq!(crc16(b"123456789"), 0x89F6);
+    }
+    #[test]
+    fn crc_mismatch_is_dropped() {
+        run(async {
+            let (a, _b) = UnixStream::pair().unwrap();
+            let mut h5 = active(a, 0x12);
+            assert_eq!(h5.negotiated(), (2, true));
+            let event = [0x0E, 0x04, 0x01, 0x03, 0x0C, 0x00];
+            let mut frame = peer_frame(H5PacketType::Event, 0, 0, true, &event[..]);
+            let last = frame.len() - 1;
+            frame[last] ^= 0x01;
+            h5.handle_frame(&frame);
+            assert_eq!(h5.dropped_frames(), 1);
+            assert!(h5.received.is_empty());
+            assert_eq!(h5.rx_ack, 0);
+
+            frame[last] ^= 0x01;
+            h5.handle_frame(&frame);
+            assert_eq!(h5.dropped_frames(), 1);
+            assert_eq!(h5.received.len(), 1);
+            assert_eq!(h5.rx_ack, 1);
+        });
+    }
+    #[test]
+    fn out_of_order_frame_is_dropped_but_acknowledged() {
+        run(async {
+            let (a, b) = UnixStream::pair().unwrap();
+            let mut h5 = active(a, 0x02);
+            let event = [0x0E, 0x04, 0x01, 0x03, 0x0C, 0x00];
+            h5.handle_frame(&peer_frame(H5PacketType::Event, 1, 0, false, &event[..]));
+            assert!(h5.received.is_empty());
+            assert_eq!(h5.rx_ack, 0);
+            assert!(h5.ack_pending);
+
+            poll_fn(|cx| {
+                h5.poll_drive(cx)?;
+                if h5.tx_raw.is_empty() {
+                    Poll::Ready(Ok::<_, adapter::Error>(()))
+                } else {
+                    Poll::Pending
+                }
+            })
+            .await
+            .unwrap();
+            let mut expected = Vec::new();
+            slip_encode(
+                &peer_frame(H5PacketType::Ack, 0, 0, false, &[]),
+                &mut expected,
+            );
+            let mut sent = [0_u8; 16];
+            b.readable().await.unwrap();
+            let len = b.try_read(&mut sent[..]).unwrap();
+            assert_eq!(&sent[..len], &expected[..]);
+
+            h5.handle_frame(&peer_frame(H5PacketType::Event, 0, 0, false, &event[..]));
+            assert_eq!(h5.received.len(), 1);
+            assert_eq!(h5.rx_ack, 1);
+        });
+    }
+    #[test]
+    fn sync_while_active_resets_link() {
+        run(async {
+            let (a, _b) = UnixStream::pair().unwrap();
+            let mut h5 = active(a, 0x12);
+            h5.handle_frame(&link_frame(LinkMessage::Sync));
+            assert_eq!(h5.state(), LinkState::Uninitialized);
+            assert_eq!(h5.negotiated(), (1, false));
+            let mut expected = Vec::new();
+            slip_encode(&link_frame(LinkMessage::SyncResponse), &mut expected);
+            assert_eq!(h5.tx_raw, expected);
+
+            let mut buf = [0_u8; 16];
+            let result = poll_fn(|cx| Poll::Ready(Pin::new(&mut h5).poll_read(cx, &mut buf[..])));
+            assert!(matches!(
+                result.await,
+                Poll::Ready(Err(adapter::Error::IOError(IOError::NotConnected)))
+            ));
+            // Only reported once.
+            poll_fn(|cx| Poll::Ready(h5.poll_drive(cx))).await.unwrap();
+        });
+    }
+    async fn write_once(
+        h5: &mut H5Transport<UnixStream>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, adapter::Error>> {
+        poll_fn(|cx| Poll::Ready(Pin::new(&mut *h5).poll_write(cx, buf))).await
+    }
+    #[test]
+    fn write_waits_for_window() {
+        run(async {
+            let (a, _b) = UnixStream::pair().unwrap();
+            let mut h5 = active(a, 0x02);
+            let reset = [0x01, 0x03, 0x0C, 0x00];
+            // 2 packets in flight and 4 (the local window size) queued.
+            for _ in 0..6 {
+                assert!(matches!(
+                    write_once(&mut h5, &reset[..]).await,
+                    Poll::Ready(Ok(4))
+                ));
+            }
+            assert!(write_once(&mut h5, &reset[..]).await.is_pending());
+            assert_eq!(h5.unacked.len(), 2);
+            assert_eq!(h5.tx_queue.len(), 4);
+
+            h5.handle_frame(&peer_frame(H5PacketType::Ack, 0, 2, false, &[]));
+            assert!(h5.unacked.is_empty());
+            assert!(matches!(
+                write_once(&mut h5, &reset[..]).await,
+                Poll::Ready(Ok(4))
+            ));
+            assert_eq!(h5.unacked.len(), 2);
+            assert_eq!(h5.tx_queue.len(), 3);
+        });
+    }
+    #[test]
+    fn overflow_keeps_packet() {
+        run(async {
+            let (a, _b) = UnixStream::pair().unwrap();
+            let mut h5 = active(a, 0x02);
+            let event = [0x0E, 0x04, 0x01, 0x03, 0x0C, 0x00];
+            h5.handle_frame(&peer_frame(H5PacketType::Event, 0, 0, false, &event[..]));
+            let mut small = [0_u8; 4];
+            let result = poll_fn(|cx| Poll::Ready(Pin::new(&mut h5).poll_read(cx, &mut small[..])));
+            assert!(matches!(
+                result.await,
+                Poll::Ready(Err(adapter::Error::IOError(IOError::Overflow)))
+            ));
+            let mut buf = [0_u8; 16];
+            let len = poll_fn(|cx| Pin::new(&mut h5).poll_read(cx, &mut buf[..]))
+                .await
+                .unwrap();
+            assert_eq!(buf[..len], [0x04, 0x0E, 0x04, 0x01, 0x03, 0x0C, 0x00]);
+        });
+    }
+}
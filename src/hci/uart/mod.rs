@@ -0,0 +1,21 @@
+//! UART HCI transports. [`h4::H4Transport`] and [`h5::H5Transport`] frame HCI packets over any
+//! async byte stream (ex: a `SerialPort` or a PTY) and plug into `hci::stream::Stream` as a
+//! `HCIReader`/`HCIWriter`.
+pub mod h4;
+pub mod h5;
+#[cfg(unix)]
+pub mod serial;
+
+use crate::error::IOError;
+#[cfg(unix)]
+pub use serial::SerialPort;
+
+/// UART line control. Implemented by `SerialPort` so transports can change the baud rate after
+/// telling the controller to (usually with a vendor command).
+pub trait UartControl {
+    /// Changes the baud rate once the pending output is sent. This is a blocking call: it can
+    /// block the thread until the output drains (indefinitely if flow control holds it back).
+    /// Use `tokio::task::block_in_place` (or move the port into `spawn_blocking`) when that
+    /// would stall other tasks on the runtime.
+    fn set_baud_rate(&mut self, baud_rate: u32) -> Result<(), IOError>;
+}
@@ -0,0 +1,156 @@
+//! Async serial TTY for the UART transports.
+use crate::error::IOError;
+use crate::hci::uart::UartControl;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use std::fs::File;
+use std::io::{Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use tokio::io::unix::AsyncFd;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+fn baud_rate_to_speed(baud_rate: u32) -> Option<libc::speed_t> {
+    Some(match baud_rate {
+        9600 => libc::B9600,
+        19200 => libc::B19200,
+        38400 => libc::B38400,
+        57600 => libc::B57600,
+        115_200 => libc::B115200,
+        230_400 => libc::B230400,
+        460_800 => libc::B460800,
+        500_000 => libc::B500000,
+        921_600 => libc::B921600,
+        1_000_000 => libc::B1000000,
+        1_500_000 => libc::B1500000,
+        2_000_000 => libc::B2000000,
+        3_000_000 => libc::B3000000,
+        4_000_000 => libc::B4000000,
+        _ => return None,
+    })
+}
+fn libc_result(r: libc::c_int) -> Result<(), IOError> {
+    if r < 0 {
+        Err(std::io::Error::last_os_error().into())
+    } else {
+        Ok(())
+    }
+}
+/// Async serial TTY (or PTY) in raw mode. Must be created inside a tokio runtime.
+#[derive(Debug)]
+pub struct SerialPort(AsyncFd<File>);
+impl SerialPort {
+    /// Opens the TTY at `path` in raw mode at `baud_rate` (8N1). `flow_control` enables RTS/CTS
+    /// hardware flow control (most UART HCI controllers need it).
+    pub fn open(path: &str, baud_rate: u32, flow_control: bool) -> Result<SerialPort, IOError> {
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_NOCTTY | libc::O_NONBLOCK)
+            .open(path)?;
+        let mut port = SerialPort::from_file(file)?;
+        port.configure(baud_rate, flow_control)?;
+        Ok(port)
+    }
+    /// Wraps an already open TTY (ex: a PTY). `file` is set to non-blocking but the line settings
+    /// aren't changed until [`SerialPort::configure`].
+    pub fn from_file(file: File) -> Result<SerialPort, IOError> {
+        let fd = file.as_raw_fd();
+        let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+        libc_result(flags)?;
+        libc_result(unsafe { libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) })?;
+        Ok(SerialPort(AsyncFd::new(file)?))
+    }
+    fn termios(&self) -> Result<libc::termios, IOError> {
+        let mut termios: libc::termios = unsafe { core::mem::zeroed() };
+        libc_result(unsafe { libc::tcgetattr(self.0.as_raw_fd(), &mut termios) })?;
+        Ok(termios)
+    }
+    fn set_termios(&mut self, termios: &libc::termios) -> Result<(), IOError> {
+        libc_result(unsafe { libc::tcsetattr(self.0.as_raw_fd(), libc::TCSANOW, termios) })
+    }
+    /// Sets raw mode (8N1), `baud_rate` and RTS/CTS flow control.
+    pub fn configure(&mut self, baud_rate: u32, flow_control: bool) -> Result<(), IOError> {
+        let speed = baud_rate_to_speed(baud_rate).ok_or(IOError::InvalidArgument)?;
+        let mut termios = self.termios()?;
+        unsafe { libc::cfmakeraw(&mut termios) };
+        termios.c_cflag |= libc::CLOCAL | libc::CREAD;
+        if flow_control {
+            termios.c_cflag |= libc::CRTSCTS;
+        } else {
+            termios.c_cflag &= !libc::CRTSCTS;
+        }
+        libc_result(unsafe { libc::cfsetspeed(&mut termios, speed) })?;
+        self.set_termios(&termios)
+    }
+    /// Returns the current baud rate if it is one of the supported rates.
+    pub fn baud_rate(&self) -> Result<Option<u32>, IOError> {
+        let speed = unsafe { libc::cfgetospeed(&self.termios()?) };
+        Ok([
+            9600, 19200, 38400, 57600, 115_200, 230_400, 460_800, 500_000, 921_600, 1_000_000,
+            1_500_000, 2_000_000, 3_000_000, 4_000_000,
+        ]
+        .iter()
+        .copied()
+        .find(|&b| baud_rate_to_speed(b) == Some(speed)))
+    }
+}
+impl UartControl for SerialPort {
+    /// Waits for the pending output to be sent (with a blocking `tcdrain`) then changes the baud
+    /// rate.
+    fn set_baud_rate(&mut self, baud_rate: u32) -> Result<(), IOError> {
+        let speed = baud_rate_to_speed(baud_rate).ok_or(IOError::InvalidArgument)?;
+        libc_result(unsafe { libc::tcdrain(self.0.as_raw_fd()) })?;
+        let mut termios = self.termios()?;
+        libc_result(unsafe { libc::cfsetspeed(&mut termios, speed) })?;
+        self.set_termios(&termios)
+    }
+}
+impl AsyncRead for SerialPort {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        loop {
+            let mut guard = match self.0.poll_read_ready(cx) {
+                Poll::Ready(guard) => guard?,
+                Poll::Pending => return Poll::Pending,
+            };
+            match guard.try_io(|inner| inner.get_ref().read(buf.initialize_unfilled())) {
+                Ok(Ok(len)) => {
+                    buf.advance(len);
+                    return Poll::Ready(Ok(()));
+                }
+                Ok(Err(e)) => return Poll::Ready(Err(e)),
+                Err(_would_block) => (),
+            }
+        }
+    }
+}
+impl AsyncWrite for SerialPort {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        loop {
+            let mut guard = match self.0.poll_write_ready(cx) {
+                Poll::Ready(guard) => guard?,
+                Poll::Pending => return Poll::Pending,
+            };
+            match guard.try_io(|inner| inner.get_ref().write(buf)) {
+                Ok(result) => return Poll::Ready(result),
+                Err(_would_block) => (),
+            }
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
@@ -1,43 +1,22 @@
 use crate::bytes::Storage;
 use crate::error::IOError;
 use crate::hci;
+use crate::hci::acl::{ACLHeader, ACLPacket};
 use crate::hci::command::CommandPacket;
 use crate::hci::event::{EventCode, EventPacket, StaticHCIBuffer};
+use crate::hci::iso::{ISOHeader, ISOPacket};
 use crate::hci::usb::device::has_bluetooth_interface;
+use crate::hci::usb::transfer::{ControlSetup, InQueue, LibusbDevice, TransferType, UsbDevice};
 use crate::hci::usb::Error;
+use crate::hci::vendor::{self, VendorSetup};
+use crate::le::connection::ConnectionHandle;
+use alloc::collections::{BTreeSet, VecDeque};
 use core::convert::TryFrom;
+use core::task::{Context, Poll};
 use core::time::Duration;
-use futures_util::future::LocalBoxFuture;
+use futures_util::future::{poll_fn, LocalBoxFuture};
 use usbw::device::DeviceIdentifier;
-use usbw::libusb::async_device::{AsyncDevice, SingleTransferDevice};
-use usbw::libusb::device_descriptor::DeviceDescriptor;
-
-/// Yield the task back to the executor. Just returns `Poll::Pending` once and calls
-/// `.waker_by_ref()` to put the task back onto the queue. Workaround for blocking futures
-pub async fn yield_now() {
-    struct YieldNow {
-        yielded: bool,
-    }
-
-    impl core::future::Future for YieldNow {
-        type Output = ();
-
-        fn poll(
-            mut self: core::pin::Pin<&mut Self>,
-            cx: &mut core::task::Context<'_>,
-        ) -> core::task::Poll<()> {
-            if self.yielded {
-                return core::task::Poll::Ready(());
-            }
-
-            self.yielded = true;
-            cx.waker().wake_by_ref();
-            core::task::Poll::Pending
-        }
-    }
-
-    YieldNow { yielded: false }.await
-}
+use usbw::libusb::async_device::AsyncDevice;
 
 pub const HCI_COMMAND_ENDPOINT: u8 = 0x01;
 pub const ACL_DATA_OUT_ENDPOINT: u8 = 0x02;
@@ -45,22 +24,66 @@ pub const HCI_EVENT_ENDPOINT: u8 = 0x81;
 pub const ACL_DATA_IN_ENDPOINT: u8 = 0x82;
 pub const HCI_COMMAND_REQUEST_TYPE: u8 = 0x20;
 pub const INTERFACE_NUM: u8 = 0x00;
+/// Interrupt IN transfers (events) kept in flight by [`Adapter::new`].
+pub const DEFAULT_EVENT_TRANSFERS: usize = 3;
+/// Bulk IN transfers (ACL and ISO Data) kept in flight by [`Adapter::new`].
+pub const DEFAULT_DATA_TRANSFERS: usize = 4;
+/// Biggest event (event code, length and parameters).
+const EVENT_TRANSFER_LEN: usize = 2 + 255;
+const DATA_TRANSFER_LEN: usize = hci::MAX_FRAME_SIZE;
+/// bmRequestType = 0x20, bRequest = 0x00, wValue = 0x00, wIndex = 0x00 according to
+/// Bluetooth Core Spec v5.2 Vol 4 Part B 2.2
+const COMMAND_SETUP: ControlSetup = ControlSetup {
+    request_type: HCI_COMMAND_REQUEST_TYPE,
+    request: 0,
+    value: 0,
+    index: 0,
+};
 
-/// USB Bluetooth HCI Adapter.
-pub struct Adapter {
-    handle: SingleTransferDevice,
-    device_descriptor: DeviceDescriptor,
-    _private: (),
+/// USB Bluetooth HCI Adapter. Keeps several interrupt IN (events) and bulk IN (ACL/ISO Data)
+/// transfers in flight so the controller doesn't wait on the host. Events and data packets
+/// spanning more than one transfer are put back together.
+///
+/// How many transfers are really in flight depends on the [`UsbDevice`]. [`LibusbDevice`] runs
+/// one transfer at a time (see its docs) so it only gets one transfer per IN endpoint.
+///
+/// ACL and ISO Data share the bulk endpoints. Incoming data packets are read as ISO Data if their
+/// handle was added with [`Adapter::add_iso_handle`] and as ACL Data otherwise. Packets are
+/// queued until they are read so read both if both are used.
+#[derive(Debug)]
+pub struct Adapter<D = LibusbDevice> {
+    device: D,
+    events: InQueue,
+    data: InQueue,
+    acl_packets: VecDeque<Box<[u8]>>,
+    iso_packets: VecDeque<Box<[u8]>>,
+    iso_handles: BTreeSet<ConnectionHandle>,
 }
-impl core::fmt::Debug for Adapter {
-    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        write!(f, "Adapter({:?})", self.device_descriptor)
+/// Length of the event at the front of `buf`.
+fn event_len(buf: &[u8]) -> Option<usize> {
+    buf.get(1).map(|&len| 2 + usize::from(len))
+}
+fn data_handle(buf: &[u8]) -> Option<ConnectionHandle> {
+    if buf.len() < 2 {
+        None
+    } else {
+        ConnectionHandle::new_checked(u16::from_le_bytes([buf[0], buf[1]]) & 0x0FFF)
     }
 }
-impl Adapter {
-    /// Timeout for USB transfers (1s). If expired, it'll return `IOError::TimedOut`. Hoping to just
-    /// be a temporary solution until I get Async USB working.
-    pub const TIMEOUT: Duration = Duration::from_secs(5);
+/// Length of the ACL or ISO Data packet at the front of `buf`.
+fn data_len(iso_handles: &BTreeSet<ConnectionHandle>, buf: &[u8]) -> Option<usize> {
+    if buf.len() < ACLHeader::BYTE_LEN {
+        return None;
+    }
+    let len = u16::from_le_bytes([buf[2], buf[3]]);
+    let is_iso = matches!(data_handle(buf), Some(handle) if iso_handles.contains(&handle));
+    if is_iso {
+        Some(ISOHeader::BYTE_LEN + usize::from(len & ISOHeader::MAX_DATA_LOAD_LEN))
+    } else {
+        Some(ACLHeader::BYTE_LEN + usize::from(len))
+    }
+}
+impl Adapter<LibusbDevice> {
     pub fn open(device_handle: AsyncDevice) -> Result<Adapter, Error> {
         if has_bluetooth_interface(&device_handle.handle_ref().device())? {
             Self::from_handle(device_handle)
@@ -71,73 +94,121 @@ impl Adapter {
     pub fn from_handle(mut handle: AsyncDevice) -> Result<Adapter, Error> {
         handle.handle_mut().reset()?;
         handle.handle_mut().claim_interface(INTERFACE_NUM)?;
-        Ok(Adapter::from_parts(
+        Ok(Adapter::new(LibusbDevice::new(
             handle.device().device_descriptor()?,
             handle,
-        ))
-    }
-    pub(crate) fn from_parts(device_descriptor: DeviceDescriptor, handle: AsyncDevice) -> Adapter {
-        Adapter {
-            handle: SingleTransferDevice::new(handle),
-            _private: (),
-            device_descriptor,
-        }
+            INTERFACE_NUM,
+        )))
     }
     pub fn device_identifier(&self) -> DeviceIdentifier {
+        let device_descriptor = self.device.device_descriptor();
         DeviceIdentifier {
-            vendor_id: self.device_descriptor.vendor_id(),
-            product_id: self.device_descriptor.product_id(),
+            vendor_id: device_descriptor.vendor_id(),
+            product_id: device_descriptor.product_id(),
         }
     }
     pub async fn get_manufacturer_string(&self) -> Result<Option<String>, Error> {
-        // Note, uses device's primary language and replaces any UTF-8 with '?'.
-        // (According to libusb)
-        match self.device_descriptor.manufacturer_string_index() {
-            Some(index) => Ok(Some(
-                self.handle
-                    .device()
-                    .get_string_descriptor_ascii(index)
-                    .await?,
-            )),
+        match self.device.device_descriptor().manufacturer_string_index() {
+            Some(index) => Ok(Some(self.device.get_string_descriptor_ascii(index).await?)),
             None => Ok(None),
         }
     }
     pub async fn get_product_string(&self) -> Result<Option<String>, Error> {
-        // Note, uses device's primary language and replaces any UTF-8 with '?'.
-        // (According to libusb)
-        match self.device_descriptor.product_string_index() {
-            Some(index) => Ok(Some(
-                self.handle
-                    .device()
-                    .get_string_descriptor_ascii(index)
-                    .await?,
-            )),
+        match self.device.device_descriptor().product_string_index() {
+            Some(index) => Ok(Some(self.device.get_string_descriptor_ascii(index).await?)),
             None => Ok(None),
         }
     }
     pub async fn get_serial_number_string(&self) -> Result<Option<String>, Error> {
-        // Note, uses device's primary language and replaces any UTF-8 with '?'.
-        // (According to libusb)
-        match self.device_descriptor.manufacturer_string_index() {
-            Some(index) => Ok(Some(
-                self.handle
-                    .device()
-                    .get_string_descriptor_ascii(index)
-                    .await?,
-            )),
+        match self.device.device_descriptor().manufacturer_string_index() {
+            Some(index) => Ok(Some(self.device.get_string_descriptor_ascii(index).await?)),
             None => Ok(None),
         }
     }
+    /// Drops events left over from a previous user of the device. Call before reading events.
+    pub async fn flush_event_buffer(&mut self) -> Result<(), Error> {
+        // This timeout can be adjusted to make the flush more reliable. Longer duration might mean
+        // more reliable but also a longer time to flush.
+        const FLUSH_TIMEOUT: Duration = Duration::from_millis(5);
+        self.device
+            .flush_interrupt(HCI_EVENT_ENDPOINT, FLUSH_TIMEOUT)
+            .await
+    }
+    pub async fn reset(&mut self) -> Result<(), Error> {
+        self.device.reset().await
+    }
+}
+impl<D: UsbDevice> Adapter<D> {
+    pub fn new(device: D) -> Adapter<D> {
+        Self::with_transfers(device, DEFAULT_EVENT_TRANSFERS, DEFAULT_DATA_TRANSFERS)
+    }
+    /// Adapter keeping `event_transfers` interrupt IN and `data_transfers` bulk IN transfers in
+    /// flight. Transfers are started the first time the matching packets are read.
+    /// # Panics
+    /// Panics if `event_transfers` or `data_transfers` is 0.
+    pub fn with_transfers(device: D, event_transfers: usize, data_transfers: usize) -> Adapter<D> {
+        Adapter {
+            device,
+            events: InQueue::new(
+                HCI_EVENT_ENDPOINT,
+                TransferType::Interrupt,
+                EVENT_TRANSFER_LEN,
+                event_transfers,
+            ),
+            data: InQueue::new(
+                ACL_DATA_IN_ENDPOINT,
+                TransferType::Bulk,
+                DATA_TRANSFER_LEN,
+                data_transfers,
+            ),
+            acl_packets: VecDeque::new(),
+            iso_packets: VecDeque::new(),
+            iso_handles: BTreeSet::new(),
+        }
+    }
+    pub fn device(&self) -> &D {
+        &self.device
+    }
+    pub fn device_mut(&mut self) -> &mut D {
+        &mut self.device
+    }
+    /// Interrupt IN (events) and bulk IN (data) transfers in flight.
+    pub fn transfers_in_flight(&self) -> (usize, usize) {
+        (self.events.in_flight(), self.data.in_flight())
+    }
+    /// Runs `setup` (ex: a firmware download picked with
+    /// [`controller_vendor`](crate::hci::usb::supported::controller_vendor)). Call right after
+    /// opening the adapter, before any other command.
+    pub async fn vendor_setup<S: VendorSetup>(
+        &mut self,
+        setup: &mut S,
+    ) -> Result<(), vendor::Error> {
+        setup.setup(self).await
+    }
+    /// Read incoming data packets for `handle` (a CIS or BIS) as ISO Data.
+    pub fn add_iso_handle(&mut self, handle: ConnectionHandle) {
+        self.iso_handles.insert(handle);
+    }
+    pub fn remove_iso_handle(&mut self, handle: ConnectionHandle) {
+        self.iso_handles.remove(&handle);
+    }
+    /// Sends a packed HCI Command (without the packet type byte).
     pub async fn write_hci_command_bytes(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        let amount = self.device.control_out(COMMAND_SETUP, bytes.into()).await?;
+        if amount == bytes.len() {
+            Ok(())
+        } else {
+            Err(Error(IOError::TimedOut))
+        }
+    }
+    /// Sends a packed ACL or ISO Data packet (without the packet type byte).
+    pub async fn write_acl_bytes(&mut self, bytes: &[u8]) -> Result<(), Error> {
         let mut index = 0;
         let size = bytes.len();
-        // TODO: Fix probably infinite loop
         while index < size {
-            // bmRequestType = 0x20, bRequest = 0x00, wValue = 0x00, wIndex = 0x00 according to
-            // Bluetooth Core Spec v5.2 Vol 4 Part B 2.2
             let amount = self
-                .handle
-                .control_write(0x20, 0, 0, 0, &bytes[index..], Self::TIMEOUT)
+                .device
+                .bulk_out(ACL_DATA_OUT_ENDPOINT, bytes[index..].into())
                 .await?;
             if amount == 0 {
                 return Err(Error(IOError::TimedOut));
@@ -146,93 +217,77 @@ impl Adapter {
         }
         Ok(())
     }
-    pub async fn read_some_event_bytes(
-        &mut self,
-        buf: &mut [u8],
-        timeout: Duration,
-    ) -> Result<usize, Error> {
-        Ok(self
-            .handle
-            .interrupt_read(HCI_EVENT_ENDPOINT, buf, timeout)
-            .await?)
-    }
-    pub async fn read_some_acl_bytes(
-        &mut self,
-        buf: &mut [u8],
-        timeout: Duration,
-    ) -> Result<usize, Error> {
-        Ok(self
-            .handle
-            .bulk_read(ACL_DATA_IN_ENDPOINT, buf, timeout)
-            .await?)
-    }
-    pub async fn read_event_bytes(&mut self, buf: &mut [u8]) -> Result<(), Error> {
-        let mut index = 0;
-        let size = buf.len();
-        // TODO: Fix probably infinite loop
-        while index < size {
-            let amount = match self
-                .read_some_event_bytes(&mut buf[index..], Self::TIMEOUT)
-                .await
-            {
-                Ok(a) => a,
-                Err(Error(IOError::TimedOut)) => 0,
-                Err(e) => return Err(e),
-            };
-            index += amount;
+    /// Polls for the next event (event code, length and parameters).
+    pub fn poll_event_bytes(&mut self, cx: &mut Context<'_>) -> Poll<Result<Box<[u8]>, Error>> {
+        loop {
+            if let Some(event) = self.events.take_packet(event_len) {
+                return Poll::Ready(Ok(event));
+            }
+            if let Err(e) = futures_util::ready!(self.events.poll_receive(&mut self.device, cx)) {
+                return Poll::Ready(Err(e));
+            }
         }
-        Ok(())
     }
-    pub async fn flush_event_buffer(&mut self) -> Result<(), Error> {
-        // This timeout can be adjusted to make the flush more reliable. Longer duration might mean
-        // more reliable but also a longer time to flush.
-        const FLUSH_TIMEOUT: Duration = Duration::from_millis(5);
-
-        let mut buf = vec![0_u8; 256];
-        while match self
-            .read_some_event_bytes(buf.as_mut_slice(), FLUSH_TIMEOUT)
-            .await
-        {
-            Ok(a) => a,
-            Err(Error(IOError::TimedOut)) => 0,
-            Err(e) => return Err(e),
-        } != 0
-        {}
-        Ok(())
+    /// Waits for the next bulk IN transfer and sorts the data packets it completed.
+    fn poll_data(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        futures_util::ready!(self.data.poll_receive(&mut self.device, cx))?;
+        let iso_handles = &self.iso_handles;
+        while let Some(packet) = self.data.take_packet(|buf| data_len(iso_handles, buf)) {
+            match data_handle(&packet) {
+                Some(handle) if iso_handles.contains(&handle) => self.iso_packets.push_back(packet),
+                _ => self.acl_packets.push_back(packet),
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+    /// Polls for the next packed ACL Data packet.
+    pub fn poll_acl_bytes(&mut self, cx: &mut Context<'_>) -> Poll<Result<Box<[u8]>, Error>> {
+        loop {
+            if let Some(packet) = self.acl_packets.pop_front() {
+                return Poll::Ready(Ok(packet));
+            }
+            futures_util::ready!(self.poll_data(cx))?;
+        }
+    }
+    /// Polls for the next packed ISO Data packet.
+    pub fn poll_iso_bytes(&mut self, cx: &mut Context<'_>) -> Poll<Result<Box<[u8]>, Error>> {
+        loop {
+            if let Some(packet) = self.iso_packets.pop_front() {
+                return Poll::Ready(Ok(packet));
+            }
+            futures_util::ready!(self.poll_data(cx))?;
+        }
     }
     pub async fn read_event_packet<Buf: Storage<u8>>(
         &mut self,
     ) -> Result<EventPacket<Buf>, hci::adapter::Error> {
-        let mut header = [0u8; 2];
-        self.read_event_bytes(&mut header[..]).await?;
-        let len = header[1];
-        let mut buf = Buf::with_size(len.into());
-        // Even if the event code is wrong, still read so we don't leave data in buffer
-        self.read_event_bytes(buf.as_mut()).await?;
+        let bytes = poll_fn(|cx| self.poll_event_bytes(cx)).await?;
         let event_code =
-            EventCode::try_from(header[0]).map_err(|_| hci::StreamError::BadEventCode)?;
+            EventCode::try_from(bytes[0]).map_err(|_| hci::StreamError::BadEventCode)?;
         Ok(EventPacket {
             event_code,
-            parameters: buf,
+            parameters: Buf::from_slice(&bytes[2..]),
         })
     }
-    pub fn reset(&mut self) -> Result<(), Error> {
-        self.handle.device().handle_ref().reset()?;
-        Ok(())
+    pub async fn read_acl_packet<Buf: Storage<u8>>(
+        &mut self,
+    ) -> Result<ACLPacket<Buf>, hci::adapter::Error> {
+        let bytes = poll_fn(|cx| self.poll_acl_bytes(cx)).await?;
+        Ok(ACLPacket::unpack_from(&bytes)
+            .map_err(hci::StreamError::DataError)?
+            .to_new_storage())
     }
-}
-impl Drop for Adapter {
-    fn drop(&mut self) {
-        // We claim the interface when we make the adapter so we must release when we drop.
-        let _ = self
-            .handle
-            .device_mut()
-            .handle_mut()
-            .release_interface(INTERFACE_NUM);
+    pub async fn read_iso_packet<Buf: Storage<u8>>(
+        &mut self,
+    ) -> Result<ISOPacket<Buf>, hci::adapter::Error> {
+        let bytes = poll_fn(|cx| self.poll_iso_bytes(cx)).await?;
+        Ok(ISOPacket::unpack_from(&bytes)
+            .map_err(hci::StreamError::DataError)?
+            .to_new_storage())
     }
 }
 
-impl hci::adapter::Adapter for Adapter {
+impl<D: UsbDevice> hci::adapter::Adapter for Adapter<D> {
     fn write_command<'s, 'p: 's>(
         &'s mut self,
         packet: CommandPacket<&'p [u8]>,
@@ -248,10 +303,236 @@ impl hci::adapter::Adapter for Adapter {
     fn read_event<'s, 'p: 's, S: Storage<u8> + 'p>(
         &'s mut self,
     ) -> LocalBoxFuture<'s, Result<EventPacket<S>, hci::adapter::Error>> {
+        Box::pin(self.read_event_packet())
+    }
+
+    fn write_acl<'s, 'p: 's>(
+        &'s mut self,
+        packet: ACLPacket<&'p [u8]>,
+    ) -> LocalBoxFuture<'s, Result<(), hci::adapter::Error>> {
+        let packed = packet.to_raw_packet::<Box<[u8]>>();
+        Box::pin(async move {
+            let packed = packed.map_err(hci::StreamError::DataError)?;
+            self.write_acl_bytes(packed.buf.as_ref())
+                .await
+                .map_err(hci::adapter::Error::from)
+        })
+    }
+
+    fn read_acl<'s, 'p: 's, S: Storage<u8> + 'p>(
+        &'s mut self,
+    ) -> LocalBoxFuture<'s, Result<ACLPacket<S>, hci::adapter::Error>> {
+        Box::pin(self.read_acl_packet())
+    }
+
+    fn write_iso<'s, 'p: 's>(
+        &'s mut self,
+        packet: ISOPacket<&'p [u8]>,
+    ) -> LocalBoxFuture<'s, Result<(), hci::adapter::Error>> {
+        let packed = packet.to_raw_packet::<Box<[u8]>>();
         Box::pin(async move {
-            self.read_event_packet()
+            let packed = packed.map_err(hci::StreamError::DataError)?;
+            self.write_acl_bytes(packed.buf.as_ref())
                 .await
                 .map_err(hci::adapter::Error::from)
         })
     }
+
+    fn read_iso<'s, 'p: 's, S: Storage<u8> + 'p>(
+        &'s mut self,
+    ) -> LocalBoxFuture<'s, Result<ISOPacket<S>, hci::adapter::Error>> {
+        Box::pin(self.read_iso_packet())
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hci::adapter::Adapter as _;
+    use crate::hci::baseband::ControllerBasebandOpcode;
+    use crate::hci::usb::transfer::TransferFuture;
+    use alloc::collections::BTreeMap;
+    use alloc::rc::Rc;
+    use core::cell::RefCell;
+    use core::future::Future;
+    use core::pin::Pin;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    use core::task::Waker;
+    use futures_util::task::ArcWake;
+    use std::sync::Arc;
+
+    /// Mock USB device. IN transfers complete (in order) with whatever the test passes to
+    /// `complete_in`. OUT transfers complete right away and are recorded.
+    #[derive(Clone, Default)]
+    struct MockDevice(Rc<RefCell<MockState>>);
+    #[derive(Default)]
+    struct MockState {
+        max_in_flight: Option<usize>,
+        /// Bytes received by each IN transfer, in the order they complete.
+        completions: BTreeMap<u8, Vec<Vec<u8>>>,
+        started: BTreeMap<u8, usize>,
+        wakers: Vec<Waker>,
+        control_out: Vec<(ControlSetup, Box<[u8]>)>,
+        bulk_out: Vec<(u8, Box<[u8]>)>,
+    }
+    impl MockDevice {
+        fn complete_in(&self, endpoint: u8, bytes: &[u8]) {
+            let mut state = self.0.borrow_mut();
+            state
+                .completions
+                .entry(endpoint)
+                .or_default()
+                .push(bytes.to_vec());
+            for waker in state.wakers.drain(..) {
+                waker.wake();
+            }
+        }
+        fn transfer_in(&mut self, endpoint: u8) -> TransferFuture<Vec<u8>> {
+            let mut state = self.0.borrow_mut();
+            let started = state.started.entry(endpoint).or_default();
+            let index = *started;
+            *started += 1;
+            let device = self.0.clone();
+            Box::pin(poll_fn(move |cx| {
+                let mut state = device.borrow_mut();
+                let completed = state
+                    .completions
+                    .get(&endpoint)
+                    .and_then(|completions| completions.get(index).cloned());
+                match completed {
+                    Some(bytes) => Poll::Ready(Ok(bytes)),
+                    None => {
+                        state.wakers.push(cx.waker().clone());
+                        Poll::Pending
+                    }
+                }
+            }))
+        }
+    }
+    impl UsbDevice for MockDevice {
+        fn max_in_flight(&self) -> usize {
+            self.0.borrow().max_in_flight.unwrap_or(usize::MAX)
+        }
+
+        fn control_out(&mut self, setup: ControlSetup, data: Box<[u8]>) -> TransferFuture<usize> {
+            let len = data.len();
+            self.0.borrow_mut().control_out.push((setup, data));
+            Box::pin(async move { Ok(len) })
+        }
+
+        fn interrupt_in(&mut self, endpoint: u8, _len: usize) -> TransferFuture<Vec<u8>> {
+            self.transfer_in(endpoint)
+        }
+
+        fn bulk_in(&mut self, endpoint: u8, _len: usize) -> TransferFuture<Vec<u8>> {
+            self.transfer_in(endpoint)
+        }
+
+        fn bulk_out(&mut self, endpoint: u8, data: Box<[u8]>) -> TransferFuture<usize> {
+            let len = data.len();
+            self.0.borrow_mut().bulk_out.push((endpoint, data));
+            Box::pin(async move { Ok(len) })
+        }
+    }
+    #[derive(Default)]
+    struct CountingWaker(AtomicUsize);
+    impl ArcWake for CountingWaker {
+        fn wake_by_ref(arc_self: &Arc<Self>) {
+            arc_self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+    fn poll_once<F: Future + Unpin>(mut future: F, cx: &mut Context<'_>) -> Poll<F::Output> {
+        Pin::new(&mut future).poll(cx)
+    }
+
+    #[test]
+    fn keeps_transfers_in_flight() {
+        let device = MockDevice::default();
+        let mut adapter = Adapter::with_transfers(device.clone(), 2, 3);
+        let wakes = Arc::new(CountingWaker::default());
+        let waker = futures_util::task::waker(wakes.clone());
+        let mut cx = Context::from_waker(&waker);
+
+        assert!(poll_once(adapter.read_event::<Box<[u8]>>(), &mut cx).is_pending());
+        assert!(poll_once(adapter.read_acl::<Box<[u8]>>(), &mut cx).is_pending());
+        assert_eq!(adapter.transfers_in_flight(), (2, 3));
+
+        // Command Complete (Reset) split over two interrupt transfers.
+        device.complete_in(HCI_EVENT_ENDPOINT, &[0x0E, 0x04, 0x01]);
+        assert!(wakes.0.load(Ordering::SeqCst) > 0);
+        assert!(poll_once(adapter.read_event::<Box<[u8]>>(), &mut cx).is_pending());
+        device.complete_in(HCI_EVENT_ENDPOINT, &[0x03, 0x0C, 0x00]);
+        match poll_once(adapter.read_event::<Box<[u8]>>(), &mut cx) {
+            Poll::Ready(Ok(event)) => {
+                assert_eq!(event.event_code, EventCode::CommandComplete);
+                assert_eq!(event.parameters.as_ref(), &[0x01, 0x03, 0x0C, 0x00]);
+            }
+            other => panic!("no event: {:?}", other.map(|r| r.map(|_| ()))),
+        }
+        assert_eq!(adapter.transfers_in_flight(), (2, 3));
+
+        // One ISO Data packet, one ACL Data packet and the start of another in one transfer.
+        let iso_handle = ConnectionHandle::new(0x060);
+        adapter.add_iso_handle(iso_handle);
+        let iso = [0x60, 0x20, 0x06, 0x00, 0x00, 0x00, 0x02, 0x00, 0xAA, 0xBB];
+        let acl = [0x40, 0x20, 0x02, 0x00, 0x01, 0x02];
+        let mut bulk = iso.to_vec();
+        bulk.extend_from_slice(&acl);
+        bulk.extend_from_slice(&acl[..3]);
+        device.complete_in(ACL_DATA_IN_ENDPOINT, &bulk);
+        device.complete_in(ACL_DATA_IN_ENDPOINT, &acl[3..]);
+        match poll_once(adapter.read_iso::<Box<[u8]>>(), &mut cx) {
+            Poll::Ready(Ok(packet)) => {
+                assert_eq!(packet.handle, iso_handle);
+                assert_eq!(packet.data.as_ref(), &[0xAA, 0xBB]);
+            }
+            _ => panic!("no ISO packet"),
+        }
+        for _ in 0..2 {
+            match poll_once(adapter.read_acl::<Box<[u8]>>(), &mut cx) {
+                Poll::Ready(Ok(packet)) => {
+                    assert_eq!(packet.handle, ConnectionHandle::new(0x040));
+                    assert_eq!(packet.data.as_ref(), &[0x01, 0x02]);
+                }
+                _ => panic!("no ACL packet"),
+            }
+        }
+        assert_eq!(adapter.transfers_in_flight(), (2, 3));
+
+        let reset = CommandPacket {
+            opcode: ControllerBasebandOpcode::Reset.into(),
+            parameters: &[][..],
+        };
+        assert!(poll_once(adapter.write_command(reset), &mut cx).is_ready());
+        let acl = ACLPacket::unpack_from(&acl[..]).unwrap();
+        assert!(poll_once(adapter.write_acl(acl), &mut cx).is_ready());
+        let state = device.0.borrow();
+        assert_eq!(
+            state.control_out,
+            vec![(COMMAND_SETUP, vec![0x03, 0x0C, 0x00].into_boxed_slice())]
+        );
+        assert_eq!(
+            state.bulk_out,
+            vec![(
+                ACL_DATA_OUT_ENDPOINT,
+                vec![0x40, 0x20, 0x02, 0x00, 0x01, 0x02].into_boxed_slice()
+            )]
+        );
+    }
+
+    #[test]
+    fn one_transfer_per_endpoint_if_device_is_serial() {
+        let device = MockDevice::default();
+        device.0.borrow_mut().max_in_flight = Some(1);
+        let mut adapter = Adapter::with_transfers(device.clone(), 2, 3);
+        let waker = futures_util::task::noop_waker();
+        let mut cx = Context::from_waker(&waker);
+
+        assert!(poll_once(adapter.read_event::<Box<[u8]>>(), &mut cx).is_pending());
+        assert!(poll_once(adapter.read_acl::<Box<[u8]>>(), &mut cx).is_pending());
+        assert_eq!(adapter.transfers_in_flight(), (1, 1));
+        device.complete_in(HCI_EVENT_ENDPOINT, &[0x0E, 0x04, 0x01]);
+        device.complete_in(HCI_EVENT_ENDPOINT, &[0x03, 0x0C, 0x00]);
+        assert!(poll_once(adapter.read_event::<Box<[u8]>>(), &mut cx).is_ready());
+        assert_eq!(adapter.transfers_in_flight(), (1, 1));
+    }
 }
@@ -1,23 +1,25 @@
 use crate::hci::usb::Error;
+use usbw::device::DeviceIdentifier;
 use usbw::libusb::device::Device;
 
 const WIRELESS_CONTROLLER_CLASS: u8 = 0xE0;
 const SUBCLASS: u8 = 0x01;
 const BLUETOOTH_PROGRAMMING_INTERFACE_PROTOCOL: u8 = 0x01;
+/// If the USB class triple is the Bluetooth Programming Interface (Bluetooth Core Spec v5.2 Vol 4
+/// Part B 2.1).
+pub fn is_bluetooth_class(class_code: u8, sub_class_code: u8, protocol_code: u8) -> bool {
+    class_code == WIRELESS_CONTROLLER_CLASS
+        && sub_class_code == SUBCLASS
+        && protocol_code == BLUETOOTH_PROGRAMMING_INTERFACE_PROTOCOL
+}
+/// If any interface (or alternate setting) of the active configuration is a Bluetooth HCI.
 pub fn has_bluetooth_interface(device: &Device) -> Result<bool, Error> {
     match device.active_config_descriptor() {
-        Ok(config) => Ok(config
-            .interfaces()
-            .iter()
-            .next()
-            .and_then(|i| {
-                i.descriptors().iter().next().map(|d| {
-                    d.class_code() == WIRELESS_CONTROLLER_CLASS
-                        && d.sub_class_code() == SUBCLASS
-                        && d.protocol_code() == BLUETOOTH_PROGRAMMING_INTERFACE_PROTOCOL
-                })
-            })
-            .unwrap_or(false)),
+        Ok(config) => Ok(config.interfaces().iter().any(|i| {
+            i.descriptors()
+                .iter()
+                .any(|d| is_bluetooth_class(d.class_code(), d.sub_class_code(), d.protocol_code()))
+        })),
         Err(usbw::libusb::error::Error::NotFound) => Ok(false),
         Err(e) => Err(Error::from(e)),
     }
@@ -32,3 +34,55 @@ pub fn bluetooth_adapters<'a>(
         Err(e) => Some(Err(e)),
     })
 }
+/// Picks the USB devices to use as HCI adapters. Devices with a Bluetooth HCI interface (see
+/// [`has_bluetooth_interface`]) are used unless denied. Allowed devices are used even without one
+/// (ex: dongles that only show a vendor specific class until their firmware is loaded). Deny wins
+/// over allow.
+#[derive(Clone, Debug, Default)]
+pub struct DeviceFilter {
+    allow: Vec<DeviceIdentifier>,
+    deny: Vec<DeviceIdentifier>,
+}
+impl DeviceFilter {
+    pub fn new() -> DeviceFilter {
+        DeviceFilter::default()
+    }
+    pub fn allow(&mut self, device: DeviceIdentifier) -> &mut DeviceFilter {
+        self.allow.push(device);
+        self
+    }
+    pub fn deny(&mut self, device: DeviceIdentifier) -> &mut DeviceFilter {
+        self.deny.push(device);
+        self
+    }
+    pub fn is_allowed(&self, device: &DeviceIdentifier) -> bool {
+        self.allow.contains(device)
+    }
+    pub fn is_denied(&self, device: &DeviceIdentifier) -> bool {
+        self.deny.contains(device)
+    }
+    /// If `device` (with a Bluetooth HCI interface or not) passes the filter.
+    pub fn accepts(&self, device: &DeviceIdentifier, has_bluetooth_interface: bool) -> bool {
+        !self.is_denied(device) && (has_bluetooth_interface || self.is_allowed(device))
+    }
+    pub fn matches(&self, device: &Device) -> Result<bool, Error> {
+        let descriptor = device.device_descriptor()?;
+        let identifier = DeviceIdentifier {
+            vendor_id: descriptor.vendor_id(),
+            product_id: descriptor.product_id(),
+        };
+        // Only inspect the interfaces if the allow and deny lists don't decide.
+        let inspect = !self.is_denied(&identifier) && !self.is_allowed(&identifier);
+        Ok(self.accepts(&identifier, inspect && has_bluetooth_interface(device)?))
+    }
+    pub fn adapters<'a>(
+        &'a self,
+        i: impl Iterator<Item = Device> + 'a,
+    ) -> impl Iterator<Item = Result<Device, Error>> + 'a {
+        i.filter_map(move |d| match self.matches(&d) {
+            Ok(true) => Some(Ok(d)),
+            Ok(false) => None,
+            Err(e) => Some(Err(e)),
+        })
+    }
+}
@@ -0,0 +1,163 @@
+//! Hot-plug notifications for USB HCI adapters. libusb only has hot-plug callbacks on some
+//! platforms so the device list is polled and compared with the last one instead.
+use crate::hci::usb::device::DeviceFilter;
+use alloc::collections::{BTreeSet, VecDeque};
+use core::time::Duration;
+use futures_util::stream::Stream;
+use usbw::libusb::device::Device;
+
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub enum HotplugEvent<K, D> {
+    Arrived(K, D),
+    Left(K),
+}
+/// Tracks which devices are attached. `K` must identify a device for as long as it's attached.
+#[derive(Clone, Debug)]
+pub struct Hotplug<K> {
+    attached: BTreeSet<K>,
+}
+impl<K: Ord + Clone> Default for Hotplug<K> {
+    fn default() -> Self {
+        Hotplug {
+            attached: BTreeSet::new(),
+        }
+    }
+}
+impl<K: Ord + Clone> Hotplug<K> {
+    pub fn new() -> Hotplug<K> {
+        Hotplug::default()
+    }
+    pub fn attached(&self) -> impl Iterator<Item = &K> + '_ {
+        self.attached.iter()
+    }
+    pub fn is_attached(&self, key: &K) -> bool {
+        self.attached.contains(key)
+    }
+    /// Compares `devices` (every device attached now) with the last update. Returns the arrivals
+    /// (in `devices` order) followed by the removals.
+    pub fn update<D>(
+        &mut self,
+        devices: impl IntoIterator<Item = (K, D)>,
+    ) -> Vec<HotplugEvent<K, D>> {
+        let mut events = Vec::new();
+        let mut attached = BTreeSet::new();
+        for (key, device) in devices {
+            if !self.attached.contains(&key) {
+                events.push(HotplugEvent::Arrived(key.clone(), device));
+            }
+            attached.insert(key);
+        }
+        events.extend(
+            self.attached
+                .difference(&attached)
+                .cloned()
+                .map(HotplugEvent::Left),
+        );
+        self.attached = attached;
+        events
+    }
+}
+/// Bus number and address of a libusb device. Stays the same until the device is unplugged.
+pub type DeviceKey = (u8, u8);
+pub fn device_key(device: &Device) -> DeviceKey {
+    (device.bus_number(), device.address())
+}
+struct Poller<L> {
+    filter: DeviceFilter,
+    list_devices: L,
+    hotplug: Hotplug<DeviceKey>,
+    interval: tokio::time::Interval,
+    pending: VecDeque<HotplugEvent<DeviceKey, Device>>,
+}
+/// Stream of HCI adapters (picked by `filter`) arriving and leaving. `list_devices` returns the
+/// attached devices (ex: `move || context.device_list().iter()`) and is called every `interval`.
+/// Adapters already attached arrive on the first poll. Devices that can't be inspected (ex:
+/// `IOError::AccessDenied`) don't arrive, but an adapter that already arrived stays attached
+/// until it's unplugged even if inspecting it fails later. Must be used inside a tokio runtime.
+pub fn hotplug_events<L, I>(
+    filter: DeviceFilter,
+    interval: Duration,
+    list_devices: L,
+) -> impl Stream<Item = HotplugEvent<DeviceKey, Device>>
+where
+    L: FnMut() -> I,
+    I: IntoIterator<Item = Device>,
+{
+    let mut interval = tokio::time::interval(interval);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let poller = Poller {
+        filter,
+        list_devices,
+        hotplug: Hotplug::new(),
+        interval,
+        pending: VecDeque::new(),
+    };
+    futures_util::stream::unfold(poller, |mut poller| async move {
+        loop {
+            if let Some(event) = poller.pending.pop_front() {
+                return Some((event, poller));
+            }
+            poller.interval.tick().await;
+            let (filter, hotplug) = (&poller.filter, &poller.hotplug);
+            let adapters: Vec<_> = (poller.list_devices)()
+                .into_iter()
+                .map(|device| (device_key(&device), device))
+                .filter(|(key, device)| {
+                    filter
+                        .matches(device)
+                        .unwrap_or_else(|_| hotplug.is_attached(key))
+                })
+                .collect();
+            let events = poller.hotplug.update(adapters);
+            poller.pending.extend(events);
+        }
+    })
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use usbw::device::{DeviceIdentifier, ProductID, VendorID};
+
+    #[test]
+    fn reports_arrivals_and_removals() {
+        let mut hotplug = Hotplug::new();
+        assert_eq!(
+            hotplug.update(vec![((1, 4), "a"), ((1, 5), "b")]),
+            vec![
+                HotplugEvent::Arrived((1, 4), "a"),
+                HotplugEvent::Arrived((1, 5), "b")
+            ]
+        );
+        assert_eq!(
+            hotplug.update(vec![((1, 5), "b")]),
+            vec![HotplugEvent::Left((1, 4))]
+        );
+        // Re-plugged dongles get a new address.
+        assert_eq!(
+            hotplug.update(vec![((1, 5), "b"), ((1, 6), "a")]),
+            vec![HotplugEvent::Arrived((1, 6), "a")]
+        );
+        assert_eq!(hotplug.attached().count(), 2);
+        assert!(hotplug.is_attached(&(1, 6)));
+        assert!(!hotplug.is_attached(&(1, 4)));
+    }
+    #[test]
+    fn filter_allow_and_deny() {
+        let known = DeviceIdentifier {
+            vendor_id: VendorID(0x0a5c),
+            product_id: ProductID(0x21e8),
+        };
+        let other = DeviceIdentifier {
+            vendor_id: VendorID(0x8087),
+            product_id: ProductID(0x0a2b),
+        };
+        let mut filter = DeviceFilter::new();
+        assert!(filter.accepts(&known, true));
+        assert!(!filter.accepts(&known, false));
+        filter.allow(known).deny(other);
+        assert!(filter.accepts(&known, false));
+        assert!(!filter.accepts(&other, true));
+        filter.deny(known);
+        assert!(!filter.accepts(&known, true));
+    }
+}
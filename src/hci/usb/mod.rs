@@ -1,6 +1,8 @@
 pub mod adapter;
 pub mod device;
+pub mod hotplug;
 pub mod supported;
+pub mod transfer;
 
 use crate::error::IOError;
 
@@ -1,5 +1,6 @@
 //! List of Known supported USB Bluetooth HCI adapters. There are many more not listed here.
 
+use crate::hci::vendor::ControllerVendor;
 use usbw::device::{DeviceIdentifier, ProductID, VendorID};
 
 pub static KNOWN_DEVICES: [DeviceIdentifier; 12] = [
@@ -52,3 +53,16 @@ pub static KNOWN_DEVICES: [DeviceIdentifier; 12] = [
         product_id: ProductID(0x065A),
     }, // Belkin BCM20702A0
 ];
+/// Vendor of the controller in the USB device `device` (for picking the firmware download). Only
+/// vendors with a [`VendorSetup`](crate::hci::vendor::VendorSetup) are returned.
+pub fn controller_vendor(device: &DeviceIdentifier) -> Option<ControllerVendor> {
+    match (device.vendor_id.0, device.product_id.0) {
+        (0x8087, _) => Some(ControllerVendor::Intel),
+        (0x0BDA, _) => Some(ControllerVendor::Realtek),
+        // Broadcom and the known Broadcom based dongles of other vendors.
+        (0x0A5C, _) | (0x19FF, 0x0239) | (0x0B05, 0x17CB) | (0x0489, 0xE07A) | (0x050D, 0x065A) => {
+            Some(ControllerVendor::Broadcom)
+        }
+        _ => None,
+    }
+}
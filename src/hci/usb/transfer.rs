@@ -0,0 +1,327 @@
+//! USB transfers used by the HCI [`Adapter`](super::adapter::Adapter). [`UsbDevice`] sits between
+//! the adapter and the USB stack (libusb through `usbw`, or a mock device in tests).
+use crate::error::IOError;
+use crate::hci::usb::Error;
+use alloc::collections::VecDeque;
+use alloc::rc::Rc;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use core::time::Duration;
+use futures_util::future::LocalBoxFuture;
+use futures_util::lock::Mutex;
+use usbw::libusb::async_device::{AsyncDevice, SingleTransferDevice};
+use usbw::libusb::device_descriptor::DeviceDescriptor;
+
+/// A USB transfer in flight. Doesn't borrow the device so many can be in flight at once.
+pub type TransferFuture<T> = LocalBoxFuture<'static, Result<T, Error>>;
+/// Setup packet of a control transfer (without `wLength`, that comes from the data).
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash, Default)]
+pub struct ControlSetup {
+    pub request_type: u8,
+    pub request: u8,
+    pub value: u16,
+    pub index: u16,
+}
+/// Transfers an HCI USB adapter needs. Each call starts a transfer and returns a future that
+/// resolves (and wakes the task polling it) when the transfer completes. Transfers on the same
+/// endpoint complete in the order they were started. IN transfers resolve to the bytes received
+/// (at most `len`) and may fail with `IOError::TimedOut` if nothing arrived in time (the adapter
+/// just starts another one). A timed out IN transfer must not have received anything.
+pub trait UsbDevice {
+    /// Most IN transfers worth keeping in flight on one endpoint. Devices running one transfer at
+    /// a time return 1 so queued IN transfers don't hold up OUT transfers.
+    fn max_in_flight(&self) -> usize {
+        usize::MAX
+    }
+    /// Starts a control OUT transfer. Resolves to the amount of `data` sent.
+    fn control_out(&mut self, setup: ControlSetup, data: Box<[u8]>) -> TransferFuture<usize>;
+    fn interrupt_in(&mut self, endpoint: u8, len: usize) -> TransferFuture<Vec<u8>>;
+    fn bulk_in(&mut self, endpoint: u8, len: usize) -> TransferFuture<Vec<u8>>;
+    /// Starts a bulk OUT transfer. Resolves to the amount of `data` sent.
+    fn bulk_out(&mut self, endpoint: u8, data: Box<[u8]>) -> TransferFuture<usize>;
+}
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub enum TransferType {
+    Interrupt,
+    Bulk,
+}
+/// Keeps up to `depth` IN transfers in flight on one endpoint and collects the received bytes.
+pub(crate) struct InQueue {
+    endpoint: u8,
+    transfer_type: TransferType,
+    transfer_len: usize,
+    depth: usize,
+    in_flight: VecDeque<TransferFuture<Vec<u8>>>,
+    /// Received bytes not yet taken by `take_packet`.
+    buf: Vec<u8>,
+}
+impl core::fmt::Debug for InQueue {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("InQueue")
+            .field("endpoint", &self.endpoint)
+            .field("transfer_type", &self.transfer_type)
+            .field("transfer_len", &self.transfer_len)
+            .field("depth", &self.depth)
+            .field("in_flight", &self.in_flight.len())
+            .field("buffered", &self.buf.len())
+            .finish()
+    }
+}
+impl InQueue {
+    pub(crate) fn new(
+        endpoint: u8,
+        transfer_type: TransferType,
+        transfer_len: usize,
+        depth: usize,
+    ) -> InQueue {
+        assert!(depth > 0, "need at least one transfer in flight");
+        InQueue {
+            endpoint,
+            transfer_type,
+            transfer_len,
+            depth,
+            in_flight: VecDeque::with_capacity(depth),
+            buf: Vec::new(),
+        }
+    }
+    pub(crate) fn in_flight(&self) -> usize {
+        self.in_flight.len()
+    }
+    fn submit<D: UsbDevice>(&mut self, device: &mut D) {
+        let depth = self.depth.min(device.max_in_flight()).max(1);
+        while self.in_flight.len() < depth {
+            let transfer = match self.transfer_type {
+                TransferType::Interrupt => device.interrupt_in(self.endpoint, self.transfer_len),
+                TransferType::Bulk => device.bulk_in(self.endpoint, self.transfer_len),
+            };
+            self.in_flight.push_back(transfer);
+        }
+    }
+    /// Waits for the oldest transfer to complete, appends what it received and starts another
+    /// transfer in its place.
+    pub(crate) fn poll_receive<D: UsbDevice>(
+        &mut self,
+        device: &mut D,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), Error>> {
+        loop {
+            self.submit(device);
+            let result = match self.in_flight.front_mut() {
+                Some(transfer) => match Pin::new(transfer).poll(cx) {
+                    Poll::Ready(result) => result,
+                    Poll::Pending => return Poll::Pending,
+                },
+                None => unreachable!("depth is at least 1"),
+            };
+            self.in_flight.pop_front();
+            self.submit(device);
+            match result {
+                Ok(bytes) => {
+                    self.buf.extend_from_slice(&bytes);
+                    return Poll::Ready(Ok(()));
+                }
+                Err(Error(IOError::TimedOut)) => (),
+                Err(e) => return Poll::Ready(Err(e)),
+            }
+        }
+    }
+    /// Removes the first packet from the received bytes if all of it has arrived. `packet_len`
+    /// returns the whole length of the packet starting at the front of the buffer or `None` if
+    /// the header isn't complete yet.
+    pub(crate) fn take_packet(
+        &mut self,
+        packet_len: impl FnOnce(&[u8]) -> Option<usize>,
+    ) -> Option<Box<[u8]>> {
+        let len = packet_len(&self.buf)?;
+        if self.buf.len() < len {
+            return None;
+        }
+        let rest = self.buf.split_off(len);
+        Some(core::mem::replace(&mut self.buf, rest).into_boxed_slice())
+    }
+}
+/// Claimed libusb device. Releases the interface when the last transfer referencing it is gone.
+struct Handle {
+    device: SingleTransferDevice,
+    interface: u8,
+}
+impl Drop for Handle {
+    fn drop(&mut self) {
+        let _ = self
+            .device
+            .device_mut()
+            .handle_mut()
+            .release_interface(self.interface);
+    }
+}
+/// [`UsbDevice`] backed by libusb (through `usbw`).
+///
+/// `usbw` only offers `SingleTransferDevice`, which runs one blocking-style transfer at a time,
+/// so this doesn't keep several transfers in flight: transfers take turns behind a lock and
+/// [`UsbDevice::max_in_flight`] is 1. IN transfers give up after [`LibusbDevice::IN_TIMEOUT`]
+/// so a quiet endpoint doesn't hold up commands, which means an idle adapter polls the device
+/// and a command can wait for up to one timed out read per IN endpoint. Concurrent transfers
+/// need asynchronous transfer submission in `usbw`.
+///
+/// `usbw` drops whatever a timed out transfer already received, so IN transfers are one max
+/// packet long (see [`LibusbDevice::set_max_packet_sizes`]). A transfer that size completes on
+/// the first packet, so a timeout means nothing arrived.
+///
+/// `usbw` doesn't expose the endpoint descriptors, so the packet sizes start at the full speed
+/// values from the spec instead of the endpoints' `wMaxPacketSize`. High speed devices (512 byte
+/// bulk packets) need [`LibusbDevice::set_max_packet_sizes`] before the first read or bulk reads
+/// fail with `IOError::Overflow`.
+pub struct LibusbDevice {
+    handle: Rc<Mutex<Handle>>,
+    device_descriptor: DeviceDescriptor,
+    interrupt_packet_len: usize,
+    bulk_packet_len: usize,
+}
+impl core::fmt::Debug for LibusbDevice {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "LibusbDevice({:?})", self.device_descriptor)
+    }
+}
+impl LibusbDevice {
+    /// Timeout for OUT transfers. If expired, it'll return `IOError::TimedOut`.
+    pub const OUT_TIMEOUT: Duration = Duration::from_secs(5);
+    pub const IN_TIMEOUT: Duration = Duration::from_millis(10);
+    /// `wMaxPacketSize` of the interrupt IN endpoint according to Bluetooth Core Spec v5.2 Vol 4
+    /// Part B 2.1.1.
+    pub const INTERRUPT_PACKET_LEN: usize = 16;
+    /// `wMaxPacketSize` of the bulk IN endpoint of a full speed device according to Bluetooth
+    /// Core Spec v5.2 Vol 4 Part B 2.1.1. Wrong for high speed devices (512).
+    pub const BULK_PACKET_LEN: usize = 64;
+    /// Wraps a device whose `interface` has already been claimed.
+    pub fn new(
+        device_descriptor: DeviceDescriptor,
+        device: AsyncDevice,
+        interface: u8,
+    ) -> LibusbDevice {
+        LibusbDevice {
+            handle: Rc::new(Mutex::new(Handle {
+                device: SingleTransferDevice::new(device),
+                interface,
+            })),
+            device_descriptor,
+            interrupt_packet_len: Self::INTERRUPT_PACKET_LEN,
+            bulk_packet_len: Self::BULK_PACKET_LEN,
+        }
+    }
+    /// Sets the `wMaxPacketSize` of the interrupt and bulk IN endpoints (ex: 512 for the bulk
+    /// endpoint of a high speed device). Reads bigger than a packet can lose data on timeout and
+    /// smaller ones fail with `IOError::Overflow`.
+    pub fn set_max_packet_sizes(&mut self, interrupt: usize, bulk: usize) {
+        self.interrupt_packet_len = interrupt;
+        self.bulk_packet_len = bulk;
+    }
+    pub fn device_descriptor(&self) -> &DeviceDescriptor {
+        &self.device_descriptor
+    }
+    pub async fn get_string_descriptor_ascii(&self, index: u8) -> Result<String, Error> {
+        // Note, uses device's primary language and replaces any UTF-8 with '?'.
+        // (According to libusb)
+        let handle = self.handle.lock().await;
+        Ok(handle
+            .device
+            .device()
+            .get_string_descriptor_ascii(index)
+            .await?)
+    }
+    pub async fn reset(&self) -> Result<(), Error> {
+        self.handle
+            .lock()
+            .await
+            .device
+            .device()
+            .handle_ref()
+            .reset()?;
+        Ok(())
+    }
+    /// Reads and drops whatever the device has queued on `endpoint` (ex: events left over from a
+    /// previous user). Stops once nothing arrives for `timeout`.
+    pub async fn flush_interrupt(&self, endpoint: u8, timeout: Duration) -> Result<(), Error> {
+        let mut handle = self.handle.lock().await;
+        let mut buf = vec![0_u8; 256];
+        loop {
+            match handle
+                .device
+                .interrupt_read(endpoint, buf.as_mut_slice(), timeout)
+                .await
+            {
+                Ok(0) | Err(usbw::libusb::error::Error::Timeout) => return Ok(()),
+                Ok(_) => (),
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+}
+impl UsbDevice for LibusbDevice {
+    fn max_in_flight(&self) -> usize {
+        1
+    }
+
+    fn control_out(&mut self, setup: ControlSetup, data: Box<[u8]>) -> TransferFuture<usize> {
+        let handle = self.handle.clone();
+        Box::pin(async move {
+            Ok(handle
+                .lock()
+                .await
+                .device
+                .control_write(
+                    setup.request_type,
+                    setup.request,
+                    setup.value,
+                    setup.index,
+                    &data[..],
+                    Self::OUT_TIMEOUT,
+                )
+                .await?)
+        })
+    }
+
+    fn interrupt_in(&mut self, endpoint: u8, len: usize) -> TransferFuture<Vec<u8>> {
+        let handle = self.handle.clone();
+        let len = len.min(self.interrupt_packet_len);
+        Box::pin(async move {
+            let mut buf = vec![0_u8; len];
+            let len = handle
+                .lock()
+                .await
+                .device
+                .interrupt_read(endpoint, buf.as_mut_slice(), Self::IN_TIMEOUT)
+                .await?;
+            buf.truncate(len);
+            Ok(buf)
+        })
+    }
+
+    fn bulk_in(&mut self, endpoint: u8, len: usize) -> TransferFuture<Vec<u8>> {
+        let handle = self.handle.clone();
+        let len = len.min(self.bulk_packet_len);
+        Box::pin(async move {
+            let mut buf = vec![0_u8; len];
+            let len = handle
+                .lock()
+                .await
+                .device
+                .bulk_read(endpoint, buf.as_mut_slice(), Self::IN_TIMEOUT)
+                .await?;
+            buf.truncate(len);
+            Ok(buf)
+        })
+    }
+
+    fn bulk_out(&mut self, endpoint: u8, data: Box<[u8]>) -> TransferFuture<usize> {
+        let handle = self.handle.clone();
+        Box::pin(async move {
+            Ok(handle
+                .lock()
+                .await
+                .device
+                .bulk_write(endpoint, &data[..], Self::OUT_TIMEOUT)
+                .await?)
+        })
+    }
+}
@@ -0,0 +1,239 @@
+//! Linux VHCI (`/dev/vhci`, the `hci_vhci` kernel module) backend. Registers a virtual
+//! controller with the kernel so BlueZ sees it as `hciN` while a [`VirtualController`] answers
+//! the HCI traffic. Lets the `bluez_socket` code paths run in containers without hardware.
+//!
+//! The kernel side is a character device. Every read returns one Host to Controller packet and
+//! every write takes one Controller to Host packet (packet type byte first).
+use crate::error::IOError;
+use crate::hci::iso::ISOHeader;
+use crate::hci::packet::{PacketType, RawPacket};
+use crate::hci::virtual_controller::VirtualController;
+use core::convert::TryFrom;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use tokio::io::unix::AsyncFd;
+use tokio::io::Interest;
+
+pub const VHCI_PATH: &str = "/dev/vhci";
+/// Biggest packet (including the packet type byte) read from the device.
+const READ_BUF_LEN: usize = 1 + ISOHeader::BYTE_LEN + ISOHeader::MAX_DATA_LOAD_LEN as usize;
+/// Length of the vendor packet the kernel answers a create request with (packet type, device
+/// type, controller index).
+const CREATE_RESPONSE_LEN: usize = 4;
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+#[repr(u8)]
+pub enum DeviceType {
+    Primary = 0x00,
+    AMP = 0x01,
+}
+impl From<DeviceType> for u8 {
+    fn from(device_type: DeviceType) -> Self {
+        device_type as u8
+    }
+}
+async fn read_bytes(fd: &AsyncFd<File>, buf: &mut [u8]) -> Result<usize, IOError> {
+    Ok(fd
+        .async_io(Interest::READABLE, |file| (&*file).read(buf))
+        .await?)
+}
+/// Registered VHCI controller. The controller goes away when this is dropped. Must be used
+/// inside a tokio runtime.
+#[derive(Debug)]
+pub struct VhciDevice {
+    fd: AsyncFd<File>,
+    index: u16,
+    read_buf: Box<[u8]>,
+}
+impl VhciDevice {
+    /// Opens [`VHCI_PATH`] and registers a controller of `device_type`.
+    pub async fn open(device_type: DeviceType) -> Result<VhciDevice, IOError> {
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_NONBLOCK | libc::O_CLOEXEC)
+            .open(VHCI_PATH)?;
+        Self::from_file(file, device_type).await
+    }
+    /// Registers a controller through an already open VHCI device (or anything that behaves like
+    /// one, ex: a `SOCK_SEQPACKET` socket in tests).
+    pub async fn from_file(file: File, device_type: DeviceType) -> Result<VhciDevice, IOError> {
+        let fd = file.as_raw_fd();
+        let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+        if flags < 0 || unsafe { libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) } < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        let mut device = VhciDevice {
+            fd: AsyncFd::new(file)?,
+            index: 0,
+            read_buf: vec![0_u8; READ_BUF_LEN].into_boxed_slice(),
+        };
+        device
+            .write_bytes(&[PacketType::Vendor.into(), device_type.into()])
+            .await?;
+        let mut response = [0_u8; CREATE_RESPONSE_LEN];
+        let len = read_bytes(&device.fd, &mut response[..]).await?;
+        if len != CREATE_RESPONSE_LEN
+            || response[0] != u8::from(PacketType::Vendor)
+            || response[1] != u8::from(device_type)
+        {
+            return Err(IOError::InvalidData);
+        }
+        device.index = u16::from_le_bytes([response[2], response[3]]);
+        Ok(device)
+    }
+    /// Kernel controller index (the `N` in `hciN`).
+    pub fn index(&self) -> u16 {
+        self.index
+    }
+    async fn write_bytes(&mut self, buf: &[u8]) -> Result<(), IOError> {
+        let len = self
+            .fd
+            .async_io(Interest::WRITABLE, |file| (&*file).write(buf))
+            .await?;
+        if len == buf.len() {
+            Ok(())
+        } else {
+            Err(IOError::Overflow)
+        }
+    }
+    /// Reads the next Host to Controller packet.
+    pub async fn read_packet(&mut self) -> Result<RawPacket<Box<[u8]>>, IOError> {
+        let len = read_bytes(&self.fd, &mut self.read_buf[..]).await?;
+        if len == 0 {
+            return Err(IOError::Closed);
+        }
+        let packet =
+            RawPacket::try_from(&self.read_buf[..len]).map_err(|_| IOError::InvalidData)?;
+        Ok(packet.clone_buf())
+    }
+    /// Sends a Controller to Host packet.
+    pub async fn write_packet(&mut self, packet: RawPacket<&[u8]>) -> Result<(), IOError> {
+        let bytes: Box<[u8]> = packet.pack().expect("boxed slices have no max length");
+        self.write_bytes(&bytes).await
+    }
+}
+/// [`VirtualController`] exposed to the kernel through a [`VhciDevice`].
+#[derive(Debug)]
+pub struct VhciController {
+    device: VhciDevice,
+    controller: VirtualController,
+}
+impl VhciController {
+    pub fn new(device: VhciDevice, controller: VirtualController) -> VhciController {
+        VhciController { device, controller }
+    }
+    /// Opens [`VHCI_PATH`] and registers `controller` as a primary controller.
+    pub async fn open(controller: VirtualController) -> Result<VhciController, IOError> {
+        Ok(Self::new(
+            VhciDevice::open(DeviceType::Primary).await?,
+            controller,
+        ))
+    }
+    pub fn index(&self) -> u16 {
+        self.device.index()
+    }
+    pub fn controller(&self) -> &VirtualController {
+        &self.controller
+    }
+    pub fn controller_mut(&mut self) -> &mut VirtualController {
+        &mut self.controller
+    }
+    pub fn into_inner(self) -> (VhciDevice, VirtualController) {
+        (self.device, self.controller)
+    }
+    /// Sends every queued Controller to Host packet.
+    pub async fn flush(&mut self) -> Result<(), IOError> {
+        while let Some(packet) = self.controller.pop_packet() {
+            self.device.write_packet(packet.as_ref()).await?;
+        }
+        Ok(())
+    }
+    /// Handles the next Host to Controller packet and sends the controller's answers.
+    pub async fn step(&mut self) -> Result<(), IOError> {
+        self.flush().await?;
+        let packet = self.device.read_packet().await?;
+        self.controller.handle_packet(packet.as_ref());
+        self.flush().await
+    }
+    /// Serves the kernel until the device fails (or is closed).
+    pub async fn run(&mut self) -> Result<(), IOError> {
+        loop {
+            self.step().await?;
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hci::info::InformationalOpcode;
+    use crate::hci::Opcode;
+    use crate::BTAddress;
+    use std::os::unix::io::FromRawFd;
+
+    /// `SOCK_SEQPACKET` socket pair standing in for `/dev/vhci` (keeps packet boundaries like the
+    /// char device). Returns `(our end, fake kernel end)`.
+    fn fake_vhci() -> (File, File) {
+        let mut fds = [0 as libc::c_int; 2];
+        let r = unsafe {
+            libc::socketpair(
+                libc::AF_UNIX,
+                libc::SOCK_SEQPACKET | libc::SOCK_CLOEXEC,
+                0,
+                fds.as_mut_ptr(),
+            )
+        };
+        assert_eq!(r, 0, "socketpair failed");
+        unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) }
+    }
+    fn read_kernel(kernel: &mut File) -> Vec<u8> {
+        let mut buf = [0_u8; 64];
+        let len = kernel.read(&mut buf[..]).unwrap();
+        buf[..len].to_vec()
+    }
+    #[test]
+    fn answers_kernel_through_fake_device() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_io()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let (file, mut kernel) = fake_vhci();
+            // The kernel assigns index 3.
+            kernel.write_all(&[0xFF, 0x00, 0x03, 0x00]).unwrap();
+            let device = VhciDevice::from_file(file, DeviceType::Primary)
+                .await
+                .unwrap();
+            assert_eq!(read_kernel(&mut kernel), [0xFF, 0x00]);
+            assert_eq!(device.index(), 3);
+
+            let address = BTAddress([0x01, 0x02, 0x03, 0x04, 0x05, 0x06]);
+            let mut vhci = VhciController::new(device, VirtualController::new(address));
+            // HCI_Reset
+            kernel.write_all(&[0x01, 0x03, 0x0C, 0x00]).unwrap();
+            vhci.step().await.unwrap();
+            assert_eq!(
+                read_kernel(&mut kernel),
+                [0x04, 0x0E, 0x04, 0x01, 0x03, 0x0C, 0x00]
+            );
+            // HCI_Read_BD_ADDR
+            kernel.write_all(&[0x01, 0x09, 0x10, 0x00]).unwrap();
+            vhci.step().await.unwrap();
+            assert_eq!(
+                read_kernel(&mut kernel),
+                [0x04, 0x0E, 0x0A, 0x01, 0x09, 0x10, 0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06]
+            );
+            // Unknown (vendor) command
+            kernel.write_all(&[0x01, 0x01, 0xFC, 0x00]).unwrap();
+            vhci.step().await.unwrap();
+            assert_eq!(
+                read_kernel(&mut kernel),
+                [0x04, 0x0E, 0x04, 0x01, 0x01, 0xFC, 0x01]
+            );
+            let opcodes: Vec<Opcode> = vhci.controller().commands().map(|c| c.opcode).collect();
+            assert_eq!(opcodes.len(), 3);
+            assert_eq!(opcodes[1], InformationalOpcode::ReadBDADDR.into());
+        });
+    }
+}
@@ -0,0 +1,241 @@
+//! Software HCI controller. Answers HCI commands from a table of Command Complete return
+//! parameters and records every packet the host sends. Backs `hci::vhci` devices and lets host
+//! code (ex: vendor setup sequences) be tested without hardware.
+use crate::bytes::Storage;
+use crate::hci::acl::ACLPacket;
+use crate::hci::adapter::{self, Adapter};
+use crate::hci::baseband::ControllerBasebandOpcode;
+use crate::hci::command::CommandPacket;
+use crate::hci::event::{EventCode, EventPacket};
+use crate::hci::info::InformationalOpcode;
+use crate::hci::iso::ISOPacket;
+use crate::hci::le::LEControllerOpcode;
+use crate::hci::packet::{PacketType, RawPacket};
+use crate::hci::{ErrorCode, Opcode, StreamError};
+use crate::{BTAddress, LocalBoxFuture};
+use alloc::collections::{BTreeMap, VecDeque};
+use core::convert::TryFrom;
+
+/// Biggest return parameters that fit in a Command Complete event.
+pub const MAX_RETURN_PARAMETERS_LEN: usize = 255 - 3;
+/// ACL data length and packet count reported by the default Read Buffer Size responses.
+const ACL_DATA_LEN: u16 = 251;
+const ACL_PACKETS: u8 = 8;
+/// Bluetooth 5.2 (HCI and LMP version).
+const VERSION: u8 = 0x0B;
+/// Company ID of the Linux Foundation.
+const MANUFACTURER: u16 = 0x05F1;
+#[derive(Clone, Debug)]
+pub struct VirtualController {
+    address: BTAddress,
+    responses: BTreeMap<Opcode, Box<[u8]>>,
+    received: Vec<RawPacket<Box<[u8]>>>,
+    pending: VecDeque<RawPacket<Box<[u8]>>>,
+}
+impl VirtualController {
+    /// LE only controller with the public `address`. Answers the commands a host needs to bring
+    /// it up (Reset, Set Event Mask, the Read Local ... commands, Read Buffer Size, ...). Commands
+    /// without a response get a Command Complete with `ErrorCode::UnknownHCICommand`.
+    pub fn new(address: BTAddress) -> VirtualController {
+        let mut controller = VirtualController {
+            address,
+            responses: BTreeMap::new(),
+            received: Vec::new(),
+            pending: VecDeque::new(),
+        };
+        let ok = u8::from(ErrorCode::Ok);
+        let acl_len = ACL_DATA_LEN.to_le_bytes();
+        let mut version = [ok, VERSION, 0, 0, VERSION, 0, 0, 0, 0];
+        version[5..7].copy_from_slice(&MANUFACTURER.to_le_bytes());
+        let mut supported_commands = [0xFF_u8; 1 + 64];
+        supported_commands[0] = ok;
+        // BR/EDR Not Supported (bit 37) and LE Supported (Controller) (bit 38).
+        let features = [ok, 0, 0, 0, 0, 0x60, 0, 0, 0];
+        let mut bd_addr = [ok; 1 + BTAddress::LEN];
+        bd_addr[1..].copy_from_slice(&address.0[..]);
+        let defaults: [(Opcode, &[u8]); 12] = [
+            (ControllerBasebandOpcode::Reset.into(), &[ok]),
+            (ControllerBasebandOpcode::SetEventMask.into(), &[ok]),
+            (
+                InformationalOpcode::ReadLocalVersionInformation.into(),
+                &version,
+            ),
+            (
+                InformationalOpcode::ReadLocalSupportedCommands.into(),
+                &supported_commands,
+            ),
+            (
+                InformationalOpcode::ReadLocalSupportedFeatures.into(),
+                &features,
+            ),
+            (
+                InformationalOpcode::ReadBufferSize.into(),
+                &[ok, acl_len[0], acl_len[1], 0, ACL_PACKETS, 0, 0, 0],
+            ),
+            (InformationalOpcode::ReadBDADDR.into(), &bd_addr),
+            (LEControllerOpcode::SetEventMask.into(), &[ok]),
+            (
+                LEControllerOpcode::ReadBufferSizeV1.into(),
+                &[ok, acl_len[0], acl_len[1], ACL_PACKETS],
+            ),
+            (
+                LEControllerOpcode::ReadLocalSupportedFeatures.into(),
+                &[ok, 0, 0, 0, 0, 0, 0, 0, 0],
+            ),
+            (
+                LEControllerOpcode::ReadSupportedState.into(),
+                &[ok, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF],
+            ),
+            (LEControllerOpcode::SetRandomAddress.into(), &[ok]),
+        ];
+        for (opcode, return_parameters) in &defaults {
+            controller.set_response(*opcode, return_parameters);
+        }
+        controller
+    }
+    pub fn address(&self) -> BTAddress {
+        self.address
+    }
+    /// Answer commands with `opcode` with a Command Complete carrying `return_parameters`
+    /// (status first).
+    /// # Panics
+    /// Panics if `return_parameters` is longer than [`MAX_RETURN_PARAMETERS_LEN`].
+    pub fn set_response(&mut self, opcode: Opcode, return_parameters: &[u8]) {
+        assert!(
+            return_parameters.len() <= MAX_RETURN_PARAMETERS_LEN,
+            "return parameters too long"
+        );
+        self.responses.insert(opcode, return_parameters.into());
+    }
+    pub fn remove_response(&mut self, opcode: Opcode) {
+        self.responses.remove(&opcode);
+    }
+    /// Every packet received from the host (in order).
+    pub fn received(&self) -> &[RawPacket<Box<[u8]>>] {
+        &self.received
+    }
+    /// Every well formed command received from the host (in order).
+    pub fn commands(&self) -> impl Iterator<Item = CommandPacket<&[u8]>> + '_ {
+        self.received
+            .iter()
+            .filter_map(|packet| CommandPacket::try_from(packet.as_ref()).ok())
+    }
+    pub fn clear_received(&mut self) {
+        self.received.clear();
+    }
+    /// Queues a Controller to Host packet (ex: an unsolicited event).
+    pub fn queue_packet(&mut self, packet: RawPacket<Box<[u8]>>) {
+        self.pending.push_back(packet);
+    }
+    /// Next queued Controller to Host packet.
+    pub fn pop_packet(&mut self) -> Option<RawPacket<Box<[u8]>>> {
+        self.pending.pop_front()
+    }
+    /// Handles a Host to Controller packet. Commands are answered with a Command Complete event
+    /// (see [`VirtualController::set_response`]). Data packets are only recorded.
+    pub fn handle_packet(&mut self, packet: RawPacket<&[u8]>) {
+        self.received.push(packet.clone_buf());
+        let opcode = match CommandPacket::try_from(packet) {
+            Ok(command) => command.opcode,
+            Err(_) => return,
+        };
+        let mut parameters = vec![1_u8, 0, 0];
+        opcode
+            .pack(&mut parameters[1..3])
+            .expect("hardcoded array length");
+        match self.responses.get(&opcode) {
+            Some(return_parameters) => parameters.extend_from_slice(return_parameters),
+            None => parameters.push(ErrorCode::UnknownHCICommand.into()),
+        }
+        let event = EventPacket::new(EventCode::CommandComplete, parameters.as_slice());
+        self.pending.push_back(event.to_raw_packet());
+    }
+    /// Removes the first queued packet of `packet_type`.
+    fn pop_packet_type(&mut self, packet_type: PacketType) -> Option<Box<[u8]>> {
+        let position = self
+            .pending
+            .iter()
+            .position(|packet| packet.packet_type == packet_type)?;
+        self.pending.remove(position).map(|packet| packet.buf)
+    }
+}
+/// Host side view of the controller. Reads wait forever when nothing of that type is queued
+/// (packets only get queued by writes or [`VirtualController::queue_packet`]).
+impl Adapter for VirtualController {
+    fn write_command<'s, 'p: 's>(
+        &'s mut self,
+        packet: CommandPacket<&'p [u8]>,
+    ) -> LocalBoxFuture<'s, Result<(), adapter::Error>> {
+        let raw = packet.to_raw_packet::<Box<[u8]>>();
+        Box::pin(async move {
+            self.handle_packet(raw.as_ref());
+            Ok(())
+        })
+    }
+
+    fn read_event<'s, 'p: 's, S: Storage<u8> + 'p>(
+        &'s mut self,
+    ) -> LocalBoxFuture<'s, Result<EventPacket<S>, adapter::Error>> {
+        Box::pin(async move {
+            match self.pop_packet_type(PacketType::Event) {
+                Some(buf) => {
+                    let event = EventPacket::try_from(RawPacket {
+                        packet_type: PacketType::Event,
+                        buf: buf.as_ref(),
+                    })
+                    .map_err(StreamError::EventError)?;
+                    Ok(event.to_new_storage())
+                }
+                None => futures_util::future::pending().await,
+            }
+        })
+    }
+
+    fn write_acl<'s, 'p: 's>(
+        &'s mut self,
+        packet: ACLPacket<&'p [u8]>,
+    ) -> LocalBoxFuture<'s, Result<(), adapter::Error>> {
+        let raw = packet.to_raw_packet::<Box<[u8]>>();
+        Box::pin(async move {
+            self.handle_packet(raw.map_err(StreamError::DataError)?.as_ref());
+            Ok(())
+        })
+    }
+
+    fn read_acl<'s, 'p: 's, S: Storage<u8> + 'p>(
+        &'s mut self,
+    ) -> LocalBoxFuture<'s, Result<ACLPacket<S>, adapter::Error>> {
+        Box::pin(async move {
+            match self.pop_packet_type(PacketType::ACLData) {
+                Some(buf) => Ok(ACLPacket::unpack_from(buf.as_ref())
+                    .map_err(StreamError::DataError)?
+                    .to_new_storage()),
+                None => futures_util::future::pending().await,
+            }
+        })
+    }
+
+    fn write_iso<'s, 'p: 's>(
+        &'s mut self,
+        packet: ISOPacket<&'p [u8]>,
+    ) -> LocalBoxFuture<'s, Result<(), adapter::Error>> {
+        let raw = packet.to_raw_packet::<Box<[u8]>>();
+        Box::pin(async move {
+            self.handle_packet(raw.map_err(StreamError::DataError)?.as_ref());
+            Ok(())
+        })
+    }
+
+    fn read_iso<'s, 'p: 's, S: Storage<u8> + 'p>(
+        &'s mut self,
+    ) -> LocalBoxFuture<'s, Result<ISOPacket<S>, adapter::Error>> {
+        Box::pin(async move {
+            match self.pop_packet_type(PacketType::ISOData) {
+                Some(buf) => Ok(ISOPacket::unpack_from(buf.as_ref())
+                    .map_err(StreamError::DataError)?
+                    .to_new_storage()),
+                None => futures_util::future::pending().await,
+            }
+        })
+    }
+}
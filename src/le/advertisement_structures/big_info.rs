@@ -0,0 +1,185 @@
+//! BIGInfo AD structure. Sent in the `ACAD` of periodic advertisements so receivers can
+//! synchronize to a Broadcast Isochronous Group (BIG).
+use crate::le::advertisement::{
+    AdStructureType, AdType, ConstAdStructType, UnpackableAdStructType,
+};
+use crate::le::phy::PHY;
+use crate::PackError;
+use core::convert::TryInto;
+
+/// Group Initialization Vector and Group Session Key Diversifier of an encrypted BIG.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash, Default)]
+pub struct BIGEncryption {
+    pub giv: [u8; 8],
+    pub gskd: [u8; 16],
+}
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct BIGInfo {
+    /// Offset to the next BIG anchor point in units of 30 µs (or 300 µs if `big_offset_units`
+    /// is set). 14-bit.
+    pub big_offset: u16,
+    pub big_offset_units: bool,
+    /// ISO interval in 1.25 ms units. 12-bit.
+    pub iso_interval: u16,
+    /// Number of BISes in the BIG. 5-bit.
+    pub num_bis: u8,
+    /// Number of subevents per BIS in each interval. 5-bit.
+    pub nse: u8,
+    /// Burst number. 3-bit.
+    pub bn: u8,
+    /// Time between subevents in microseconds. 20-bit.
+    pub sub_interval: u32,
+    /// Pre-transmission offset. 4-bit.
+    pub pto: u8,
+    /// Time between the start of consecutive BISes in microseconds. 20-bit.
+    pub bis_spacing: u32,
+    /// Immediate repetition count. 4-bit.
+    pub irc: u8,
+    pub max_pdu: u8,
+    pub seed_access_address: u32,
+    /// SDU interval in microseconds. 20-bit.
+    pub sdu_interval: u32,
+    /// 12-bit.
+    pub max_sdu: u16,
+    pub base_crc_init: u16,
+    /// 37-bit data channel map.
+    pub channel_map: u64,
+    pub phy: PHY,
+    /// 39-bit.
+    pub bis_payload_count: u64,
+    pub framed: bool,
+    pub encryption: Option<BIGEncryption>,
+}
+impl BIGInfo {
+    pub const AD_TYPE: AdType = AdType::BIGInfo;
+    pub const UNENCRYPTED_BYTE_LEN: usize = 33;
+    pub const ENCRYPTED_BYTE_LEN: usize = Self::UNENCRYPTED_BYTE_LEN + 8 + 16;
+    const CHANNEL_MAP_MASK: u64 = (1 << 37) - 1;
+    const BIS_PAYLOAD_COUNT_MASK: u64 = (1 << 39) - 1;
+}
+/// The BIGInfo `PHY` field is `PHY - 1` (0 = LE 1M).
+fn phy_from_bits(bits: u8) -> Option<PHY> {
+    match bits {
+        0 => Some(PHY::LE1M),
+        1 => Some(PHY::LE2M),
+        2 => Some(PHY::LECoded),
+        _ => None,
+    }
+}
+fn u40_from_le(buf: &[u8]) -> u64 {
+    let mut bytes = [0_u8; 8];
+    bytes[..5].copy_from_slice(&buf[..5]);
+    u64::from_le_bytes(bytes)
+}
+impl AdStructureType for BIGInfo {
+    fn ad_type(&self) -> AdType {
+        Self::AD_TYPE
+    }
+
+    fn byte_len(&self) -> usize {
+        if self.encryption.is_some() {
+            Self::ENCRYPTED_BYTE_LEN
+        } else {
+            Self::UNENCRYPTED_BYTE_LEN
+        }
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(self.byte_len(), buf)?;
+        if self.big_offset >= 1 << 14
+            || self.iso_interval >= 1 << 12
+            || self.num_bis >= 1 << 5
+            || self.nse >= 1 << 5
+            || self.bn >= 1 << 3
+            || self.sub_interval >= 1 << 20
+            || self.pto >= 1 << 4
+            || self.bis_spacing >= 1 << 20
+            || self.irc >= 1 << 4
+            || self.sdu_interval >= 1 << 20
+            || self.max_sdu >= 1 << 12
+            || self.channel_map > Self::CHANNEL_MAP_MASK
+            || self.bis_payload_count > Self::BIS_PAYLOAD_COUNT_MASK
+        {
+            return Err(PackError::InvalidFields);
+        }
+        let first = u32::from(self.big_offset)
+            | (u32::from(self.big_offset_units) << 14)
+            | (u32::from(self.iso_interval) << 15)
+            | (u32::from(self.num_bis) << 27);
+        buf[0..4].copy_from_slice(&first.to_le_bytes());
+        buf[4] = self.nse | (self.bn << 5);
+        let sub_interval = self.sub_interval | (u32::from(self.pto) << 20);
+        buf[5..8].copy_from_slice(&sub_interval.to_le_bytes()[..3]);
+        let bis_spacing = self.bis_spacing | (u32::from(self.irc) << 20);
+        buf[8..11].copy_from_slice(&bis_spacing.to_le_bytes()[..3]);
+        buf[11] = self.max_pdu;
+        buf[12] = 0;
+        buf[13..17].copy_from_slice(&self.seed_access_address.to_le_bytes());
+        let sdu = self.sdu_interval | (u32::from(self.max_sdu) << 20);
+        buf[17..21].copy_from_slice(&sdu.to_le_bytes());
+        buf[21..23].copy_from_slice(&self.base_crc_init.to_le_bytes());
+        let channel_map = self.channel_map | (u64::from(u8::from(self.phy) - 1) << 37);
+        buf[23..28].copy_from_slice(&channel_map.to_le_bytes()[..5]);
+        let payload_count = self.bis_payload_count | (u64::from(self.framed) << 39);
+        buf[28..33].copy_from_slice(&payload_count.to_le_bytes()[..5]);
+        if let Some(encryption) = &self.encryption {
+            buf[33..41].copy_from_slice(&encryption.giv);
+            buf[41..57].copy_from_slice(&encryption.gskd);
+        }
+        Ok(())
+    }
+}
+impl UnpackableAdStructType for BIGInfo {
+    fn unpack_from(ad_type: AdType, buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        if ad_type != Self::AD_TYPE {
+            return Err(PackError::InvalidFields);
+        }
+        let encryption = match buf.len() {
+            Self::UNENCRYPTED_BYTE_LEN => None,
+            Self::ENCRYPTED_BYTE_LEN => Some(BIGEncryption {
+                giv: buf[33..41].try_into().expect("length checked above"),
+                gskd: buf[41..57].try_into().expect("length checked above"),
+            }),
+            got => {
+                return Err(PackError::BadLength {
+                    expected: Self::UNENCRYPTED_BYTE_LEN,
+                    got,
+                })
+            }
+        };
+        let first = u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]);
+        let sub_interval = u32::from_le_bytes([buf[5], buf[6], buf[7], 0]);
+        let bis_spacing = u32::from_le_bytes([buf[8], buf[9], buf[10], 0]);
+        let sdu = u32::from_le_bytes([buf[17], buf[18], buf[19], buf[20]]);
+        let channel_map = u40_from_le(&buf[23..28]);
+        let payload_count = u40_from_le(&buf[28..33]);
+        Ok(BIGInfo {
+            big_offset: (first & 0x3FFF) as u16,
+            big_offset_units: first & (1 << 14) != 0,
+            iso_interval: ((first >> 15) & 0x0FFF) as u16,
+            num_bis: (first >> 27) as u8,
+            nse: buf[4] & 0x1F,
+            bn: buf[4] >> 5,
+            sub_interval: sub_interval & 0x000F_FFFF,
+            pto: (sub_interval >> 20) as u8,
+            bis_spacing: bis_spacing & 0x000F_FFFF,
+            irc: (bis_spacing >> 20) as u8,
+            max_pdu: buf[11],
+            seed_access_address: u32::from_le_bytes([buf[13], buf[14], buf[15], buf[16]]),
+            sdu_interval: sdu & 0x000F_FFFF,
+            max_sdu: (sdu >> 20) as u16,
+            base_crc_init: u16::from_le_bytes([buf[21], buf[22]]),
+            channel_map: channel_map & Self::CHANNEL_MAP_MASK,
+            phy: phy_from_bits((channel_map >> 37) as u8).ok_or(PackError::bad_index(27))?,
+            bis_payload_count: payload_count & Self::BIS_PAYLOAD_COUNT_MASK,
+            framed: payload_count & (1 << 39) != 0,
+            encryption,
+        })
+    }
+}
+impl ConstAdStructType for BIGInfo {
+    const AD_TYPE: AdType = AdType::BIGInfo;
+}
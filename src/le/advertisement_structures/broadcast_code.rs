@@ -0,0 +1,60 @@
+//! Broadcast Code AD structure. The 16 byte code used to encrypt a Broadcast Isochronous Group.
+use crate::le::advertisement::{
+    AdStructureType, AdType, ConstAdStructType, UnpackableAdStructType,
+};
+use crate::PackError;
+use core::convert::TryInto;
+
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Default, Debug, Hash)]
+pub struct BroadcastCode(pub [u8; BroadcastCode::BYTE_LEN]);
+impl BroadcastCode {
+    pub const AD_TYPE: AdType = AdType::BroadcastCode;
+    pub const BYTE_LEN: usize = 16;
+    /// Broadcast Code from a UTF-8 string (at most 16 bytes) padded with zeros. Returns `None`
+    /// if `code` is too long.
+    pub fn from_str_code(code: &str) -> Option<BroadcastCode> {
+        let code = code.as_bytes();
+        if code.len() > Self::BYTE_LEN {
+            return None;
+        }
+        let mut out = [0_u8; Self::BYTE_LEN];
+        out[..code.len()].copy_from_slice(code);
+        Some(BroadcastCode(out))
+    }
+}
+impl AsRef<[u8]> for BroadcastCode {
+    fn as_ref(&self) -> &[u8] {
+        &self.0[..]
+    }
+}
+impl AdStructureType for BroadcastCode {
+    fn ad_type(&self) -> AdType {
+        Self::AD_TYPE
+    }
+
+    fn byte_len(&self) -> usize {
+        Self::BYTE_LEN
+    }
+
+    fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        buf.copy_from_slice(&self.0);
+        Ok(())
+    }
+}
+impl UnpackableAdStructType for BroadcastCode {
+    fn unpack_from(ad_type: AdType, buf: &[u8]) -> Result<Self, PackError>
+    where
+        Self: Sized,
+    {
+        if ad_type != Self::AD_TYPE {
+            Err(PackError::InvalidFields)
+        } else {
+            PackError::expect_length(Self::BYTE_LEN, buf)?;
+            Ok(BroadcastCode(buf.try_into().expect("length checked above")))
+        }
+    }
+}
+impl ConstAdStructType for BroadcastCode {
+    const AD_TYPE: AdType = AdType::BroadcastCode;
+}
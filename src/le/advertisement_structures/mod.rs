@@ -2,12 +2,16 @@ use crate::bytes::Storage;
 use crate::le::advertisement::{AdStructureType, AdType, UnpackableAdStructType};
 use crate::PackError;
 
+pub mod big_info;
+pub mod broadcast_code;
 pub mod flags;
 pub mod local_name;
 pub mod manufacturer_data;
 pub mod tx_power_level;
 
 pub enum Structs<Buf> {
+    BIGInfo(big_info::BIGInfo),
+    BroadcastCode(broadcast_code::BroadcastCode),
     Flags(flags::Flags),
     LocalName(local_name::LocalName<Buf>),
     ManufacturerData(manufacturer_data::ManufacturerSpecificData<Buf>),
@@ -16,6 +20,8 @@ pub enum Structs<Buf> {
 impl<Buf: AsRef<[u8]>> AdStructureType for Structs<Buf> {
     fn ad_type(&self) -> AdType {
         match self {
+            Structs::BIGInfo(_) => big_info::BIGInfo::AD_TYPE,
+            Structs::BroadcastCode(_) => broadcast_code::BroadcastCode::AD_TYPE,
             Structs::Flags(_) => flags::Flags::AD_TYPE,
             Structs::LocalName(l) => l.ad_type(),
             Structs::ManufacturerData(_) => {
@@ -27,6 +33,8 @@ impl<Buf: AsRef<[u8]>> AdStructureType for Structs<Buf> {
 
     fn byte_len(&self) -> usize {
         match self {
+            Structs::BIGInfo(b) => b.byte_len(),
+            Structs::BroadcastCode(c) => c.byte_len(),
             Structs::Flags(f) => f.byte_len(),
             Structs::LocalName(l) => l.byte_len(),
             Structs::ManufacturerData(d) => d.byte_len(),
@@ -36,6 +44,8 @@ impl<Buf: AsRef<[u8]>> AdStructureType for Structs<Buf> {
 
     fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
         match self {
+            Structs::BIGInfo(b) => b.pack_into(buf),
+            Structs::BroadcastCode(c) => c.pack_into(buf),
             Structs::Flags(f) => f.pack_into(buf),
             Structs::LocalName(l) => l.pack_into(buf),
             Structs::ManufacturerData(d) => d.pack_into(buf),
@@ -49,6 +59,12 @@ impl<Buf: Storage<u8>> UnpackableAdStructType for Structs<Buf> {
         Self: Sized,
     {
         match ad_type {
+            AdType::BIGInfo => Ok(Structs::BIGInfo(big_info::BIGInfo::unpack_from(
+                ad_type, buf,
+            )?)),
+            AdType::BroadcastCode => Ok(Structs::BroadcastCode(
+                broadcast_code::BroadcastCode::unpack_from(ad_type, buf)?,
+            )),
             AdType::CompleteLocalName | AdType::ShortenLocalName => Ok(Structs::LocalName(
                 local_name::LocalName::unpack_from(ad_type, buf)?,
             )),
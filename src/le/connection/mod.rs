@@ -1,7 +1,11 @@
 pub mod central;
 
-use crate::ConversionError;
+use crate::le::advertiser::PeerAddressType;
+use crate::le::features::LEFeatures;
+use crate::le::phy::PHY;
+use crate::{BTAddress, ConversionError, PackError};
 use core::convert::TryFrom;
+use core::time::Duration;
 
 #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
 pub struct MTU(u16);
@@ -54,6 +58,15 @@ impl ConnectionInterval {
         }
     }
 }
+impl ConnectionInterval {
+    /// Interval in microseconds (1.25 ms units).
+    pub fn as_micros(self) -> u32 {
+        u32::from(self.0) * 1250
+    }
+    pub fn as_duration(self) -> Duration {
+        Duration::from_micros(self.as_micros().into())
+    }
+}
 impl From<ConnectionInterval> for u16 {
     fn from(i: ConnectionInterval) -> Self {
         i.0
@@ -127,7 +140,7 @@ impl From<ConnectionLatency> for u16 {
         l.0
     }
 }
-#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash, Default)]
 pub struct ConnectionHandle(u16);
 impl ConnectionHandle {
     pub fn new(value: u16) -> Self {
@@ -148,6 +161,15 @@ impl ConnectionHandle {
     pub const MIN: ConnectionHandle = ConnectionHandle(Self::MIN_U16);
     pub const MAX_U16: u16 = 0x0EFF;
     pub const MAX: ConnectionHandle = ConnectionHandle(Self::MAX_U16);
+    pub fn pack_into(self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        buf.copy_from_slice(&self.0.to_le_bytes());
+        Ok(())
+    }
+    pub fn unpack_from(buf: &[u8]) -> Result<Self, PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        Self::new_checked(u16::from_le_bytes([buf[0], buf[1]])).ok_or(PackError::bad_index(0))
+    }
 }
 
 impl From<ConnectionHandle> for u16 {
@@ -261,3 +283,162 @@ impl TryFrom<u8> for MasterClockAccuracy {
         }
     }
 }
+/// Host side state of an LE connection. Updated as the controller reports changes to it.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct Connection {
+    pub handle: ConnectionHandle,
+    pub role: Role,
+    pub peer_address_type: PeerAddressType,
+    pub peer_address: BTAddress,
+    pub interval: ConnectionInterval,
+    pub latency: ConnectionLatency,
+    pub supervision_timeout: SupervisionTimeout,
+    /// `PHY` used to transmit. Connections start on `PHY::LE1M`.
+    pub tx_phy: PHY,
+    /// `PHY` used to receive. Connections start on `PHY::LE1M`.
+    pub rx_phy: PHY,
+    pub data_length: DataLength,
+    /// LE features of the peer. `None` until read.
+    pub remote_features: Option<LEFeatures>,
+    /// Version of the peer. `None` until read.
+    pub remote_version: Option<RemoteVersion>,
+    /// Sleep clock accuracy of the peer. Known from the start if the peer is the master,
+    /// otherwise `None` until requested.
+    pub peer_sca: Option<MasterClockAccuracy>,
+}
+impl Connection {
+    /// Worst case window widening if `latency` connection events are skipped. `None` if
+    /// `peer_sca` isn't known yet. See [`window_widening`].
+    pub fn window_widening(&self, local_sca: MasterClockAccuracy) -> Option<Duration> {
+        let peer_sca = self.peer_sca?;
+        let events = u64::from(u16::from(self.latency)) + 1;
+        let since_anchor = u64::from(self.interval.as_micros()) * events;
+        let max = u64::from(self.interval.as_micros() / 2).saturating_sub(T_IFS_MICROS);
+        Some(window_widening(peer_sca, local_sca, since_anchor).min(Duration::from_micros(max)))
+    }
+}
+/// Inter Frame Space. Time between two consecutive packets on the same channel.
+pub const T_IFS_MICROS: u64 = 150;
+/// Window widening. How much earlier (and later) than expected the slave has to listen for the
+/// anchor point of the master after `since_anchor_micros` because of the drift of both sleep
+/// clocks. `((master_sca + slave_sca) / 1_000_000) * time_since_last_anchor`. Rounded up to
+/// the next microsecond.
+/// # Example
+/// ```
+/// use btle::le::connection::{window_widening, MasterClockAccuracy};
+/// use core::time::Duration;
+/// // (50 + 50) ppm over 1 second.
+/// assert_eq!(
+///     window_widening(MasterClockAccuracy::PPM50, MasterClockAccuracy::PPM50, 1_000_000),
+///     Duration::from_micros(100)
+/// );
+/// ```
+pub fn window_widening(
+    master_sca: MasterClockAccuracy,
+    slave_sca: MasterClockAccuracy,
+    since_anchor_micros: u64,
+) -> Duration {
+    let ppm = u64::from(master_sca.ppm()) + u64::from(slave_sca.ppm());
+    Duration::from_micros((ppm * since_anchor_micros).div_ceil(1_000_000))
+}
+/// Link Layer version information of a peer.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash, Default)]
+pub struct RemoteVersion {
+    /// Same values as the HCI `Version`.
+    pub version: u8,
+    /// Company identifier of the peer controller manufacturer.
+    pub company_id: u16,
+    pub subversion: u16,
+}
+/// Max Link Layer payload octets and air time (in microseconds) of a connection in each
+/// direction. Connections start at `DataLength::DEFAULT` until the Data Length Extension (DLE)
+/// procedure allows bigger payloads.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct DataLength {
+    pub max_tx_octets: u16,
+    pub max_tx_time: u16,
+    pub max_rx_octets: u16,
+    pub max_rx_time: u16,
+}
+impl DataLength {
+    pub const MIN_OCTETS: u16 = 27;
+    pub const MAX_OCTETS: u16 = 251;
+    pub const MIN_TIME: u16 = 328;
+    /// Max time on the LE Coded PHY. The uncoded PHYs only need 2120 µs for `MAX_OCTETS`.
+    pub const MAX_TIME: u16 = 17040;
+    pub const DEFAULT: DataLength = DataLength {
+        max_tx_octets: Self::MIN_OCTETS,
+        max_tx_time: Self::MIN_TIME,
+        max_rx_octets: Self::MIN_OCTETS,
+        max_rx_time: Self::MIN_TIME,
+    };
+    pub fn is_valid_octets(octets: u16) -> bool {
+        (Self::MIN_OCTETS..=Self::MAX_OCTETS).contains(&octets)
+    }
+    pub fn is_valid_time(time: u16) -> bool {
+        (Self::MIN_TIME..=Self::MAX_TIME).contains(&time)
+    }
+}
+impl Default for DataLength {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn connection(interval: u16, latency: u16) -> Connection {
+        Connection {
+            handle: ConnectionHandle::new(0x0040),
+            role: Role::Slave,
+            peer_address_type: PeerAddressType::Public,
+            peer_address: BTAddress::ZEROED,
+            interval: ConnectionInterval::new(interval),
+            latency: ConnectionLatency::new(latency),
+            supervision_timeout: SupervisionTimeout::new(0x0C80),
+            tx_phy: PHY::LE1M,
+            rx_phy: PHY::LE1M,
+            data_length: DataLength::DEFAULT,
+            remote_features: None,
+            remote_version: None,
+            peer_sca: Some(MasterClockAccuracy::PPM500),
+        }
+    }
+    #[test]
+    fn window_widening_rounds_up() {
+        // 40 ppm over 7.5 ms is 0.3 us.
+        assert_eq!(
+            window_widening(
+                MasterClockAccuracy::PPM20,
+                MasterClockAccuracy::PPM20,
+                7_500
+            ),
+            Duration::from_micros(1)
+        );
+        assert_eq!(
+            window_widening(MasterClockAccuracy::PPM20, MasterClockAccuracy::PPM20, 0),
+            Duration::from_micros(0)
+        );
+    }
+    #[test]
+    fn connection_window_widening() {
+        let mut c = connection(0x0018, 4);
+        // 1000 ppm over 5 events of 30 ms.
+        assert_eq!(
+            c.window_widening(MasterClockAccuracy::PPM500),
+            Some(Duration::from_micros(150))
+        );
+        c.peer_sca = None;
+        assert_eq!(c.window_widening(MasterClockAccuracy::PPM500), None);
+    }
+    #[test]
+    fn connection_window_widening_is_capped() {
+        // 1000 ppm over 500 events of 4 s would be 2 s, more than the 4 s interval allows.
+        let c = connection(0x0C80, 0x01F3);
+        assert_eq!(
+            c.window_widening(MasterClockAccuracy::PPM500),
+            Some(Duration::from_micros(4_000_000 / 2 - T_IFS_MICROS))
+        );
+    }
+}
@@ -0,0 +1,178 @@
+//! Constant Tone Extension (CTE). A tone appended to LE packets so the receiver can sample IQ
+//! data for direction finding (Angle of Arrival or Angle of Departure).
+use crate::{ConversionError, PackError};
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+#[repr(u8)]
+pub enum CTEType {
+    AoA = 0x00,
+    AoD1us = 0x01,
+    AoD2us = 0x02,
+}
+impl CTEType {
+    pub const BYTE_LEN: usize = 1;
+}
+impl From<CTEType> for u8 {
+    fn from(t: CTEType) -> Self {
+        t as u8
+    }
+}
+impl TryFrom<u8> for CTEType {
+    type Error = ConversionError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x00 => Ok(CTEType::AoA),
+            0x01 => Ok(CTEType::AoD1us),
+            0x02 => Ok(CTEType::AoD2us),
+            _ => Err(ConversionError(())),
+        }
+    }
+}
+/// Switching and sampling slot duration.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+#[repr(u8)]
+pub enum SlotDuration {
+    Slot1us = 0x01,
+    Slot2us = 0x02,
+}
+impl From<SlotDuration> for u8 {
+    fn from(s: SlotDuration) -> Self {
+        s as u8
+    }
+}
+impl TryFrom<u8> for SlotDuration {
+    type Error = ConversionError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x01 => Ok(SlotDuration::Slot1us),
+            0x02 => Ok(SlotDuration::Slot2us),
+            _ => Err(ConversionError(())),
+        }
+    }
+}
+/// CTE length in 8 µs units (16 µs to 160 µs).
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct CTELength(u8);
+impl CTELength {
+    pub const MIN_U8: u8 = 0x02;
+    pub const MAX_U8: u8 = 0x14;
+    pub const MIN: CTELength = CTELength(Self::MIN_U8);
+    pub const MAX: CTELength = CTELength(Self::MAX_U8);
+    pub fn new_checked(value: u8) -> Option<CTELength> {
+        if (Self::MIN_U8..=Self::MAX_U8).contains(&value) {
+            Some(CTELength(value))
+        } else {
+            None
+        }
+    }
+    /// Length in microseconds.
+    pub fn micros(self) -> u16 {
+        u16::from(self.0) * 8
+    }
+}
+impl From<CTELength> for u8 {
+    fn from(l: CTELength) -> Self {
+        l.0
+    }
+}
+/// Bit field of `CTEType`s. Used when more than one CTE type can be allowed.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash, Default)]
+pub struct CTETypes(u8);
+impl CTETypes {
+    pub const BYTE_LEN: usize = 1;
+    const ALL_BITS: u8 = 0b111;
+    pub const fn zeroed() -> CTETypes {
+        CTETypes(0)
+    }
+    pub const fn all() -> CTETypes {
+        CTETypes(Self::ALL_BITS)
+    }
+    fn bit(cte_type: CTEType) -> u8 {
+        1_u8 << u8::from(cte_type)
+    }
+    pub fn enable(&mut self, cte_type: CTEType) {
+        self.0 |= Self::bit(cte_type);
+    }
+    pub fn disable(&mut self, cte_type: CTEType) {
+        self.0 &= !Self::bit(cte_type);
+    }
+    pub fn get(self, cte_type: CTEType) -> bool {
+        self.0 & Self::bit(cte_type) != 0
+    }
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+}
+impl From<CTETypes> for u8 {
+    fn from(t: CTETypes) -> Self {
+        t.0
+    }
+}
+impl TryFrom<u8> for CTETypes {
+    type Error = ConversionError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        if value & !Self::ALL_BITS == 0 {
+            Ok(CTETypes(value))
+        } else {
+            Err(ConversionError(()))
+        }
+    }
+}
+/// Antenna switching pattern. The antenna IDs in the order they are switched to while sending
+/// or sampling a CTE. Empty if the antennas aren't switched (ex: when sending an AoA CTE).
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash, Default)]
+pub struct SwitchingPattern(Vec<u8>);
+impl SwitchingPattern {
+    pub const MIN_LEN: usize = 0x02;
+    pub const MAX_LEN: usize = 0x4B;
+    pub fn empty() -> SwitchingPattern {
+        SwitchingPattern(Vec::new())
+    }
+    /// Returns `None` if `antenna_ids` isn't empty and isn't 2 to 75 antennas long.
+    pub fn new_checked(antenna_ids: &[u8]) -> Option<SwitchingPattern> {
+        if antenna_ids.is_empty() || (Self::MIN_LEN..=Self::MAX_LEN).contains(&antenna_ids.len()) {
+            Some(SwitchingPattern(antenna_ids.to_vec()))
+        } else {
+            None
+        }
+    }
+    pub fn antenna_ids(&self) -> &[u8] {
+        &self.0
+    }
+    /// Packed length (including the `Switching_Pattern_Length` byte).
+    pub fn byte_len(&self) -> usize {
+        1 + self.0.len()
+    }
+    pub fn pack_into(&self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(self.byte_len(), buf)?;
+        buf[0] = self.0.len() as u8;
+        buf[1..].copy_from_slice(&self.0);
+        Ok(())
+    }
+    pub fn unpack_from(buf: &[u8]) -> Result<SwitchingPattern, PackError> {
+        let len = usize::from(*buf.get(0).ok_or(PackError::BadLength {
+            expected: 1,
+            got: 0,
+        })?);
+        PackError::expect_length(1 + len, buf)?;
+        SwitchingPattern::new_checked(&buf[1..]).ok_or(PackError::bad_index(0))
+    }
+}
+/// One IQ sample of a CTE. Either component is [`IQSample::INVALID`] if the sample isn't valid.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash, Default)]
+pub struct IQSample {
+    pub i: i8,
+    pub q: i8,
+}
+impl IQSample {
+    pub const BYTE_LEN: usize = 2;
+    pub const INVALID: i8 = -128;
+    pub fn is_valid(self) -> bool {
+        self.i != Self::INVALID && self.q != Self::INVALID
+    }
+}
@@ -0,0 +1,141 @@
+//! LE Link Layer features. Exchanged between controllers and reported to the host so it can
+//! check what the local (and remote) controller supports.
+use crate::{ConversionError, PackError};
+use core::convert::TryFrom;
+
+/// Bit index of each feature in [`LEFeatures`].
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+#[repr(u8)]
+pub enum LEFeature {
+    Encryption = 0,
+    ConnectionParametersRequest = 1,
+    ExtendedRejectIndication = 2,
+    SlaveInitiatedFeaturesExchange = 3,
+    Ping = 4,
+    DataPacketLengthExtension = 5,
+    LLPrivacy = 6,
+    ExtendedScannerFilterPolicies = 7,
+    LE2MPHY = 8,
+    StableModulationIndexTransmitter = 9,
+    StableModulationIndexReceiver = 10,
+    LECodedPHY = 11,
+    ExtendedAdvertising = 12,
+    PeriodicAdvertising = 13,
+    ChannelSelectionAlgorithm2 = 14,
+    PowerClass1 = 15,
+    MinimumNumberOfUsedChannels = 16,
+    ConnectionCTERequest = 17,
+    ConnectionCTEResponse = 18,
+    ConnectionlessCTETransmitter = 19,
+    ConnectionlessCTEReceiver = 20,
+    AntennaSwitchingDuringCTETransmission = 21,
+    AntennaSwitchingDuringCTEReception = 22,
+    ReceivingConstantToneExtensions = 23,
+    PeriodicAdvertisingSyncTransferSender = 24,
+    PeriodicAdvertisingSyncTransferRecipient = 25,
+    SleepClockAccuracyUpdates = 26,
+    RemotePublicKeyValidation = 27,
+    ConnectedIsochronousStreamMaster = 28,
+    ConnectedIsochronousStreamSlave = 29,
+    IsochronousBroadcaster = 30,
+    SynchronizedReceiver = 31,
+    IsochronousChannelsHostSupport = 32,
+    PowerControlRequest = 33,
+    PowerChangeIndication = 34,
+    PathLossMonitoring = 35,
+}
+impl LEFeature {
+    pub const MAX_BIT: u8 = LEFeature::PathLossMonitoring as u8;
+}
+impl From<LEFeature> for u8 {
+    fn from(f: LEFeature) -> Self {
+        f as u8
+    }
+}
+impl TryFrom<u8> for LEFeature {
+    type Error = ConversionError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(LEFeature::Encryption),
+            1 => Ok(LEFeature::ConnectionParametersRequest),
+            2 => Ok(LEFeature::ExtendedRejectIndication),
+            3 => Ok(LEFeature::SlaveInitiatedFeaturesExchange),
+            4 => Ok(LEFeature::Ping),
+            5 => Ok(LEFeature::DataPacketLengthExtension),
+            6 => Ok(LEFeature::LLPrivacy),
+            7 => Ok(LEFeature::ExtendedScannerFilterPolicies),
+            8 => Ok(LEFeature::LE2MPHY),
+            9 => Ok(LEFeature::StableModulationIndexTransmitter),
+            10 => Ok(LEFeature::StableModulationIndexReceiver),
+            11 => Ok(LEFeature::LECodedPHY),
+            12 => Ok(LEFeature::ExtendedAdvertising),
+            13 => Ok(LEFeature::PeriodicAdvertising),
+            14 => Ok(LEFeature::ChannelSelectionAlgorithm2),
+            15 => Ok(LEFeature::PowerClass1),
+            16 => Ok(LEFeature::MinimumNumberOfUsedChannels),
+            17 => Ok(LEFeature::ConnectionCTERequest),
+            18 => Ok(LEFeature::ConnectionCTEResponse),
+            19 => Ok(LEFeature::ConnectionlessCTETransmitter),
+            20 => Ok(LEFeature::ConnectionlessCTEReceiver),
+            21 => Ok(LEFeature::AntennaSwitchingDuringCTETransmission),
+            22 => Ok(LEFeature::AntennaSwitchingDuringCTEReception),
+            23 => Ok(LEFeature::ReceivingConstantToneExtensions),
+            24 => Ok(LEFeature::PeriodicAdvertisingSyncTransferSender),
+            25 => Ok(LEFeature::PeriodicAdvertisingSyncTransferRecipient),
+            26 => Ok(LEFeature::SleepClockAccuracyUpdates),
+            27 => Ok(LEFeature::RemotePublicKeyValidation),
+            28 => Ok(LEFeature::ConnectedIsochronousStreamMaster),
+            29 => Ok(LEFeature::ConnectedIsochronousStreamSlave),
+            30 => Ok(LEFeature::IsochronousBroadcaster),
+            31 => Ok(LEFeature::SynchronizedReceiver),
+            32 => Ok(LEFeature::IsochronousChannelsHostSupport),
+            33 => Ok(LEFeature::PowerControlRequest),
+            34 => Ok(LEFeature::PowerChangeIndication),
+            35 => Ok(LEFeature::PathLossMonitoring),
+            _ => Err(ConversionError(())),
+        }
+    }
+}
+/// 64-bit LE features bit field. Unknown (future) bits are kept as is.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash, Default)]
+pub struct LEFeatures(pub u64);
+impl LEFeatures {
+    pub const BYTE_LEN: usize = 8;
+    pub const fn zeroed() -> LEFeatures {
+        LEFeatures(0)
+    }
+    pub fn get(self, feature: LEFeature) -> bool {
+        self.0 & (1 << u8::from(feature)) != 0
+    }
+    pub fn enable(&mut self, feature: LEFeature) {
+        self.0 |= 1 << u8::from(feature);
+    }
+    pub fn disable(&mut self, feature: LEFeature) {
+        self.0 &= !(1 << u8::from(feature));
+    }
+    /// Iterates over every known enabled `LEFeature`.
+    pub fn features(self) -> impl Iterator<Item = LEFeature> {
+        (0..=LEFeature::MAX_BIT)
+            .filter_map(|bit| LEFeature::try_from(bit).ok())
+            .filter(move |f| self.get(*f))
+    }
+    pub fn pack_into(self, buf: &mut [u8]) -> Result<(), PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        buf.copy_from_slice(&self.0.to_le_bytes());
+        Ok(())
+    }
+    pub fn unpack_from(buf: &[u8]) -> Result<Self, PackError> {
+        PackError::expect_length(Self::BYTE_LEN, buf)?;
+        let mut bytes = [0_u8; Self::BYTE_LEN];
+        bytes.copy_from_slice(buf);
+        Ok(LEFeatures(u64::from_le_bytes(bytes)))
+    }
+}
+impl From<LEFeature> for LEFeatures {
+    fn from(feature: LEFeature) -> Self {
+        let mut out = LEFeatures::zeroed();
+        out.enable(feature);
+        out
+    }
+}
@@ -0,0 +1,232 @@
+//! LE Channel Selection Algorithms. Pick the data channel of each connection event from the
+//! connection's `ChannelMap`. Both algorithms are pure functions of the connection event
+//! counter so the hop sequence can be computed (and sniffed) ahead of time.
+use crate::channel::{ChannelMap, Index};
+use crate::ConversionError;
+use core::convert::TryFrom;
+
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+#[repr(u8)]
+pub enum ChannelSelectionAlgorithm {
+    Algorithm1 = 0x00,
+    Algorithm2 = 0x01,
+}
+impl ChannelSelectionAlgorithm {
+    pub const BYTE_LEN: usize = 1;
+}
+impl From<ChannelSelectionAlgorithm> for u8 {
+    fn from(a: ChannelSelectionAlgorithm) -> Self {
+        a as u8
+    }
+}
+impl TryFrom<u8> for ChannelSelectionAlgorithm {
+    type Error = ConversionError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x00 => Ok(ChannelSelectionAlgorithm::Algorithm1),
+            0x01 => Ok(ChannelSelectionAlgorithm::Algorithm2),
+            _ => Err(ConversionError(())),
+        }
+    }
+}
+/// Maps an unmapped channel to a used one (by `remapping_index` into the used channels) if it
+/// isn't used.
+fn remap(map: ChannelMap, unmapped: u8, remapping_index: impl FnOnce(u8) -> u8) -> Option<Index> {
+    let unmapped = Index::new_checked(unmapped)?;
+    if map.get(unmapped) {
+        return Some(unmapped);
+    }
+    match map.used_count() {
+        0 => None,
+        used => map.used_channels().nth(usize::from(remapping_index(used))),
+    }
+}
+/// Channel Selection Algorithm #1 state of a connection.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct Algorithm1 {
+    hop_increment: u8,
+}
+impl Algorithm1 {
+    pub const MIN_HOP_INCREMENT: u8 = 5;
+    pub const MAX_HOP_INCREMENT: u8 = 16;
+    /// Returns `None` if `hop_increment` isn't between 5 and 16.
+    pub fn new(hop_increment: u8) -> Option<Algorithm1> {
+        if (Self::MIN_HOP_INCREMENT..=Self::MAX_HOP_INCREMENT).contains(&hop_increment) {
+            Some(Algorithm1 { hop_increment })
+        } else {
+            None
+        }
+    }
+    pub fn hop_increment(self) -> u8 {
+        self.hop_increment
+    }
+    /// Data channel of connection event `counter`. Returns `None` if `map` has no used
+    /// channels. Only matches the spec until the 16-bit `connEventCounter` first wraps (the
+    /// unmapped channel keeps going from where it was), use [`Algorithm1::event_channel`] past
+    /// that.
+    pub fn channel(self, map: ChannelMap, counter: u16) -> Option<Index> {
+        self.event_channel(map, u64::from(counter))
+    }
+    /// Data channel of the `event`th connection event since the connection was created (not
+    /// wrapped at `0xFFFF` like `connEventCounter`). Returns `None` if `map` has no used
+    /// channels.
+    pub fn event_channel(self, map: ChannelMap, event: u64) -> Option<Index> {
+        // `lastUnmappedChannel` starts at 0 and each event adds `hop_increment`.
+        let channels = u64::from(ChannelMap::DATA_CHANNEL_COUNT);
+        let unmapped = (event % channels + 1) * u64::from(self.hop_increment) % channels;
+        let unmapped = unmapped as u8;
+        remap(map, unmapped, |used| unmapped % used)
+    }
+}
+/// Channel Selection Algorithm #2 state of a connection.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub struct Algorithm2 {
+    channel_identifier: u16,
+}
+impl Algorithm2 {
+    pub fn new(access_address: u32) -> Algorithm2 {
+        Algorithm2 {
+            channel_identifier: ((access_address >> 16) ^ (access_address & 0xFFFF)) as u16,
+        }
+    }
+    pub fn channel_identifier(self) -> u16 {
+        self.channel_identifier
+    }
+    /// Multiply, Add and Modulo.
+    fn mam(a: u16, b: u16) -> u16 {
+        a.wrapping_mul(17).wrapping_add(b)
+    }
+    /// Reverses the bits of each byte.
+    fn perm(a: u16) -> u16 {
+        u16::from_le_bytes([(a as u8).reverse_bits(), ((a >> 8) as u8).reverse_bits()])
+    }
+    /// Pseudo random number for the event `counter`.
+    fn prn_e(self, counter: u16) -> u16 {
+        let mut prn_s = counter ^ self.channel_identifier;
+        for _ in 0..3 {
+            prn_s = Self::mam(Self::perm(prn_s), self.channel_identifier);
+        }
+        prn_s ^ self.channel_identifier
+    }
+    /// Data channel of (connection or periodic advertising) event `counter`. Returns `None` if
+    /// `map` has no used channels.
+    pub fn channel(self, map: ChannelMap, counter: u16) -> Option<Index> {
+        let prn_e = self.prn_e(counter);
+        let unmapped = (prn_e % u16::from(ChannelMap::DATA_CHANNEL_COUNT)) as u8;
+        remap(map, unmapped, |used| {
+            ((u32::from(used) * u32::from(prn_e)) >> 16) as u8
+        })
+    }
+}
+/// Channel Selection Algorithm of a connection.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+pub enum ChannelSelection {
+    Algorithm1(Algorithm1),
+    Algorithm2(Algorithm2),
+}
+impl ChannelSelection {
+    pub fn algorithm(self) -> ChannelSelectionAlgorithm {
+        match self {
+            ChannelSelection::Algorithm1(_) => ChannelSelectionAlgorithm::Algorithm1,
+            ChannelSelection::Algorithm2(_) => ChannelSelectionAlgorithm::Algorithm2,
+        }
+    }
+    pub fn channel(self, map: ChannelMap, counter: u16) -> Option<Index> {
+        match self {
+            ChannelSelection::Algorithm1(a) => a.channel(map, counter),
+            ChannelSelection::Algorithm2(a) => a.channel(map, counter),
+        }
+    }
+    /// Data channel of the `event`th connection event since the connection was created (not
+    /// wrapped at `0xFFFF`). Algorithm #2 only uses the low 16 bits.
+    pub fn event_channel(self, map: ChannelMap, event: u64) -> Option<Index> {
+        match self {
+            ChannelSelection::Algorithm1(a) => a.event_channel(map, event),
+            ChannelSelection::Algorithm2(a) => a.channel(map, event as u16),
+        }
+    }
+    /// Data channels of each connection event starting at the `event`th one (see
+    /// [`ChannelSelection::event_channel`]). Empty if `map` has no used channels.
+    pub fn hop_sequence(self, map: ChannelMap, event: u64) -> impl Iterator<Item = Index> {
+        (event..).scan((), move |_, e| self.event_channel(map, e))
+    }
+}
+impl From<Algorithm1> for ChannelSelection {
+    fn from(a: Algorithm1) -> Self {
+        ChannelSelection::Algorithm1(a)
+    }
+}
+impl From<Algorithm2> for ChannelSelection {
+    fn from(a: Algorithm2) -> Self {
+        ChannelSelection::Algorithm2(a)
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    fn map_of(channels: &[u8]) -> ChannelMap {
+        let mut map = ChannelMap::zeroed();
+        for &c in channels {
+            map.enable(Index::new(c));
+        }
+        map
+    }
+    /// Core Spec v5.2 Vol 6 Part C Section 3.1 Sample Data 1 (all channels used).
+    #[test]
+    fn csa2_sample_data_1() {
+        let csa = Algorithm2::new(0x8E89_BED6);
+        assert_eq!(csa.channel_identifier(), 0x305F);
+        let channels = [(1, 20), (2, 6), (3, 21)];
+        for &(counter, channel) in channels.iter() {
+            assert_eq!(
+                csa.channel(ChannelMap::ALL, counter),
+                Some(Index::new(channel))
+            );
+        }
+    }
+    /// Core Spec v5.2 Vol 6 Part C Section 3.2 Sample Data 2 (9 channels used).
+    #[test]
+    fn csa2_sample_data_2() {
+        let csa = Algorithm2::new(0x8E89_BED6);
+        let map = map_of(&[9, 10, 21, 22, 23, 33, 34, 35, 36]);
+        let channels = [(6, 23), (7, 9), (8, 34)];
+        for &(counter, channel) in channels.iter() {
+            assert_eq!(csa.channel(map, counter), Some(Index::new(channel)));
+        }
+    }
+    #[test]
+    fn csa1_remaps_unused_channels() {
+        let csa = Algorithm1::new(7).unwrap();
+        let sequence = ChannelSelection::from(csa)
+            .hop_sequence(ChannelMap::ALL, 0)
+            .take(3)
+            .map(u8::from)
+            .collect::<Vec<_>>();
+        assert_eq!(sequence, [7, 14, 21]);
+        // Unmapped channel 7 % 2 used channels = index 1.
+        let map = map_of(&[1, 2]);
+        assert_eq!(csa.channel(map, 0), Some(Index::new(2)));
+    }
+    #[test]
+    fn csa1_continues_past_counter_wrap() {
+        let csa = Algorithm1::new(7).unwrap();
+        let selection = ChannelSelection::from(csa);
+        // `lastUnmappedChannel` of the event after 0xFFFF.
+        let expected = ((0x1_0000 + 1) * 7 % 37) as u8;
+        assert_eq!(
+            csa.event_channel(ChannelMap::ALL, 0x1_0000),
+            Some(Index::new(expected))
+        );
+        assert_ne!(csa.channel(ChannelMap::ALL, 0), Some(Index::new(expected)));
+        let mut sequence = selection.hop_sequence(ChannelMap::ALL, 0xFFFF);
+        assert_eq!(sequence.next(), csa.channel(ChannelMap::ALL, 0xFFFF));
+        assert_eq!(sequence.next(), Some(Index::new(expected)));
+        // Algorithm #2 only uses the wrapped counter.
+        let csa2 = ChannelSelection::from(Algorithm2::new(0x8E89_BED6));
+        assert_eq!(
+            csa2.event_channel(ChannelMap::ALL, 0x1_0001),
+            csa2.channel(ChannelMap::ALL, 1)
+        );
+    }
+}
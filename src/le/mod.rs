@@ -4,7 +4,10 @@ pub mod advertisement_structures;
 pub mod advertiser;
 pub mod att;
 pub mod connection;
+pub mod cte;
+pub mod features;
 pub mod gatt;
 pub mod link;
+pub mod phy;
 pub mod report;
 pub mod scan;
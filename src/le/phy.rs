@@ -0,0 +1,131 @@
+//! LE PHYs (physical layers). `LE1M` is supported by every controller, `LE2M` doubles the
+//! symbol rate and `LECoded` trades speed for range.
+use crate::ConversionError;
+use core::convert::TryFrom;
+
+/// LE PHY.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
+#[repr(u8)]
+pub enum PHY {
+    LE1M = 0x01,
+    LE2M = 0x02,
+    LECoded = 0x03,
+}
+impl PHY {
+    pub const BYTE_LEN: usize = 1;
+    pub const ALL: [PHY; 3] = [PHY::LE1M, PHY::LE2M, PHY::LECoded];
+}
+impl Default for PHY {
+    fn default() -> Self {
+        PHY::LE1M
+    }
+}
+impl From<PHY> for u8 {
+    fn from(p: PHY) -> Self {
+        p as u8
+    }
+}
+impl TryFrom<u8> for PHY {
+    type Error = ConversionError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x01 => Ok(PHY::LE1M),
+            0x02 => Ok(PHY::LE2M),
+            0x03 => Ok(PHY::LECoded),
+            _ => Err(ConversionError(())),
+        }
+    }
+}
+/// Bit field of `PHY`s. Used when more than one PHY can be allowed or preferred.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash, Default)]
+pub struct PHYMask(u8);
+impl PHYMask {
+    pub const BYTE_LEN: usize = 1;
+    const ALL_BITS: u8 = 0b111;
+    pub const fn zeroed() -> PHYMask {
+        PHYMask(0)
+    }
+    pub const fn all() -> PHYMask {
+        PHYMask(Self::ALL_BITS)
+    }
+    fn bit(phy: PHY) -> u8 {
+        1_u8 << (u8::from(phy) - 1)
+    }
+    pub fn enable(&mut self, phy: PHY) {
+        self.0 |= Self::bit(phy);
+    }
+    pub fn disable(&mut self, phy: PHY) {
+        self.0 &= !Self::bit(phy);
+    }
+    pub fn get(self, phy: PHY) -> bool {
+        self.0 & Self::bit(phy) != 0
+    }
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+    /// Iterates over every enabled `PHY`.
+    pub fn phys(self) -> impl Iterator<Item = PHY> {
+        PHY::ALL.iter().copied().filter(move |phy| self.get(*phy))
+    }
+}
+impl From<PHY> for PHYMask {
+    fn from(phy: PHY) -> Self {
+        PHYMask(PHYMask::bit(phy))
+    }
+}
+impl From<PHYMask> for u8 {
+    fn from(m: PHYMask) -> Self {
+        m.0
+    }
+}
+impl TryFrom<u8> for PHYMask {
+    type Error = ConversionError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        if value & !PHYMask::ALL_BITS == 0 {
+            Ok(PHYMask(value))
+        } else {
+            Err(ConversionError(()))
+        }
+    }
+}
+/// PHY a transmitter uses. Unlike `PHY`, it includes the LE Coded coding scheme (S=8 or S=2).
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Hash, Default)]
+#[repr(u8)]
+pub enum TxPHY {
+    #[default]
+    LE1M = 0x01,
+    LE2M = 0x02,
+    LECodedS8 = 0x03,
+    LECodedS2 = 0x04,
+}
+impl TxPHY {
+    pub const BYTE_LEN: usize = 1;
+    /// The `PHY` a receiver listens on. Receivers decode both LE Coded schemes.
+    pub fn phy(self) -> PHY {
+        match self {
+            TxPHY::LE1M => PHY::LE1M,
+            TxPHY::LE2M => PHY::LE2M,
+            TxPHY::LECodedS8 | TxPHY::LECodedS2 => PHY::LECoded,
+        }
+    }
+}
+impl From<TxPHY> for u8 {
+    fn from(p: TxPHY) -> Self {
+        p as u8
+    }
+}
+impl TryFrom<u8> for TxPHY {
+    type Error = ConversionError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x01 => Ok(TxPHY::LE1M),
+            0x02 => Ok(TxPHY::LE2M),
+            0x03 => Ok(TxPHY::LECodedS8),
+            0x04 => Ok(TxPHY::LECodedS2),
+            _ => Err(ConversionError(())),
+        }
+    }
+}
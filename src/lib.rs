@@ -72,7 +72,7 @@ impl PackError {
     /// `Err(HCIPackError::BadLength)` not.
     #[inline]
     pub fn atleast_length(expected: usize, buf: &[u8]) -> Result<(), PackError> {
-        if buf.len() == expected {
+        if buf.len() >= expected {
             Ok(())
         } else {
             Err(PackError::BadLength {